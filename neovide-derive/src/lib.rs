@@ -1,8 +1,12 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, Attribute, Data, DataStruct, DeriveInput, Error, Ident, Lit, Meta};
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DataStruct, DeriveInput, Error, Ident,
+    Lit, Meta, NestedMeta, Type,
+};
 
-#[proc_macro_derive(SettingGroup, attributes(setting_prefix))]
+#[proc_macro_derive(SettingGroup, attributes(setting_prefix, setting))]
 pub fn setting_group(item: TokenStream) -> TokenStream {
     let input = parse_macro_input!(item as DeriveInput);
     let prefix = setting_prefix(input.attrs.as_ref())
@@ -11,6 +15,96 @@ pub fn setting_group(item: TokenStream) -> TokenStream {
     stream(input, prefix)
 }
 
+// Implements FromValue and From<T> for Value for a struct which is set from a dictionary, so that
+// it can be the type of a SettingGroup field, for example g:neovide_cursor_vfx = {'mode': ...}
+#[proc_macro_derive(SettingMap)]
+pub fn setting_map(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    match input.data {
+        Data::Struct(ref data) => map_stream(input.ident, data),
+        _ => Error::new_spanned(input.ident, "SettingMap expects a struct")
+            .to_compile_error()
+            .into(),
+    }
+}
+
+// Fails to compile at the field's type, instead of somewhere in the generated code, when the type
+// doesn't implement the trait
+fn assert_implements(ty: &Type, trait_path: TokenStream2) -> TokenStream2 {
+    quote_spanned! {ty.span()=>
+        const _: fn() = || {
+            fn setting_field_type_must_implement<T: #trait_path>() {}
+            setting_field_type_must_implement::<#ty>();
+        };
+    }
+}
+
+fn map_stream(name: Ident, data: &DataStruct) -> TokenStream {
+    let mut idents = Vec::new();
+    let mut assertions = Vec::new();
+    for field in data.fields.iter() {
+        assertions.push(assert_implements(
+            &field.ty,
+            quote! { crate::settings::SettingValue },
+        ));
+        match field.ident {
+            Some(ref ident) => idents.push(ident),
+            None => {
+                return Error::new_spanned(field, "Expected named struct fields")
+                    .to_compile_error()
+                    .into()
+            }
+        }
+    }
+    let keys: Vec<String> = idents.iter().map(|ident| ident.to_string()).collect();
+    let type_name = name.to_string();
+
+    let expanded = quote! {
+        #(#assertions)*
+
+        impl crate::settings::FromValue for #name {
+            // Keys left out keep their value. A value which isn't a dictionary or has a key the
+            // struct doesn't know is rejected as a whole, while a field with a wrong type keeps
+            // its value like any other setting.
+            fn from_value(&mut self, value: rmpv::Value) {
+                let entries = match value {
+                    rmpv::Value::Map(entries) => entries,
+                    value => {
+                        log::error!("Setting expected a dictionary, but received {:?}", value);
+                        return;
+                    }
+                };
+
+                let mut updated = self.clone();
+                for (key, value) in entries {
+                    match key.as_str() {
+                        #(Some(#keys) => {
+                            crate::settings::FromValue::from_value(&mut updated.#idents, value)
+                        })*
+                        _ => {
+                            log::error!("Unknown key {:?} in a {} dictionary", key, #type_name);
+                            return;
+                        }
+                    }
+                }
+                *self = updated;
+            }
+        }
+
+        impl From<#name> for rmpv::Value {
+            fn from(map: #name) -> Self {
+                rmpv::Value::Map(vec![
+                    #((
+                        rmpv::Value::from(#keys),
+                        crate::settings::SettingValue::to_value(map.#idents)
+                    )),*
+                ])
+            }
+        }
+    };
+    TokenStream::from(expanded)
+}
+
 fn stream(input: DeriveInput, prefix: String) -> TokenStream {
     const ERR_MSG: &str = "Derive macro expects a struct";
     match input.data {
@@ -25,32 +119,93 @@ fn stream(input: DeriveInput, prefix: String) -> TokenStream {
 }
 
 fn struct_stream(name: Ident, prefix: String, data: &DataStruct) -> TokenStream {
-    let fragments = data.fields.iter().map(|field| match field.ident {
-        Some(ref ident) => {
-            let vim_setting_name = format!("{}{}", prefix, ident);
-            quote! {{
+    let owner = name.to_string();
+    let mut fragments = Vec::new();
+    let mut setting_names = Vec::new();
+    for field in data.fields.iter() {
+        let ident = match field.ident {
+            Some(ref ident) => ident,
+            None => {
+                fragments.push(
+                    Error::new_spanned(field.colon_token, "Expected named struct fields")
+                        .to_compile_error(),
+                );
+                continue;
+            }
+        };
+        let attributes = match field_attributes(field.attrs.as_ref()) {
+            Ok(attributes) => attributes,
+            Err(error) => {
+                fragments.push(error.to_compile_error());
+                continue;
+            }
+        };
+
+        // Option backed fields follow a vim option, which neovide never writes back to
+        if let Some(ref option_name) = attributes.option {
+            if !attributes.aliases.is_empty() {
+                fragments.push(
+                    Error::new_spanned(ident, "Option backed settings can't have an alias")
+                        .to_compile_error(),
+                );
+                continue;
+            }
+            let range_check = range_check(ident, &attributes, &format!("&{}", option_name));
+            let assertion = assert_implements(&field.ty, quote! { crate::settings::FromValue });
+            fragments.push(quote! {{
+                #assertion
+
                 fn update_func(value: rmpv::Value) {
                     let mut s = crate::settings::SETTINGS.get::<#name>();
-                    s.#ident.from_value(value);
+                    crate::settings::FromValue::from_value(&mut s.#ident, value);
+                    #range_check
                     crate::settings::SETTINGS.set(&s);
                 }
 
-                fn reader_func() -> rmpv::Value {
-                    let s = crate::settings::SETTINGS.get::<#name>();
-                    s.#ident.into()
-                }
-
-                crate::settings::SETTINGS.set_setting_handlers(
-                    #vim_setting_name,
-                    update_func,
-                    reader_func
-                );
-            }}
+                crate::settings::SETTINGS.set_option_handler(#option_name, update_func);
+            }});
+            continue;
         }
-        None => {
-            Error::new_spanned(field.colon_token, "Expected named struct fields").to_compile_error()
-        }
-    });
+
+        // A field prefix nests under the struct prefix rather than replacing it
+        let field_prefix = attributes
+            .prefix
+            .map(|p| format!("{}_", p))
+            .unwrap_or_else(|| "".to_string());
+        let vim_setting_name = format!("{}{}{}", prefix, field_prefix, ident);
+        let range_check = range_check(
+            ident,
+            &attributes,
+            &format!("g:neovide_{}", vim_setting_name),
+        );
+        let aliases = &attributes.aliases;
+        let assertion = assert_implements(&field.ty, quote! { crate::settings::SettingValue });
+        fragments.push(quote! {{
+            #assertion
+
+            fn update_func(value: rmpv::Value) {
+                let mut s = crate::settings::SETTINGS.get::<#name>();
+                crate::settings::FromValue::from_value(&mut s.#ident, value);
+                #range_check
+                crate::settings::SETTINGS.set(&s);
+            }
+
+            fn reader_func() -> rmpv::Value {
+                let s = crate::settings::SETTINGS.get::<#name>();
+                crate::settings::SettingValue::to_value(s.#ident)
+            }
+
+            crate::settings::SETTINGS.set_setting_handlers(
+                #vim_setting_name,
+                #owner,
+                update_func,
+                reader_func
+            );
+            #(crate::settings::SETTINGS.set_setting_alias(#aliases, #vim_setting_name);)*
+        }});
+        setting_names.push(vim_setting_name);
+    }
+
     let expanded = quote! {
         impl #name {
             pub fn register() {
@@ -58,6 +213,18 @@ fn struct_stream(name: Ident, prefix: String, data: &DataStruct) -> TokenStream
                 crate::settings::SETTINGS.set(&s);
                 #(#fragments)*
             }
+
+            // The names of the g:neovide_ variables the struct is registered under
+            #[allow(dead_code)]
+            pub fn setting_names() -> Vec<&'static str> {
+                vec![#(#setting_names),*]
+            }
+
+            // The current value of each of the struct's g:neovide_ variables, keyed by name
+            #[allow(dead_code)]
+            pub fn snapshot() -> rmpv::Value {
+                crate::settings::SETTINGS.snapshot(&Self::setting_names())
+            }
         }
     };
     TokenStream::from(expanded)
@@ -75,3 +242,86 @@ fn setting_prefix(attrs: &[Attribute]) -> Option<String> {
     }
     None
 }
+
+// A value outside of the field's bounds is rejected, and the setting keeps its current value
+fn range_check(ident: &Ident, attributes: &FieldAttributes, setting: &str) -> TokenStream2 {
+    let (checks, description) = match (&attributes.min, &attributes.max) {
+        (None, None) => return quote! {},
+        (Some(min), None) => (
+            quote! { s.#ident < #min },
+            format!("at least {}", quote!(#min)),
+        ),
+        (None, Some(max)) => (
+            quote! { s.#ident > #max },
+            format!("at most {}", quote!(#max)),
+        ),
+        (Some(min), Some(max)) => (
+            quote! { s.#ident < #min || s.#ident > #max },
+            format!("between {} and {}", quote!(#min), quote!(#max)),
+        ),
+    };
+    let message = format!("{} must be {}, but received {{:?}}", setting, description);
+    quote! {
+        if #checks {
+            log::error!(#message, s.#ident);
+            return;
+        }
+    }
+}
+
+#[derive(Default)]
+struct FieldAttributes {
+    prefix: Option<String>,
+    option: Option<String>,
+    min: Option<Lit>,
+    max: Option<Lit>,
+    // Former names, without g:neovide_, which keep working with a deprecation warning
+    aliases: Vec<String>,
+}
+
+// Reads #[setting(prefix = "...")], #[setting(option = "...")], the numeric bounds
+// #[setting(min = 0.0, max = 1.0)] and any number of #[setting(alias = "...")] from a field. The
+// bounds are compared with the field, so they have to be literals of its type.
+fn field_attributes(attrs: &[Attribute]) -> Result<FieldAttributes, Error> {
+    const ERR_MSG: &str = "Expected #[setting(prefix = \"...\")], #[setting(option = \"...\")], \
+                           #[setting(alias = \"...\")] or #[setting(min = ..., max = ...)]";
+    let mut attributes = FieldAttributes::default();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("setting")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new_spanned(meta, ERR_MSG)),
+        };
+        for nested in list.nested.iter() {
+            let name_value = match nested {
+                NestedMeta::Meta(Meta::NameValue(name_value)) => name_value,
+                _ => return Err(Error::new_spanned(nested, ERR_MSG)),
+            };
+            if name_value.path.is_ident("min") || name_value.path.is_ident("max") {
+                let bound = match name_value.lit {
+                    Lit::Int(_) | Lit::Float(_) => Some(name_value.lit.clone()),
+                    _ => return Err(Error::new_spanned(&name_value.lit, ERR_MSG)),
+                };
+                if name_value.path.is_ident("min") {
+                    attributes.min = bound;
+                } else {
+                    attributes.max = bound;
+                }
+                continue;
+            }
+            let value = match name_value.lit {
+                Lit::Str(ref literal) => literal.value(),
+                _ => return Err(Error::new_spanned(&name_value.lit, ERR_MSG)),
+            };
+            if name_value.path.is_ident("prefix") {
+                attributes.prefix = Some(value);
+            } else if name_value.path.is_ident("option") {
+                attributes.option = Some(value);
+            } else if name_value.path.is_ident("alias") {
+                attributes.aliases.push(value);
+            } else {
+                return Err(Error::new_spanned(nested, ERR_MSG));
+            }
+        }
+    }
+    Ok(attributes)
+}
@@ -0,0 +1,161 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use parking_lot::RwLock;
+use rmpv::Value;
+
+use crate::{
+    bridge::UI_COMMAND_STATS, event_aggregator::EVENT_AGGREGATOR, settings::SETTINGS,
+    window::WindowCommand,
+};
+
+// How frames are drawn, decided when the window is created
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderBackend {
+    // Skia draws through the gpu's opengl
+    Gpu,
+    // Skia draws through the software implementation of opengl
+    Software,
+    // Skia draws in memory and the frame is copied to the window
+    Raster,
+}
+
+impl RenderBackend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderBackend::Gpu => "skia-opengl",
+            RenderBackend::Software => "skia-opengl-software",
+            RenderBackend::Raster => "skia-raster",
+        }
+    }
+}
+
+lazy_static! {
+    // The scale factor of the monitor the window is on, stored as the bits of an f64 so that the
+    // window can update it without a lock
+    static ref SCALE_FACTOR: AtomicU64 = AtomicU64::new(1.0f64.to_bits());
+    static ref RENDER_BACKEND: RwLock<RenderBackend> = RwLock::new(RenderBackend::Gpu);
+}
+
+pub fn set_render_backend(backend: RenderBackend) {
+    *RENDER_BACKEND.write() = backend;
+}
+
+pub fn set_scale_factor(scale_factor: f64) {
+    SCALE_FACTOR.store(scale_factor.to_bits(), Ordering::Relaxed);
+}
+
+fn scale_factor() -> f64 {
+    f64::from_bits(SCALE_FACTOR.load(Ordering::Relaxed))
+}
+
+// The requests plugins can make with rpcrequest on the neovide channel. Every method is declared
+// to neovim through nvim_set_client_info, so adding one here is all it takes to expose it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ApiMethod {
+    // Returns the ui command counters, see UI_COMMAND_STATS
+    Stats,
+    // Returns the neovide version, the render backend and the current scale factor
+    GetInfo,
+    // Turns zen mode on or off without changing g:neovide_zen
+    ToggleZen,
+    // Returns the current value of every g:neovide_ setting, grouped by setting group
+    DumpSettings,
+}
+
+impl ApiMethod {
+    pub const ALL: &'static [ApiMethod] = &[
+        ApiMethod::Stats,
+        ApiMethod::GetInfo,
+        ApiMethod::ToggleZen,
+        ApiMethod::DumpSettings,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApiMethod::Stats => "neovide.stats",
+            ApiMethod::GetInfo => "neovide.get_info",
+            ApiMethod::ToggleZen => "neovide.toggle_zen",
+            ApiMethod::DumpSettings => "neovide.dump_settings",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ApiMethod> {
+        ApiMethod::ALL
+            .iter()
+            .copied()
+            .find(|method| method.name() == name)
+    }
+
+    fn nargs(&self) -> u64 {
+        match self {
+            ApiMethod::Stats
+            | ApiMethod::GetInfo
+            | ApiMethod::ToggleZen
+            | ApiMethod::DumpSettings => 0,
+        }
+    }
+
+    pub fn handle(&self, _arguments: Vec<Value>) -> Result<Value, Value> {
+        match self {
+            ApiMethod::Stats => Ok(UI_COMMAND_STATS.to_value()),
+            ApiMethod::GetInfo => Ok(Value::Map(vec![
+                (Value::from("version"), Value::from(crate_version!())),
+                (
+                    Value::from("renderer"),
+                    Value::from(RENDER_BACKEND.read().name()),
+                ),
+                (Value::from("scale_factor"), Value::from(scale_factor())),
+            ])),
+            ApiMethod::ToggleZen => {
+                EVENT_AGGREGATOR.send(WindowCommand::ToggleZen);
+                Ok(Value::Nil)
+            }
+            ApiMethod::DumpSettings => Ok(SETTINGS.snapshot_all()),
+        }
+    }
+}
+
+// The methods argument of nvim_set_client_info
+pub fn method_table() -> Vec<(Value, Value)> {
+    ApiMethod::ALL
+        .iter()
+        .map(|method| {
+            (
+                Value::from(method.name()),
+                Value::Map(vec![
+                    (Value::from("async"), Value::from(false)),
+                    (Value::from("nargs"), Value::from(method.nargs())),
+                ]),
+            )
+        })
+        .collect()
+}
+
+// The version argument of nvim_set_client_info
+pub fn client_version() -> Vec<(Value, Value)> {
+    let mut parts = crate_version!()
+        .split('.')
+        .map(|part| part.parse::<u64>().unwrap_or(0));
+    ["major", "minor", "patch"]
+        .iter()
+        .map(|key| (Value::from(*key), Value::from(parts.next().unwrap_or(0))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_method_is_declared() {
+        let methods = method_table();
+
+        assert_eq!(methods.len(), ApiMethod::ALL.len());
+        for method in ApiMethod::ALL {
+            assert_eq!(ApiMethod::from_name(method.name()), Some(*method));
+            assert!(methods
+                .iter()
+                .any(|(name, _)| name.as_str() == Some(method.name())));
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use rmpv::Value;
+
+// The state of the current buffer which the window shows around the grid. Neovim reports it from
+// autocommands as &modified and &readonly || !&modifiable.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChromeState {
+    pub modified: bool,
+    pub readonly: bool,
+}
+
+// Vimscript booleans arrive as numbers, lua ones as booleans
+fn as_flag(value: &Value) -> Option<bool> {
+    value
+        .as_bool()
+        .or_else(|| value.as_i64().map(|value| value != 0))
+}
+
+pub fn parse_chrome_state(arguments: &[Value]) -> Option<ChromeState> {
+    match arguments {
+        [modified, readonly, ..] => Some(ChromeState {
+            modified: as_flag(modified)?,
+            readonly: as_flag(readonly)?,
+        }),
+        _ => None,
+    }
+}
+
+impl ChromeState {
+    // A dot in front of the title for unsaved changes and a mark after it for buffers which can't
+    // be written, like the title editors show on macos
+    pub fn decorate_title(&self, title: &str) -> String {
+        let mut decorated = String::new();
+        if self.modified {
+            decorated.push_str("● ");
+        }
+        decorated.push_str(title);
+        if self.readonly {
+            decorated.push_str(" [RO]");
+        }
+        decorated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chrome_state() {
+        assert_eq!(
+            parse_chrome_state(&[Value::from(1), Value::from(0)]),
+            Some(ChromeState {
+                modified: true,
+                readonly: false,
+            })
+        );
+        assert_eq!(
+            parse_chrome_state(&[Value::from(false), Value::from(true)]),
+            Some(ChromeState {
+                modified: false,
+                readonly: true,
+            })
+        );
+        assert_eq!(parse_chrome_state(&[Value::from(1)]), None);
+        assert_eq!(
+            parse_chrome_state(&[Value::from("yes"), Value::from(0)]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decorated_titles() {
+        let title = "main.rs - NVIM";
+        assert_eq!(ChromeState::default().decorate_title(title), title);
+        assert_eq!(
+            ChromeState {
+                modified: true,
+                readonly: true,
+            }
+            .decorate_title(title),
+            "● main.rs - NVIM [RO]"
+        );
+    }
+}
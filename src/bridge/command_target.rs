@@ -0,0 +1,91 @@
+use async_trait::async_trait;
+use nvim_rs::{
+    error::{CallError, EncodeError},
+    Neovim,
+};
+use rmpv::Value;
+
+use crate::bridge::TxWrapper;
+
+// The parts of the neovim api which ui commands are executed against. Neovim is the only real
+// target, but the command handler can be driven by a recording fake in tests.
+#[async_trait]
+pub trait UiCommandTarget: Send + Sync {
+    async fn input(&self, keys: &str) -> Result<i64, Box<CallError>>;
+    async fn input_mouse(
+        &self,
+        button: &str,
+        action: &str,
+        modifier: &str,
+        grid: i64,
+        row: i64,
+        col: i64,
+    ) -> Result<(), Box<CallError>>;
+    async fn command(&self, command: &str) -> Result<(), Box<CallError>>;
+    async fn eval(&self, expr: &str) -> Result<Value, Box<CallError>>;
+    async fn call(
+        &self,
+        method: &str,
+        args: Vec<Value>,
+    ) -> Result<Result<Value, Value>, Box<EncodeError>>;
+    async fn err_writeln(&self, message: &str) -> Result<(), Box<CallError>>;
+    async fn ui_try_resize(&self, width: i64, height: i64) -> Result<(), Box<CallError>>;
+    async fn ui_set_option(&self, name: &str, value: Value) -> Result<(), Box<CallError>>;
+    async fn ui_detach(&self) -> Result<(), Box<CallError>>;
+    async fn paste(&self, data: &str, crlf: bool, phase: i64) -> Result<bool, Box<CallError>>;
+}
+
+#[async_trait]
+impl UiCommandTarget for Neovim<TxWrapper> {
+    async fn input(&self, keys: &str) -> Result<i64, Box<CallError>> {
+        Neovim::input(self, keys).await
+    }
+
+    async fn input_mouse(
+        &self,
+        button: &str,
+        action: &str,
+        modifier: &str,
+        grid: i64,
+        row: i64,
+        col: i64,
+    ) -> Result<(), Box<CallError>> {
+        Neovim::input_mouse(self, button, action, modifier, grid, row, col).await
+    }
+
+    async fn command(&self, command: &str) -> Result<(), Box<CallError>> {
+        Neovim::command(self, command).await
+    }
+
+    async fn eval(&self, expr: &str) -> Result<Value, Box<CallError>> {
+        Neovim::eval(self, expr).await
+    }
+
+    async fn call(
+        &self,
+        method: &str,
+        args: Vec<Value>,
+    ) -> Result<Result<Value, Value>, Box<EncodeError>> {
+        Neovim::call(self, method, args).await
+    }
+
+    async fn err_writeln(&self, message: &str) -> Result<(), Box<CallError>> {
+        Neovim::err_writeln(self, message).await
+    }
+
+    async fn ui_try_resize(&self, width: i64, height: i64) -> Result<(), Box<CallError>> {
+        Neovim::ui_try_resize(self, width, height).await
+    }
+
+    async fn ui_set_option(&self, name: &str, value: Value) -> Result<(), Box<CallError>> {
+        Neovim::ui_set_option(self, name, value).await
+    }
+
+    async fn ui_detach(&self) -> Result<(), Box<CallError>> {
+        Neovim::ui_detach(self).await
+    }
+
+    async fn paste(&self, data: &str, crlf: bool, phase: i64) -> Result<bool, Box<CallError>> {
+        Neovim::paste(self, data, crlf, phase).await
+    }
+}
@@ -4,6 +4,7 @@
 use std::{
     io::{self, Error, ErrorKind},
     process::Stdio,
+    sync::Arc,
 };
 
 use nvim_rs::{error::LoopError, neovim::Neovim, Handler};
@@ -16,7 +17,10 @@ use tokio::{
 };
 use tokio_util::compat::TokioAsyncReadCompatExt;
 
-use crate::bridge::{TxWrapper, WrapTx};
+use crate::bridge::{
+    process::{CheckedStdout, NeovimProcess, StartupOutput},
+    TxWrapper, WrapTx,
+};
 
 /// Connect to a neovim instance via tcp
 pub async fn new_tcp<A, H>(
@@ -37,20 +41,26 @@ where
 
 /// Connect to a neovim instance by spawning a new one
 ///
-/// stdin/stdout will be rewritten to `Stdio::piped()`
+/// stdin/stdout will be rewritten to `Stdio::piped()`. The process is returned so that its exit
+/// status can be waited on, and its stdout is checked to start with a message.
 pub async fn new_child_cmd<H>(
     cmd: &mut Command,
     handler: H,
-) -> io::Result<(Neovim<TxWrapper>, JoinHandle<Result<(), Box<LoopError>>>)>
+) -> io::Result<(
+    Neovim<TxWrapper>,
+    JoinHandle<Result<(), Box<LoopError>>>,
+    NeovimProcess,
+)>
 where
     H: Handler<Writer = TxWrapper>,
 {
     let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+    let startup_output = Arc::new(StartupOutput::default());
     let stdout = child
         .stdout
         .take()
-        .ok_or_else(|| Error::new(ErrorKind::Other, "Can't open stdout"))?
-        .compat();
+        .ok_or_else(|| Error::new(ErrorKind::Other, "Can't open stdout"))?;
+    let stdout = CheckedStdout::new(stdout, startup_output.clone()).compat();
     let stdin = child
         .stdin
         .take()
@@ -60,5 +70,5 @@ where
     let (neovim, io) = Neovim::<TxWrapper>::new(stdout, stdin, handler);
     let io_handle = spawn(io);
 
-    Ok((neovim, io_handle))
+    Ok((neovim, io_handle, NeovimProcess::new(child, startup_output)))
 }
@@ -8,7 +8,10 @@ use log::debug;
 use rmpv::Value;
 use skia_safe::Color4f;
 
-use crate::editor::{Colors, CursorMode, CursorShape, Style};
+use crate::{
+    bridge::UNHANDLED_EVENTS,
+    editor::{Colors, CursorMode, CursorShape, Style},
+};
 
 #[derive(Clone, Debug)]
 pub enum ParseError {
@@ -59,6 +62,21 @@ pub struct GridLineCell {
 
 pub type StyledContent = Vec<(u64, String)>;
 
+#[derive(Clone, Debug)]
+pub struct PopupMenuItem {
+    pub word: String,
+    pub kind: String,
+    pub menu: String,
+    pub info: String,
+}
+
+// A tab of ext_tabline, named after the buffer shown in its current window
+#[derive(Clone, Debug, PartialEq)]
+pub struct TablineTab {
+    pub tab: u64,
+    pub name: String,
+}
+
 #[derive(Clone, Debug)]
 pub enum MessageKind {
     Unknown,
@@ -119,7 +137,7 @@ pub enum WindowAnchor {
     SouthEast,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum EditorMode {
     // The set of modes reported will change in new versions of Nvim, for
     // instance more sub-modes and temporary states might be represented as
@@ -139,6 +157,9 @@ pub enum RedrawEvent {
     SetTitle {
         title: String,
     },
+    SetIcon {
+        icon: String,
+    },
     ModeInfoSet {
         cursor_modes: Vec<CursorMode>,
     },
@@ -153,6 +174,8 @@ pub enum RedrawEvent {
     MouseOff,
     BusyStart,
     BusyStop,
+    // Sent for :suspend and <C-z>, which a ui is left to handle
+    Suspend,
     Flush,
     Resize {
         grid: u64,
@@ -166,6 +189,10 @@ pub enum RedrawEvent {
         id: u64,
         style: Style,
     },
+    HighlightGroupSet {
+        name: String,
+        id: u64,
+    },
     GridLine {
         grid: u64,
         row: u64,
@@ -231,6 +258,18 @@ pub enum RedrawEvent {
         current_column: f64,
         line_count: Option<f64>,
     },
+    PopupMenuShow {
+        items: Vec<PopupMenuItem>,
+        selected: Option<u64>,
+        row: u64,
+        column: u64,
+        // None when the menu belongs to the external command line rather than a grid
+        grid: Option<u64>,
+    },
+    PopupMenuSelect {
+        selected: Option<u64>,
+    },
+    PopupMenuHide,
     CommandLineShow {
         content: StyledContent,
         position: u64,
@@ -256,6 +295,10 @@ pub enum RedrawEvent {
         line: StyledContent,
     },
     CommandLineBlockHide,
+    TablineUpdate {
+        current_tab: u64,
+        tabs: Vec<TablineTab>,
+    },
     MessageShow {
         kind: MessageKind,
         content: StyledContent,
@@ -435,6 +478,14 @@ fn parse_set_title(set_title_arguments: Vec<Value>) -> Result<RedrawEvent> {
     })
 }
 
+fn parse_set_icon(set_icon_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [icon] = extract_values(set_icon_arguments)?;
+
+    Ok(RedrawEvent::SetIcon {
+        icon: parse_string(icon)?,
+    })
+}
+
 fn parse_mode_info_set(mode_info_set_arguments: Vec<Value>) -> Result<RedrawEvent> {
     let [_cursor_style_enabled, mode_info] = extract_values(mode_info_set_arguments)?;
 
@@ -583,6 +634,15 @@ fn parse_hl_attr_define(hl_attr_define_arguments: Vec<Value>) -> Result<RedrawEv
     })
 }
 
+fn parse_hl_group_set(hl_group_set_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [name, id] = extract_values(hl_group_set_arguments)?;
+
+    Ok(RedrawEvent::HighlightGroupSet {
+        name: parse_string(name)?,
+        id: parse_u64(id)?,
+    })
+}
+
 fn parse_grid_line_cell(grid_line_cell: Value) -> Result<GridLineCell> {
     fn take_value(val: &mut Value) -> Value {
         std::mem::replace(val, Value::Nil)
@@ -766,6 +826,93 @@ fn parse_win_viewport(win_viewport_arguments: Vec<Value>) -> Result<RedrawEvent>
     })
 }
 
+// Neovim uses -1 to signal that no item is selected or that the menu has no grid
+fn parse_optional_index(index_value: Value) -> Result<Option<u64>> {
+    let index = parse_i64(index_value)?;
+    Ok(if index < 0 { None } else { Some(index as u64) })
+}
+
+fn parse_popupmenu_item(popupmenu_item: Value) -> Result<PopupMenuItem> {
+    let [word, kind, menu, info] = extract_values(parse_array(popupmenu_item)?)?;
+
+    Ok(PopupMenuItem {
+        word: parse_string(word)?,
+        kind: parse_string(kind)?,
+        menu: parse_string(menu)?,
+        info: parse_string(info)?,
+    })
+}
+
+fn parse_popupmenu_show(popupmenu_show_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let ([items, selected, row, column], [grid]) =
+        extract_values_with_optional(popupmenu_show_arguments)?;
+
+    let grid = if let Some(grid) = grid {
+        parse_optional_index(grid)?
+    } else {
+        Some(1)
+    };
+
+    Ok(RedrawEvent::PopupMenuShow {
+        items: parse_array(items)?
+            .into_iter()
+            .map(parse_popupmenu_item)
+            .collect::<Result<_>>()?,
+        selected: parse_optional_index(selected)?,
+        row: parse_u64(row)?,
+        column: parse_u64(column)?,
+        grid,
+    })
+}
+
+fn parse_popupmenu_select(popupmenu_select_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [selected] = extract_values(popupmenu_select_arguments)?;
+
+    Ok(RedrawEvent::PopupMenuSelect {
+        selected: parse_optional_index(selected)?,
+    })
+}
+
+// Tabpages are sent as msgpack extensions wrapping the handle
+fn parse_handle(handle_value: Value) -> Result<u64> {
+    match handle_value {
+        Value::Ext(_, bytes) => rmpv::decode::read_value(&mut bytes.as_slice())
+            .map_err(|error| ParseError::Format(error.to_string()))
+            .and_then(parse_u64),
+        handle_value => parse_u64(handle_value),
+    }
+}
+
+fn parse_tabline_tab(tab_value: Value) -> Result<TablineTab> {
+    let mut tab = None;
+    let mut name = String::new();
+    for (key, value) in parse_map(tab_value)? {
+        match parse_string(key)?.as_str() {
+            "tab" => tab = Some(parse_handle(value)?),
+            "name" => name = parse_string(value)?,
+            _ => {}
+        }
+    }
+
+    Ok(TablineTab {
+        tab: tab.ok_or_else(|| ParseError::Format("tab without a handle".to_owned()))?,
+        name,
+    })
+}
+
+fn parse_tabline_update(tabline_update_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    // Neovim 0.8 added the current buffer and the buffer list, which the tabs already name
+    let [current_tab, tabs] = extract_values(tabline_update_arguments)?;
+
+    Ok(RedrawEvent::TablineUpdate {
+        current_tab: parse_handle(current_tab)?,
+        tabs: parse_array(tabs)?
+            .into_iter()
+            .map(parse_tabline_tab)
+            .collect::<Result<_>>()?,
+    })
+}
+
 fn parse_styled_content(line: Value) -> Result<StyledContent> {
     parse_array(line)?
         .into_iter()
@@ -897,7 +1044,7 @@ pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
         let event_parameters = parse_array(event)?;
         let possible_parsed_event = match event_name.as_str() {
             "set_title" => Some(parse_set_title(event_parameters)?),
-            "set_icon" => None, // Ignore set icon for now
+            "set_icon" => Some(parse_set_icon(event_parameters)?),
             "mode_info_set" => Some(parse_mode_info_set(event_parameters)?),
             "option_set" => Some(parse_option_set(event_parameters)?),
             "mode_change" => Some(parse_mode_change(event_parameters)?),
@@ -905,10 +1052,12 @@ pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
             "mouse_off" => Some(RedrawEvent::MouseOff),
             "busy_start" => Some(RedrawEvent::BusyStart),
             "busy_stop" => Some(RedrawEvent::BusyStop),
+            "suspend" => Some(RedrawEvent::Suspend),
             "flush" => Some(RedrawEvent::Flush),
             "grid_resize" => Some(parse_grid_resize(event_parameters)?),
             "default_colors_set" => Some(parse_default_colors(event_parameters)?),
             "hl_attr_define" => Some(parse_hl_attr_define(event_parameters)?),
+            "hl_group_set" => Some(parse_hl_group_set(event_parameters)?),
             "grid_line" => Some(parse_grid_line(event_parameters)?),
             "grid_clear" => Some(parse_grid_clear(event_parameters)?),
             "grid_destroy" => Some(parse_grid_destroy(event_parameters)?),
@@ -921,6 +1070,9 @@ pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
             "win_close" => Some(parse_win_close(event_parameters)?),
             "msg_set_pos" => Some(parse_msg_set_pos(event_parameters)?),
             "win_viewport" => Some(parse_win_viewport(event_parameters)?),
+            "popupmenu_show" => Some(parse_popupmenu_show(event_parameters)?),
+            "popupmenu_select" => Some(parse_popupmenu_select(event_parameters)?),
+            "popupmenu_hide" => Some(RedrawEvent::PopupMenuHide),
             "cmdline_show" => Some(parse_cmdline_show(event_parameters)?),
             "cmdline_pos" => Some(parse_cmdline_pos(event_parameters)?),
             "cmdline_special_char" => Some(parse_cmdline_special_char(event_parameters)?),
@@ -928,13 +1080,17 @@ pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
             "cmdline_block_show" => Some(parse_cmdline_block_show(event_parameters)?),
             "cmdline_block_append" => Some(parse_cmdline_block_append(event_parameters)?),
             "cmdline_block_hide" => Some(RedrawEvent::CommandLineBlockHide),
+            "tabline_update" => Some(parse_tabline_update(event_parameters)?),
             "msg_show" => Some(parse_msg_show(event_parameters)?),
             "msg_clear" => Some(RedrawEvent::MessageClear),
             "msg_showmode" => Some(parse_msg_showmode(event_parameters)?),
             "msg_showcmd" => Some(parse_msg_showcmd(event_parameters)?),
             "msg_ruler" => Some(parse_msg_ruler(event_parameters)?),
             "msg_history_show" => Some(parse_msg_history_show(event_parameters)?),
-            _ => None,
+            _ => {
+                UNHANDLED_EVENTS.handle(&event_name, &event_parameters);
+                None
+            }
         };
 
         if let Some(parsed_event) = possible_parsed_event {
@@ -3,16 +3,43 @@ use log::trace;
 use nvim_rs::{Handler, Neovim};
 use rmpv::Value;
 
-#[cfg(windows)]
 use crate::bridge::ui_commands::{ParallelCommand, UiCommand};
 use crate::{
-    bridge::{events::parse_redraw_event, TxWrapper},
+    bridge::{
+        api::ApiMethod, chrome_state::parse_chrome_state, events::parse_redraw_event, TxWrapper,
+        REDRAW_QUEUE,
+    },
+    dimensions::Dimensions,
     editor::EditorCommand,
     error_handling::ResultPanicExplanation,
     event_aggregator::EVENT_AGGREGATOR,
+    running_tracker::RUNNING_TRACKER,
     settings::SETTINGS,
+    window::{parse_progress, WindowCommand},
 };
 
+// The optional dictionary argument of neovide.new_window, for example
+// {'cwd': '/path', 'files': ['main.rs']}
+fn parse_new_window(arguments: Vec<Value>) -> ParallelCommand {
+    let mut cwd = None;
+    let mut files = Vec::new();
+    if let Some(Value::Map(options)) = arguments.into_iter().next() {
+        for (key, value) in options {
+            match (key.as_str(), value) {
+                (Some("cwd"), Value::String(path)) => cwd = path.into_str(),
+                (Some("files"), Value::Array(paths)) => {
+                    files = paths
+                        .into_iter()
+                        .filter_map(|path| path.as_str().map(str::to_owned))
+                        .collect()
+                }
+                _ => {}
+            }
+        }
+    }
+    ParallelCommand::NewWindow { cwd, files }
+}
+
 #[derive(Clone)]
 pub struct NeovimHandler {}
 
@@ -26,6 +53,20 @@ impl NeovimHandler {
 impl Handler for NeovimHandler {
     type Writer = TxWrapper;
 
+    async fn handle_request(
+        &self,
+        event_name: String,
+        arguments: Vec<Value>,
+        _neovim: Neovim<TxWrapper>,
+    ) -> Result<Value, Value> {
+        trace!("Neovim request: {:?}", &event_name);
+
+        match ApiMethod::from_name(&event_name) {
+            Some(method) => method.handle(arguments),
+            None => Err(Value::from(format!("Unknown request: {}", event_name))),
+        }
+    }
+
     async fn handle_notify(
         &self,
         event_name: String,
@@ -36,26 +77,105 @@ impl Handler for NeovimHandler {
 
         match event_name.as_ref() {
             "redraw" => {
+                // The whole notification is parsed up front and sent as one command so that the
+                // editor handles the batch in a single pass
+                let mut parsed_events = Vec::new();
                 for events in arguments {
-                    let parsed_events = parse_redraw_event(events)
-                        .unwrap_or_explained_panic("Could not parse event from neovim");
-
-                    for parsed_event in parsed_events {
-                        EVENT_AGGREGATOR.send(EditorCommand::NeovimRedrawEvent(parsed_event));
-                    }
+                    parsed_events.extend(
+                        parse_redraw_event(events)
+                            .unwrap_or_explained_panic("Could not parse event from neovim"),
+                    );
                 }
+                // Waits while the editor is behind, which stops reading from neovim until it isn't
+                REDRAW_QUEUE.reserve().await;
+                EVENT_AGGREGATOR.send(EditorCommand::NeovimRedrawEvents(parsed_events));
             }
             "setting_changed" => {
                 SETTINGS.handle_changed_notification(arguments);
             }
-            #[cfg(windows)]
+            "option_changed" => {
+                SETTINGS.handle_option_changed_notification(arguments);
+            }
+            "neovide.quit" => {
+                // Neovim passes along v:exiting, so that :cq reaches the shell
+                let exit_code = arguments.first().and_then(Value::as_i64).unwrap_or(0);
+                RUNNING_TRACKER.quit_with_code(exit_code as i32, "neovim exited");
+            }
+            "neovide.set_opacity" => {
+                let opacity = arguments.first().and_then(|opacity| {
+                    opacity
+                        .as_f64()
+                        .or_else(|| opacity.as_i64().map(|opacity| opacity as f64))
+                });
+                if let Some(opacity) = opacity {
+                    EVENT_AGGREGATOR.send(WindowCommand::SetOpacity(opacity as f32));
+                }
+            }
+            "neovide.set_grid_size" => {
+                let mut size = arguments.iter().map(Value::as_u64);
+                if let (Some(Some(width)), Some(Some(height))) = (size.next(), size.next()) {
+                    EVENT_AGGREGATOR.send(WindowCommand::SetGridSize(Dimensions { width, height }));
+                }
+            }
+            "neovide.chrome_state" => {
+                if let Some(state) = parse_chrome_state(&arguments) {
+                    EVENT_AGGREGATOR.send(WindowCommand::SetChromeState(state));
+                }
+            }
+            "neovide.set_progress" => {
+                let percent = arguments.first().and_then(|percent| {
+                    percent
+                        .as_f64()
+                        .or_else(|| percent.as_i64().map(|percent| percent as f64))
+                });
+                if let Some(percent) = percent {
+                    EVENT_AGGREGATOR.send(WindowCommand::SetProgress(parse_progress(percent)));
+                }
+            }
+            "neovide.set_icon_badge" => {
+                // An empty or missing badge clears it
+                let badge = arguments
+                    .first()
+                    .and_then(Value::as_str)
+                    .filter(|badge| !badge.is_empty())
+                    .map(str::to_owned);
+                EVENT_AGGREGATOR.send(WindowCommand::SetBadge(badge));
+            }
+            "neovide.new_window" => {
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(parse_new_window(arguments)));
+            }
             "neovide.register_right_click" => {
                 EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::RegisterRightClick));
             }
-            #[cfg(windows)]
             "neovide.unregister_right_click" => {
                 EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::UnregisterRightClick));
             }
+            "neovide.reload_fallback_fonts" => {
+                EVENT_AGGREGATOR.send(WindowCommand::ReloadFallbackFonts);
+            }
+            "neovide.debug.recreate_renderer" => {
+                EVENT_AGGREGATOR.send(WindowCommand::RecreateRenderer);
+            }
+            "neovide.trace" => {
+                let path = arguments
+                    .first()
+                    .and_then(Value::as_str)
+                    .filter(|path| !path.is_empty())
+                    .map(str::to_owned);
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::ToggleTrace(path)));
+            }
+            "neovide.refresh" => {
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::Refresh));
+            }
+            "neovide.copy_screen" => {
+                // Either a boolean or the number <bang>0 gives
+                let highlights = match arguments.first() {
+                    Some(Value::Boolean(highlights)) => *highlights,
+                    Some(value) => value.as_i64().map_or(false, |number| number != 0),
+                    None => false,
+                };
+                EVENT_AGGREGATOR.send(EditorCommand::CopyScreen { highlights });
+            }
             _ => {}
         }
     }
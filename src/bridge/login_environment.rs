@@ -0,0 +1,76 @@
+use std::{env, process::Command as StdCommand};
+
+use log::{info, warn};
+use tokio::process::Command;
+
+// Apps launched from Finder inherit the minimal launchd environment and start in /, so neovim
+// wouldn't find anything installed through the user's shell profile. Terminals always set TERM,
+// which makes its absence a cheap way to tell the two apart without slowing down terminal launches.
+fn launched_from_finder() -> bool {
+    env::var_os("TERM").is_none()
+}
+
+fn parse_environment(env_output: &str) -> Vec<(String, String)> {
+    env_output
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            if key.is_empty() || key.contains(char::is_whitespace) {
+                None
+            } else {
+                Some((key.to_owned(), value.to_owned()))
+            }
+        })
+        .collect()
+}
+
+fn login_shell_environment() -> Option<Vec<(String, String)>> {
+    let shell = env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_owned());
+    let output = StdCommand::new(&shell)
+        .args(&["-lc", "env"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(parse_environment(&String::from_utf8_lossy(&output.stdout)))
+}
+
+pub fn apply_login_environment(cmd: &mut Command) {
+    if !launched_from_finder() {
+        return;
+    }
+
+    info!("Launched from Finder, using the login shell environment for neovim");
+    match login_shell_environment() {
+        Some(environment) => {
+            cmd.envs(environment);
+        }
+        None => warn!("Could not read the login shell environment"),
+    }
+
+    let in_root = env::current_dir().map_or(true, |current_dir| current_dir.parent().is_none());
+    if let (true, Some(home)) = (in_root, dirs::home_dir()) {
+        cmd.current_dir(home);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_environment() {
+        let environment =
+            parse_environment("PATH=/usr/local/bin:/usr/bin\nEMPTY=\nA=b=c\nnot a var\n");
+
+        assert_eq!(
+            environment,
+            vec![
+                ("PATH".to_owned(), "/usr/local/bin:/usr/bin".to_owned()),
+                ("EMPTY".to_owned(), "".to_owned()),
+                ("A".to_owned(), "b=c".to_owned()),
+            ]
+        );
+    }
+}
@@ -1,35 +1,153 @@
+mod api;
+mod chrome_state;
+mod command_target;
 pub mod create;
 mod events;
 mod handler;
+#[cfg(target_os = "macos")]
+mod login_environment;
+mod new_window;
+mod process;
+mod redraw_queue;
+mod session;
 mod tx_wrapper;
+mod ui_command_trace;
 mod ui_commands;
+mod unhandled_events;
+mod watchdog;
+mod wsl;
 
 use std::{path::Path, process::Stdio, sync::Arc};
 
 use log::{error, info, warn};
-use nvim_rs::UiAttachOptions;
+use nvim_rs::{Neovim, UiAttachOptions};
 use rmpv::Value;
 use tokio::{process::Command, runtime::Runtime};
 
 use crate::{
-    cmd_line::CmdLineSettings, error_handling::ResultPanicExplanation, running_tracker::*,
+    cmd_line::{CmdLineSettings, FilePosition},
+    error_handling::ResultPanicExplanation,
+    running_tracker::*,
     settings::*,
 };
 
+pub use api::{set_render_backend, set_scale_factor, RenderBackend};
+pub use chrome_state::ChromeState;
+pub use command_target::UiCommandTarget;
 pub use events::*;
 use handler::NeovimHandler;
+pub use new_window::spawn_new_window;
+use process::NeovimProcess;
+pub use redraw_queue::REDRAW_QUEUE;
+use session::restore_session;
+pub use session::SessionSettings;
 pub use tx_wrapper::{TxWrapper, WrapTx};
-pub use ui_commands::{start_ui_command_handler, ParallelCommand, SerialCommand, UiCommand};
+pub use ui_commands::{
+    start_ui_command_handler, DirectoryDropMode, OpenMode, ParallelCommand, SerialCommand,
+    ShortcutAction, UiCommand, UiCommandSettings, UI_COMMAND_STATS,
+};
+pub use unhandled_events::UNHANDLED_EVENTS;
+use watchdog::start_ping_watchdog;
+pub use watchdog::WatchdogSettings;
+use wsl::quote_for_wsl_shell;
+pub use wsl::windows_path_to_wsl;
+
+// Older versions are missing parts of the ui api which neovide relies on
+const MINIMUM_NEOVIM_VERSION: (u64, u64) = (0, 4);
+
+// Moves the cursor to each position given as file:line. The files are already open as arguments,
+// drop switches to the window a file is shown in rather than replacing another one. The first
+// argument is dropped to last so that it stays the file shown, like it is without positions.
+fn file_position_commands(file_positions: &[FilePosition], wsl: bool) -> Vec<String> {
+    let mut commands: Vec<String> = file_positions
+        .iter()
+        .rev()
+        .map(|position| {
+            let path = if wsl {
+                windows_path_to_wsl(&position.path)
+            } else {
+                position.path.clone()
+            };
+            format!(
+                "execute 'drop ' . fnameescape('{}') | call cursor({}, {})",
+                path.replace('\'', "''"),
+                position.line,
+                position.column
+            )
+        })
+        .collect();
+    if !commands.is_empty() {
+        commands.push("execute 'drop ' . fnameescape(argv(0))".to_owned());
+    }
+    commands
+}
 
 #[cfg(windows)]
 fn set_windows_creation_flags(cmd: &mut Command) {
     cmd.creation_flags(0x0800_0000); // CREATE_NO_WINDOW
 }
 
+// Reads the major and minor version from the first line of `nvim --version`, for example
+// "NVIM v0.5.0-dev+1357-g192f89ea1"
+fn parse_neovim_version(version_output: &str) -> Option<(u64, u64)> {
+    let version = version_output
+        .lines()
+        .next()?
+        .trim()
+        .strip_prefix("NVIM v")?;
+    let mut numbers = version.split('.').map(|part| {
+        part.chars()
+            .take_while(|character| character.is_ascii_digit())
+            .collect::<String>()
+            .parse::<u64>()
+            .ok()
+    });
+    Some((numbers.next()??, numbers.next()??))
+}
+
+// Only run for a binary given with --neovim-bin or NEOVIM_BIN, the nvim on the path isn't spawned
+// an extra time on every launch
+fn check_neovim_version(bin: &str) {
+    let version = std::process::Command::new(bin)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| parse_neovim_version(&String::from_utf8_lossy(&output.stdout)));
+
+    match version {
+        Some(version) if version >= MINIMUM_NEOVIM_VERSION => {}
+        Some((major, minor)) => exit_with_error(&format!(
+            "{} is neovim {}.{}, but neovide requires version {}.{} or higher",
+            bin, major, minor, MINIMUM_NEOVIM_VERSION.0, MINIMUM_NEOVIM_VERSION.1
+        )),
+        None => exit_with_error(&format!("{} does not appear to be a neovim binary", bin)),
+    }
+}
+
+// Neovide is often launched without a console, where neither the log nor stderr is seen, so the
+// reason is shown in a dialog as well on windows
+fn exit_with_error(message: &str) -> ! {
+    error!("{}", message);
+    eprintln!("{}", message);
+    #[cfg(windows)]
+    crate::windows_utils::show_error_dialog("Neovide", message);
+    std::process::exit(1);
+}
+
+// Arguments selecting the distribution to run in, when one was given with --wsl-distro
+#[cfg(windows)]
+fn wsl_distro_args() -> Vec<String> {
+    match SETTINGS.get::<CmdLineSettings>().wsl_distro {
+        Some(distro) => vec!["-d".to_owned(), distro],
+        None => Vec::new(),
+    }
+}
+
 #[cfg(windows)]
 fn platform_build_nvim_cmd(bin: &str) -> Option<Command> {
     if SETTINGS.get::<CmdLineSettings>().wsl {
         let mut cmd = Command::new("wsl");
+        cmd.args(wsl_distro_args());
         cmd.args(&[
             bin.trim(),
             "-c",
@@ -55,6 +173,10 @@ fn platform_build_nvim_cmd(bin: &str) -> Option<Command> {
 fn build_nvim_cmd() -> Command {
     if let Some(path) = SETTINGS.get::<CmdLineSettings>().neovim_bin {
         if let Some(cmd) = platform_build_nvim_cmd(&path) {
+            // Inside wsl the binary is checked by neovim failing to start instead
+            if !SETTINGS.get::<CmdLineSettings>().wsl {
+                check_neovim_version(&path);
+            }
             return cmd;
         } else {
             warn!("NEOVIM_BIN is invalid falling back to first bin in PATH");
@@ -63,12 +185,14 @@ fn build_nvim_cmd() -> Command {
     #[cfg(windows)]
     if SETTINGS.get::<CmdLineSettings>().wsl {
         if let Ok(output) = std::process::Command::new("wsl")
+            .args(wsl_distro_args())
             .args(&["$SHELL", "-lic", "which nvim"])
             .output()
         {
             if output.status.success() {
                 let path = String::from_utf8(output.stdout).unwrap();
                 let mut cmd = Command::new("wsl");
+                cmd.args(wsl_distro_args());
                 cmd.args(&[
                     path.trim(),
                     "-c",
@@ -76,28 +200,29 @@ fn build_nvim_cmd() -> Command {
                 ]);
                 return cmd;
             } else {
-                error!("nvim not found in WSL path");
-                std::process::exit(1);
+                exit_with_error(
+                    "nvim was not found inside WSL. Install neovim in the distribution, pick another \
+                    one with --wsl-distro or point --neovim-bin at it.",
+                );
             }
         } else {
-            error!("wsl which nvim failed");
-            std::process::exit(1);
+            exit_with_error(
+                "WSL could not be started. Make sure the Windows Subsystem for Linux is installed \
+                and enabled, or run neovide without --wsl.",
+            );
         }
     }
     if let Ok(path) = which::which("nvim") {
         if let Some(cmd) = platform_build_nvim_cmd(path.to_str().unwrap()) {
             cmd
         } else {
-            error!("nvim does not have proper permissions!");
-            std::process::exit(1);
+            exit_with_error("nvim does not have proper permissions!");
         }
     } else {
-        error!("nvim not found!");
-        std::process::exit(1);
+        exit_with_error("nvim not found!");
     }
 }
 
-#[cfg(windows)]
 pub fn build_neovide_command(channel: u64, num_args: u64, command: &str, event: &str) -> String {
     let nargs: String = if num_args > 1 {
         "+".to_string()
@@ -105,32 +230,49 @@ pub fn build_neovide_command(channel: u64, num_args: u64, command: &str, event:
         num_args.to_string()
     };
     if num_args == 0 {
-        return format!(
+        format!(
             "command! -nargs={} {} call rpcnotify({}, 'neovide.{}')",
             nargs, command, channel, event
-        );
+        )
     } else {
-        return format!(
+        format!(
             "command! -nargs={} -complete=expression {} call rpcnotify({}, 'neovide.{}', <args>)",
             nargs, command, channel, event
-        );
-    };
+        )
+    }
 }
 
 pub fn create_nvim_command() -> Command {
     let mut cmd = build_nvim_cmd();
 
-    cmd.arg("--embed")
-        .args(SETTINGS.get::<CmdLineSettings>().neovim_args.iter());
+    let settings = SETTINGS.get::<CmdLineSettings>();
+    // Unlike the files, these aren't paths to translate for wsl
+    let extra_neovim_args = settings.extra_neovim_args.iter().map(|arg| {
+        if settings.wsl {
+            quote_for_wsl_shell(arg)
+        } else {
+            arg.to_owned()
+        }
+    });
+    let neovim_args = settings.neovim_args.iter().map(|arg| {
+        if settings.wsl {
+            quote_for_wsl_shell(&windows_path_to_wsl(arg))
+        } else {
+            arg.to_owned()
+        }
+    });
+
+    cmd.arg("--embed").args(extra_neovim_args).args(neovim_args);
+
+    #[cfg(target_os = "macos")]
+    login_environment::apply_login_environment(&mut cmd);
 
     info!("Starting neovim with: {:?}", cmd);
 
-    #[cfg(not(debug_assertions))]
+    // Stderr goes to the log as it arrives, and what came before the ui attached is also shown
+    // if neovim exits
     cmd.stderr(Stdio::piped());
 
-    #[cfg(debug_assertions)]
-    cmd.stderr(Stdio::inherit());
-
     #[cfg(windows)]
     set_windows_creation_flags(&mut cmd);
 
@@ -142,6 +284,10 @@ enum ConnectionMode {
     RemoteTcp(String),
 }
 
+pub fn is_remote() -> bool {
+    matches!(connection_mode(), ConnectionMode::RemoteTcp(_))
+}
+
 fn connection_mode() -> ConnectionMode {
     if let Some(arg) = SETTINGS.get::<CmdLineSettings>().remote_tcp {
         ConnectionMode::RemoteTcp(arg)
@@ -152,17 +298,38 @@ fn connection_mode() -> ConnectionMode {
 
 async fn start_neovim_runtime() {
     let handler = NeovimHandler::new();
-    let (nvim, io_handler) = match connection_mode() {
-        ConnectionMode::Child => create::new_child_cmd(&mut create_nvim_command(), handler).await,
-        ConnectionMode::RemoteTcp(address) => create::new_tcp(address, handler).await,
-    }
-    .unwrap_or_explained_panic("Could not locate or start neovim process");
+    let (nvim, io_handler, mut neovim_process) = match connection_mode() {
+        ConnectionMode::Child => {
+            let (nvim, io_handler, neovim_process) =
+                create::new_child_cmd(&mut create_nvim_command(), handler)
+                    .await
+                    .unwrap_or_explained_panic("Could not locate or start neovim process");
+            (nvim, io_handler, Some(neovim_process))
+        }
+        ConnectionMode::RemoteTcp(address) => {
+            match create::new_tcp(address.clone(), handler).await {
+                Ok((nvim, io_handler)) => (nvim, io_handler, None),
+                Err(connection_error) => {
+                    error!(
+                        "Could not connect to neovim server at {}: {}",
+                        address, connection_error
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+    };
 
     if nvim.get_api_info().await.is_err() {
         error!("Cannot get neovim api info, either neovide is launched with an unknown command line option or neovim version not supported!");
-        std::process::exit(-1);
+        let exit_code = match neovim_process.take() {
+            Some(neovim_process) => neovim_process.wait_after_failed_start().await,
+            None => -1,
+        };
+        std::process::exit(exit_code);
     }
 
+    let startup_output = neovim_process.as_ref().map(NeovimProcess::startup_output);
     tokio::spawn(async move {
         info!("Close watcher started");
         match io_handler.await {
@@ -174,7 +341,13 @@ async fn start_neovim_runtime() {
             }
             Ok(Ok(())) => {}
         };
-        RUNNING_TRACKER.quit("neovim processed failed");
+        match neovim_process {
+            Some(neovim_process) => {
+                let exit_code = neovim_process.wait().await;
+                RUNNING_TRACKER.quit_with_code(exit_code, "neovim process exited");
+            }
+            None => RUNNING_TRACKER.quit("neovim processed failed"),
+        }
     });
 
     match nvim.command_output("echo has('nvim-0.4')").await.as_deref() {
@@ -185,9 +358,19 @@ async fn start_neovim_runtime() {
         }
     }
 
+    if let Ok(version) = nvim.command_output("version").await {
+        info!(
+            "Connected to {}",
+            version.trim().lines().next().unwrap_or_default()
+        );
+    }
+
     nvim.set_var("neovide", Value::Boolean(true))
         .await
         .unwrap_or_explained_panic("Could not communicate with neovim process");
+    nvim.set_var("neovide_version", Value::from(crate_version!()))
+        .await
+        .ok();
 
     if let Err(command_error) = nvim.command("runtime! ginit.vim").await {
         nvim.command(&format!(
@@ -200,12 +383,9 @@ async fn start_neovim_runtime() {
 
     nvim.set_client_info(
         "neovide",
-        vec![
-            (Value::from("major"), Value::from(0u64)),
-            (Value::from("minor"), Value::from(6u64)),
-        ],
+        api::client_version(),
         "ui",
-        vec![],
+        api::method_table(),
         vec![],
     )
     .await
@@ -233,7 +413,73 @@ async fn start_neovim_runtime() {
         neovide_channel
     );
 
-    #[cfg(windows)]
+    nvim.command(&format!(
+        "command! NeovideStats echo rpcrequest({}, 'neovide.stats')",
+        neovide_channel
+    ))
+    .await
+    .ok();
+
+    // As json so that it can be pasted into a bug report as is
+    nvim.command(&format!(
+        "command! NeovideDumpSettings echo json_encode(rpcrequest({}, 'neovide.dump_settings'))",
+        neovide_channel
+    ))
+    .await
+    .ok();
+
+    nvim.command(
+        "command! NeovideFrameStats echo get(g:, 'neovide_frame_stats', \
+         'Set g:neovide_report_frame_stats to collect frame stats')",
+    )
+    .await
+    .ok();
+
+    nvim.command(&format!(
+        "command! -nargs=1 NeovideSetOpacity call rpcnotify({}, 'neovide.set_opacity', <args>)",
+        neovide_channel
+    ))
+    .await
+    .ok();
+
+    // Lets neovim announce that it is exiting instead of leaving the window to notice the channel
+    // closing
+    nvim.command(&format!(
+        "autocmd VimLeave * call rpcnotify({}, 'neovide.quit', v:exiting)",
+        neovide_channel
+    ))
+    .await
+    .ok();
+
+    // Only fires for :set and friends, not when columns and lines follow a resize of the window,
+    // so the window doesn't fight the user resizing it
+    nvim.command(&format!(
+        "autocmd OptionSet columns,lines call rpcnotify({}, 'neovide.set_grid_size', &columns, &lines)",
+        neovide_channel
+    ))
+    .await
+    .ok();
+
+    // The modified and readonly state of the current buffer, sent whenever it may have changed and
+    // once for the buffer neovim started with
+    let chrome_state_notification = format!(
+        "call rpcnotify({}, 'neovide.chrome_state', &modified, &readonly || !&modifiable)",
+        neovide_channel
+    );
+    nvim.command(&format!(
+        "autocmd BufEnter,WinEnter,BufModifiedSet,BufWritePost * {}",
+        chrome_state_notification
+    ))
+    .await
+    .ok();
+    nvim.command(&format!(
+        "autocmd OptionSet readonly,modifiable {}",
+        chrome_state_notification
+    ))
+    .await
+    .ok();
+    nvim.command(&chrome_state_notification).await.ok();
+
     nvim.command(&build_neovide_command(
         neovide_channel,
         0,
@@ -243,7 +489,6 @@ async fn start_neovim_runtime() {
     .await
     .ok();
 
-    #[cfg(windows)]
     nvim.command(&build_neovide_command(
         neovide_channel,
         0,
@@ -253,6 +498,40 @@ async fn start_neovim_runtime() {
     .await
     .ok();
 
+    nvim.command(&build_neovide_command(
+        neovide_channel,
+        0,
+        "NeovideRefresh",
+        "refresh",
+    ))
+    .await
+    .ok();
+
+    nvim.command(&build_neovide_command(
+        neovide_channel,
+        0,
+        "NeovideFallbackFonts",
+        "reload_fallback_fonts",
+    ))
+    .await
+    .ok();
+
+    // Without a file the trace toggles, written to the cache directory
+    nvim.command(&format!(
+        "command! -nargs=? -complete=file NeovideTrace call rpcnotify({}, 'neovide.trace', <q-args>)",
+        neovide_channel
+    ))
+    .await
+    .ok();
+
+    // With a bang the highlights are kept as escape sequences
+    nvim.command(&format!(
+        "command! -bang NeovideCopyScreen call rpcnotify({}, 'neovide.copy_screen', <bang>0)",
+        neovide_channel
+    ))
+    .await
+    .ok();
+
     nvim.set_option("lazyredraw", Value::Boolean(false))
         .await
         .ok();
@@ -265,18 +544,35 @@ async fn start_neovim_runtime() {
     let mut options = UiAttachOptions::new();
     options.set_linegrid_external(true);
     options.set_multigrid_external(settings.multi_grid);
+    options.set_popupmenu_external(true);
     options.set_rgb(true);
     nvim.ui_attach(geometry.width as i64, geometry.height as i64, &options)
         .await
         .unwrap_or_explained_panic("Could not attach ui to neovim process");
 
     info!("Neovim process attached");
+    if let Some(startup_output) = startup_output {
+        startup_output.mark_attached();
+    }
 
     let nvim = Arc::new(nvim);
 
-    start_ui_command_handler(nvim.clone());
-    SETTINGS.read_initial_values(&nvim).await;
+    // The settings are synced before any ui command is sent, so that the first resize and frames
+    // already use the values from the user's config
     SETTINGS.setup_changed_listeners(&nvim).await;
+    SETTINGS.read_initial_values(&*nvim).await;
+
+    // Restored before jumping to file positions, a session replaces the windows which are open
+    restore_session(&*nvim).await;
+
+    for command in file_position_commands(&settings.file_positions, settings.wsl) {
+        if let Err(error) = nvim.command(&command).await {
+            warn!("Could not jump to a file position: {}", error);
+        }
+    }
+
+    start_ui_command_handler(nvim.clone());
+    start_ping_watchdog(nvim.clone());
 }
 
 pub struct Bridge {
@@ -288,3 +584,44 @@ pub fn start_bridge() -> Bridge {
     runtime.spawn(start_neovim_runtime());
     Bridge { _runtime: runtime }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_neovim_version() {
+        assert_eq!(
+            parse_neovim_version("NVIM v0.5.0-dev+1357-g192f89ea1\nBuild type: Release"),
+            Some((0, 5))
+        );
+        assert_eq!(parse_neovim_version("NVIM v0.4.4"), Some((0, 4)));
+        assert_eq!(parse_neovim_version("VIM - Vi IMproved 8.2"), None);
+    }
+
+    #[test]
+    fn test_file_position_commands() {
+        assert!(file_position_commands(&[], false).is_empty());
+
+        let positions = vec![
+            FilePosition {
+                path: "src/main.rs".to_owned(),
+                line: 42,
+                column: 1,
+            },
+            FilePosition {
+                path: "it's.md".to_owned(),
+                line: 3,
+                column: 7,
+            },
+        ];
+        assert_eq!(
+            file_position_commands(&positions, false),
+            vec![
+                "execute 'drop ' . fnameescape('it''s.md') | call cursor(3, 7)",
+                "execute 'drop ' . fnameescape('src/main.rs') | call cursor(42, 1)",
+                "execute 'drop ' . fnameescape(argv(0))",
+            ]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use std::{env, process::Command};
+
+use log::{error, info};
+
+use crate::{cmd_line::CmdLineSettings, settings::SETTINGS};
+
+// The flags which decide how neovim is found and started are passed on so that the new window
+// behaves like this one. A remote server is deliberately not shared, the new window gets its own
+// neovim.
+fn inherited_arguments(settings: &CmdLineSettings) -> Vec<String> {
+    let mut arguments = Vec::new();
+    if let Some(neovim_bin) = &settings.neovim_bin {
+        arguments.push("--neovim-bin".to_owned());
+        arguments.push(neovim_bin.clone());
+    }
+    if settings.wsl {
+        arguments.push("--wsl".to_owned());
+    }
+    if let Some(wsl_distro) = &settings.wsl_distro {
+        arguments.push("--wsl-distro".to_owned());
+        arguments.push(wsl_distro.clone());
+    }
+    if settings.multi_grid {
+        arguments.push("--multigrid".to_owned());
+    }
+    if settings.frameless {
+        arguments.push("--frameless".to_owned());
+    }
+    if settings.no_idle {
+        arguments.push("--noidle".to_owned());
+    }
+    if !settings.srgb {
+        arguments.push("--nosrgb".to_owned());
+    }
+    arguments
+}
+
+fn escape_cd_path(path: &str) -> String {
+    path.replace(' ', "\\ ")
+}
+
+fn new_window_arguments(
+    settings: &CmdLineSettings,
+    cwd: Option<&str>,
+    files: &[String],
+) -> Vec<String> {
+    let mut arguments = inherited_arguments(settings);
+    arguments.extend(files.iter().cloned());
+
+    // Neovim runs inside wsl there, so the directory is only meaningful to it and not to the new
+    // process
+    if let (true, Some(cwd)) = (settings.wsl, cwd) {
+        arguments.push("--".to_owned());
+        arguments.push("--cmd".to_owned());
+        arguments.push(format!("cd {}", escape_cd_path(cwd)));
+    }
+    arguments
+}
+
+pub fn spawn_new_window(cwd: Option<String>, files: Vec<String>) {
+    let current_exe = match env::current_exe() {
+        Ok(current_exe) => current_exe,
+        Err(error) => {
+            error!("Could not find the neovide executable: {}", error);
+            return;
+        }
+    };
+
+    let settings = SETTINGS.get::<CmdLineSettings>();
+    let mut command = Command::new(current_exe);
+    command.args(new_window_arguments(&settings, cwd.as_deref(), &files));
+    if let (false, Some(cwd)) = (settings.wsl, &cwd) {
+        command.current_dir(cwd);
+    }
+
+    info!("Opening a new window in {:?}", cwd);
+    if let Err(error) = command.spawn() {
+        error!("Could not open a new window: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_window_arguments() {
+        let settings = CmdLineSettings {
+            neovim_bin: Some("/opt/nvim".to_owned()),
+            remote_tcp: Some("localhost:6666".to_owned()),
+            multi_grid: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            new_window_arguments(&settings, Some("/project"), &["main.rs".to_owned()]),
+            vec!["--neovim-bin", "/opt/nvim", "--multigrid", "main.rs"]
+        );
+    }
+
+    #[test]
+    fn test_new_window_arguments_wsl_changes_directory_in_neovim() {
+        let settings = CmdLineSettings {
+            wsl: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            new_window_arguments(&settings, Some("/home/user/my project"), &[]),
+            vec!["--wsl", "--", "--cmd", "cd /home/user/my\\ project"]
+        );
+    }
+}
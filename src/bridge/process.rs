@@ -0,0 +1,282 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use log::{error, info};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader, ReadBuf},
+    process::Child,
+    task::JoinHandle,
+    time::timeout,
+};
+
+// Keeps the reported output readable if neovim floods stderr before exiting
+const MAX_STARTUP_OUTPUT_LENGTH: usize = 10_000;
+
+// How much of something that isn't a message is shown when it turns up on neovim's stdout
+const MAX_UNEXPECTED_STDOUT_LENGTH: usize = 200;
+
+// Anything neovim writes to stderr before the ui attaches, such as an unknown command line
+// option, has nowhere to be shown. It is collected so that it can be reported when neovim exits.
+#[derive(Default)]
+pub struct StartupOutput {
+    output: Mutex<String>,
+    attached: AtomicBool,
+    // What arrived on stdout in place of the first message
+    unexpected_stdout: Mutex<Option<Vec<u8>>>,
+}
+
+impl StartupOutput {
+    pub fn mark_attached(&self) {
+        self.attached.store(true, Ordering::Relaxed);
+    }
+
+    fn push_line(&self, line: &str) {
+        info!("Neovim stderr: {}", line);
+        if self.attached.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut output = self.output.lock().unwrap();
+        if output.len() < MAX_STARTUP_OUTPUT_LENGTH {
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+
+    fn take(&self) -> String {
+        std::mem::take(&mut *self.output.lock().unwrap())
+    }
+
+    fn unexpected_stdout(&self) -> Option<Vec<u8>> {
+        self.unexpected_stdout.lock().unwrap().clone()
+    }
+}
+
+// Every msgpack-rpc message is an array of three or four elements, so the stream from neovim
+// starts with one of these bytes
+fn starts_message(byte: u8) -> bool {
+    byte == 0x93 || byte == 0x94
+}
+
+// Checks that neovim's stdout starts with a message. A shell config which greets or echoes
+// something, for example in the login shell neovim is started through for wsl, writes it into
+// the stream ahead of neovim. The rpc loop would fail on it with a decoding error which says
+// nothing about the cause, so the stream fails with the offending bytes instead.
+pub struct CheckedStdout<R> {
+    inner: R,
+    checked: bool,
+    startup_output: Arc<StartupOutput>,
+}
+
+impl<R> CheckedStdout<R> {
+    pub fn new(inner: R, startup_output: Arc<StartupOutput>) -> CheckedStdout<R> {
+        CheckedStdout {
+            inner,
+            checked: false,
+            startup_output,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CheckedStdout<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.checked {
+            return Pin::new(&mut self.inner).poll_read(context, buf);
+        }
+
+        let previously_filled = buf.filled().len();
+        match Pin::new(&mut self.inner).poll_read(context, buf) {
+            Poll::Ready(Ok(())) => {}
+            other => return other,
+        }
+        let read = &buf.filled()[previously_filled..];
+        let first_byte = match read.first() {
+            Some(first_byte) => *first_byte,
+            // Neovim exited without writing anything, which is reported from its stderr
+            None => return Poll::Ready(Ok(())),
+        };
+        self.checked = true;
+        if starts_message(first_byte) {
+            return Poll::Ready(Ok(()));
+        }
+
+        let unexpected = read[..read.len().min(MAX_UNEXPECTED_STDOUT_LENGTH)].to_vec();
+        let message = unexpected_stdout_message(&unexpected);
+        *self.startup_output.unexpected_stdout.lock().unwrap() = Some(unexpected);
+        buf.set_filled(previously_filled);
+        Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, message)))
+    }
+}
+
+fn unexpected_stdout_message(unexpected: &[u8]) -> String {
+    format!(
+        "Something wrote to stdout while neovim was starting, which is where neovide reads \
+        neovim's messages from. This is usually a greeting or echo in a shell config, or a \
+        plugin printing during startup. It wrote:\n{}",
+        String::from_utf8_lossy(unexpected).escape_debug()
+    )
+}
+
+pub struct NeovimProcess {
+    child: Child,
+    startup_output: Arc<StartupOutput>,
+    stderr_reader: Option<JoinHandle<()>>,
+}
+
+impl NeovimProcess {
+    pub fn new(mut child: Child, startup_output: Arc<StartupOutput>) -> NeovimProcess {
+        let stderr_reader = child.stderr.take().map(|stderr| {
+            let startup_output = startup_output.clone();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    startup_output.push_line(&line);
+                }
+            })
+        });
+
+        NeovimProcess {
+            child,
+            startup_output,
+            stderr_reader,
+        }
+    }
+
+    pub fn startup_output(&self) -> Arc<StartupOutput> {
+        self.startup_output.clone()
+    }
+
+    // Waits for neovim to exit, reports what it wrote before the ui attached and returns its exit
+    // code so that neovide can exit with the same one
+    pub async fn wait(mut self) -> i32 {
+        let exit_code = match self.child.wait().await {
+            Ok(status) => status.code().unwrap_or(1),
+            Err(wait_error) => {
+                error!("Could not get the neovim exit status: {}", wait_error);
+                1
+            }
+        };
+
+        // Processes started by neovim may hold on to its stderr, so the rest of the output is
+        // only waited for briefly
+        if let Some(stderr_reader) = self.stderr_reader.take() {
+            timeout(Duration::from_millis(100), stderr_reader)
+                .await
+                .ok();
+        }
+        report_startup_output(exit_code, &self.startup_output.take());
+
+        exit_code
+    }
+
+    // Called when neovim never answered the first request. Neovim keeps running when its stdout
+    // was garbled by something else, so it is stopped after the cause is reported rather than
+    // waited for.
+    pub async fn wait_after_failed_start(mut self) -> i32 {
+        match self.startup_output.unexpected_stdout() {
+            Some(unexpected) => {
+                report_error(&unexpected_stdout_message(&unexpected));
+                self.child.kill().await.ok();
+                1
+            }
+            None => self.wait().await,
+        }
+    }
+}
+
+fn report_startup_output(exit_code: i32, output: &str) {
+    let output = output.trim();
+    if output.is_empty() {
+        return;
+    }
+
+    report_error(&format!(
+        "Neovim exited with code {}:\n{}",
+        exit_code, output
+    ));
+}
+
+fn report_error(message: &str) {
+    eprintln!("{}", message);
+    #[cfg(windows)]
+    crate::windows_utils::show_error_dialog("Neovide", message);
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    async fn read_checked<R: AsyncRead + Unpin>(
+        stdout: R,
+    ) -> (io::Result<Vec<u8>>, Arc<StartupOutput>) {
+        let startup_output = Arc::new(StartupOutput::default());
+        let mut checked = CheckedStdout::new(stdout, startup_output.clone());
+        let mut read = Vec::new();
+        let result = checked.read_to_end(&mut read).await.map(|_| read);
+        (result, startup_output)
+    }
+
+    #[tokio::test]
+    async fn test_checked_stdout_passes_messages_through() {
+        let messages: &[u8] = &[0x94, 0x01, 0x00, 0xc0, 0xc0, 0x93, 0x02];
+        let (result, startup_output) = read_checked(messages).await;
+
+        assert_eq!(result.unwrap(), messages);
+        assert_eq!(startup_output.unexpected_stdout(), None);
+    }
+
+    #[tokio::test]
+    async fn test_checked_stdout_reports_output_before_the_first_message() {
+        let stdout: &[u8] = b"Welcome to fish\n\x94\x01";
+        let (result, startup_output) = read_checked(stdout).await;
+
+        let error = result.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("Welcome to fish\\n"));
+        assert_eq!(startup_output.unexpected_stdout(), Some(stdout.to_vec()));
+    }
+
+    // A stand in for neovim started through a shell which greets before exec'ing it
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_checked_stdout_of_a_child_which_prints_first() {
+        let mut child = tokio::process::Command::new("sh")
+            .args(&["-c", "echo 'Hello from .profile'; printf '\\224\\001'"])
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let (result, startup_output) = read_checked(child.stdout.take().unwrap()).await;
+        child.wait().await.ok();
+
+        assert!(result.is_err());
+        let unexpected = startup_output.unexpected_stdout().unwrap();
+        assert!(unexpected.starts_with(b"Hello from .profile\n"));
+    }
+
+    #[test]
+    fn test_startup_output_stops_collecting_once_attached() {
+        let startup_output = StartupOutput::default();
+        startup_output.push_line("E5113: Error while calling lua chunk");
+        startup_output.mark_attached();
+        startup_output.push_line("later output");
+
+        assert_eq!(
+            startup_output.take(),
+            "E5113: Error while calling lua chunk\n"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use tokio::sync::Semaphore;
+
+// How many redraw notifications may wait for the editor. Neovim flushes once per redraw cycle, so
+// this is a handful of screens worth of events.
+const REDRAW_QUEUE_CAPACITY: usize = 64;
+
+lazy_static! {
+    pub static ref REDRAW_QUEUE: RedrawQueue = RedrawQueue::new(REDRAW_QUEUE_CAPACITY);
+}
+
+// Bounds the redraw batches between the rpc handler and the editor. The handler waits for a slot
+// before passing a batch on, and nvim-rs doesn't read the next message until the handler returns,
+// so while the editor is behind neovim blocks on writing to its output instead of neovide
+// buffering everything a flood of output such as :term yes produces.
+pub struct RedrawQueue {
+    slots: Semaphore,
+    capacity: usize,
+}
+
+impl RedrawQueue {
+    pub fn new(capacity: usize) -> RedrawQueue {
+        RedrawQueue {
+            slots: Semaphore::new(capacity),
+            capacity,
+        }
+    }
+
+    pub async fn reserve(&self) {
+        self.slots
+            .acquire()
+            .await
+            .expect("The redraw queue is never closed")
+            .forget();
+    }
+
+    // Called by the editor once it handled a batch
+    pub fn release(&self) {
+        self.slots.add_permits(1);
+    }
+
+    // The batches waiting for the editor, shown by the profiler
+    pub fn depth(&self) -> usize {
+        self.capacity.saturating_sub(self.slots.available_permits())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{mpsc, Arc},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::bridge::{GridLineCell, RedrawEvent};
+
+    fn grid_line(row: u64) -> RedrawEvent {
+        RedrawEvent::GridLine {
+            grid: 1,
+            row,
+            column_start: 0,
+            cells: vec![GridLineCell {
+                text: "y".to_owned(),
+                highlight_id: Some(0),
+                repeat: None,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_a_flood_of_grid_lines_stays_bounded() {
+        const CAPACITY: usize = 8;
+        const BATCH_SIZE: usize = 100;
+        const EVENTS: usize = 1_000_000;
+
+        let queue = Arc::new(RedrawQueue::new(CAPACITY));
+        let (sender, receiver) = mpsc::channel::<Vec<RedrawEvent>>();
+
+        // An editor which falls behind now and then
+        let editor_queue = queue.clone();
+        let editor = thread::spawn(move || {
+            let mut handled_events = 0;
+            let mut most_queued = 0;
+            for batch in receiver {
+                most_queued = most_queued.max(editor_queue.depth());
+                handled_events += batch.len();
+                if handled_events % (BATCH_SIZE * 1000) == 0 {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                editor_queue.release();
+            }
+            (handled_events, most_queued)
+        });
+
+        for batch_start in (0..EVENTS).step_by(BATCH_SIZE) {
+            queue.reserve().await;
+            assert!(queue.depth() <= CAPACITY);
+            let batch = (batch_start..batch_start + BATCH_SIZE)
+                .map(|row| grid_line(row as u64 % 50))
+                .collect();
+            sender.send(batch).unwrap();
+        }
+        drop(sender);
+
+        let (handled_events, most_queued) = editor.join().unwrap();
+        assert_eq!(handled_events, EVENTS);
+        // No more than CAPACITY * BATCH_SIZE events were ever held in memory at once
+        assert!(most_queued <= CAPACITY);
+        assert_eq!(queue.depth(), 0);
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use rmpv::Value;
+
+use crate::{
+    bridge::{is_remote, windows_path_to_wsl, UiCommandTarget},
+    cmd_line::CmdLineSettings,
+    settings::*,
+};
+
+#[derive(Clone, Default, SettingGroup)]
+pub struct SessionSettings {
+    // Closing the window saves the session for the working directory, and starting neovide in
+    // that directory again restores it
+    pub auto_session: bool,
+}
+
+// FNV-1a, which unlike the hasher of the standard library is guaranteed to stay the same between
+// builds, so a session saved by one version of neovide is found by the next
+fn directory_hash(directory: &str) -> u64 {
+    directory.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// Every working directory gets its own session file in the cache directory
+fn session_path(directory: &str) -> Option<PathBuf> {
+    let sessions = dirs::cache_dir()?.join("neovide").join("sessions");
+    Some(sessions.join(format!("{:016x}.vim", directory_hash(directory))))
+}
+
+// Only --restore asks for a session when files are given or the server is shared with other
+// clients, g:neovide_auto_session alone is about starting in a directory
+fn should_restore(settings: &CmdLineSettings, auto_session: bool) -> bool {
+    if settings.no_restore_session {
+        return false;
+    }
+    settings.restore_session
+        || auto_session && settings.neovim_args.is_empty() && settings.remote_tcp.is_none()
+}
+
+async fn working_directory<T: UiCommandTarget>(nvim: &T) -> Option<String> {
+    match nvim.eval("getcwd()").await {
+        Ok(Value::String(directory)) => directory.into_str(),
+        Ok(_) => None,
+        Err(error) => {
+            warn!(
+                "Could not get the working directory for the session: {}",
+                error
+            );
+            None
+        }
+    }
+}
+
+// The path as neovim sees it, quoted for a vim string
+fn vim_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let path = if SETTINGS.get::<CmdLineSettings>().wsl {
+        windows_path_to_wsl(&path)
+    } else {
+        path.into_owned()
+    };
+    format!("fnameescape('{}')", path.replace('\'', "''"))
+}
+
+// Writes the session before the window closes. The caller waits for this to finish before
+// quitting, otherwise neovim may exit halfway through writing it.
+pub async fn save_session<T: UiCommandTarget>(nvim: &T) {
+    if !SETTINGS.get::<SessionSettings>().auto_session || is_remote() {
+        return;
+    }
+    let path = match working_directory(nvim)
+        .await
+        .and_then(|cwd| session_path(&cwd))
+    {
+        Some(path) => path,
+        None => return,
+    };
+    if let Some(sessions) = path.parent() {
+        if let Err(error) = fs::create_dir_all(sessions) {
+            warn!("Could not create {}: {}", sessions.display(), error);
+            return;
+        }
+    }
+
+    let command = format!("execute 'mksession! ' . {}", vim_path(&path));
+    match nvim.command(&command).await {
+        Ok(()) => info!("Saved the session to {}", path.display()),
+        Err(error) => warn!("Could not save the session: {}", error),
+    }
+}
+
+// Sources the session saved for the working directory once the ui is attached. A missing session
+// just starts neovim as usual, and a broken one is left as far as neovim got with it.
+pub async fn restore_session<T: UiCommandTarget>(nvim: &T) {
+    let auto_session = SETTINGS.get::<SessionSettings>().auto_session;
+    if !should_restore(&SETTINGS.get::<CmdLineSettings>(), auto_session) {
+        return;
+    }
+    let path = match working_directory(nvim)
+        .await
+        .and_then(|cwd| session_path(&cwd))
+    {
+        Some(path) => path,
+        None => return,
+    };
+    if !path.is_file() {
+        info!("No session to restore at {}", path.display());
+        return;
+    }
+
+    let command = format!("execute 'silent source ' . {}", vim_path(&path));
+    match nvim.command(&command).await {
+        Ok(()) => info!("Restored the session from {}", path.display()),
+        Err(error) => warn!("Ignoring the session in {}: {}", path.display(), error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_paths_differ_per_directory() {
+        assert_eq!(directory_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(directory_hash("/home/user"), directory_hash("/home/user"));
+        assert_ne!(directory_hash("/home/user"), directory_hash("/home/user2"));
+
+        if let Some(path) = session_path("/home/user") {
+            assert!(path.ends_with(format!("{:016x}.vim", directory_hash("/home/user"))));
+        }
+    }
+
+    #[test]
+    fn test_no_restore_always_wins() {
+        let mut settings = CmdLineSettings::default();
+        assert!(!should_restore(&settings, false));
+        assert!(should_restore(&settings, true));
+
+        settings.neovim_args = vec!["file.txt".to_owned()];
+        assert!(!should_restore(&settings, true));
+        settings.restore_session = true;
+        assert!(should_restore(&settings, false));
+
+        settings.no_restore_session = true;
+        assert!(!should_restore(&settings, false));
+        assert!(!should_restore(&settings, true));
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    fs::{self, File},
+    io::{self, LineWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Instant,
+};
+
+use log::{error, info};
+use parking_lot::Mutex;
+
+// A path to trace to from startup, the default path when empty
+pub const UI_COMMAND_TRACE_VARIABLE: &str = "NEOVIDE_UI_TRACE";
+
+lazy_static! {
+    pub static ref UI_COMMAND_TRACE: UiCommandTrace = UiCommandTrace::new();
+}
+
+struct TraceOutput {
+    path: PathBuf,
+    writer: LineWriter<File>,
+    start: Instant,
+}
+
+// A file with a line for every step a ui command takes, for attaching to reports of input bugs.
+// Commands are numbered once they are queued so that the lines of one command can be told apart,
+// the commands which were merged into another only show up as received. Nothing is formatted
+// while tracing is off, so that the cost is a single atomic load.
+pub struct UiCommandTrace {
+    enabled: AtomicBool,
+    next_id: AtomicU64,
+    output: Mutex<Option<TraceOutput>>,
+}
+
+// Milliseconds since the trace started, the command's number and what happened to it
+fn trace_line(milliseconds: f64, id: Option<u64>, event: &str, details: &str) -> String {
+    let id = id.map_or_else(|| "-".to_owned(), |id| format!("#{}", id));
+    format!("{:>12.3} {:>8} {:<8} {}", milliseconds, id, event, details)
+}
+
+pub fn default_trace_path() -> Option<PathBuf> {
+    Some(dirs::cache_dir()?.join("neovide").join("ui_trace.log"))
+}
+
+impl UiCommandTrace {
+    pub fn new() -> UiCommandTrace {
+        UiCommandTrace {
+            enabled: AtomicBool::new(false),
+            next_id: AtomicU64::new(1),
+            output: Mutex::new(None),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // Replaces the file of a running trace
+    pub fn start(&self, path: &Path) -> io::Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let mut writer = LineWriter::new(File::create(path)?);
+        writeln!(writer, "# Neovide {} ui command trace", crate_version!())?;
+        *self.output.lock() = Some(TraceOutput {
+            path: path.to_owned(),
+            writer,
+            start: Instant::now(),
+        });
+        self.enabled.store(true, Ordering::Relaxed);
+        info!("Tracing ui commands to {}", path.display());
+        Ok(())
+    }
+
+    // Returns the file the trace was written to
+    pub fn stop(&self) -> Option<PathBuf> {
+        self.enabled.store(false, Ordering::Relaxed);
+        self.output.lock().take().map(|output| output.path)
+    }
+
+    // Without a path a running trace stops and otherwise one starts at the default path. Returns
+    // the file which is traced to from now on.
+    pub fn toggle(&self, path: Option<PathBuf>) -> io::Result<Option<PathBuf>> {
+        let path = match path {
+            Some(path) => path,
+            None if self.is_enabled() => {
+                self.stop();
+                return Ok(None);
+            }
+            None => default_trace_path().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "No cache directory to trace to")
+            })?,
+        };
+        self.start(&path)?;
+        Ok(Some(path))
+    }
+
+    // The details are only formatted while tracing
+    pub fn record(&self, id: Option<u64>, event: &str, details: impl FnOnce() -> String) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut output = self.output.lock();
+        if let Some(trace) = output.as_mut() {
+            let milliseconds = trace.start.elapsed().as_secs_f64() * 1000.0;
+            let line = trace_line(milliseconds, id, event, &details());
+            if let Err(write_error) = writeln!(trace.writer, "{}", line) {
+                error!(
+                    "Stopped tracing ui commands to {}: {}",
+                    trace.path.display(),
+                    write_error
+                );
+                *output = None;
+                self.enabled.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_lines() {
+        assert_eq!(
+            trace_line(1.5, Some(12), "execute", "Keyboard(\"a\")"),
+            "       1.500      #12 execute  Keyboard(\"a\")"
+        );
+        assert_eq!(
+            trace_line(20.0, None, "receive", "FocusLost"),
+            "      20.000        - receive  FocusLost"
+        );
+    }
+
+    #[test]
+    fn test_commands_are_only_traced_while_enabled() {
+        let path =
+            std::env::temp_dir().join(format!("neovide_ui_trace_test_{}.log", std::process::id()));
+        let trace = UiCommandTrace::new();
+        trace.record(Some(1), "receive", || panic!("Formatted while disabled"));
+
+        assert_eq!(
+            trace.toggle(Some(path.clone())).unwrap(),
+            Some(path.clone())
+        );
+        let id = trace.next_id();
+        trace.record(Some(id), "enqueue", || "serial Keyboard(\"a\")".to_owned());
+        trace.record(Some(id), "execute", || "serial".to_owned());
+        assert_eq!(trace.toggle(None).unwrap(), None);
+        assert!(!trace.is_enabled());
+        trace.record(Some(id), "finish", || panic!("Formatted after stopping"));
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).ok();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("# Neovide"));
+        assert!(lines[1].ends_with("#1 enqueue  serial Keyboard(\"a\")"));
+        assert!(lines[2].ends_with("#1 execute  serial"));
+    }
+}
@@ -1,20 +1,370 @@
-use std::sync::Arc;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-#[cfg(windows)]
-use log::error;
-use log::trace;
+use log::{error, trace};
 
 use nvim_rs::{call_args, rpc::model::IntoVal, Neovim};
-use tokio::sync::mpsc::unbounded_channel;
+use parking_lot::Mutex;
+use rmpv::Value;
+use tokio::{
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver},
+        oneshot, Notify,
+    },
+    time::{sleep, timeout},
+};
 
+#[cfg(target_os = "linux")]
+use crate::linux_utils::{
+    register_rightclick_directory, register_rightclick_file, unregister_rightclick,
+};
+#[cfg(target_os = "macos")]
+use crate::macos_utils::{
+    register_rightclick_directory, register_rightclick_file, unregister_rightclick,
+};
 #[cfg(windows)]
 use crate::windows_utils::{
     register_rightclick_directory, register_rightclick_file, unregister_rightclick,
 };
 use crate::{
-    bridge::TxWrapper, event_aggregator::EVENT_AGGREGATOR, running_tracker::RUNNING_TRACKER,
+    bridge::{
+        session::save_session,
+        spawn_new_window,
+        ui_command_trace::{UI_COMMAND_TRACE, UI_COMMAND_TRACE_VARIABLE},
+        windows_path_to_wsl, TxWrapper, UiCommandTarget,
+    },
+    cmd_line::CmdLineSettings,
+    dimensions::Dimensions,
+    editor::EditorCommand,
+    event_aggregator::EVENT_AGGREGATOR,
+    profiling::FrameStats,
+    redraw_scheduler::REDRAW_SCHEDULER,
+    running_tracker::{ShutdownSignal, RUNNING_TRACKER},
+    settings::*,
+    window::{MouseSettings, WindowCommand, WindowSettings},
 };
 
+#[derive(Clone, Default, SettingGroup)]
+#[setting_prefix = "ui_command"]
+pub struct UiCommandSettings {
+    // The most commands gathered into one batch before prioritizing them. Low priority commands
+    // are only coalesced within a batch, so a limit lets some intermediate resizes and scrolls
+    // through under load. Zero gathers everything that is queued.
+    pub coalesce_limit: u64,
+}
+
+// The priority of a command determines how it is treated when several commands are queued at once.
+// High priority commands are sent before anything else, and low priority commands may be
+// coalesced or skipped entirely when a newer command supersedes them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High,
+    Normal,
+    Low,
+}
+
+// Counts of the commands that pass through the handler. Commands which are received but never
+// executed were coalesced away by prioritize, which is useful to know when diagnosing laggy input.
+pub struct UiCommandStats {
+    received: AtomicU64,
+    coalesced: AtomicU64,
+    executed: AtomicU64,
+}
+
+impl UiCommandStats {
+    const fn new() -> Self {
+        Self {
+            received: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+            executed: AtomicU64::new(0),
+        }
+    }
+
+    // Commands which were received but are still waiting to execute
+    pub fn pending(&self) -> u64 {
+        let received = self.received.load(Ordering::Relaxed);
+        let dropped =
+            self.coalesced.load(Ordering::Relaxed) + self.executed.load(Ordering::Relaxed);
+        received.saturating_sub(dropped)
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::Map(vec![
+            (
+                Value::from("received"),
+                Value::from(self.received.load(Ordering::Relaxed)),
+            ),
+            (
+                Value::from("coalesced"),
+                Value::from(self.coalesced.load(Ordering::Relaxed)),
+            ),
+            (
+                Value::from("executed"),
+                Value::from(self.executed.load(Ordering::Relaxed)),
+            ),
+        ])
+    }
+}
+
+pub static UI_COMMAND_STATS: UiCommandStats = UiCommandStats::new();
+
+// How long to wait before asking for a size neovim refused again
+const RESIZE_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+// The longest a mode ending key waits for the parallel commands queued before it
+const MODE_BARRIER_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Keys which leave insert, visual or command line mode. The input after them is meant for the mode
+// they switch to.
+const MODE_ENDING_KEYS: [&str; 4] = ["<Esc>", "<C-c>", "<C-[>", "<C-Bslash>"];
+
+// confirm qa alone only asks when there are unsaved buffers
+const CONFIRM_QUIT_COMMAND: &str =
+    "if confirm('Quit Neovide?', \"&Quit\\n&Cancel\", 2) == 1 | confirm qa | endif";
+
+// Lets statusline plugins redraw once g:neovide_frame_stats changed
+const FRAME_STATS_AUTOCMD: &str =
+    "if exists('#User#NeovideFrameStats') | doautocmd <nomodeline> User NeovideFrameStats | endif";
+
+// Neovim refuses resizes while it isn't ready for them, for example before the ui attaches. The
+// newest refused size is kept so that a retry never sends a size which a later resize replaced.
+// Each resize is numbered to tell which is newer.
+#[derive(Default)]
+struct PendingResize {
+    next_sequence: u64,
+    pending: Option<(u64, Dimensions)>,
+}
+
+impl PendingResize {
+    fn start(&mut self) -> u64 {
+        self.next_sequence += 1;
+        self.next_sequence
+    }
+
+    // Returns whether a retry has to be scheduled, which is only the case when none is already
+    fn failed(&mut self, sequence: u64, size: Dimensions) -> bool {
+        match self.pending {
+            None => {
+                self.pending = Some((sequence, size));
+                true
+            }
+            Some((pending_sequence, _)) => {
+                if pending_sequence < sequence {
+                    self.pending = Some((sequence, size));
+                }
+                false
+            }
+        }
+    }
+
+    // A newer size went through, so an older refused one must not be retried
+    fn succeeded(&mut self, sequence: u64) {
+        if matches!(self.pending, Some((pending_sequence, _)) if pending_sequence < sequence) {
+            self.pending = None;
+        }
+    }
+
+    fn take(&mut self) -> Option<Dimensions> {
+        self.pending.take().map(|(_, size)| size)
+    }
+
+    fn is_newest(&self, sequence: u64) -> bool {
+        self.next_sequence == sequence
+    }
+}
+
+lazy_static! {
+    static ref PENDING_RESIZE: Mutex<PendingResize> = Mutex::new(PendingResize::default());
+}
+
+// Holds the newest size the window asked for until the resize task gets to it. Resizes run one
+// at a time off this slot instead of in parallel, so an older size can never finish after a newer
+// one, and the notify keeps a wakeup for a size stored while the task was busy.
+#[derive(Default)]
+struct ResizeSlot {
+    // Along with the resize's number in the ui command trace
+    size: Mutex<Option<(Dimensions, u64)>>,
+    notify: Notify,
+}
+
+impl ResizeSlot {
+    // Returns the trace number of a resize which wasn't sent yet and was replaced
+    fn replace(&self, size: Dimensions, trace_id: u64) -> Option<u64> {
+        let replaced = self.size.lock().replace((size, trace_id));
+        self.notify.notify_one();
+        replaced.map(|(_, replaced_id)| replaced_id)
+    }
+
+    fn take(&self) -> Option<(Dimensions, u64)> {
+        self.size.lock().take()
+    }
+}
+
+// Where a dropped file is opened
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpenMode {
+    // In the current window, replacing its buffer
+    Edit,
+    Tab,
+    Split,
+    VSplit,
+}
+
+impl OpenMode {
+    fn command(&self) -> &'static str {
+        match self {
+            OpenMode::Edit => "e",
+            OpenMode::Tab => "tabnew",
+            OpenMode::Split => "split",
+            OpenMode::VSplit => "vsplit",
+        }
+    }
+}
+
+impl FromValue for OpenMode {
+    fn from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "edit" => OpenMode::Edit,
+                "tab" | "tabedit" => OpenMode::Tab,
+                "split" => OpenMode::Split,
+                "vsplit" => OpenMode::VSplit,
+                value => {
+                    error!("Expected an open mode, but received {:?}", value);
+                    return;
+                }
+            };
+        } else {
+            error!("Expected an open mode string, but received {:?}", value);
+        }
+    }
+}
+
+impl From<OpenMode> for Value {
+    fn from(mode: OpenMode) -> Self {
+        match mode {
+            OpenMode::Edit => Value::from("edit"),
+            OpenMode::Tab => Value::from("tab"),
+            OpenMode::Split => Value::from("split"),
+            OpenMode::VSplit => Value::from("vsplit"),
+        }
+    }
+}
+
+// What a dropped directory changes the working directory of, or the command it is opened with.
+// Any other command is given the path as its argument, such as edit or Explore, and one starting
+// with call names a function which is called with the path.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DirectoryDropMode {
+    Tab,
+    Global,
+    Window,
+    Command(String),
+}
+
+impl DirectoryDropMode {
+    fn command(&self) -> &str {
+        match self {
+            DirectoryDropMode::Tab => "tcd",
+            DirectoryDropMode::Global => "cd",
+            DirectoryDropMode::Window => "lcd",
+            DirectoryDropMode::Command(command) => command,
+        }
+    }
+
+    fn drop_command(&self, path: &str) -> String {
+        let path = path.replace('\'', "''");
+        match self.command().strip_prefix("call ") {
+            Some(function) => format!("call {}('{}')", function.trim(), path),
+            None => format!("execute '{} ' . fnameescape('{}')", self.command(), path),
+        }
+    }
+}
+
+impl FromValue for DirectoryDropMode {
+    fn from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap().trim() {
+                "tcd" => DirectoryDropMode::Tab,
+                "cd" => DirectoryDropMode::Global,
+                "lcd" => DirectoryDropMode::Window,
+                "" => {
+                    error!("Expected a directory command, but received an empty string");
+                    return;
+                }
+                command => DirectoryDropMode::Command(command.to_owned()),
+            };
+        } else {
+            error!(
+                "Expected a directory command string, but received {:?}",
+                value
+            );
+        }
+    }
+}
+
+impl From<DirectoryDropMode> for Value {
+    fn from(mode: DirectoryDropMode) -> Self {
+        Value::from(mode.command())
+    }
+}
+
+// Commands sent while the command line or a prompt waits for input would be typed into it, or
+// wait until it is answered. nvim_get_mode is answered even then.
+// The mode neovim is in and whether it is blocked waiting for input, None when it couldn't be
+// asked
+async fn current_mode<T: UiCommandTarget>(nvim: &T) -> Option<(String, bool)> {
+    let mode = match nvim.call("nvim_get_mode", Vec::new()).await {
+        Ok(Ok(mode)) => mode,
+        _ => return None,
+    };
+    let field = |name: &str| {
+        mode.as_map().and_then(|fields| {
+            fields
+                .iter()
+                .find(|(key, _)| key.as_str() == Some(name))
+                .map(|(_, value)| value.clone())
+        })
+    };
+    let blocking = field("blocking").and_then(|blocking| blocking.as_bool());
+    let mode_name = field("mode");
+    let mode_name = mode_name.as_ref().and_then(Value::as_str).unwrap_or("");
+    Some((mode_name.to_owned(), blocking.unwrap_or(false)))
+}
+
+async fn accepts_file_drop<T: UiCommandTarget>(nvim: &T) -> bool {
+    match current_mode(nvim).await {
+        Some((mode, blocking)) => !blocking && !mode.starts_with('c') && !mode.starts_with('r'),
+        None => true,
+    }
+}
+
+// What the standard shortcuts, such as Cmd+S to save, do in neovim
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShortcutAction {
+    // Yanks or deletes the visual selection into the + register, nothing happens without one
+    Copy,
+    Cut,
+    // Inserts the + register as one edit
+    Paste,
+    SelectAll,
+    Save,
+    // Asks like closing the window with g:neovide_confirm_quit does
+    Quit,
+}
+
+// Charwise, linewise and blockwise visual mode, but not select mode where typing replaces the
+// selection
+fn is_visual_mode(mode: &str) -> bool {
+    mode.starts_with(|character| matches!(character, 'v' | 'V' | '\x16'))
+}
+
 // Serial commands are any commands which must complete before the next value is sent. This
 // includes keyboard and mouse input which would cause problems if sent out of order.
 //
@@ -29,8 +379,10 @@ pub enum SerialCommand {
         position: (u32, u32),
         modifier_string: String,
     },
+    // Count wheel steps in the same direction, sent one after the other
     Scroll {
         direction: String,
+        count: u64,
         grid_id: u64,
         position: (u32, u32),
         modifier_string: String,
@@ -41,13 +393,155 @@ pub enum SerialCommand {
         position: (u32, u32),
         modifier_string: String,
     },
+    // Text inserted as one edit with nvim_paste instead of being typed through nvim_input
+    Paste(String),
+    Shortcut(ShortcutAction),
+}
+
+// How much text goes into one nvim_paste call
+const PASTE_CHUNK_SIZE: usize = 64 * 1024;
+
+// Splits text for nvim_paste along with the phase of each call. Neovim joins calls from the first
+// phase (1) through the last (3) into a single undo step, and a text which fits into one call is
+// sent with phase -1. Chunks end at line breaks where possible, a line longer than the chunk size
+// is split between characters.
+fn paste_chunks(text: &str, chunk_size: usize) -> Vec<(&str, i64)> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while text.len() - start > chunk_size {
+        let mut end = start + chunk_size;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        if let Some(line_end) = text[start..end].rfind('\n') {
+            end = start + line_end + 1;
+        }
+        chunks.push(&text[start..end]);
+        start = end;
+    }
+    chunks.push(&text[start..]);
+
+    let last = chunks.len() - 1;
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let phase = match index {
+                _ if last == 0 => -1,
+                0 => 1,
+                index if index == last => 3,
+                _ => 2,
+            };
+            (chunk, phase)
+        })
+        .collect()
+}
+
+// The key notation of a scroll, such as <S-ScrollWheelUp> repeated for each step. None for a
+// direction neovim has no key for.
+fn scroll_keys(direction: &str, modifier_string: &str, count: u64) -> Option<String> {
+    let key = match direction {
+        "up" => "Up",
+        "down" => "Down",
+        "left" => "Left",
+        "right" => "Right",
+        _ => return None,
+    };
+    Some(format!("<{}ScrollWheel{}>", modifier_string, key).repeat(count as usize))
+}
+
+// Sends the text in chunks, so that a huge paste doesn't exceed neovim's message size
+async fn paste_text<T: UiCommandTarget>(nvim: &T, text: &str) {
+    for (chunk, phase) in paste_chunks(text, PASTE_CHUNK_SIZE) {
+        match nvim.paste(chunk, true, phase).await {
+            Ok(true) => {}
+            // Neovim cancels the paste when it was interrupted, for example with <C-c>
+            Ok(false) => break,
+            Err(error) => {
+                error!("Paste failed: {}", error);
+                break;
+            }
+        }
+    }
+}
+
+// The text of the clipboard register. Contents which aren't valid utf-8 are converted lossily, and
+// an image leaves the register empty, which has no text to paste.
+fn clipboard_text(register: Value) -> Option<String> {
+    let text = match register {
+        Value::String(text) => String::from_utf8_lossy(text.as_bytes()).into_owned(),
+        Value::Binary(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        _ => return None,
+    };
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+async fn run_shortcut<T: UiCommandTarget>(nvim: &T, action: ShortcutAction) {
+    match action {
+        ShortcutAction::Copy | ShortcutAction::Cut => {
+            let visual = current_mode(nvim)
+                .await
+                .map_or(false, |(mode, _)| is_visual_mode(&mode));
+            if visual {
+                let keys = if action == ShortcutAction::Copy {
+                    "\"+y"
+                } else {
+                    "\"+d"
+                };
+                nvim.input(keys).await.ok();
+            }
+        }
+        ShortcutAction::Paste => match nvim.eval("getreg('+')").await {
+            Ok(register) => match clipboard_text(register) {
+                Some(text) => paste_text(nvim, &text).await,
+                None => {
+                    nvim.err_writeln("Clipboard has no text").await.ok();
+                }
+            },
+            Err(error) => error!("Could not read the clipboard: {}", error),
+        },
+        ShortcutAction::SelectAll => {
+            nvim.input("<C-\\><C-n>ggVG").await.ok();
+        }
+        ShortcutAction::Save => {
+            if let Err(error) = nvim.command("write").await {
+                nvim.err_writeln(&error.to_string()).await.ok();
+            }
+        }
+        ShortcutAction::Quit => {
+            save_session(nvim).await;
+            nvim.command(CONFIRM_QUIT_COMMAND).await.ok();
+        }
+    }
 }
 
 impl SerialCommand {
-    async fn execute(self, nvim: &Neovim<TxWrapper>) {
+    fn ends_mode(&self) -> bool {
+        match self {
+            SerialCommand::Keyboard(keys) => MODE_ENDING_KEYS.iter().any(|key| keys.contains(key)),
+            _ => false,
+        }
+    }
+
+    fn priority(&self) -> Priority {
+        match self {
+            SerialCommand::Keyboard(_) | SerialCommand::Paste(_) | SerialCommand::Shortcut(_) => {
+                Priority::High
+            }
+            SerialCommand::MouseButton { .. } => Priority::Normal,
+            SerialCommand::Scroll { .. } | SerialCommand::Drag { .. } => Priority::Low,
+        }
+    }
+
+    async fn execute<T: UiCommandTarget>(self, nvim: &T) {
         match self {
             SerialCommand::Keyboard(input_command) => {
                 trace!("Keyboard Input Sent: {}", input_command);
+                REDRAW_SCHEDULER.input_sent();
                 nvim.input(&input_command).await.expect("Input failed");
             }
             SerialCommand::MouseButton {
@@ -70,20 +564,33 @@ impl SerialCommand {
             }
             SerialCommand::Scroll {
                 direction,
+                count,
                 grid_id,
                 position: (grid_x, grid_y),
                 modifier_string,
             } => {
-                nvim.input_mouse(
-                    "wheel",
-                    &direction,
-                    &modifier_string,
-                    grid_id as i64,
-                    grid_y as i64,
-                    grid_x as i64,
-                )
-                .await
-                .expect("Mouse Scroll Failed");
+                let keys = if SETTINGS.get::<MouseSettings>().scroll_as_keys {
+                    scroll_keys(&direction, &modifier_string, count)
+                } else {
+                    None
+                };
+                if let Some(keys) = keys {
+                    nvim.input(&keys).await.expect("Input failed");
+                } else {
+                    // Neovim takes one wheel step per call
+                    for _ in 0..count {
+                        nvim.input_mouse(
+                            "wheel",
+                            &direction,
+                            &modifier_string,
+                            grid_id as i64,
+                            grid_y as i64,
+                            grid_x as i64,
+                        )
+                        .await
+                        .expect("Mouse Scroll Failed");
+                    }
+                }
             }
             SerialCommand::Drag {
                 button,
@@ -102,6 +609,8 @@ impl SerialCommand {
                 .await
                 .expect("Mouse Drag Failed");
             }
+            SerialCommand::Paste(text) => paste_text(nvim, &text).await,
+            SerialCommand::Shortcut(action) => run_shortcut(nvim, action).await,
         }
     }
 }
@@ -109,30 +618,132 @@ impl SerialCommand {
 #[derive(Debug, Clone)]
 pub enum ParallelCommand {
     Quit,
+    // Asks before quitting, and then lets neovim ask about unsaved buffers. Used for closing the
+    // window with g:neovide_confirm_quit set, cancelling keeps everything open.
+    ConfirmQuit,
+    // Leaves neovim running and only disconnects this ui, used to close a remote session
+    Detach,
     Resize {
         width: u64,
         height: u64,
     },
-    FileDrop(String),
+    FileDrop {
+        path: String,
+        mode: OpenMode,
+    },
     FocusLost,
     FocusGained,
+    // Tabpage handles from the native tabline
+    TabSelect(u64),
+    TabClose(u64),
     DisplayAvailableFonts(Vec<String>),
-    #[cfg(windows)]
+    // Echoes the fallback fonts in the order they are tried, for :NeovideFallbackFonts
+    ShowFallbackFonts(Vec<String>),
+    // Starts or stops the ui command trace for :NeovideTrace, optionally at the given path
+    ToggleTrace(Option<String>),
+    // Shown with err_writeln, for example when a deprecated setting name is used
+    ShowError(String),
+    SetUiOption {
+        name: String,
+        value: bool,
+    },
     RegisterRightClick,
-    #[cfg(windows)]
     UnregisterRightClick,
+    NewWindow {
+        cwd: Option<String>,
+        files: Vec<String>,
+    },
+    Refresh,
+    // Sets the + register, which neovim's clipboard provider puts on the system clipboard
+    SetClipboard(String),
+    // Stores the stats in g:neovide_frame_stats, sent once a second while
+    // g:neovide_report_frame_stats is set
+    ReportFrameStats(FrameStats),
 }
 
 impl ParallelCommand {
-    async fn execute(self, nvim: &Neovim<TxWrapper>) {
+    fn priority(&self) -> Priority {
+        match self {
+            ParallelCommand::Quit | ParallelCommand::Detach => Priority::High,
+            ParallelCommand::Resize { .. } | ParallelCommand::ReportFrameStats(_) => Priority::Low,
+            _ => Priority::Normal,
+        }
+    }
+
+    async fn execute<T: UiCommandTarget>(self, nvim: &T) {
         match self {
+            // The session is written before quitting, neovim exits as soon as it gets qa
             ParallelCommand::Quit => {
+                save_session(nvim).await;
                 nvim.command("qa!").await.ok();
             }
-            ParallelCommand::Resize { width, height } => nvim
-                .ui_try_resize(width.max(10) as i64, height.max(3) as i64)
+            ParallelCommand::ConfirmQuit => {
+                save_session(nvim).await;
+                nvim.command(CONFIRM_QUIT_COMMAND).await.ok();
+            }
+            ParallelCommand::Detach => {
+                nvim.ui_detach().await.ok();
+                RUNNING_TRACKER.quit("detached from neovim");
+            }
+            ParallelCommand::Resize { width, height } => {
+                let sequence = PENDING_RESIZE.lock().start();
+                let window_size = Dimensions { width, height };
+                let Dimensions { width, height } = window_size.limited();
+                let requested = Dimensions {
+                    width: width.max(10),
+                    height: height.max(3),
+                };
+                if let Err(resize_error) = nvim
+                    .ui_try_resize(requested.width as i64, requested.height as i64)
+                    .await
+                {
+                    error!(
+                        "Resize to {}x{} failed, retrying: {}",
+                        width, height, resize_error
+                    );
+                    let size = Dimensions { width, height };
+                    if PENDING_RESIZE.lock().failed(sequence, size) {
+                        tokio::spawn(async {
+                            sleep(RESIZE_RETRY_DELAY).await;
+                            if let Some(Dimensions { width, height }) = PENDING_RESIZE.lock().take()
+                            {
+                                EVENT_AGGREGATOR.send(UiCommand::Parallel(
+                                    ParallelCommand::Resize { width, height },
+                                ));
+                            }
+                        });
+                    }
+                    return;
+                }
+                PENDING_RESIZE.lock().succeeded(sequence);
+
+                // Only the newest resize is reconciled, an older one's size is already stale
+                if !PENDING_RESIZE.lock().is_newest(sequence) {
+                    return;
+                }
+                if let Some(grid_size) = query_grid_size(nvim).await {
+                    if grid_size != requested {
+                        trace!(
+                            "Requested a {:?} grid but neovim settled on {:?}",
+                            requested,
+                            grid_size
+                        );
+                        EVENT_AGGREGATOR.send(WindowCommand::GridSizeSettled {
+                            requested: window_size,
+                            settled: grid_size,
+                        });
+                    }
+                }
+            }
+            ParallelCommand::ReportFrameStats(stats) => {
+                nvim.call(
+                    "nvim_set_var",
+                    vec![Value::from("neovide_frame_stats"), stats.to_value()],
+                )
                 .await
-                .expect("Resize failed"),
+                .ok();
+                nvim.command(FRAME_STATS_AUTOCMD).await.ok();
+            }
             ParallelCommand::FocusLost => nvim
                 .command("if exists('#FocusLost') | doautocmd <nomodeline> FocusLost | endif")
                 .await
@@ -141,9 +752,49 @@ impl ParallelCommand {
                 .command("if exists('#FocusGained') | doautocmd <nomodeline> FocusGained | endif")
                 .await
                 .expect("Focus Gained Failed"),
-            ParallelCommand::FileDrop(path) => {
-                nvim.command(format!("e {}", path).as_str()).await.ok();
+            // A dropped directory becomes the working directory instead of being opened
+            ParallelCommand::FileDrop { path, mode } => {
+                if !accepts_file_drop(nvim).await {
+                    nvim.err_writeln(
+                        "Leave the command line or answer the prompt before dropping files",
+                    )
+                    .await
+                    .ok();
+                    return;
+                }
+                // The directory is looked up on this side, before the path is translated for wsl
+                let directory_mode = Path::new(&path)
+                    .is_dir()
+                    .then(|| SETTINGS.get::<WindowSettings>().file_drop_directory_mode);
+                let path = if SETTINGS.get::<CmdLineSettings>().wsl {
+                    windows_path_to_wsl(&path)
+                } else {
+                    path
+                };
+                let command = match directory_mode {
+                    Some(directory_mode) => directory_mode.drop_command(&path),
+                    None => format!(
+                        "execute '{} ' . fnameescape('{}')",
+                        mode.command(),
+                        path.replace('\'', "''")
+                    ),
+                };
+                nvim.command(&command).await.ok();
+            }
+            ParallelCommand::TabSelect(tab) => {
+                nvim.command(&format!("call nvim_set_current_tabpage({})", tab))
+                    .await
+                    .ok();
             }
+            // Closing the last tab fails the same way :tabclose does
+            ParallelCommand::TabClose(tab) => {
+                let command = format!("execute nvim_tabpage_get_number({}) . 'tabclose'", tab);
+                nvim.command(&command).await.ok();
+            }
+            ParallelCommand::SetUiOption { name, value } => nvim
+                .ui_set_option(&name, Value::from(value))
+                .await
+                .expect("Set UI Option Failed"),
             ParallelCommand::DisplayAvailableFonts(fonts) => {
                 let mut content: Vec<String> = vec![
                     "What follows are the font names available for guifont. You can try any of them with <CR> in normal mode.",
@@ -187,7 +838,6 @@ impl ParallelCommand {
                 .await
                 .ok();
             }
-            #[cfg(windows)]
             ParallelCommand::RegisterRightClick => {
                 if unregister_rightclick() {
                     let msg =
@@ -207,7 +857,67 @@ impl ParallelCommand {
                     error!("{}", msg);
                 }
             }
-            #[cfg(windows)]
+            ParallelCommand::SetClipboard(text) => {
+                let arguments = vec![
+                    Value::from("setreg"),
+                    Value::Array(vec![Value::from("+"), Value::from(text)]),
+                ];
+                match nvim.call("nvim_call_function", arguments).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(error)) => error!("Could not set the clipboard: {}", error),
+                    Err(error) => error!("Could not set the clipboard: {}", error),
+                }
+            }
+            ParallelCommand::ShowFallbackFonts(fonts) => {
+                let message = if fonts.is_empty() {
+                    "No fallback fonts are loaded".to_owned()
+                } else {
+                    format!("Fallback fonts: {}", fonts.join(", "))
+                };
+                nvim.command(&format!("echomsg '{}'", message.replace('\'', "''")))
+                    .await
+                    .ok();
+            }
+            ParallelCommand::ShowError(message) => {
+                nvim.err_writeln(&message).await.ok();
+            }
+            ParallelCommand::ToggleTrace(path) => {
+                match UI_COMMAND_TRACE.toggle(path.map(PathBuf::from)) {
+                    Ok(path) => {
+                        let message = match path {
+                            Some(path) => format!("Tracing ui commands to {}", path.display()),
+                            None => "Stopped tracing ui commands".to_owned(),
+                        };
+                        nvim.command(&format!("echomsg '{}'", message.replace('\'', "''")))
+                            .await
+                            .ok();
+                    }
+                    Err(trace_error) => {
+                        nvim.err_writeln(&format!("Could not trace ui commands: {}", trace_error))
+                            .await
+                            .ok();
+                    }
+                }
+            }
+            ParallelCommand::Refresh => {
+                // :mode makes neovim send the whole screen again, and the editor redraws every
+                // window in case the stale contents were only on neovide's side. The redraw is
+                // queued first so that the flush neovim ends :mode with sends it out.
+                EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
+                nvim.command("mode").await.ok();
+            }
+            ParallelCommand::NewWindow { cwd, files } => {
+                // Without an explicit directory the new window opens where this neovim is
+                let cwd = match cwd {
+                    Some(cwd) => Some(cwd),
+                    None => nvim
+                        .eval("getcwd()")
+                        .await
+                        .ok()
+                        .and_then(|cwd| cwd.as_str().map(str::to_owned)),
+                };
+                spawn_new_window(cwd, files);
+            }
             ParallelCommand::UnregisterRightClick => {
                 if !unregister_rightclick() {
                     let msg = "Could not remove context menu items. Possibly already removed.";
@@ -219,12 +929,93 @@ impl ParallelCommand {
     }
 }
 
+// Neovim may not use the exact size requested by ui_try_resize, for example when another ui is
+// attached and smaller. The size it actually settled on is the value of columns and lines.
+pub async fn query_grid_size<T: UiCommandTarget>(nvim: &T) -> Option<Dimensions> {
+    let size = nvim.eval("[&columns, &lines]").await.ok()?;
+    let mut size = match size {
+        Value::Array(size) => size.into_iter(),
+        _ => return None,
+    };
+    let width = size.next()?.as_u64()?;
+    let height = size.next()?.as_u64()?;
+    Some(Dimensions { width, height })
+}
+
+// Whether shift with the left mouse button extends the selection, which is the case in visual
+// mode unless 'mousemodel' is extend, where it searches for the word under the pointer
+async fn shift_extends_selection<T: UiCommandTarget>(nvim: &T) -> bool {
+    let state = match nvim.eval("[mode(), &mousemodel]").await {
+        Ok(Value::Array(state)) => state,
+        _ => return false,
+    };
+    match (
+        state.get(0).and_then(Value::as_str),
+        state.get(1).and_then(Value::as_str),
+    ) {
+        (Some(mode), Some(mouse_model)) => {
+            matches!(mode, "v" | "V" | "\u{16}") && mouse_model != "extend"
+        }
+        _ => false,
+    }
+}
+
+// A selection started with the keyboard is extended with the mouse by holding shift, without it
+// a click leaves visual mode and a drag starts a new selection. A left press made while neovim is
+// in visual mode gets shift added, and keeps it for the drags until the button is released. The
+// mode is asked for on every press, so leaving visual mode in between makes the next press a
+// plain one again.
+#[derive(Default)]
+struct StickyShift {
+    active: bool,
+}
+
+impl StickyShift {
+    async fn apply<T: UiCommandTarget>(&mut self, serial_command: &mut SerialCommand, nvim: &T) {
+        match serial_command {
+            SerialCommand::MouseButton {
+                button,
+                action,
+                modifier_string,
+                ..
+            } if button == "left" => {
+                if action == "press" {
+                    self.active = modifier_string.is_empty()
+                        && SETTINGS.get::<MouseSettings>().extend_visual_selection
+                        && shift_extends_selection(nvim).await;
+                }
+                if self.active {
+                    modifier_string.insert_str(0, "S-");
+                }
+                if action == "release" {
+                    self.active = false;
+                }
+            }
+            SerialCommand::Drag {
+                button,
+                modifier_string,
+                ..
+            } if button == "left" && self.active => modifier_string.insert_str(0, "S-"),
+            _ => {}
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum UiCommand {
     Serial(SerialCommand),
     Parallel(ParallelCommand),
 }
 
+impl UiCommand {
+    pub fn priority(&self) -> Priority {
+        match self {
+            UiCommand::Serial(serial_command) => serial_command.priority(),
+            UiCommand::Parallel(parallel_command) => parallel_command.priority(),
+        }
+    }
+}
+
 impl From<SerialCommand> for UiCommand {
     fn from(serial: SerialCommand) -> Self {
         UiCommand::Serial(serial)
@@ -237,39 +1028,1279 @@ impl From<ParallelCommand> for UiCommand {
     }
 }
 
+// Orders a batch of queued commands for sending. Serial commands keep their relative order since
+// reordering input would change its meaning, but parallel commands are sent highest priority
+// first. Low priority commands are dropped if a quit is pending, and only the most recent resize
+// is kept since earlier ones are superseded by it. Consecutive serial commands are merged by
+// CoalescedState.
+fn prioritize(ui_commands: Vec<UiCommand>) -> Vec<UiCommand> {
+    let quitting = ui_commands.iter().any(|ui_command| {
+        matches!(
+            ui_command,
+            UiCommand::Parallel(ParallelCommand::Quit | ParallelCommand::Detach)
+        )
+    });
+    let last_resize = ui_commands.iter().rposition(|ui_command| {
+        matches!(
+            ui_command,
+            UiCommand::Parallel(ParallelCommand::Resize { .. })
+        )
+    });
+
+    let (mut parallel_commands, serial_commands): (Vec<UiCommand>, Vec<UiCommand>) = ui_commands
+        .into_iter()
+        .enumerate()
+        .filter(|(index, ui_command)| match ui_command {
+            _ if quitting && ui_command.priority() == Priority::Low => false,
+            UiCommand::Parallel(ParallelCommand::Resize { .. }) => Some(*index) == last_resize,
+            _ => true,
+        })
+        .map(|(_, ui_command)| ui_command)
+        .partition(|ui_command| matches!(ui_command, UiCommand::Parallel(_)));
+
+    // sort_by_key is stable, so commands of the same priority stay in the order they arrived
+    parallel_commands.sort_by_key(|ui_command| ui_command.priority());
+    let mut coalesced = CoalescedState::default();
+    for serial_command in serial_commands {
+        coalesced.push(serial_command);
+    }
+    parallel_commands.extend(coalesced.commands);
+    parallel_commands
+}
+
+// Merges each serial command into the one before it where that doesn't change what neovim ends
+// up doing, which saves round trips when input arrives faster than it is sent. Commands only
+// merge with their direct predecessor, so nothing moves past another command:
+// - Keystrokes are joined, neovim's input call accepts any sequence of keys.
+// - Scrolls in the same direction add up their steps. A reversal starts a new command, so both
+//   directions still reach neovim in the order they happened.
+// - A drag replaces the previous drag's position, only where the pointer ended up matters.
+#[derive(Default)]
+struct CoalescedState {
+    commands: Vec<UiCommand>,
+}
+
+impl CoalescedState {
+    fn push(&mut self, ui_command: UiCommand) {
+        if let (Some(UiCommand::Serial(previous)), UiCommand::Serial(next)) =
+            (self.commands.last_mut(), &ui_command)
+        {
+            if Self::merge(previous, next) {
+                return;
+            }
+        }
+        self.commands.push(ui_command);
+    }
+
+    // Returns whether next was merged into previous
+    fn merge(previous: &mut SerialCommand, next: &SerialCommand) -> bool {
+        match (previous, next) {
+            (SerialCommand::Keyboard(previous_input), SerialCommand::Keyboard(input)) => {
+                previous_input.push_str(input);
+                true
+            }
+            (
+                SerialCommand::Scroll {
+                    direction: previous_direction,
+                    count: previous_count,
+                    grid_id: previous_grid_id,
+                    position: previous_position,
+                    modifier_string: previous_modifier_string,
+                },
+                SerialCommand::Scroll {
+                    direction,
+                    count,
+                    grid_id,
+                    position,
+                    modifier_string,
+                },
+            ) if previous_direction == direction
+                && previous_grid_id == grid_id
+                && previous_modifier_string == modifier_string =>
+            {
+                *previous_count += count;
+                *previous_position = *position;
+                true
+            }
+            (
+                SerialCommand::Drag {
+                    button: previous_button,
+                    grid_id: previous_grid_id,
+                    position: previous_position,
+                    modifier_string: previous_modifier_string,
+                },
+                SerialCommand::Drag {
+                    button,
+                    grid_id,
+                    position,
+                    modifier_string,
+                },
+            ) if previous_button == button
+                && previous_grid_id == grid_id
+                && previous_modifier_string == modifier_string =>
+            {
+                *previous_position = *position;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// A serial command along with the parallel commands it has to wait for. Each of those holds the
+// receiving end of a channel which it drops once it finished, closing the sender kept here.
+struct QueuedSerialCommand {
+    command: SerialCommand,
+    waits_for: Vec<oneshot::Sender<()>>,
+    trace_id: u64,
+}
+
+// Which of the processors a command is handed to, and whether it may be coalesced away, for the
+// ui command trace
+fn trace_lane(ui_command: &UiCommand) -> String {
+    let lane = match ui_command {
+        UiCommand::Serial(_) => "serial",
+        UiCommand::Parallel(ParallelCommand::Resize { .. }) => "resize",
+        UiCommand::Parallel(_) => "parallel",
+    };
+    if ui_command.priority() == Priority::Low {
+        format!("{} droppable", lane)
+    } else {
+        lane.to_owned()
+    }
+}
+
+// Parallel commands run as tasks of their own, so without this a mode ending key could overtake a
+// command queued before it, like a file drop or a tab switch being applied in the mode the key
+// switched to. Commands which wait for input themselves, such as a confirm prompt, only finish
+// once the key is sent, so the wait is bounded.
+async fn wait_for_parallel_commands(mut waits_for: Vec<oneshot::Sender<()>>) {
+    if waits_for.is_empty() {
+        return;
+    }
+    let count = waits_for.len();
+    let all_finished = async {
+        for command in waits_for.iter_mut() {
+            command.closed().await;
+        }
+    };
+    if timeout(MODE_BARRIER_TIMEOUT, all_finished).await.is_err() {
+        trace!(
+            "Sending a mode ending key before {} parallel commands finished",
+            count
+        );
+    }
+}
+
+// Grabs the commands which are already queued behind the first one so that they can be
+// prioritized against each other
+fn take_queued_commands(
+    ui_command: UiCommand,
+    ui_command_receiver: &mut UnboundedReceiver<UiCommand>,
+    coalesce_limit: usize,
+) -> Vec<UiCommand> {
+    let mut ui_commands = vec![ui_command];
+    while coalesce_limit == 0 || ui_commands.len() < coalesce_limit {
+        match ui_command_receiver.try_recv() {
+            Ok(ui_command) => ui_commands.push(ui_command),
+            Err(_) => break,
+        }
+    }
+    ui_commands
+}
+
 pub fn start_ui_command_handler(nvim: Arc<Neovim<TxWrapper>>) {
-    let (serial_tx, mut serial_rx) = unbounded_channel::<SerialCommand>();
+    if let Some(path) = std::env::var_os(UI_COMMAND_TRACE_VARIABLE) {
+        let path = Some(PathBuf::from(path)).filter(|path| !path.as_os_str().is_empty());
+        if let Err(trace_error) = UI_COMMAND_TRACE.toggle(path) {
+            error!("Could not trace ui commands: {}", trace_error);
+        }
+    }
+    start_ui_command_processors(
+        EVENT_AGGREGATOR.register_event::<UiCommand>(),
+        nvim,
+        RUNNING_TRACKER.shutdown_signal(),
+        &UI_COMMAND_STATS,
+    );
+}
+
+// Executes the commands received on ui_command_receiver against the target until shutdown fires,
+// counting them in stats. Kept apart from the event aggregator so that tests can feed commands in
+// directly.
+pub fn start_ui_command_processors<T: UiCommandTarget + 'static>(
+    mut ui_command_receiver: UnboundedReceiver<UiCommand>,
+    nvim: Arc<T>,
+    shutdown: Arc<ShutdownSignal>,
+    stats: &'static UiCommandStats,
+) {
+    let (serial_tx, mut serial_rx) = unbounded_channel::<QueuedSerialCommand>();
+    let resize_slot = Arc::new(ResizeSlot::default());
+    let ui_command_resize_slot = resize_slot.clone();
     let ui_command_nvim = nvim.clone();
+    let ui_command_shutdown = shutdown.clone();
     tokio::spawn(async move {
-        let mut ui_command_receiver = EVENT_AGGREGATOR.register_event::<UiCommand>();
-        while RUNNING_TRACKER.is_running() {
-            match ui_command_receiver.recv().await {
-                Some(UiCommand::Serial(serial_command)) => serial_tx
-                    .send(serial_command)
-                    .expect("Could not send serial ui command"),
-                Some(UiCommand::Parallel(parallel_command)) => {
-                    let ui_command_nvim = ui_command_nvim.clone();
-                    tokio::spawn(async move {
-                        parallel_command.execute(&ui_command_nvim).await;
-                    });
+        // The parallel commands which may still be running
+        let mut running: Vec<oneshot::Sender<()>> = Vec::new();
+        loop {
+            let ui_command = tokio::select! {
+                _ = ui_command_shutdown.triggered() => break,
+                ui_command = ui_command_receiver.recv() => ui_command,
+            };
+            match ui_command {
+                Some(ui_command) => {
+                    let coalesce_limit =
+                        SETTINGS.get::<UiCommandSettings>().coalesce_limit as usize;
+                    let ui_commands =
+                        take_queued_commands(ui_command, &mut ui_command_receiver, coalesce_limit);
+                    for ui_command in ui_commands.iter() {
+                        UI_COMMAND_TRACE.record(None, "receive", || format!("{:?}", ui_command));
+                    }
+
+                    let received = ui_commands.len() as u64;
+                    let ui_commands = prioritize(ui_commands);
+                    stats.received.fetch_add(received, Ordering::Relaxed);
+                    stats
+                        .coalesced
+                        .fetch_add(received - ui_commands.len() as u64, Ordering::Relaxed);
+
+                    for ui_command in ui_commands {
+                        let trace_id = UI_COMMAND_TRACE.next_id();
+                        UI_COMMAND_TRACE.record(Some(trace_id), "enqueue", || {
+                            format!("{} {:?}", trace_lane(&ui_command), ui_command)
+                        });
+                        match ui_command {
+                            UiCommand::Parallel(ParallelCommand::Resize { width, height }) => {
+                                let size = Dimensions { width, height };
+                                if let Some(replaced_id) =
+                                    ui_command_resize_slot.replace(size, trace_id)
+                                {
+                                    UI_COMMAND_TRACE.record(Some(replaced_id), "coalesce", || {
+                                        format!("replaced by #{}", trace_id)
+                                    });
+                                    stats.coalesced.fetch_add(1, Ordering::Relaxed);
+                                }
+                            }
+                            UiCommand::Serial(serial_command) => {
+                                running.retain(|command| !command.is_closed());
+                                let waits_for = if serial_command.ends_mode() {
+                                    std::mem::take(&mut running)
+                                } else {
+                                    Vec::new()
+                                };
+                                serial_tx
+                                    .send(QueuedSerialCommand {
+                                        command: serial_command,
+                                        waits_for,
+                                        trace_id,
+                                    })
+                                    .expect("Could not send serial ui command")
+                            }
+                            UiCommand::Parallel(parallel_command) => {
+                                let ui_command_nvim = ui_command_nvim.clone();
+                                let (finished, running_guard) = oneshot::channel::<()>();
+                                running.push(finished);
+                                tokio::spawn(async move {
+                                    UI_COMMAND_TRACE.record(Some(trace_id), "execute", || {
+                                        "parallel".to_owned()
+                                    });
+                                    parallel_command.execute(&*ui_command_nvim).await;
+                                    drop(running_guard);
+                                    UI_COMMAND_TRACE
+                                        .record(Some(trace_id), "finish", || "parallel".to_owned());
+                                    stats.executed.fetch_add(1, Ordering::Relaxed);
+                                });
+                            }
+                        }
+                    }
                 }
                 None => {
                     RUNNING_TRACKER.quit("ui command channel failed");
+                    break;
                 }
             }
         }
+        // Dropping the serial sender stops the serial task, and the resize task is woken by the
+        // same shutdown signal
     });
 
+    let resize_nvim = nvim.clone();
+    let resize_shutdown = shutdown.clone();
     tokio::spawn(async move {
-        while RUNNING_TRACKER.is_running() {
-            match serial_rx.recv().await {
-                Some(serial_command) => {
-                    serial_command.execute(&nvim).await;
-                }
-                None => {
-                    RUNNING_TRACKER.quit("serial ui command channel failed");
+        loop {
+            tokio::select! {
+                _ = resize_shutdown.triggered() => break,
+                _ = resize_slot.notify.notified() => {}
+            }
+            if let Some((Dimensions { width, height }, trace_id)) = resize_slot.take() {
+                UI_COMMAND_TRACE.record(Some(trace_id), "execute", || "resize".to_owned());
+                ParallelCommand::Resize { width, height }
+                    .execute(&*resize_nvim)
+                    .await;
+                UI_COMMAND_TRACE.record(Some(trace_id), "finish", || "resize".to_owned());
+                stats.executed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut sticky_shift = StickyShift::default();
+        loop {
+            let serial_command = tokio::select! {
+                _ = shutdown.triggered() => break,
+                serial_command = serial_rx.recv() => serial_command,
+            };
+            match serial_command {
+                Some(QueuedSerialCommand {
+                    command: mut serial_command,
+                    waits_for,
+                    trace_id,
+                }) => {
+                    wait_for_parallel_commands(waits_for).await;
+                    sticky_shift.apply(&mut serial_command, &*nvim).await;
+                    // Sticky shift may have changed the command since it was queued
+                    UI_COMMAND_TRACE.record(Some(trace_id), "execute", || {
+                        format!("serial {:?}", serial_command)
+                    });
+                    serial_command.execute(&*nvim).await;
+                    UI_COMMAND_TRACE.record(Some(trace_id), "finish", || "serial".to_owned());
+                    stats.executed.fetch_add(1, Ordering::Relaxed);
                 }
+                // The ui command task stopped
+                None => break,
             }
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use async_trait::async_trait;
+    use nvim_rs::error::{CallError, EncodeError};
+
+    use super::*;
+    use crate::{bridge::SessionSettings, dimensions::GridSizeSettings};
+
+    // Records the calls made by executed commands instead of sending them to neovim
+    #[derive(Default)]
+    struct RecordingTarget {
+        calls: Mutex<Vec<String>>,
+        // What eval returns, nil when unset
+        eval_result: Mutex<Option<Value>>,
+        // What call returns, nil when unset
+        call_result: Mutex<Option<Value>>,
+        // How long commands take before they are recorded
+        command_delay: Option<Duration>,
+    }
+
+    impl RecordingTarget {
+        fn record(&self, call: String) {
+            self.calls.lock().unwrap().push(call);
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl UiCommandTarget for RecordingTarget {
+        async fn input(&self, keys: &str) -> Result<i64, Box<CallError>> {
+            self.record(format!("input {}", keys));
+            Ok(keys.len() as i64)
+        }
+
+        async fn input_mouse(
+            &self,
+            button: &str,
+            action: &str,
+            _modifier: &str,
+            grid: i64,
+            row: i64,
+            col: i64,
+        ) -> Result<(), Box<CallError>> {
+            self.record(format!(
+                "mouse {} {} {} {} {}",
+                button, action, grid, row, col
+            ));
+            Ok(())
+        }
+
+        async fn command(&self, command: &str) -> Result<(), Box<CallError>> {
+            if let Some(command_delay) = self.command_delay {
+                sleep(command_delay).await;
+            }
+            self.record(format!("command {}", command));
+            Ok(())
+        }
+
+        async fn eval(&self, _expr: &str) -> Result<Value, Box<CallError>> {
+            Ok(self
+                .eval_result
+                .lock()
+                .unwrap()
+                .clone()
+                .unwrap_or(Value::Nil))
+        }
+
+        async fn call(
+            &self,
+            method: &str,
+            _args: Vec<Value>,
+        ) -> Result<Result<Value, Value>, Box<EncodeError>> {
+            self.record(format!("call {}", method));
+            Ok(Ok(self
+                .call_result
+                .lock()
+                .unwrap()
+                .clone()
+                .unwrap_or(Value::Nil)))
+        }
+
+        async fn err_writeln(&self, message: &str) -> Result<(), Box<CallError>> {
+            self.record(format!("err_writeln {}", message));
+            Ok(())
+        }
+
+        async fn ui_try_resize(&self, width: i64, height: i64) -> Result<(), Box<CallError>> {
+            self.record(format!("resize {} {}", width, height));
+            Ok(())
+        }
+
+        async fn ui_set_option(&self, name: &str, _value: Value) -> Result<(), Box<CallError>> {
+            self.record(format!("ui_set_option {}", name));
+            Ok(())
+        }
+
+        async fn ui_detach(&self) -> Result<(), Box<CallError>> {
+            self.record("ui_detach".to_owned());
+            Ok(())
+        }
+
+        async fn paste(&self, data: &str, _crlf: bool, phase: i64) -> Result<bool, Box<CallError>> {
+            self.record(format!("paste {} {:?}", phase, data));
+            Ok(true)
+        }
+    }
+
+    fn scroll(direction: &str) -> UiCommand {
+        SerialCommand::Scroll {
+            direction: direction.to_owned(),
+            count: 1,
+            grid_id: 1,
+            position: (0, 0),
+            modifier_string: "".to_owned(),
+        }
+        .into()
+    }
+
+    fn keyboard(input: &str) -> UiCommand {
+        SerialCommand::Keyboard(input.to_owned()).into()
+    }
+
+    fn resize(width: u64) -> UiCommand {
+        ParallelCommand::Resize { width, height: 10 }.into()
+    }
+
+    #[tokio::test]
+    async fn test_processors_batch_input_and_coalesce_resizes() {
+        SETTINGS.set(&UiCommandSettings::default());
+        SETTINGS.set(&MouseSettings::default());
+        SETTINGS.set(&GridSizeSettings::default());
+        let (sender, receiver) = unbounded_channel();
+        for ui_command in vec![
+            keyboard("a"),
+            resize(20),
+            keyboard("b"),
+            scroll("up"),
+            resize(30),
+        ] {
+            sender.send(ui_command).unwrap();
+        }
+
+        let target = Arc::new(RecordingTarget::default());
+        start_ui_command_processors(
+            receiver,
+            target.clone(),
+            Arc::new(ShutdownSignal::new()),
+            &UI_COMMAND_STATS,
+        );
+
+        for _ in 0..100 {
+            if target.calls().len() >= 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let calls = target.calls();
+        assert_eq!(calls.len(), 3);
+        assert!(calls.contains(&"resize 30 10".to_owned()));
+        let serial_calls: Vec<&String> = calls
+            .iter()
+            .filter(|call| *call != "resize 30 10")
+            .collect();
+        assert_eq!(serial_calls, vec!["input ab", "mouse wheel up 1 0 0"]);
+    }
+
+    #[tokio::test]
+    async fn test_stats_count_received_coalesced_and_executed_commands() {
+        // Other tests run processors with the shared counters at the same time
+        static STATS: UiCommandStats = UiCommandStats::new();
+        SETTINGS.set(&UiCommandSettings::default());
+        SETTINGS.set(&MouseSettings::default());
+        SETTINGS.set(&GridSizeSettings::default());
+        let (sender, receiver) = unbounded_channel();
+        for ui_command in vec![
+            keyboard("a"),
+            keyboard("b"),
+            scroll("up"),
+            scroll("up"),
+            resize(20),
+            resize(30),
+        ] {
+            sender.send(ui_command).unwrap();
+        }
+
+        let target = Arc::new(RecordingTarget::default());
+        start_ui_command_processors(receiver, target, Arc::new(ShutdownSignal::new()), &STATS);
+
+        for _ in 0..100 {
+            if STATS.pending() == 0 && STATS.executed.load(Ordering::Relaxed) > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // The keys, the scrolls and the resizes each end up as one command
+        assert_eq!(STATS.pending(), 0);
+        assert_eq!(
+            STATS.to_value(),
+            Value::Map(vec![
+                (Value::from("received"), Value::from(6u64)),
+                (Value::from("coalesced"), Value::from(3u64)),
+                (Value::from("executed"), Value::from(3u64)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_mode_ending_keys() {
+        let keys = |keys: &str| SerialCommand::Keyboard(keys.to_owned());
+
+        assert!(keys("<Esc>").ends_mode());
+        assert!(keys("abc<C-c>").ends_mode());
+        assert!(keys("<C-Bslash><C-n>").ends_mode());
+        assert!(!keys("i").ends_mode());
+        // Typing the text <Esc>
+        assert!(!keys("<lt>Esc>").ends_mode());
+        assert!(!left_mouse("press").ends_mode());
+    }
+
+    #[tokio::test]
+    async fn test_mode_ending_keys_wait_for_earlier_commands() {
+        SETTINGS.set(&UiCommandSettings::default());
+        let (sender, receiver) = unbounded_channel();
+        sender.send(ParallelCommand::TabSelect(2).into()).unwrap();
+        sender.send(keyboard("x")).unwrap();
+        sender.send(ParallelCommand::TabSelect(3).into()).unwrap();
+        sender.send(keyboard("<Esc>")).unwrap();
+
+        let target = Arc::new(RecordingTarget {
+            command_delay: Some(Duration::from_millis(20)),
+            ..RecordingTarget::default()
+        });
+        start_ui_command_processors(
+            receiver,
+            target.clone(),
+            Arc::new(ShutdownSignal::new()),
+            &UI_COMMAND_STATS,
+        );
+
+        for _ in 0..100 {
+            if target.calls().len() >= 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // The keys are joined into one input, which waits until both tabs were selected
+        let calls = target.calls();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[2], "input x<Esc>");
+    }
+
+    #[tokio::test]
+    async fn test_idle_processors_stop_on_shutdown() {
+        let (sender, receiver) = unbounded_channel();
+        let target = Arc::new(RecordingTarget::default());
+        let shutdown = Arc::new(ShutdownSignal::new());
+        start_ui_command_processors(
+            receiver,
+            target.clone(),
+            shutdown.clone(),
+            &UI_COMMAND_STATS,
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // Nothing is sent, so only the signal can wake the processors up
+        shutdown.trigger();
+        for _ in 0..100 {
+            if sender.is_closed() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert!(sender.is_closed());
+        assert!(target.calls().is_empty());
+    }
+
+    fn left_mouse(action: &str) -> SerialCommand {
+        SerialCommand::MouseButton {
+            button: "left".to_owned(),
+            action: action.to_owned(),
+            grid_id: 1,
+            position: (0, 0),
+            modifier_string: "".to_owned(),
+        }
+    }
+
+    fn modifiers(serial_command: &SerialCommand) -> &str {
+        match serial_command {
+            SerialCommand::MouseButton {
+                modifier_string, ..
+            }
+            | SerialCommand::Drag {
+                modifier_string, ..
+            } => modifier_string,
+            _ => panic!("Not a mouse command"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sticky_shift_extends_a_visual_selection() {
+        SETTINGS.set(&MouseSettings::default());
+        let target = RecordingTarget::default();
+        let mut sticky_shift = StickyShift::default();
+        let drag = match drag(3) {
+            UiCommand::Serial(serial_command) => serial_command,
+            _ => unreachable!(),
+        };
+
+        let mut sent = Vec::new();
+        for (mode, serial_command) in vec![
+            ("v", left_mouse("press")),
+            ("v", drag.clone()),
+            ("v", left_mouse("release")),
+            // Once visual mode was left the next press is a plain one
+            ("n", left_mouse("press")),
+            ("n", drag),
+            ("n", left_mouse("release")),
+        ] {
+            *target.eval_result.lock().unwrap() = Some(Value::Array(vec![
+                Value::from(mode),
+                Value::from("popup_setpos"),
+            ]));
+            let mut serial_command = serial_command;
+            sticky_shift.apply(&mut serial_command, &target).await;
+            sent.push(modifiers(&serial_command).to_owned());
+        }
+
+        assert_eq!(sent, vec!["S-", "S-", "S-", "", "", ""]);
+    }
+
+    #[test]
+    fn test_pending_resize_retries_newest_size() {
+        let size = |width| Dimensions { width, height: 10 };
+        let mut pending_resize = PendingResize::default();
+        let (first, second, third) = (
+            pending_resize.start(),
+            pending_resize.start(),
+            pending_resize.start(),
+        );
+
+        assert!(pending_resize.failed(second, size(20)));
+        assert!(!pending_resize.failed(first, size(10)));
+        assert_eq!(pending_resize.take(), Some(size(20)));
+
+        assert!(pending_resize.failed(first, size(10)));
+        pending_resize.succeeded(third);
+        assert_eq!(pending_resize.take(), None);
+
+        assert!(pending_resize.is_newest(third));
+        assert!(!pending_resize.is_newest(second));
+    }
+
+    #[test]
+    fn test_paste_chunks() {
+        assert_eq!(paste_chunks("one line", 64), vec![("one line", -1)]);
+        assert_eq!(
+            paste_chunks("aaa\nbbb\nccc\n", 9),
+            vec![("aaa\nbbb\n", 1), ("ccc\n", 3)]
+        );
+        assert_eq!(
+            paste_chunks("aaaaaaaa\nb", 4),
+            vec![("aaaa", 1), ("aaaa", 2), ("\nb", 3)]
+        );
+        assert_eq!(paste_chunks("ééé", 3), vec![("é", 1), ("é", 2), ("é", 3)]);
+    }
+
+    #[tokio::test]
+    async fn test_chunked_paste_is_one_undo_step() {
+        let target = RecordingTarget::default();
+        let text = "line\n".repeat(PASTE_CHUNK_SIZE / 5 * 2);
+
+        SerialCommand::Paste(text.clone()).execute(&target).await;
+
+        // Neovim only merges the calls into one undo step when the phases go 1, 2.., 3
+        let phases: Vec<String> = target
+            .calls()
+            .iter()
+            .map(|call| call.split(' ').nth(1).unwrap().to_owned())
+            .collect();
+        assert!(phases.len() > 1);
+        assert_eq!(phases.first().map(String::as_str), Some("1"));
+        assert_eq!(phases.last().map(String::as_str), Some("3"));
+        assert!(phases[1..phases.len() - 1].iter().all(|phase| phase == "2"));
+    }
+
+    #[test]
+    fn test_trace_lanes() {
+        assert_eq!(
+            trace_lane(&UiCommand::from(SerialCommand::Keyboard("a".to_owned()))),
+            "serial"
+        );
+        assert_eq!(trace_lane(&resize(10)), "resize droppable");
+        assert_eq!(
+            trace_lane(&UiCommand::from(ParallelCommand::Quit)),
+            "parallel"
+        );
+    }
+
+    #[test]
+    fn test_resize_slot_keeps_newest_size() {
+        let size = |width| Dimensions { width, height: 10 };
+        let slot = ResizeSlot::default();
+
+        assert_eq!(slot.replace(size(10), 1), None);
+        assert_eq!(slot.replace(size(20), 2), Some(1));
+        assert_eq!(slot.take(), Some((size(20), 2)));
+        assert_eq!(slot.take(), None);
+    }
+
+    #[test]
+    fn test_take_queued_commands_respects_coalesce_limit() {
+        let (sender, mut receiver) = unbounded_channel();
+        for width in 2..=5 {
+            sender.send(resize(width)).unwrap();
+        }
+
+        let limited = take_queued_commands(resize(1), &mut receiver, 2);
+        assert_eq!(limited.len(), 2);
+
+        let unlimited = take_queued_commands(resize(1), &mut receiver, 0);
+        assert_eq!(unlimited.len(), 4);
+    }
+
+    #[test]
+    fn test_priority() {
+        assert_eq!(
+            UiCommand::from(SerialCommand::Keyboard("a".to_owned())).priority(),
+            Priority::High
+        );
+        assert_eq!(
+            UiCommand::from(ParallelCommand::Quit).priority(),
+            Priority::High
+        );
+        assert_eq!(
+            UiCommand::from(ParallelCommand::FocusLost).priority(),
+            Priority::Normal
+        );
+        assert_eq!(scroll("up").priority(), Priority::Low);
+        assert_eq!(resize(10).priority(), Priority::Low);
+    }
+
+    #[test]
+    fn test_prioritize_sends_quit_first_and_drops_low_priority() {
+        let ui_commands = vec![
+            scroll("up"),
+            SerialCommand::Keyboard("a".to_owned()).into(),
+            scroll("down"),
+            resize(10),
+            ParallelCommand::Quit.into(),
+        ];
+
+        let prioritized = prioritize(ui_commands);
+
+        assert_eq!(prioritized.len(), 2);
+        assert!(matches!(
+            prioritized[0],
+            UiCommand::Parallel(ParallelCommand::Quit)
+        ));
+        assert!(matches!(
+            prioritized[1],
+            UiCommand::Serial(SerialCommand::Keyboard(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_file_drops_open_in_the_given_mode() {
+        SETTINGS.set(&CmdLineSettings::default());
+        let target = RecordingTarget::default();
+        let drop = |mode| ParallelCommand::FileDrop {
+            path: "/tmp/notes.txt".to_owned(),
+            mode,
+        };
+
+        drop(OpenMode::Edit).execute(&target).await;
+        drop(OpenMode::Tab).execute(&target).await;
+        drop(OpenMode::VSplit).execute(&target).await;
+
+        assert_eq!(
+            target.calls(),
+            vec![
+                "call nvim_get_mode",
+                "command execute 'e ' . fnameescape('/tmp/notes.txt')",
+                "call nvim_get_mode",
+                "command execute 'tabnew ' . fnameescape('/tmp/notes.txt')",
+                "call nvim_get_mode",
+                "command execute 'vsplit ' . fnameescape('/tmp/notes.txt')",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropped_directories_change_the_working_directory() {
+        SETTINGS.set(&CmdLineSettings::default());
+        SETTINGS.set(&WindowSettings::default());
+        let target = RecordingTarget::default();
+        let directory = std::env::temp_dir().to_string_lossy().into_owned();
+
+        ParallelCommand::FileDrop {
+            path: directory.clone(),
+            mode: OpenMode::Edit,
+        }
+        .execute(&target)
+        .await;
+        ParallelCommand::FileDrop {
+            path: "/tmp/it's a file.txt".to_owned(),
+            mode: OpenMode::Edit,
+        }
+        .execute(&target)
+        .await;
+
+        assert_eq!(
+            target.calls(),
+            vec![
+                "call nvim_get_mode".to_owned(),
+                format!(
+                    "command execute 'tcd ' . fnameescape('{}')",
+                    directory.replace('\'', "''")
+                ),
+                "call nvim_get_mode".to_owned(),
+                "command execute 'e ' . fnameescape('/tmp/it''s a file.txt')".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_directory_drop_commands() {
+        let mode = |command: &str| {
+            let mut mode = DirectoryDropMode::Tab;
+            mode.from_value(Value::from(command));
+            mode
+        };
+        assert_eq!(mode("lcd"), DirectoryDropMode::Window);
+        assert_eq!(mode(""), DirectoryDropMode::Tab);
+        assert_eq!(
+            mode("Explore").drop_command("/tmp/it's here"),
+            "execute 'Explore ' . fnameescape('/tmp/it''s here')"
+        );
+        assert_eq!(
+            mode("call OpenProject").drop_command("/tmp/project"),
+            "call OpenProject('/tmp/project')"
+        );
+        assert_eq!(Value::from(mode("edit")), Value::from("edit"));
+    }
+
+    #[tokio::test]
+    async fn test_file_drops_are_refused_in_the_command_line() {
+        let target = RecordingTarget::default();
+        let drop = || ParallelCommand::FileDrop {
+            path: "/tmp/notes.txt".to_owned(),
+            mode: OpenMode::Edit,
+        };
+        let mode = |mode: &str, blocking: bool| {
+            Value::Map(vec![
+                (Value::from("mode"), Value::from(mode)),
+                (Value::from("blocking"), Value::from(blocking)),
+            ])
+        };
+
+        *target.call_result.lock().unwrap() = Some(mode("c", false));
+        drop().execute(&target).await;
+        // A hit-enter prompt or getchar() blocks neovim on input
+        *target.call_result.lock().unwrap() = Some(mode("n", true));
+        drop().execute(&target).await;
+
+        let refusal =
+            "err_writeln Leave the command line or answer the prompt before dropping files";
+        assert_eq!(
+            target.calls(),
+            vec!["call nvim_get_mode", refusal, "call nvim_get_mode", refusal]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fallback_fonts_are_echoed() {
+        let target = RecordingTarget::default();
+
+        ParallelCommand::ShowFallbackFonts(vec![
+            "Noto Color Emoji".to_owned(),
+            "Rock'n Sans".to_owned(),
+        ])
+        .execute(&target)
+        .await;
+        ParallelCommand::ShowFallbackFonts(Vec::new())
+            .execute(&target)
+            .await;
+
+        assert_eq!(
+            target.calls(),
+            vec![
+                "command echomsg 'Fallback fonts: Noto Color Emoji, Rock''n Sans'",
+                "command echomsg 'No fallback fonts are loaded'",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_huge_resizes_are_limited() {
+        SETTINGS.set(&GridSizeSettings::default());
+        let target = RecordingTarget::default();
+
+        ParallelCommand::Resize {
+            width: 1_000_000,
+            height: 1,
+        }
+        .execute(&target)
+        .await;
+
+        assert_eq!(target.calls(), vec!["resize 2000 3"]);
+    }
+
+    #[tokio::test]
+    async fn test_tab_commands_use_the_tabpage_handle() {
+        let target = RecordingTarget::default();
+
+        ParallelCommand::TabSelect(3).execute(&target).await;
+        ParallelCommand::TabClose(5).execute(&target).await;
+
+        assert_eq!(
+            target.calls(),
+            vec![
+                "command call nvim_set_current_tabpage(3)",
+                "command execute nvim_tabpage_get_number(5) . 'tabclose'",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_confirm_quit_asks_neovim() {
+        SETTINGS.set(&SessionSettings::default());
+        let target = RecordingTarget::default();
+
+        ParallelCommand::ConfirmQuit.execute(&target).await;
+
+        assert_eq!(
+            target.calls(),
+            vec![format!("command {}", CONFIRM_QUIT_COMMAND)]
+        );
+    }
+
+    #[test]
+    fn test_scroll_keys() {
+        assert_eq!(
+            scroll_keys("up", "", 2),
+            Some("<ScrollWheelUp><ScrollWheelUp>".to_owned())
+        );
+        assert_eq!(
+            scroll_keys("right", "S-C-", 1),
+            Some("<S-C-ScrollWheelRight>".to_owned())
+        );
+        assert_eq!(scroll_keys("sideways", "", 1), None);
+    }
+
+    #[tokio::test]
+    async fn test_copy_and_cut_need_a_visual_selection() {
+        let target = RecordingTarget::default();
+        let mode = |mode: &str| {
+            Value::Map(vec![
+                (Value::from("mode"), Value::from(mode)),
+                (Value::from("blocking"), Value::from(false)),
+            ])
+        };
+
+        *target.call_result.lock().unwrap() = Some(mode("n"));
+        SerialCommand::Shortcut(ShortcutAction::Copy)
+            .execute(&target)
+            .await;
+        *target.call_result.lock().unwrap() = Some(mode("V"));
+        SerialCommand::Shortcut(ShortcutAction::Copy)
+            .execute(&target)
+            .await;
+        *target.call_result.lock().unwrap() = Some(mode("\x16"));
+        SerialCommand::Shortcut(ShortcutAction::Cut)
+            .execute(&target)
+            .await;
+
+        assert_eq!(
+            target.calls(),
+            vec![
+                "call nvim_get_mode",
+                "call nvim_get_mode",
+                "input \"+y",
+                "call nvim_get_mode",
+                "input \"+d",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_paste_shortcut_inserts_the_clipboard() {
+        let target = RecordingTarget::default();
+        *target.eval_result.lock().unwrap() = Some(Value::from("copied\ntext"));
+
+        SerialCommand::Shortcut(ShortcutAction::Paste)
+            .execute(&target)
+            .await;
+        SerialCommand::Shortcut(ShortcutAction::Save)
+            .execute(&target)
+            .await;
+
+        assert_eq!(
+            target.calls(),
+            vec!["paste -1 \"copied\\ntext\"", "command write"]
+        );
+    }
+
+    #[test]
+    fn test_clipboard_text_converts_invalid_bytes() {
+        // A msgpack string of two bytes which aren't utf-8
+        let invalid = rmpv::decode::read_value(&mut &[0xa2, 0xff, 0xfe][..]).unwrap();
+        assert_eq!(clipboard_text(invalid), Some("\u{fffd}\u{fffd}".to_owned()));
+        assert_eq!(
+            clipboard_text(Value::Binary(vec![b'a', 0xc3])),
+            Some("a\u{fffd}".to_owned())
+        );
+        assert_eq!(clipboard_text(Value::from("text")), Some("text".to_owned()));
+        assert_eq!(clipboard_text(Value::from("")), None);
+        assert_eq!(clipboard_text(Value::Nil), None);
+    }
+
+    #[tokio::test]
+    async fn test_paste_shortcut_without_text_tells_the_user() {
+        let target = RecordingTarget::default();
+        *target.eval_result.lock().unwrap() = Some(Value::from(""));
+
+        SerialCommand::Shortcut(ShortcutAction::Paste)
+            .execute(&target)
+            .await;
+
+        assert_eq!(target.calls(), vec!["err_writeln Clipboard has no text"]);
+    }
+
+    #[tokio::test]
+    async fn test_frame_stats_are_stored_in_a_variable() {
+        let target = RecordingTarget::default();
+        let stats = FrameStats {
+            fps: 60.0,
+            average_frame_time: 2.0,
+            max_frame_time: 5.0,
+        };
+
+        ParallelCommand::ReportFrameStats(stats)
+            .execute(&target)
+            .await;
+
+        assert_eq!(
+            target.calls(),
+            vec![
+                "call nvim_set_var".to_owned(),
+                format!("command {}", FRAME_STATS_AUTOCMD)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prioritize_treats_detach_like_quit() {
+        let ui_commands = vec![scroll("up"), resize(10), ParallelCommand::Detach.into()];
+
+        let prioritized = prioritize(ui_commands);
+
+        assert_eq!(prioritized.len(), 1);
+        assert!(matches!(
+            prioritized[0],
+            UiCommand::Parallel(ParallelCommand::Detach)
+        ));
+    }
+
+    #[test]
+    fn test_prioritize_keeps_serial_order_and_latest_resize() {
+        let ui_commands = vec![
+            resize(10),
+            scroll("up"),
+            ParallelCommand::FocusGained.into(),
+            scroll("down"),
+            resize(20),
+        ];
+
+        let prioritized = prioritize(ui_commands);
+
+        assert_eq!(prioritized.len(), 4);
+        assert!(matches!(
+            prioritized[0],
+            UiCommand::Parallel(ParallelCommand::FocusGained)
+        ));
+        assert!(matches!(
+            prioritized[1],
+            UiCommand::Parallel(ParallelCommand::Resize { width: 20, .. })
+        ));
+        assert!(
+            matches!(&prioritized[2], UiCommand::Serial(SerialCommand::Scroll { direction, .. }) if direction == "up")
+        );
+        assert!(
+            matches!(&prioritized[3], UiCommand::Serial(SerialCommand::Scroll { direction, .. }) if direction == "down")
+        );
+    }
+
+    #[test]
+    fn test_prioritize_keeps_scroll_reversals() {
+        let ui_commands = vec![scroll("up"), scroll("down"), scroll("up"), scroll("down")];
+
+        let prioritized = prioritize(ui_commands);
+
+        let directions: Vec<&str> = prioritized
+            .iter()
+            .filter_map(|ui_command| match ui_command {
+                UiCommand::Serial(SerialCommand::Scroll { direction, .. }) => {
+                    Some(direction.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(directions, vec!["up", "down", "up", "down"]);
+    }
+
+    fn drag(column: u32) -> UiCommand {
+        SerialCommand::Drag {
+            button: "left".to_owned(),
+            grid_id: 1,
+            position: (column, 0),
+            modifier_string: "".to_owned(),
+        }
+        .into()
+    }
+
+    fn scroll_counts(ui_commands: &[UiCommand]) -> Vec<(&str, u64)> {
+        ui_commands
+            .iter()
+            .filter_map(|ui_command| match ui_command {
+                UiCommand::Serial(SerialCommand::Scroll {
+                    direction, count, ..
+                }) => Some((direction.as_str(), *count)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_scrolls_keep_every_wheel_step() {
+        SETTINGS.set(&MouseSettings::default());
+        let mut coalesced = CoalescedState::default();
+        for _ in 0..5 {
+            coalesced.push(scroll("up"));
+        }
+        assert_eq!(scroll_counts(&coalesced.commands), vec![("up", 5)]);
+
+        let target = RecordingTarget::default();
+        for ui_command in coalesced.commands {
+            if let UiCommand::Serial(serial_command) = ui_command {
+                serial_command.execute(&target).await;
+            }
+        }
+        assert_eq!(target.calls(), vec!["mouse wheel up 1 0 0"; 5]);
+    }
+
+    #[test]
+    fn test_coalesced_scrolls_only_merge_the_same_direction_and_grid() {
+        let mut other_grid = scroll("up");
+        if let UiCommand::Serial(SerialCommand::Scroll { grid_id, .. }) = &mut other_grid {
+            *grid_id = 2;
+        }
+
+        let mut coalesced = CoalescedState::default();
+        for ui_command in vec![
+            scroll("up"),
+            scroll("up"),
+            scroll("down"),
+            scroll("up"),
+            other_grid,
+            keyboard("a"),
+            scroll("up"),
+        ] {
+            coalesced.push(ui_command);
+        }
+
+        assert_eq!(
+            scroll_counts(&coalesced.commands),
+            vec![("up", 2), ("down", 1), ("up", 1), ("up", 1), ("up", 1)]
+        );
+        assert_eq!(coalesced.commands.len(), 6);
+    }
+
+    #[test]
+    fn test_coalesced_drags_keep_the_last_position() {
+        let mut coalesced = CoalescedState::default();
+        for ui_command in vec![drag(1), drag(2), drag(3), scroll("down"), drag(4)] {
+            coalesced.push(ui_command);
+        }
+
+        let positions: Vec<(u32, u32)> = coalesced
+            .commands
+            .iter()
+            .filter_map(|ui_command| match ui_command {
+                UiCommand::Serial(SerialCommand::Drag { position, .. }) => Some(*position),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(positions, vec![(3, 0), (4, 0)]);
+    }
+
+    #[test]
+    fn test_prioritize_batches_consecutive_keyboard_input() {
+        let ui_commands = vec![
+            keyboard("a"),
+            keyboard("b"),
+            keyboard("<CR>"),
+            scroll("up"),
+            keyboard("c"),
+        ];
+
+        let prioritized = prioritize(ui_commands);
+
+        assert_eq!(prioritized.len(), 3);
+        assert!(
+            matches!(&prioritized[0], UiCommand::Serial(SerialCommand::Keyboard(input)) if input == "ab<CR>")
+        );
+        assert!(matches!(
+            prioritized[1],
+            UiCommand::Serial(SerialCommand::Scroll { .. })
+        ));
+        assert!(
+            matches!(&prioritized[2], UiCommand::Serial(SerialCommand::Keyboard(input)) if input == "c")
+        );
+    }
+}
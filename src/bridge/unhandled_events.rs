@@ -0,0 +1,145 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use log::info;
+use parking_lot::{Mutex, RwLock};
+use rmpv::Value;
+
+// Unhandled events which fire on every redraw would otherwise flood the log
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+type UnhandledEventHandler = Box<dyn Fn(&[Value]) + Send + Sync>;
+
+lazy_static! {
+    pub static ref UNHANDLED_EVENTS: UnhandledEvents = UnhandledEvents::new();
+}
+
+struct LogState {
+    last_logged: Instant,
+    suppressed: u64,
+}
+
+// Decides when an unhandled event is logged. Each event name is logged the first time it is seen
+// and at most once per interval after that, along with how many were left out in between.
+#[derive(Default)]
+struct LogLimiter {
+    events: HashMap<String, LogState>,
+}
+
+impl LogLimiter {
+    // Returns the number of events suppressed since the last log, if this one should be logged
+    fn should_log(&mut self, event_name: &str, now: Instant) -> Option<u64> {
+        match self.events.get_mut(event_name) {
+            Some(state) if now.duration_since(state.last_logged) < LOG_INTERVAL => {
+                state.suppressed += 1;
+                None
+            }
+            Some(state) => {
+                let suppressed = state.suppressed;
+                state.last_logged = now;
+                state.suppressed = 0;
+                Some(suppressed)
+            }
+            None => {
+                self.events.insert(
+                    event_name.to_owned(),
+                    LogState {
+                        last_logged: now,
+                        suppressed: 0,
+                    },
+                );
+                Some(0)
+            }
+        }
+    }
+}
+
+// Redraw events neovide doesn't parse end up here. Handlers can be registered for them by name
+// to experiment with new ui events, anything else is logged so that it is easy to spot which
+// events a newer neovim sends.
+pub struct UnhandledEvents {
+    handlers: RwLock<HashMap<String, UnhandledEventHandler>>,
+    log_limiter: Mutex<LogLimiter>,
+}
+
+impl UnhandledEvents {
+    fn new() -> UnhandledEvents {
+        UnhandledEvents {
+            handlers: RwLock::new(HashMap::new()),
+            log_limiter: Mutex::new(LogLimiter::default()),
+        }
+    }
+
+    pub fn register_handler<F>(&self, event_name: &str, handler: F)
+    where
+        F: Fn(&[Value]) + Send + Sync + 'static,
+    {
+        self.handlers
+            .write()
+            .insert(event_name.to_owned(), Box::new(handler));
+    }
+
+    pub fn handle(&self, event_name: &str, parameters: &[Value]) {
+        if let Some(handler) = self.handlers.read().get(event_name) {
+            handler(parameters);
+            return;
+        }
+
+        let suppressed = self
+            .log_limiter
+            .lock()
+            .should_log(event_name, Instant::now());
+        match suppressed {
+            Some(0) => info!("Unhandled redraw event {}: {:?}", event_name, parameters),
+            Some(suppressed) => info!(
+                "Unhandled redraw event {} ({} more since the last one logged): {:?}",
+                event_name, suppressed, parameters
+            ),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_log_limiter() {
+        let mut limiter = LogLimiter::default();
+        let start = Instant::now();
+
+        assert_eq!(limiter.should_log("ui_new_event", start), Some(0));
+        assert_eq!(limiter.should_log("ui_new_event", start), None);
+        assert_eq!(limiter.should_log("ui_other_event", start), Some(0));
+        assert_eq!(
+            limiter.should_log("ui_new_event", start + Duration::from_secs(1)),
+            None
+        );
+        assert_eq!(
+            limiter.should_log("ui_new_event", start + LOG_INTERVAL),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_registered_handler_receives_event() {
+        let events = UnhandledEvents::new();
+        let received = Arc::new(AtomicU64::new(0));
+
+        let handler_received = received.clone();
+        events.register_handler("ui_new_event", move |parameters| {
+            handler_received.fetch_add(parameters.len() as u64, Ordering::Relaxed);
+        });
+        events.handle("ui_new_event", &[Value::from(1), Value::from(2)]);
+
+        assert_eq!(received.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use nvim_rs::Neovim;
+use tokio::time::{sleep, timeout};
+
+use crate::{
+    bridge::TxWrapper, event_aggregator::EVENT_AGGREGATOR, profiling::PROFILER,
+    running_tracker::RUNNING_TRACKER, settings::*, window::WindowCommand,
+};
+
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, SettingGroup)]
+pub struct WatchdogSettings {
+    // Seconds a ping may take before neovim is reported as not responding, 0 turns it off
+    pub ping_timeout: f32,
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        Self { ping_timeout: 5.0 }
+    }
+}
+
+// Pings neovim every second while the watchdog or the profiler is on. The round trip is recorded
+// for the profiler overlay, and a ping which takes longer than the timeout tells the window to
+// show that neovim is not responding until it answers. Only one ping is ever in flight, so a
+// blocked neovim doesn't build up a queue of them.
+pub fn start_ping_watchdog(nvim: Arc<Neovim<TxWrapper>>) {
+    tokio::spawn(async move {
+        while RUNNING_TRACKER.is_running() {
+            sleep(PING_INTERVAL).await;
+
+            // Nothing needs the ping when the watchdog is off and the overlay isn't shown
+            let ping_timeout = SETTINGS.get::<WatchdogSettings>().ping_timeout;
+            if ping_timeout <= 0.0 && !PROFILER.is_enabled() {
+                continue;
+            }
+
+            let ping_started = Instant::now();
+            let ping = nvim.eval("1");
+            tokio::pin!(ping);
+
+            let result = if ping_timeout > 0.0 {
+                match timeout(Duration::from_secs_f32(ping_timeout), &mut ping).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        warn!("Neovim has not responded for {}s", ping_timeout);
+                        EVENT_AGGREGATOR.send(WindowCommand::SetNeovimResponding(false));
+                        let result = ping.await;
+                        info!(
+                            "Neovim responded again after {:.1}s",
+                            ping_started.elapsed().as_secs_f32()
+                        );
+                        EVENT_AGGREGATOR.send(WindowCommand::SetNeovimResponding(true));
+                        result
+                    }
+                }
+            } else {
+                ping.await
+            };
+
+            if result.is_ok() {
+                PROFILER.record_ping(ping_started.elapsed());
+            }
+        }
+    });
+}
@@ -0,0 +1,91 @@
+// Converts windows style paths into the form they are reachable at from inside WSL. Drives are
+// mounted under /mnt, paths inside the WSL file system share map back to their linux locations and
+// anything which isn't a windows path is left untouched.
+pub fn windows_path_to_wsl(path: &str) -> String {
+    let bytes = path.as_bytes();
+
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        let drive = (bytes[0] as char).to_ascii_lowercase();
+        let rest = path[2..].replace('\\', "/");
+        let rest = rest.trim_start_matches('/');
+        return if rest.is_empty() {
+            format!("/mnt/{}", drive)
+        } else {
+            format!("/mnt/{}/{}", drive, rest)
+        };
+    }
+
+    if let Some(unc_path) = path.strip_prefix("\\\\") {
+        let unc_path = unc_path.replace('\\', "/");
+        let mut components = unc_path.splitn(3, '/');
+        let host = components.next().unwrap_or("");
+        let _distribution = components.next();
+        let rest = components.next().unwrap_or("");
+
+        return if host.eq_ignore_ascii_case("wsl$") || host.eq_ignore_ascii_case("wsl.localhost") {
+            format!("/{}", rest)
+        } else {
+            format!("//{}", unc_path)
+        };
+    }
+
+    path.to_owned()
+}
+
+// Arguments given to wsl are joined back into a single command for the linux shell, so anything
+// with spaces or shell characters needs quoting to survive as one argument
+pub fn quote_for_wsl_shell(argument: &str) -> String {
+    let is_safe =
+        |character: char| character.is_ascii_alphanumeric() || "-_./=:+,@%".contains(character);
+    if !argument.is_empty() && argument.chars().all(is_safe) {
+        argument.to_owned()
+    } else {
+        format!("'{}'", argument.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_for_wsl_shell() {
+        assert_eq!(quote_for_wsl_shell("/mnt/c/foo.txt"), "/mnt/c/foo.txt");
+        assert_eq!(
+            quote_for_wsl_shell("/mnt/c/My Documents/a.txt"),
+            "'/mnt/c/My Documents/a.txt'"
+        );
+        assert_eq!(quote_for_wsl_shell("it's"), "'it'\\''s'");
+        assert_eq!(quote_for_wsl_shell(""), "''");
+    }
+
+    #[test]
+    fn test_drive_letter_paths() {
+        assert_eq!(windows_path_to_wsl("C:\\foo\\bar"), "/mnt/c/foo/bar");
+        assert_eq!(windows_path_to_wsl("d:/foo/bar.txt"), "/mnt/d/foo/bar.txt");
+        assert_eq!(windows_path_to_wsl("C:\\"), "/mnt/c");
+    }
+
+    #[test]
+    fn test_unc_paths() {
+        assert_eq!(
+            windows_path_to_wsl("\\\\wsl$\\Ubuntu\\home\\user\\file"),
+            "/home/user/file"
+        );
+        assert_eq!(
+            windows_path_to_wsl("\\\\wsl.localhost\\Debian\\etc\\hosts"),
+            "/etc/hosts"
+        );
+        assert_eq!(
+            windows_path_to_wsl("\\\\server\\share\\file"),
+            "//server/share/file"
+        );
+    }
+
+    #[test]
+    fn test_posix_paths_are_unchanged() {
+        assert_eq!(windows_path_to_wsl("/home/user/file"), "/home/user/file");
+        assert_eq!(windows_path_to_wsl("relative/file"), "relative/file");
+        assert_eq!(windows_path_to_wsl("--clean"), "--clean");
+    }
+}
@@ -1,23 +1,152 @@
+use std::path::Path;
+
 use crate::{dimensions::Dimensions, settings::*};
 
 use clap::{App, Arg};
 
+// A file opened as file:line or file:line:column, the form compilers and grep print positions in
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilePosition {
+    pub path: String,
+    pub line: u64,
+    pub column: u64,
+}
+
+impl FilePosition {
+    // Splits the position off a file argument. Files which exist under the full name, and
+    // arguments such as +42 which neovim handles itself, are left alone.
+    pub fn parse(argument: &str) -> Option<FilePosition> {
+        if argument.starts_with('+') || argument.starts_with('-') || Path::new(argument).exists() {
+            return None;
+        }
+
+        let parse_suffix = |text: &str| {
+            let (rest, number) = text.rsplit_once(':')?;
+            let number = number.parse::<u64>().ok().filter(|number| *number > 0)?;
+            Some((rest, number))
+        };
+        let (rest, last_number) = parse_suffix(argument)?;
+        let (path, line, column) = match parse_suffix(rest) {
+            Some((path, line)) => (path, line, last_number),
+            None => (rest, last_number, 1),
+        };
+
+        // A bare drive letter such as C:12 isn't a path
+        if path.is_empty() || path.len() == 1 && path.ends_with(char::is_alphabetic) {
+            return None;
+        }
+        Some(FilePosition {
+            path: path.to_owned(),
+            line,
+            column,
+        })
+    }
+}
+
+// Arguments neovim can't be started with, since the ui attaches over stdin and stdout after
+// --embed. They either run neovim without a ui, exit before attaching or read from stdin.
+const RESERVED_NEOVIM_ARGS: &[&str] = &[
+    "--embed",
+    "--headless",
+    "--api-info",
+    "--version",
+    "-v",
+    "--help",
+    "-h",
+    "-?",
+    "-",
+    "-e",
+    "-E",
+    "-es",
+    "-Es",
+];
+
+// Splits --neovim-args into arguments the way a shell would, with single quotes taken literally.
+// Outside of them a backslash escapes a quote, a space or another backslash, and is kept as it is
+// anywhere else so that windows paths don't need escaping.
+fn split_neovim_args(text: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote = None;
+    let mut characters = text.chars();
+
+    while let Some(character) = characters.next() {
+        match (quote, character) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => current.get_or_insert_with(String::new).push(character),
+            (_, '\\') => {
+                let argument = current.get_or_insert_with(String::new);
+                match characters.clone().next() {
+                    Some(next) if "\"'\\".contains(next) || next.is_whitespace() => {
+                        argument.push(next);
+                        characters.next();
+                    }
+                    _ => argument.push(character),
+                }
+            }
+            (Some('"'), '"') => quote = None,
+            (Some('"'), _) => current.get_or_insert_with(String::new).push(character),
+            (None, '\'' | '"') => {
+                quote = Some(character);
+                current.get_or_insert_with(String::new);
+            }
+            (None, _) if character.is_whitespace() => args.extend(current.take()),
+            (None, _) => current.get_or_insert_with(String::new).push(character),
+            _ => unreachable!(),
+        }
+    }
+
+    if quote.is_some() {
+        return Err(format!("Unterminated quote in --neovim-args: {}", text));
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+fn check_neovim_args(args: &[String]) -> Result<(), String> {
+    match args
+        .iter()
+        .find(|arg| RESERVED_NEOVIM_ARGS.contains(&arg.as_str()))
+    {
+        Some(arg) => Err(format!(
+            "Neovim can't be started with {} since neovide attaches to it as its ui",
+            arg
+        )),
+        None => Ok(()),
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct CmdLineSettings {
     // Pass through arguments
     pub neovim_args: Vec<String>,
+    // From --neovim-args or NEOVIDE_NEOVIM_ARGS, passed before the ones above
+    pub extra_neovim_args: Vec<String>,
+    // Files given as file:line, which are passed to neovim without the position and jumped to
+    // once the ui is attached
+    pub file_positions: Vec<FilePosition>,
     // Command-line arguments only
     pub geometry: Dimensions,
     pub log_to_file: bool,
+    pub log_path: Option<String>,
+    pub log_level: Option<String>,
     pub no_fork: bool,
     pub remote_tcp: Option<String>,
     pub wsl: bool,
+    pub wsl_distro: Option<String>,
+    pub list_settings: bool,
+    // Source the session saved by g:neovide_auto_session, --no-restore wins over both
+    pub restore_session: bool,
+    pub no_restore_session: bool,
     // Command-line flags with environment variable fallback
     pub frameless: bool,
     pub maximized: bool,
     pub multi_grid: bool,
     pub no_idle: bool,
     pub srgb: bool,
+    // Frames are drawn in memory by skia and copied to the window through a software opengl
+    // context, so the gpu isn't used at all. --no-gpu and NEOVIDE_NO_GPU are aliases.
+    pub software_render: bool,
     // Command-line arguments with environment variable fallback
     pub neovim_bin: Option<String>,
     pub wayland_app_id: String,
@@ -29,18 +158,27 @@ impl Default for CmdLineSettings {
         Self {
             // Pass through arguments
             neovim_args: vec![],
+            extra_neovim_args: vec![],
+            file_positions: vec![],
             // Command-line arguments only
             geometry: DEFAULT_WINDOW_GEOMETRY,
             log_to_file: false,
+            log_path: None,
+            log_level: None,
             no_fork: false,
             remote_tcp: None,
             wsl: false,
+            wsl_distro: None,
+            list_settings: false,
+            restore_session: false,
+            no_restore_session: false,
             // Command-line flags with environment variable fallback
             frameless: false,
             maximized: false,
             multi_grid: false,
             no_idle: false,
             srgb: true,
+            software_render: false,
             // Command-line arguments with environment variable fallback
             neovim_bin: None,
             wayland_app_id: String::new(),
@@ -78,7 +216,18 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
         .arg(
             Arg::with_name("log_to_file")
                 .long("log")
-                .help("Log to a file"),
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .value_name("path")
+                .help("Log to a file, by default neovide_<date>.log in the cache directory"),
+        )
+        .arg(
+            Arg::with_name("log_level")
+                .long("log-level")
+                .takes_value(true)
+                .possible_values(&["error", "warn", "info", "debug", "trace"])
+                .help("Only log messages of this level or above"),
         )
         .arg(
             Arg::with_name("nofork")
@@ -88,14 +237,21 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
         .arg(
             Arg::with_name("remote_tcp")
                 .long("remote-tcp")
+                .alias("server")
                 .takes_value(true)
-                .help("Connect to Remote TCP"),
+                .help("Connect to a neovim server listening on the given host:port instead of starting one"),
         )
         .arg(
             Arg::with_name("wsl")
                 .long("wsl")
                 .help("Run in WSL")
         )
+        .arg(
+            Arg::with_name("wsl_distro")
+                .long("wsl-distro")
+                .takes_value(true)
+                .help("Run in the given WSL distribution instead of the default one"),
+        )
         // Command-line flags with environment variable fallback
         .arg(
             Arg::with_name("frameless")
@@ -112,6 +268,22 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
                 .long("multigrid")
                 .help("Enable Multigrid"),
         )
+        .arg(
+            Arg::with_name("list_settings")
+                .long("list-settings")
+                .hidden(true)
+                .help("Print the g:neovide_ variables neovide reads and exit"),
+        )
+        .arg(
+            Arg::with_name("restore")
+                .long("restore")
+                .help("Restore the session saved for the working directory when neovide last closed"),
+        )
+        .arg(
+            Arg::with_name("no_restore")
+                .long("no-restore")
+                .help("Don't restore a saved session, even with --restore or g:neovide_auto_session"),
+        )
         .arg(
             Arg::with_name("noidle")
                 .long("noidle")
@@ -122,7 +294,20 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
                 .long("nosrgb")
                 .help("Do not use standard color space to initialize the window. Swapping this variable sometimes fixes issues on startup"),
         )
+        .arg(
+            Arg::with_name("software_render")
+                .long("software-render")
+                .alias("no-gpu")
+                .help("Render without the gpu, for example over VNC or RDP. Frames are drawn in memory and copied to the window through software opengl"),
+        )
         // Command-line arguments with environment variable fallback
+        .arg(
+            Arg::with_name("extra_neovim_args")
+                .long("neovim-args")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .help("Extra arguments to start neovim with, quoted as in a shell. For example --neovim-args=\"-u NONE --noplugin\""),
+        )
         .arg(
             Arg::with_name("neovim_bin")
                 .long("neovim-bin")
@@ -147,12 +332,27 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
         .values_of("neovim_args")
         .map(|opt| opt.map(|v| v.to_owned()).collect())
         .unwrap_or_default();
-    neovim_args.extend::<Vec<String>>(
-        matches
-            .values_of("files_to_open")
-            .map(|opt| opt.map(|v| v.to_owned()).collect())
-            .unwrap_or_default(),
-    );
+    let mut file_positions = Vec::new();
+    for file in matches.values_of("files_to_open").into_iter().flatten() {
+        match FilePosition::parse(file) {
+            Some(file_position) => {
+                neovim_args.push(file_position.path.clone());
+                file_positions.push(file_position);
+            }
+            None => neovim_args.push(file.to_owned()),
+        }
+    }
+
+    let extra_neovim_args = match matches
+        .value_of("extra_neovim_args")
+        .map(|v| v.to_owned())
+        .or_else(|| std::env::var("NEOVIDE_NEOVIM_ARGS").ok())
+    {
+        Some(text) => split_neovim_args(&text)?,
+        None => Vec::new(),
+    };
+    check_neovim_args(&extra_neovim_args)?;
+    check_neovim_args(&neovim_args)?;
 
     /*
      * Integrate Environment Variables as Defaults to the command-line ones.
@@ -162,12 +362,20 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
     SETTINGS.set::<CmdLineSettings>(&CmdLineSettings {
         // Pass through arguments
         neovim_args,
+        extra_neovim_args,
+        file_positions,
         // Command-line arguments only
         geometry: parse_window_geometry(matches.value_of("geometry").map(|i| i.to_owned()))?,
         log_to_file: matches.is_present("log_to_file"),
+        log_path: matches.value_of("log_to_file").map(|v| v.to_owned()),
+        log_level: matches.value_of("log_level").map(|v| v.to_owned()),
         no_fork: matches.is_present("nofork"),
         remote_tcp: matches.value_of("remote_tcp").map(|i| i.to_owned()),
-        wsl: matches.is_present("wsl"),
+        wsl: matches.is_present("wsl") || std::env::var("NEOVIDE_WSL").is_ok(),
+        wsl_distro: matches.value_of("wsl_distro").map(|v| v.to_owned()),
+        list_settings: matches.is_present("list_settings"),
+        restore_session: matches.is_present("restore"),
+        no_restore_session: matches.is_present("no_restore"),
         // Command-line flags with environment variable fallback
         frameless: matches.is_present("frameless") || std::env::var("NEOVIDE_FRAMELESS").is_ok(),
         maximized: matches.is_present("maximized") || std::env::var("NEOVIDE_MAXIMIZED").is_ok(),
@@ -175,6 +383,9 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
         no_idle: matches.is_present("noidle") || std::env::var("NEOVIDE_NO_IDLE").is_ok(),
         // Srgb is enabled by default, so set it to false if nosrgb or NOEVIDE_NO_SRGB is set
         srgb: !(matches.is_present("nosrgb") || std::env::var("NEOVIDE_NO_SRGB").is_ok()),
+        software_render: matches.is_present("software_render")
+            || std::env::var("NEOVIDE_SOFTWARE_RENDER").is_ok()
+            || std::env::var("NEOVIDE_NO_GPU").is_ok(),
         // Command-line arguments with environment variable fallback
         neovim_bin: matches
             .value_of("neovim_bin")
@@ -253,6 +464,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_files_to_open_with_positions() {
+        let args: Vec<String> = vec!["neovide", "src/main.rs:42", "+3", "README.md:7:5"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        let settings = SETTINGS.get::<CmdLineSettings>();
+        assert_eq!(settings.neovim_args, vec!["src/main.rs", "+3", "README.md"]);
+        assert_eq!(
+            settings.file_positions,
+            vec![
+                FilePosition {
+                    path: "src/main.rs".to_owned(),
+                    line: 42,
+                    column: 1,
+                },
+                FilePosition {
+                    path: "README.md".to_owned(),
+                    line: 7,
+                    column: 5,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_position_parsing() {
+        assert_eq!(
+            FilePosition::parse("C:\\code\\main.rs:12"),
+            Some(FilePosition {
+                path: "C:\\code\\main.rs".to_owned(),
+                line: 12,
+                column: 1,
+            })
+        );
+        assert_eq!(FilePosition::parse("main.rs"), None);
+        assert_eq!(FilePosition::parse("main.rs:"), None);
+        assert_eq!(FilePosition::parse("main.rs:0"), None);
+        assert_eq!(FilePosition::parse(":12"), None);
+        assert_eq!(FilePosition::parse("C:12"), None);
+        assert_eq!(FilePosition::parse("+12"), None);
+    }
+
     #[test]
     fn test_files_to_open_with_flag() {
         let args: Vec<String> = vec!["neovide", "./foo.txt", "./bar.md", "--geometry=42x24"]
@@ -306,6 +563,71 @@ mod tests {
         assert_eq!(SETTINGS.get::<CmdLineSettings>().log_to_file, true);
     }
 
+    #[test]
+    fn test_log_path_and_level() {
+        let args: Vec<String> = vec![
+            "neovide",
+            "--log=/tmp/neovide.log",
+            "--log-level",
+            "debug",
+            "./foo.txt",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        let settings = SETTINGS.get::<CmdLineSettings>();
+        assert!(settings.log_to_file);
+        assert_eq!(settings.log_path, Some("/tmp/neovide.log".to_owned()));
+        assert_eq!(settings.log_level, Some("debug".to_owned()));
+        assert_eq!(settings.neovim_args, vec!["./foo.txt"]);
+    }
+
+    #[test]
+    fn test_server_arg() {
+        let args: Vec<String> = vec!["neovide", "--server", "localhost:6666"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().remote_tcp,
+            Some("localhost:6666".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_wsl_distro_arg() {
+        let args: Vec<String> = vec!["neovide", "--wsl", "--wsl-distro", "Ubuntu"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        let settings = SETTINGS.get::<CmdLineSettings>();
+        assert!(settings.wsl);
+        assert_eq!(settings.wsl_distro, Some("Ubuntu".to_owned()));
+    }
+
+    #[test]
+    fn test_restore_flags() {
+        let args: Vec<String> = vec!["neovide", "--restore", "--no-restore"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        let settings = SETTINGS.get::<CmdLineSettings>();
+        assert!(settings.restore_session);
+        assert!(settings.no_restore_session);
+    }
+
     #[test]
     fn test_frameless_flag() {
         let args: Vec<String> = vec!["neovide", "--frameless"]
@@ -328,6 +650,99 @@ mod tests {
         assert_eq!(SETTINGS.get::<CmdLineSettings>().frameless, true);
     }
 
+    #[test]
+    fn test_software_render_flag() {
+        let args: Vec<String> = vec!["neovide", "--software-render"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert!(SETTINGS.get::<CmdLineSettings>().software_render);
+    }
+
+    #[test]
+    fn test_no_gpu_environment_variable() {
+        let args: Vec<String> = vec!["neovide"].iter().map(|s| s.to_string()).collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        set_var("NEOVIDE_NO_GPU", "1");
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert!(SETTINGS.get::<CmdLineSettings>().software_render);
+    }
+
+    #[test]
+    fn test_no_gpu_flag_is_software_render() {
+        let args: Vec<String> = vec!["neovide", "--no-gpu"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert!(SETTINGS.get::<CmdLineSettings>().software_render);
+    }
+
+    #[test]
+    fn test_extra_neovim_args() {
+        let args: Vec<String> = vec![
+            "neovide",
+            "--neovim-args",
+            "-u NONE --cmd 'set rtp+=~/my plugin' --cmd \"let g:a = \\\"b\\\"\"",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().extra_neovim_args,
+            vec![
+                "-u",
+                "NONE",
+                "--cmd",
+                "set rtp+=~/my plugin",
+                "--cmd",
+                "let g:a = \"b\""
+            ]
+        );
+    }
+
+    #[test]
+    fn test_splitting_neovim_args() {
+        assert_eq!(
+            split_neovim_args("  a  ''  b\\ c "),
+            Ok(vec!["a".to_owned(), "".to_owned(), "b c".to_owned()])
+        );
+        assert!(split_neovim_args("--cmd 'set").is_err());
+        assert_eq!(
+            split_neovim_args("-u C:\\nvim\\init.vim a\\"),
+            Ok(vec![
+                "-u".to_owned(),
+                "C:\\nvim\\init.vim".to_owned(),
+                "a\\".to_owned()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_reserved_neovim_args_are_rejected() {
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        let args: Vec<String> = vec!["neovide", "--neovim-args=--noplugin --headless"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert!(handle_command_line_arguments(args).is_err());
+
+        let args: Vec<String> = vec!["neovide", "--", "--embed"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert!(handle_command_line_arguments(args).is_err());
+    }
+
     #[test]
     fn test_neovim_bin_arg() {
         let args: Vec<String> = vec!["neovide", "--neovim-bin", "foo"]
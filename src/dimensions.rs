@@ -1,8 +1,31 @@
 use std::ops::{Div, Mul};
 
 use glutin::dpi::PhysicalSize;
+use log::warn;
 use serde::{Deserialize, Serialize};
 
+use crate::settings::*;
+
+#[derive(Clone, SettingGroup)]
+pub struct GridSizeSettings {
+    // The largest grid neovide asks neovim for or allocates, in cells. A bogus window size, such
+    // as the ones some tiling window managers report mid layout change, would otherwise allocate
+    // millions of cells.
+    #[setting(min = 10)]
+    pub max_grid_columns: u64,
+    #[setting(min = 3)]
+    pub max_grid_rows: u64,
+}
+
+impl Default for GridSizeSettings {
+    fn default() -> Self {
+        Self {
+            max_grid_columns: 2000,
+            max_grid_rows: 1000,
+        }
+    }
+}
+
 // Maybe this should be independent from serialization?
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Dimensions {
@@ -23,6 +46,25 @@ macro_rules! impl_from_tuple_to_dimensions {
     };
 }
 
+impl Dimensions {
+    // Caps the size at g:neovide_max_grid_columns by g:neovide_max_grid_rows, logging when it had
+    // to be cut down
+    pub fn limited(self) -> Dimensions {
+        let settings = SETTINGS.get::<GridSizeSettings>();
+        let limited = Dimensions {
+            width: self.width.min(settings.max_grid_columns),
+            height: self.height.min(settings.max_grid_rows),
+        };
+        if limited != self {
+            warn!(
+                "Grid size {}x{} is over the limit, using {}x{}",
+                self.width, self.height, limited.width, limited.height
+            );
+        }
+        limited
+    }
+}
+
 impl_from_tuple_to_dimensions!(u64);
 impl_from_tuple_to_dimensions!(f32);
 
@@ -0,0 +1,109 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    bridge::StyledContent,
+    editor::{DrawCommandBatcher, Style},
+    renderer::{CommandLineContents, CommandLineLevel, DrawCommand, StyledLine},
+};
+
+fn resolve_styles(content: StyledContent, defined_styles: &HashMap<u64, Arc<Style>>) -> StyledLine {
+    content
+        .into_iter()
+        .map(|(style_id, text)| (defined_styles.get(&style_id).cloned(), text))
+        .collect()
+}
+
+// Tracks the state of the external command line. Levels are numbered from 1 by neovim, and a
+// level greater than 1 is a command line opened from within another one such as with <C-r>=
+pub struct CommandLine {
+    contents: CommandLineContents,
+    draw_command_batcher: Arc<DrawCommandBatcher>,
+}
+
+impl CommandLine {
+    pub fn new(draw_command_batcher: Arc<DrawCommandBatcher>) -> CommandLine {
+        CommandLine {
+            contents: CommandLineContents::default(),
+            draw_command_batcher,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn show(
+        &mut self,
+        content: StyledContent,
+        position: u64,
+        first_character: String,
+        prompt: String,
+        indent: u64,
+        level: u64,
+        defined_styles: &HashMap<u64, Arc<Style>>,
+    ) {
+        self.contents
+            .levels
+            .truncate(level.saturating_sub(1) as usize);
+        self.contents.levels.push(CommandLineLevel {
+            content: resolve_styles(content, defined_styles),
+            position,
+            first_character,
+            prompt,
+            indent,
+            special_character: None,
+        });
+        self.send();
+    }
+
+    pub fn set_position(&mut self, position: u64, level: u64) {
+        if let Some(command_line_level) = self.level_mut(level) {
+            command_line_level.position = position;
+            self.send();
+        }
+    }
+
+    pub fn set_special_character(&mut self, character: String, shift: bool, level: u64) {
+        if let Some(command_line_level) = self.level_mut(level) {
+            command_line_level.special_character = Some((character, shift));
+            self.send();
+        }
+    }
+
+    pub fn hide(&mut self) {
+        self.contents.levels.pop();
+        self.send();
+    }
+
+    pub fn show_block(
+        &mut self,
+        lines: Vec<StyledContent>,
+        defined_styles: &HashMap<u64, Arc<Style>>,
+    ) {
+        self.contents.block = lines
+            .into_iter()
+            .map(|line| resolve_styles(line, defined_styles))
+            .collect();
+        self.send();
+    }
+
+    pub fn append_block(&mut self, line: StyledContent, defined_styles: &HashMap<u64, Arc<Style>>) {
+        self.contents
+            .block
+            .push(resolve_styles(line, defined_styles));
+        self.send();
+    }
+
+    pub fn hide_block(&mut self) {
+        self.contents.block.clear();
+        self.send();
+    }
+
+    fn level_mut(&mut self, level: u64) -> Option<&mut CommandLineLevel> {
+        let index = level.checked_sub(1)? as usize;
+        self.contents.levels.get_mut(index)
+    }
+
+    fn send(&self) {
+        self.draw_command_batcher
+            .queue(DrawCommand::CommandLine(self.contents.clone()))
+            .ok();
+    }
+}
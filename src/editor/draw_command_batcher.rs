@@ -1,10 +1,15 @@
-use std::sync::mpsc::{channel, Receiver, SendError, Sender};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc::{channel, Receiver, SendError, Sender},
+};
 
 use crate::{editor::DrawCommand, event_aggregator::EVENT_AGGREGATOR};
 
+// Draw commands are held until neovim flushes, so the renderer never sees half of a redraw
 pub struct DrawCommandBatcher {
     window_draw_command_sender: Sender<DrawCommand>,
     window_draw_command_receiver: Receiver<DrawCommand>,
+    pending_count: AtomicUsize,
 }
 
 impl DrawCommandBatcher {
@@ -14,15 +19,31 @@ impl DrawCommandBatcher {
         DrawCommandBatcher {
             window_draw_command_sender: sender,
             window_draw_command_receiver: receiver,
+            pending_count: AtomicUsize::new(0),
         }
     }
 
     pub fn queue(&self, draw_command: DrawCommand) -> Result<(), SendError<DrawCommand>> {
-        self.window_draw_command_sender.send(draw_command)
+        self.window_draw_command_sender.send(draw_command)?;
+        self.pending_count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
     }
 
-    pub fn send_batch(&self) {
-        let batch: Vec<DrawCommand> = self.window_draw_command_receiver.try_iter().collect();
+    // How many draw commands are waiting for the next flush
+    pub fn pending(&self) -> usize {
+        self.pending_count.load(Ordering::Relaxed)
+    }
+
+    pub fn take_batch(&self) -> Vec<DrawCommand> {
+        self.pending_count.store(0, Ordering::Relaxed);
+        self.window_draw_command_receiver.try_iter().collect()
+    }
+
+    // Returns the number of draw commands sent
+    pub fn send_batch(&self) -> usize {
+        let batch = self.take_batch();
+        let draw_command_count = batch.len();
         EVENT_AGGREGATOR.send(batch);
+        draw_command_count
     }
 }
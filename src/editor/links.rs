@@ -0,0 +1,142 @@
+use std::process::Command;
+
+use log::{error, info};
+
+use crate::{editor::grid::GridCell, settings::*};
+
+// The text a link starts with. Anything else under the pointer is left to neovim.
+const LINK_SCHEMES: &[&str] = &["https://", "http://", "file://", "ftp://", "mailto:"];
+
+// Punctuation which ends a sentence rather than the link when it is the last character
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?'];
+
+#[derive(Clone, SettingGroup)]
+pub struct LinkSettings {
+    // Ctrl+click opens the link under the pointer, a click anywhere else still reaches neovim
+    pub open_links: bool,
+    // Holding ctrl underlines the link under the pointer
+    pub underline_links: bool,
+}
+
+impl Default for LinkSettings {
+    fn default() -> Self {
+        Self {
+            open_links: true,
+            underline_links: true,
+        }
+    }
+}
+
+// The cells a link covers in one row of a grid
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkSpan {
+    pub grid_id: u64,
+    pub row: u64,
+    pub start: u64,
+    pub end: u64,
+}
+
+fn is_link_character(cell: &str) -> bool {
+    let mut characters = cell.chars();
+    match (characters.next(), characters.next()) {
+        (Some(character), None) => {
+            character.is_ascii_graphic() && !"<>\"'`()[]{}".contains(character)
+        }
+        _ => false,
+    }
+}
+
+// Finds the link covering the column in a row of cells, returning the columns it spans and the
+// link itself
+pub fn find_link(row: &[GridCell], column: u64) -> Option<(u64, u64, String)> {
+    let column = column as usize;
+    if !is_link_character(&row.get(column)?.0) {
+        return None;
+    }
+
+    let mut start = column;
+    while start > 0 && is_link_character(&row[start - 1].0) {
+        start -= 1;
+    }
+    let mut end = column + 1;
+    while end < row.len() && is_link_character(&row[end].0) {
+        end += 1;
+    }
+
+    let word: String = row[start..end]
+        .iter()
+        .map(|(text, _)| text.as_str())
+        .collect();
+    // The cells are single ascii characters, so byte offsets in the word are columns
+    let (scheme_start, scheme) = LINK_SCHEMES
+        .iter()
+        .filter_map(|scheme| word.find(scheme).map(|offset| (offset, scheme)))
+        .filter(|(offset, _)| start + offset <= column)
+        .min()?;
+    let link = word[scheme_start..].trim_end_matches(TRAILING_PUNCTUATION);
+    let link_start = start + scheme_start;
+    let link_end = link_start + link.len();
+
+    if column >= link_end || link.len() <= scheme.len() {
+        return None;
+    }
+    Some((link_start as u64, link_end as u64, link.to_owned()))
+}
+
+pub fn open_link(link: &str) {
+    info!("Opening {}", link);
+    let result = if cfg!(target_os = "windows") {
+        // The empty title keeps start from taking a quoted link as the window title
+        Command::new("cmd").args(&["/C", "start", "", link]).spawn()
+    } else if cfg!(target_os = "macos") {
+        Command::new("open").arg(link).spawn()
+    } else {
+        Command::new("xdg-open").arg(link).spawn()
+    };
+
+    if let Err(error) = result {
+        error!("Could not open {}: {}", link, error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(text: &str) -> Vec<GridCell> {
+        text.chars()
+            .map(|character| (character.to_string(), None))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_link() {
+        let line = row("see (https://neovide.dev/faq.html). or not");
+
+        let link = Some((5, 33, "https://neovide.dev/faq.html".to_owned()));
+        assert_eq!(find_link(&line, 5), link);
+        assert_eq!(find_link(&line, 20), link);
+        assert_eq!(find_link(&line, 32), link);
+        // The closing paren and the full stop aren't part of it
+        assert_eq!(find_link(&line, 33), None);
+        assert_eq!(find_link(&line, 34), None);
+        assert_eq!(find_link(&line, 1), None);
+        assert_eq!(find_link(&line, 100), None);
+    }
+
+    #[test]
+    fn test_find_link_needs_a_scheme() {
+        assert_eq!(find_link(&row("neovide.dev/faq"), 3), None);
+        assert_eq!(find_link(&row("https://"), 3), None);
+        assert_eq!(find_link(&row("mailto:"), 3), None);
+        assert_eq!(
+            find_link(&row("url=https://a.b"), 1),
+            None,
+            "The text before the scheme isn't part of the link"
+        );
+        assert_eq!(
+            find_link(&row("url=https://a.b"), 6),
+            Some((4, 15, "https://a.b".to_owned()))
+        );
+    }
+}
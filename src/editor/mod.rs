@@ -1,24 +1,38 @@
+mod command_line;
 mod cursor;
 mod draw_command_batcher;
 mod grid;
+mod links;
+mod screen_text;
 mod style;
 mod window;
 
-use std::{collections::HashMap, sync::Arc, thread};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
 use log::{error, trace};
 
 use crate::{
-    bridge::{GuiOption, RedrawEvent, WindowAnchor},
+    bridge::{
+        GuiOption, ParallelCommand, PopupMenuItem, RedrawEvent, TablineTab, UiCommand,
+        WindowAnchor, REDRAW_QUEUE,
+    },
     event_aggregator::EVENT_AGGREGATOR,
+    profiling::PROFILER,
     redraw_scheduler::REDRAW_SCHEDULER,
-    renderer::DrawCommand,
+    renderer::{DrawCommand, PopupMenuDrawCommand, PopupMenuStyles, TablineContents},
     window::WindowCommand,
 };
 
+use command_line::CommandLine;
 pub use cursor::{Cursor, CursorMode, CursorShape};
 pub use draw_command_batcher::DrawCommandBatcher;
 pub use grid::CharacterGrid;
+pub use links::{LinkSettings, LinkSpan};
 pub use style::{Colors, Style};
 pub use window::*;
 
@@ -50,161 +64,341 @@ impl WindowAnchor {
 
 #[derive(Clone, Debug)]
 pub enum EditorCommand {
-    NeovimRedrawEvent(RedrawEvent),
+    NeovimRedrawEvents(Vec<RedrawEvent>),
     RedrawScreen,
+    // A ctrl+click which opens the link in the cell, or is sent on to neovim as the fallback
+    // commands when there is none
+    OpenLink {
+        grid_id: u64,
+        column: u64,
+        row: u64,
+        fallback: Vec<UiCommand>,
+    },
+    // The cell under the pointer while ctrl is held, so that a link in it is underlined
+    HoverLink(Option<(u64, u64, u64)>),
+    // Puts the text on the screen on the clipboard, optionally with the highlights as escape
+    // sequences
+    CopyScreen {
+        highlights: bool,
+    },
 }
 
 pub struct Editor {
     pub windows: HashMap<u64, Window>,
     pub cursor: Cursor,
     pub defined_styles: HashMap<u64, Arc<Style>>,
+    // Kept so that a full redraw clears the windows with the current background
+    pub default_colors: Option<Colors>,
+    pub highlight_groups: HashMap<String, u64>,
     pub mode_list: Vec<CursorMode>,
+    pub command_line: CommandLine,
     pub draw_command_batcher: Arc<DrawCommandBatcher>,
+    // Time spent handling the events of the current redraw batch, tracked for the profiler
+    redraw_batch_duration: Duration,
+    // The cell under the pointer while ctrl is held and the link in it, which is looked for
+    // again after each redraw in case the text changed
+    hovered_cell: Option<(u64, u64, u64)>,
+    hovered_link: Option<LinkSpan>,
 }
 
 impl Editor {
     pub fn new() -> Editor {
+        let draw_command_batcher = Arc::new(DrawCommandBatcher::new());
+
         Editor {
             windows: HashMap::new(),
             cursor: Cursor::new(),
             defined_styles: HashMap::new(),
+            default_colors: None,
+            highlight_groups: HashMap::new(),
             mode_list: Vec::new(),
-            draw_command_batcher: Arc::new(DrawCommandBatcher::new()),
+            command_line: CommandLine::new(draw_command_batcher.clone()),
+            draw_command_batcher,
+            redraw_batch_duration: Duration::ZERO,
+            hovered_cell: None,
+            hovered_link: None,
         }
     }
 
     pub fn handle_editor_command(&mut self, command: EditorCommand) {
         match command {
-            EditorCommand::NeovimRedrawEvent(event) => match event {
-                RedrawEvent::SetTitle { title } => {
-                    EVENT_AGGREGATOR.send(WindowCommand::TitleChanged(title));
-                }
-                RedrawEvent::ModeInfoSet { cursor_modes } => self.mode_list = cursor_modes,
-                RedrawEvent::OptionSet { gui_option } => self.set_option(gui_option),
-                RedrawEvent::ModeChange { mode, mode_index } => {
-                    if let Some(cursor_mode) = self.mode_list.get(mode_index as usize) {
-                        self.cursor.change_mode(cursor_mode, &self.defined_styles);
-                    }
-                    self.draw_command_batcher
-                        .queue(DrawCommand::ModeChanged(mode))
-                        .ok();
+            EditorCommand::NeovimRedrawEvents(events) => {
+                for event in events {
+                    self.handle_redraw_event(event);
                 }
-                RedrawEvent::MouseOn => {
-                    EVENT_AGGREGATOR.send(WindowCommand::SetMouseEnabled(true));
+            }
+            EditorCommand::RedrawScreen => self.redraw_screen(),
+            EditorCommand::OpenLink {
+                grid_id,
+                column,
+                row,
+                fallback,
+            } => self.open_link(grid_id, column, row, fallback),
+            EditorCommand::HoverLink(cell) => self.hover_link(cell),
+            EditorCommand::CopyScreen { highlights } => self.copy_screen(highlights),
+        };
+    }
+
+    fn link_at(&self, grid_id: u64, column: u64, row: u64) -> Option<(u64, u64, String)> {
+        self.windows.get(&grid_id)?.link_at(column, row)
+    }
+
+    fn open_link(&self, grid_id: u64, column: u64, row: u64, fallback: Vec<UiCommand>) {
+        match self.link_at(grid_id, column, row) {
+            Some((_, _, link)) => links::open_link(&link),
+            // Plugins may map <C-LeftMouse> themselves
+            None => {
+                for command in fallback {
+                    EVENT_AGGREGATOR.send(command);
                 }
-                RedrawEvent::MouseOff => {
-                    EVENT_AGGREGATOR.send(WindowCommand::SetMouseEnabled(false));
+            }
+        }
+    }
+
+    fn hover_link(&mut self, cell: Option<(u64, u64, u64)>) {
+        self.hovered_cell = cell;
+        // Sent on its own, the batcher may hold the first half of a redraw
+        if let Some(draw_command) = self.update_hovered_link() {
+            EVENT_AGGREGATOR.send(vec![draw_command]);
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+    }
+
+    // Returns the draw command for the underline when the hovered link changed
+    fn update_hovered_link(&mut self) -> Option<DrawCommand> {
+        let hovered_link = self.hovered_cell.and_then(|(grid_id, column, row)| {
+            let (start, end, _) = self.link_at(grid_id, column, row)?;
+            Some(LinkSpan {
+                grid_id,
+                row,
+                start,
+                end,
+            })
+        });
+
+        if self.hovered_link == hovered_link {
+            return None;
+        }
+        self.hovered_link = hovered_link.clone();
+        Some(DrawCommand::HoverLink(hovered_link))
+    }
+
+    fn handle_redraw_event(&mut self, event: RedrawEvent) {
+        let handling_started = PROFILER.is_enabled().then(Instant::now);
+
+        match event {
+            RedrawEvent::SetTitle { title } => {
+                EVENT_AGGREGATOR.send(WindowCommand::TitleChanged(title));
+            }
+            RedrawEvent::SetIcon { icon } => {
+                EVENT_AGGREGATOR.send(WindowCommand::IconChanged(icon));
+            }
+            RedrawEvent::ModeInfoSet { cursor_modes } => self.mode_list = cursor_modes,
+            RedrawEvent::OptionSet { gui_option } => self.set_option(gui_option),
+            RedrawEvent::ModeChange { mode, mode_index } => {
+                if let Some(cursor_mode) = self.mode_list.get(mode_index as usize) {
+                    self.cursor.change_mode(cursor_mode, &self.defined_styles);
                 }
-                RedrawEvent::BusyStart => {
-                    trace!("Cursor off");
-                    self.cursor.enabled = false;
+                self.draw_command_batcher
+                    .queue(DrawCommand::ModeChanged(mode))
+                    .ok();
+            }
+            RedrawEvent::MouseOn => {
+                EVENT_AGGREGATOR.send(WindowCommand::SetMouseEnabled(true));
+            }
+            RedrawEvent::MouseOff => {
+                EVENT_AGGREGATOR.send(WindowCommand::SetMouseEnabled(false));
+            }
+            RedrawEvent::Suspend => EVENT_AGGREGATOR.send(WindowCommand::Suspend),
+            RedrawEvent::BusyStart => {
+                trace!("Cursor off");
+                self.cursor.enabled = false;
+            }
+            RedrawEvent::BusyStop => {
+                trace!("Cursor on");
+                self.cursor.enabled = true;
+            }
+            RedrawEvent::Flush => {
+                trace!("Image flushed");
+                for window in self.windows.values_mut() {
+                    window.flush();
                 }
-                RedrawEvent::BusyStop => {
-                    trace!("Cursor on");
-                    self.cursor.enabled = true;
+                self.send_cursor_info();
+                if let Some(draw_command) = self.update_hovered_link() {
+                    self.draw_command_batcher.queue(draw_command).ok();
                 }
-                RedrawEvent::Flush => {
-                    trace!("Image flushed");
-                    self.send_cursor_info();
-                    self.draw_command_batcher.send_batch();
-                    REDRAW_SCHEDULER.queue_next_frame();
+                let draw_command_count = self.draw_command_batcher.send_batch();
+                PROFILER.record_redraw_batch(self.redraw_batch_duration, draw_command_count);
+                self.redraw_batch_duration = Duration::ZERO;
+                REDRAW_SCHEDULER.force_next_frame();
+            }
+            RedrawEvent::DefaultColorsSet { colors } => {
+                // The window surfaces are filled with the default background, so they are drawn
+                // again from scratch
+                if self.default_colors.as_ref() != Some(&colors) {
+                    self.default_colors = Some(colors);
+                    self.redraw_screen();
                 }
-                RedrawEvent::DefaultColorsSet { colors } => {
+            }
+            RedrawEvent::HighlightAttributesDefine { id, style } => {
+                self.defined_styles.insert(id, Arc::new(style));
+            }
+            RedrawEvent::HighlightGroupSet { name, id } => {
+                let is_tabline = name == "TabLine";
+                self.highlight_groups.insert(name, id);
+                if is_tabline {
+                    let style = self.highlight_group_style("TabLine");
                     self.draw_command_batcher
-                        .queue(DrawCommand::DefaultStyleChanged(Style::new(colors)))
+                        .queue(DrawCommand::TitlebarStyle(style))
                         .ok();
                 }
-                RedrawEvent::HighlightAttributesDefine { id, style } => {
-                    self.defined_styles.insert(id, Arc::new(style));
-                }
-                RedrawEvent::CursorGoto {
-                    grid,
-                    column: left,
-                    row: top,
-                } => self.set_cursor_position(grid, left, top),
-                RedrawEvent::Resize {
-                    grid,
-                    width,
-                    height,
-                } => {
-                    self.resize_window(grid, width, height);
-                }
-                RedrawEvent::GridLine {
-                    grid,
-                    row,
-                    column_start,
-                    cells,
-                } => {
-                    let defined_styles = &self.defined_styles;
-                    let window = self.windows.get_mut(&grid);
-                    if let Some(window) = window {
-                        window.draw_grid_line(row, column_start, cells, defined_styles);
-                    }
-                }
-                RedrawEvent::Clear { grid } => {
-                    let window = self.windows.get_mut(&grid);
-                    if let Some(window) = window {
-                        window.clear();
-                    }
+            }
+            RedrawEvent::PopupMenuShow {
+                items,
+                selected,
+                row,
+                column,
+                grid,
+            } => self.show_popup_menu(items, selected, row, column, grid),
+            RedrawEvent::PopupMenuSelect { selected } => {
+                self.draw_command_batcher
+                    .queue(DrawCommand::PopupMenu(PopupMenuDrawCommand::Select {
+                        selected,
+                    }))
+                    .ok();
+            }
+            RedrawEvent::PopupMenuHide => {
+                self.draw_command_batcher
+                    .queue(DrawCommand::PopupMenu(PopupMenuDrawCommand::Hide))
+                    .ok();
+            }
+            RedrawEvent::CursorGoto {
+                grid,
+                column: left,
+                row: top,
+            } => self.set_cursor_position(grid, left, top),
+            RedrawEvent::Resize {
+                grid,
+                width,
+                height,
+            } => {
+                self.resize_window(grid, width, height);
+            }
+            RedrawEvent::GridLine {
+                grid,
+                row,
+                column_start,
+                cells,
+            } => {
+                let defined_styles = &self.defined_styles;
+                let window = self.windows.get_mut(&grid);
+                if let Some(window) = window {
+                    window.draw_grid_line(row, column_start, cells, defined_styles);
                 }
-                RedrawEvent::Destroy { grid } => self.close_window(grid),
-                RedrawEvent::Scroll {
-                    grid,
-                    top,
-                    bottom,
-                    left,
-                    right,
-                    rows,
-                    columns,
-                } => {
-                    let window = self.windows.get_mut(&grid);
-                    if let Some(window) = window {
-                        window.scroll_region(top, bottom, left, right, rows, columns);
-                    }
+            }
+            RedrawEvent::Clear { grid } => {
+                let window = self.windows.get_mut(&grid);
+                if let Some(window) = window {
+                    window.clear();
                 }
-                RedrawEvent::WindowPosition {
-                    grid,
-                    start_row,
-                    start_column,
-                    width,
-                    height,
-                } => self.set_window_position(grid, start_column, start_row, width, height),
-                RedrawEvent::WindowFloatPosition {
-                    grid,
-                    anchor,
-                    anchor_grid,
-                    anchor_column: anchor_left,
-                    anchor_row: anchor_top,
-                    sort_order,
-                    ..
-                } => self.set_window_float_position(
-                    grid,
-                    anchor_grid,
-                    anchor,
-                    anchor_left,
-                    anchor_top,
-                    sort_order,
-                ),
-                RedrawEvent::WindowHide { grid } => {
-                    let window = self.windows.get(&grid);
-                    if let Some(window) = window {
-                        window.hide();
-                    }
+            }
+            RedrawEvent::Destroy { grid } => self.close_window(grid),
+            RedrawEvent::Scroll {
+                grid,
+                top,
+                bottom,
+                left,
+                right,
+                rows,
+                columns,
+            } => {
+                let window = self.windows.get_mut(&grid);
+                if let Some(window) = window {
+                    window.scroll_region(top, bottom, left, right, rows, columns);
                 }
-                RedrawEvent::WindowClose { grid } => self.close_window(grid),
-                RedrawEvent::MessageSetPosition { grid, row, .. } => {
-                    self.set_message_position(grid, row)
+            }
+            RedrawEvent::WindowPosition {
+                grid,
+                start_row,
+                start_column,
+                width,
+                height,
+            } => self.set_window_position(grid, start_column, start_row, width, height),
+            RedrawEvent::WindowFloatPosition {
+                grid,
+                anchor,
+                anchor_grid,
+                anchor_column: anchor_left,
+                anchor_row: anchor_top,
+                sort_order,
+                ..
+            } => self.set_window_float_position(
+                grid,
+                anchor_grid,
+                anchor,
+                anchor_left,
+                anchor_top,
+                sort_order,
+            ),
+            RedrawEvent::WindowHide { grid } => {
+                let window = self.windows.get_mut(&grid);
+                if let Some(window) = window {
+                    window.hide();
                 }
-                RedrawEvent::WindowViewport {
-                    grid,
-                    top_line,
-                    bottom_line,
-                    ..
-                } => self.send_updated_viewport(grid, top_line, bottom_line),
-                _ => {}
-            },
-            EditorCommand::RedrawScreen => self.redraw_screen(),
+            }
+            RedrawEvent::WindowClose { grid } => self.close_window(grid),
+            RedrawEvent::MessageSetPosition { grid, row, .. } => {
+                self.set_message_position(grid, row)
+            }
+            RedrawEvent::WindowViewport {
+                grid,
+                top_line,
+                bottom_line,
+                ..
+            } => self.send_updated_viewport(grid, top_line, bottom_line),
+            RedrawEvent::CommandLineShow {
+                content,
+                position,
+                first_character,
+                prompt,
+                indent,
+                level,
+            } => self.command_line.show(
+                content,
+                position,
+                first_character,
+                prompt,
+                indent,
+                level,
+                &self.defined_styles,
+            ),
+            RedrawEvent::CommandLinePosition { position, level } => {
+                self.command_line.set_position(position, level)
+            }
+            RedrawEvent::CommandLineSpecialCharacter {
+                character,
+                shift,
+                level,
+            } => self
+                .command_line
+                .set_special_character(character, shift, level),
+            RedrawEvent::CommandLineHide => self.command_line.hide(),
+            RedrawEvent::CommandLineBlockShow { lines } => {
+                self.command_line.show_block(lines, &self.defined_styles)
+            }
+            RedrawEvent::CommandLineBlockAppend { line } => {
+                self.command_line.append_block(line, &self.defined_styles)
+            }
+            RedrawEvent::CommandLineBlockHide => self.command_line.hide_block(),
+            RedrawEvent::TablineUpdate { current_tab, tabs } => {
+                self.update_tabline(current_tab, tabs)
+            }
+            _ => {}
         };
+
+        if let Some(handling_started) = handling_started {
+            self.redraw_batch_duration += handling_started.elapsed();
+        }
     }
 
     fn close_window(&mut self, grid: u64) {
@@ -399,19 +593,85 @@ impl Editor {
             .ok();
     }
 
+    fn highlight_group_style(&self, name: &str) -> Option<Arc<Style>> {
+        self.highlight_groups
+            .get(name)
+            .and_then(|id| self.defined_styles.get(id))
+            .cloned()
+    }
+
+    fn show_popup_menu(
+        &mut self,
+        items: Vec<PopupMenuItem>,
+        selected: Option<u64>,
+        row: u64,
+        column: u64,
+        grid: Option<u64>,
+    ) {
+        let anchor = match grid {
+            Some(grid) => {
+                let (grid_left, grid_top) = self.get_window_top_left(grid).unwrap_or((0.0, 0.0));
+                (grid_left + column as f64, grid_top + row as f64)
+            }
+            None => {
+                // Wildmenu completions from the external command line are anchored to the last
+                // row of the base grid where the command line is typed
+                let base_height = self
+                    .windows
+                    .get(&1)
+                    .map(|base| base.get_height())
+                    .unwrap_or(1);
+                (column as f64, base_height.saturating_sub(1) as f64)
+            }
+        };
+
+        let styles = PopupMenuStyles {
+            normal: self.highlight_group_style("Pmenu"),
+            selected: self.highlight_group_style("PmenuSel"),
+            scrollbar: self.highlight_group_style("PmenuSbar"),
+            thumb: self.highlight_group_style("PmenuThumb"),
+        };
+
+        self.draw_command_batcher
+            .queue(DrawCommand::PopupMenu(PopupMenuDrawCommand::Show {
+                items,
+                selected,
+                anchor,
+                cmdline: grid.is_none(),
+                styles,
+            }))
+            .ok();
+    }
+
+    fn update_tabline(&mut self, current_tab: u64, tabs: Vec<TablineTab>) {
+        let contents = TablineContents {
+            current_tab,
+            tabs,
+            normal: self.highlight_group_style("TabLine"),
+            selected: self.highlight_group_style("TabLineSel"),
+            fill: self.highlight_group_style("TabLineFill"),
+        };
+        self.draw_command_batcher
+            .queue(DrawCommand::Tabline(contents))
+            .ok();
+    }
+
     fn set_option(&mut self, gui_option: GuiOption) {
         trace!("Option set {:?}", &gui_option);
-        if let GuiOption::GuiFont(guifont) = gui_option {
-            if guifont == *"*" {
-                EVENT_AGGREGATOR.send(WindowCommand::ListAvailableFonts);
+        let draw_command = match gui_option {
+            GuiOption::GuiFont(guifont) => {
+                if guifont == *"*" {
+                    EVENT_AGGREGATOR.send(WindowCommand::ListAvailableFonts);
+                }
+                DrawCommand::FontChanged(guifont)
             }
+            GuiOption::GuiFontWide(guifontwide) => DrawCommand::WideFontChanged(guifontwide),
+            GuiOption::LineSpace(linespace) => DrawCommand::LineSpaceChanged(linespace),
+            _ => return,
+        };
 
-            self.draw_command_batcher
-                .queue(DrawCommand::FontChanged(guifont))
-                .ok();
-
-            self.redraw_screen();
-        }
+        self.draw_command_batcher.queue(draw_command).ok();
+        self.redraw_screen();
     }
 
     fn send_updated_viewport(&mut self, grid: u64, top_line: f64, bottom_line: f64) {
@@ -422,8 +682,63 @@ impl Editor {
         }
     }
 
+    // The windows are layered like the renderer draws them, the ones which aren't floating by
+    // grid id and then the floating ones by their order
+    fn screen_text(&self, highlights: bool) -> String {
+        let (width, height) = match self.windows.get(&1) {
+            Some(root) => (root.get_width(), root.get_height()),
+            None => return String::new(),
+        };
+
+        let mut windows: Vec<(&u64, &Window)> = self
+            .windows
+            .iter()
+            .filter(|(_, window)| !window.is_hidden())
+            .collect();
+        windows.sort_by_key(|(grid_id, window)| {
+            (
+                window.anchor_info.as_ref().map(|anchor| anchor.sort_order),
+                **grid_id,
+            )
+        });
+        let layers: Vec<screen_text::ScreenLayer> = windows
+            .into_iter()
+            .map(|(_, window)| {
+                let (left, top) = window.get_grid_position();
+                screen_text::ScreenLayer {
+                    left: left.floor() as i64,
+                    top: top.floor() as i64,
+                    rows: (0..window.get_height())
+                        .filter_map(|row| window.row(row))
+                        .collect(),
+                }
+            })
+            .collect();
+        let screen = screen_text::compose(width, height, &layers);
+
+        match &self.default_colors {
+            Some(colors)
+                if highlights && colors.foreground.is_some() && colors.background.is_some() =>
+            {
+                screen_text::ansi_text(&screen, colors)
+            }
+            _ => screen_text::plain_text(&screen),
+        }
+    }
+
+    fn copy_screen(&self, highlights: bool) {
+        let text = self.screen_text(highlights);
+        EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::SetClipboard(text)));
+    }
+
     fn redraw_screen(&mut self) {
-        for window in self.windows.values() {
+        // The default style goes first so that the cleared windows use the current background
+        if let Some(colors) = &self.default_colors {
+            self.draw_command_batcher
+                .queue(DrawCommand::DefaultStyleChanged(Style::new(colors.clone())))
+                .ok();
+        }
+        for window in self.windows.values_mut() {
             window.redraw();
         }
     }
@@ -435,7 +750,237 @@ pub fn start_editor() {
 
         let mut editor_command_receiver = EVENT_AGGREGATOR.register_event::<EditorCommand>();
         while let Some(editor_command) = editor_command_receiver.blocking_recv() {
+            let is_redraw = matches!(editor_command, EditorCommand::NeovimRedrawEvents(_));
             editor.handle_editor_command(editor_command);
+            if is_redraw {
+                REDRAW_QUEUE.release();
+            }
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bridge::GridLineCell,
+        renderer::{GridRenderer, RendererSettings, WindowDrawCommand},
+        settings::SETTINGS,
+    };
+    use skia_safe::{Color4f, Surface};
+
+    fn colors(background: Color4f) -> Colors {
+        Colors::new(
+            Some(Color4f::new(1.0, 1.0, 1.0, 1.0)),
+            Some(background),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_default_colors_follow_each_batch() {
+        let mut editor = Editor::new();
+        let dark = colors(Color4f::new(0.0, 0.0, 0.0, 1.0));
+        let light = colors(Color4f::new(0.9, 0.9, 0.9, 1.0));
+
+        for expected in [&dark, &light, &dark] {
+            editor.handle_editor_command(EditorCommand::NeovimRedrawEvents(vec![
+                RedrawEvent::DefaultColorsSet {
+                    colors: expected.clone(),
+                },
+                RedrawEvent::Flush,
+            ]));
+
+            assert_eq!(editor.default_colors.as_ref(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_draw_commands_are_held_until_flush() {
+        let mut editor = Editor::new();
+        let grid_line = |row| RedrawEvent::GridLine {
+            grid: 1,
+            row,
+            column_start: 0,
+            cells: vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: None,
+                repeat: Some(10),
+            }],
+        };
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvents(vec![
+            RedrawEvent::Resize {
+                grid: 1,
+                width: 10,
+                height: 5,
+            },
+            grid_line(0),
+        ]));
+        let held = editor.draw_command_batcher.pending();
+        assert!(held > 0);
+
+        // A redraw may be split over several notifications, none of which is drawn on its own
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvents(vec![grid_line(1)]));
+        assert_eq!(editor.draw_command_batcher.pending(), held);
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvents(vec![RedrawEvent::Flush]));
+        assert_eq!(editor.draw_command_batcher.pending(), 0);
+    }
+
+    #[test]
+    fn test_dirty_rows_are_drawn_once() {
+        let mut editor = Editor::new();
+        let grid_line = |row, text: &str| RedrawEvent::GridLine {
+            grid: 1,
+            row,
+            column_start: 0,
+            cells: vec![GridLineCell {
+                text: text.to_owned(),
+                highlight_id: None,
+                repeat: None,
+            }],
+        };
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvents(vec![
+            RedrawEvent::Resize {
+                grid: 1,
+                width: 10,
+                height: 5,
+            },
+            RedrawEvent::Flush,
+        ]));
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvents(vec![
+            grid_line(0, "a"),
+            grid_line(0, "b"),
+            grid_line(1, "c"),
+        ]));
+        let window = editor.windows.get_mut(&1).unwrap();
+        window.flush();
+        assert_eq!(editor.draw_command_batcher.pending(), 2);
+        editor.draw_command_batcher.send_batch();
+
+        // The row goes out before the scroll, which moves it on the surface
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvents(vec![
+            grid_line(3, "d"),
+            RedrawEvent::Scroll {
+                grid: 1,
+                top: 0,
+                bottom: 5,
+                left: 0,
+                right: 10,
+                rows: 1,
+                columns: 0,
+            },
+        ]));
+        assert_eq!(editor.draw_command_batcher.pending(), 2);
+    }
+
+    // Shapes and draws the lines of a batch the way the window surfaces do, returning how many
+    // blobs that took
+    fn shaped_blobs(grid_renderer: &mut GridRenderer, batch: Vec<DrawCommand>) -> u64 {
+        let mut surface = Surface::new_raster_n32_premul((1200, 800)).unwrap();
+        let (hits, misses) = grid_renderer.shaper.cache_stats();
+        for command in batch {
+            if let DrawCommand::Window {
+                command: WindowDrawCommand::DrawLine(fragments),
+                ..
+            } = command
+            {
+                for fragment in fragments {
+                    grid_renderer.draw_foreground(
+                        surface.canvas(),
+                        fragment.text,
+                        (fragment.window_left, fragment.window_top),
+                        fragment.width,
+                        (0, 0),
+                        &fragment.style,
+                    );
+                }
+            }
+        }
+        let (new_hits, new_misses) = grid_renderer.shaper.cache_stats();
+        new_hits + new_misses - hits - misses
+    }
+
+    #[test]
+    fn bench_full_screen_scroll_shapes_only_exposed_lines() {
+        SETTINGS.set(&RendererSettings::default());
+        let mut grid_renderer = GridRenderer::new(1.0);
+        let mut editor = Editor::new();
+        let (width, height) = (120, 40);
+        let grid_line = |row: u64, text: String| RedrawEvent::GridLine {
+            grid: 1,
+            row,
+            column_start: 0,
+            cells: vec![GridLineCell {
+                text,
+                highlight_id: None,
+                repeat: None,
+            }],
+        };
+
+        let mut events = vec![RedrawEvent::Resize {
+            grid: 1,
+            width,
+            height,
+        }];
+        events.extend((0..height).map(|row| grid_line(row, format!("line {}", row))));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvents(events));
+        editor.windows.get_mut(&1).unwrap().flush();
+        let full_repaint =
+            shaped_blobs(&mut grid_renderer, editor.draw_command_batcher.take_batch());
+        assert_eq!(full_repaint, height);
+
+        // Scrolling a page down a few lines at a time, the way neovim sends it
+        let (step, steps) = (3, 20);
+        let mut scrolled = 0;
+        for index in 0..steps {
+            let mut events = vec![RedrawEvent::Scroll {
+                grid: 1,
+                top: 0,
+                bottom: height,
+                left: 0,
+                right: width,
+                rows: step as i64,
+                columns: 0,
+            }];
+            events.extend(
+                (height - step..height)
+                    .map(|row| grid_line(row, format!("line {}", height + index * step + row))),
+            );
+            editor.handle_editor_command(EditorCommand::NeovimRedrawEvents(events));
+            editor.windows.get_mut(&1).unwrap().flush();
+            scrolled += shaped_blobs(&mut grid_renderer, editor.draw_command_batcher.take_batch());
+        }
+        assert_eq!(scrolled, step * steps);
+    }
+
+    #[test]
+    fn test_large_redraw_batch_is_handled_quickly() {
+        let mut editor = Editor::new();
+        let mut events = vec![RedrawEvent::Resize {
+            grid: 1,
+            width: 100,
+            height: 50,
+        }];
+        events.extend((0..5000).map(|index| RedrawEvent::GridLine {
+            grid: 1,
+            row: index % 50,
+            column_start: 0,
+            cells: vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: None,
+                repeat: Some(100),
+            }],
+        }));
+        events.push(RedrawEvent::Flush);
+
+        let handling_started = Instant::now();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvents(events));
+
+        // Generous enough for debug builds on slow machines, but catches accidentally quadratic
+        // handling of a batch
+        assert!(handling_started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,202 @@
+use skia_safe::Color4f;
+
+use crate::editor::{grid::GridCell, style::Colors};
+
+// A grid placed on the screen. Layers are composed in the order they are given, so that later
+// ones cover what is under them.
+pub struct ScreenLayer<'a> {
+    pub left: i64,
+    pub top: i64,
+    pub rows: Vec<&'a [GridCell]>,
+}
+
+// Lays the grids out into one screen sized grid of cells, clipping what lies outside of it
+pub fn compose(width: u64, height: u64, layers: &[ScreenLayer]) -> Vec<Vec<GridCell>> {
+    let mut screen = vec![vec![(" ".to_owned(), None); width as usize]; height as usize];
+    for layer in layers {
+        for (row_offset, row) in layer.rows.iter().enumerate() {
+            let screen_row = layer.top + row_offset as i64;
+            if !(0..height as i64).contains(&screen_row) {
+                continue;
+            }
+            for (column_offset, cell) in row.iter().enumerate() {
+                let screen_column = layer.left + column_offset as i64;
+                if (0..width as i64).contains(&screen_column) {
+                    screen[screen_row as usize][screen_column as usize] = cell.clone();
+                }
+            }
+        }
+    }
+    screen
+}
+
+// The text of each row with the trailing whitespace trimmed. The cell following a double width
+// character is empty, so joining the cells gives the characters as they were typed.
+pub fn plain_text(screen: &[Vec<GridCell>]) -> String {
+    screen
+        .iter()
+        .map(|row| {
+            let text: String = row.iter().map(|(text, _)| text.as_str()).collect();
+            text.trim_end().to_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn color_code(color: Color4f) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!(
+        "{};{};{}",
+        channel(color.r),
+        channel(color.g),
+        channel(color.b)
+    )
+}
+
+// The select graphic rendition sequence for a cell, starting from a reset so that nothing from
+// the previous cell carries over
+fn style_sequence(cell: &GridCell, default_colors: &Colors) -> String {
+    let mut codes = vec!["0".to_owned()];
+    if let Some(style) = &cell.1 {
+        if style.bold {
+            codes.push("1".to_owned());
+        }
+        if style.italic {
+            codes.push("3".to_owned());
+        }
+        if style.underline || style.undercurl {
+            codes.push("4".to_owned());
+        }
+        if style.strikethrough {
+            codes.push("9".to_owned());
+        }
+        codes.push(format!(
+            "38;2;{}",
+            color_code(style.foreground(default_colors))
+        ));
+        codes.push(format!(
+            "48;2;{}",
+            color_code(style.background(default_colors))
+        ));
+    }
+    format!("\x1b[{}m", codes.join(";"))
+}
+
+// Like plain_text, but with escape sequences which give each cell the colors and attributes of
+// its highlight, for pasting into a terminal. Trailing whitespace is only trimmed where it has no
+// background of its own. Needs both default colors, since unset colors of a highlight use them.
+pub fn ansi_text(screen: &[Vec<GridCell>], default_colors: &Colors) -> String {
+    screen
+        .iter()
+        .map(|row| {
+            let visible_length = row
+                .iter()
+                .rposition(|(text, style)| {
+                    !text.trim().is_empty()
+                        || style
+                            .as_ref()
+                            .map_or(false, |style| style.colors.background.is_some())
+                })
+                .map_or(0, |last| last + 1);
+
+            let mut text = String::new();
+            let mut previous_sequence = style_sequence(&(String::new(), None), default_colors);
+            for cell in &row[..visible_length] {
+                let sequence = style_sequence(cell, default_colors);
+                if sequence != previous_sequence {
+                    text.push_str(&sequence);
+                    previous_sequence = sequence;
+                }
+                text.push_str(&cell.0);
+            }
+            if row[..visible_length]
+                .iter()
+                .any(|(_, style)| style.is_some())
+            {
+                text.push_str("\x1b[0m");
+            }
+            text
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::editor::style::Style;
+
+    fn row(text: &str) -> Vec<GridCell> {
+        text.chars()
+            .map(|character| (character.to_string(), None))
+            .collect()
+    }
+
+    #[test]
+    fn test_floating_layers_cover_the_text_under_them() {
+        let base = vec![row("hello world  "), row("second line  ")];
+        let float = vec![row("[*]")];
+        let layers = [
+            ScreenLayer {
+                left: 0,
+                top: 0,
+                rows: base.iter().map(Vec::as_slice).collect(),
+            },
+            ScreenLayer {
+                left: 10,
+                top: 1,
+                rows: float.iter().map(Vec::as_slice).collect(),
+            },
+            // Off screen parts are clipped
+            ScreenLayer {
+                left: -1,
+                top: -1,
+                rows: vec![&float[0], &float[0]],
+            },
+        ];
+
+        let screen = compose(13, 2, &layers);
+        assert_eq!(plain_text(&screen), "*]llo world\nsecond lin[*]");
+    }
+
+    #[test]
+    fn test_double_width_characters_are_joined() {
+        let cells: Vec<GridCell> = vec![
+            ("日".to_owned(), None),
+            ("".to_owned(), None),
+            ("本".to_owned(), None),
+            ("".to_owned(), None),
+            (" ".to_owned(), None),
+        ];
+        assert_eq!(plain_text(&[cells]), "日本");
+    }
+
+    #[test]
+    fn test_ansi_text_uses_the_highlights() {
+        let default_colors = Colors {
+            foreground: Some(Color4f::new(1.0, 1.0, 1.0, 1.0)),
+            background: Some(Color4f::new(0.0, 0.0, 0.0, 1.0)),
+            special: None,
+        };
+        let mut style = Style::new(Colors {
+            foreground: Some(Color4f::new(1.0, 0.0, 0.0, 1.0)),
+            background: None,
+            special: None,
+        });
+        style.bold = true;
+        let style = Some(Arc::new(style));
+
+        let cells: Vec<GridCell> = vec![
+            ("a".to_owned(), None),
+            ("b".to_owned(), style.clone()),
+            ("c".to_owned(), style),
+            (" ".to_owned(), None),
+        ];
+        assert_eq!(
+            ansi_text(&[cells, row("plain  ")], &default_colors),
+            "a\x1b[0;1;38;2;255;0;0;48;2;0;0;0mbc\x1b[0m\nplain"
+        );
+    }
+}
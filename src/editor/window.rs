@@ -1,11 +1,20 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
 
 use log::warn;
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
     bridge::GridLineCell,
-    editor::{grid::CharacterGrid, style::Style, AnchorInfo, DrawCommand, DrawCommandBatcher},
+    dimensions::Dimensions,
+    editor::{
+        grid::{CharacterGrid, GridCell},
+        links::find_link,
+        style::Style,
+        AnchorInfo, DrawCommand, DrawCommandBatcher,
+    },
     renderer::{LineFragment, WindowDrawCommand},
 };
 
@@ -21,6 +30,10 @@ pub struct Window {
 
     pub anchor_info: Option<AnchorInfo>,
     grid_position: (f64, f64),
+    hidden: bool,
+    // Rows changed since the last flush. Each one is drawn once when neovim flushes, however many
+    // grid_line events touched it.
+    dirty_rows: BTreeSet<u64>,
 
     draw_command_batcher: Arc<DrawCommandBatcher>,
 }
@@ -36,10 +49,12 @@ impl Window {
     ) -> Window {
         let window = Window {
             grid_id,
-            grid: CharacterGrid::new(grid_size),
+            grid: CharacterGrid::new(Dimensions::from(grid_size).limited().into()),
             window_type,
             anchor_info,
             grid_position,
+            hidden: false,
+            dirty_rows: BTreeSet::new(),
             draw_command_batcher,
         };
         window.send_updated_position();
@@ -77,6 +92,19 @@ impl Window {
         (character, double_width)
     }
 
+    // The link covering the cell, with the columns it spans
+    pub fn link_at(&self, column: u64, row: u64) -> Option<(u64, u64, String)> {
+        find_link(self.grid.row(row)?, column)
+    }
+
+    pub fn row(&self, row: u64) -> Option<&[GridCell]> {
+        self.grid.row(row)
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
     pub fn get_width(&self) -> u64 {
         self.grid.width
     }
@@ -95,7 +123,8 @@ impl Window {
         grid_size: (u64, u64),
         grid_position: (f64, f64),
     ) {
-        self.grid.resize(grid_size);
+        self.grid
+            .resize(Dimensions::from(grid_size).limited().into());
         self.anchor_info = anchor_info;
         self.grid_position = grid_position;
         self.send_updated_position();
@@ -103,7 +132,8 @@ impl Window {
     }
 
     pub fn resize(&mut self, new_size: (u64, u64)) {
-        self.grid.resize(new_size);
+        self.grid
+            .resize(Dimensions::from(new_size).limited().into());
         self.send_updated_position();
         self.redraw();
     }
@@ -221,7 +251,7 @@ impl Window {
                 );
             }
 
-            self.redraw_line(row);
+            self.dirty_rows.insert(row);
         } else {
             warn!("Draw command out of bounds");
         }
@@ -236,6 +266,10 @@ impl Window {
         rows: i64,
         cols: i64,
     ) {
+        // The scroll moves what is on the surface already, so the changed rows have to be there
+        // first
+        self.flush();
+
         let mut top_to_bottom;
         let mut bottom_to_top;
         let y_iter: &mut dyn Iterator<Item = i64> = if rows > 0 {
@@ -288,21 +322,32 @@ impl Window {
 
     pub fn clear(&mut self) {
         self.grid.clear();
+        self.dirty_rows.clear();
         self.send_command(WindowDrawCommand::Clear);
     }
 
-    pub fn redraw(&self) {
+    // Invalidates the whole window, used when the size, the font or the default colors change
+    pub fn redraw(&mut self) {
         self.send_command(WindowDrawCommand::Clear);
-        for row in 0..self.grid.height {
-            self.redraw_line(row);
+        self.dirty_rows.extend(0..self.grid.height);
+    }
+
+    // Draws the rows changed since the last flush
+    pub fn flush(&mut self) {
+        for row in std::mem::take(&mut self.dirty_rows) {
+            if row < self.grid.height {
+                self.redraw_line(row);
+            }
         }
     }
 
-    pub fn hide(&self) {
+    pub fn hide(&mut self) {
+        self.hidden = true;
         self.send_command(WindowDrawCommand::Hide);
     }
 
-    pub fn show(&self) {
+    pub fn show(&mut self) {
+        self.hidden = false;
         self.send_command(WindowDrawCommand::Show);
     }
 
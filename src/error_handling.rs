@@ -1,5 +1,18 @@
+use std::{backtrace::Backtrace, panic};
+
 use log::error;
 
+// Panics are written to the log along with a backtrace, so that a log file attached to a bug report
+// shows where it crashed. The default hook still runs afterwards to print it to stderr.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        error!("{}\n{}", panic_info, Backtrace::force_capture());
+        log::logger().flush();
+        default_hook(panic_info);
+    }));
+}
+
 fn show_error(explanation: &str) -> ! {
     error!("{}", explanation);
     panic!("{}", explanation.to_string());
@@ -4,6 +4,7 @@ use std::{
     fmt::Debug,
 };
 
+use log::trace;
 use parking_lot::{Mutex, RwLock};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
 
@@ -50,9 +51,12 @@ impl EventAggregator {
         }
     }
 
+    // Events sent after their receiver stopped during shutdown are dropped
     pub fn send<T: Any + Clone + Debug + Send>(&self, event: T) {
         let sender = self.get_sender::<T>();
-        sender.send(event).unwrap();
+        if let Err(error) = sender.send(event) {
+            trace!("Dropped {:?}, its receiver has stopped", error.0);
+        }
     }
 
     pub fn register_event<T: Any + Clone + Debug + Send>(&self) -> UnboundedReceiver<T> {
@@ -0,0 +1,129 @@
+use std::{env, fs, io, os::unix::fs::PermissionsExt, path::PathBuf, process::Command};
+
+use log::error;
+
+const DESKTOP_ENTRY: &str = "applications/neovide.desktop";
+const DOLPHIN_SERVICE_MENU: &str = "kio/servicemenus/neovide.desktop";
+const NAUTILUS_SCRIPT: &str = "nautilus/scripts/Open with Neovide";
+
+const MIME_TYPES: &str = "text/english;text/plain;text/x-makefile;text/x-c++hdr;text/x-c++src;text/x-chdr;text/x-csrc;text/x-java;text/x-moc;text/x-pascal;text/x-tcl;text/x-tex;application/x-shellscript;text/x-c;text/x-c++;";
+
+fn get_binary_path() -> String {
+    env::current_exe()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| "neovide".to_owned())
+}
+
+fn data_file_path(relative_path: &str) -> io::Result<PathBuf> {
+    xdg::BaseDirectories::new()
+        .map_err(|error| io::Error::new(io::ErrorKind::NotFound, error))?
+        .place_data_file(relative_path)
+}
+
+fn write_data_file(relative_path: &str, contents: &str, executable: bool) -> bool {
+    let result = data_file_path(relative_path).and_then(|path| {
+        fs::write(&path, contents)?;
+        if executable {
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    });
+    if let Err(error) = &result {
+        error!("Could not write {}: {}", relative_path, error);
+    }
+    result.is_ok()
+}
+
+fn remove_data_file(relative_path: &str) -> bool {
+    data_file_path(relative_path)
+        .and_then(fs::remove_file)
+        .is_ok()
+}
+
+fn desktop_entry(binary_path: &str) -> String {
+    format!(
+        "[Desktop Entry]
+Type=Application
+Exec={} %F
+Icon=neovide
+Name=Neovide (nvim)
+Keywords=Text;Editor;
+Categories=Utility;TextEditor;
+Comment=No Nonsense Neovim Client in Rust
+MimeType={}
+",
+        binary_path, MIME_TYPES
+    )
+}
+
+fn dolphin_service_menu(binary_path: &str) -> String {
+    format!(
+        "[Desktop Entry]
+Type=Service
+MimeType=all/allfiles;inode/directory;
+Actions=openWithNeovide;
+
+[Desktop Action openWithNeovide]
+Name=Open with Neovide
+Icon=neovide
+Exec={} %F
+",
+        binary_path
+    )
+}
+
+fn nautilus_script(binary_path: &str) -> String {
+    // Nautilus runs scripts from the current folder with the selected files as arguments, so
+    // opening the background of a folder opens that folder
+    format!("#!/bin/sh\nexec \"{}\" \"$@\"\n", binary_path)
+}
+
+// Lets file managers pick up the new or removed mime types right away. Not every desktop ships
+// the tool, in which case the cache is refreshed at the next login instead.
+fn refresh_desktop_database() {
+    if let Ok(applications) = data_file_path("applications") {
+        Command::new("update-desktop-database")
+            .arg(applications)
+            .status()
+            .ok();
+    }
+}
+
+pub fn unregister_rightclick() -> bool {
+    let removed = [DESKTOP_ENTRY, DOLPHIN_SERVICE_MENU, NAUTILUS_SCRIPT]
+        .iter()
+        .map(|relative_path| remove_data_file(relative_path))
+        .fold(true, |removed_all, removed| removed_all && removed);
+    refresh_desktop_database();
+    removed
+}
+
+pub fn register_rightclick_directory() -> bool {
+    let binary_path = get_binary_path();
+    let dolphin = write_data_file(
+        DOLPHIN_SERVICE_MENU,
+        &dolphin_service_menu(&binary_path),
+        true,
+    );
+    let nautilus = write_data_file(NAUTILUS_SCRIPT, &nautilus_script(&binary_path), true);
+    dolphin && nautilus
+}
+
+pub fn register_rightclick_file() -> bool {
+    let registered = write_data_file(DESKTOP_ENTRY, &desktop_entry(&get_binary_path()), false);
+    refresh_desktop_database();
+    registered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_desktop_entry_opens_files() {
+        let entry = desktop_entry("/usr/bin/neovide");
+
+        assert!(entry.contains("\nExec=/usr/bin/neovide %F\n"));
+        assert!(entry.contains(&format!("\nMimeType={}\n", MIME_TYPES)));
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::error;
+
+const PLIST_BUDDY: &str = "/usr/libexec/PlistBuddy";
+const LSREGISTER: &str = "/System/Library/Frameworks/CoreServices.framework/Frameworks/LaunchServices.framework/Support/lsregister";
+
+const TEXT_CONTENT_TYPES: &[&str] = &[
+    "public.text",
+    "public.plain-text",
+    "public.source-code",
+    "public.script",
+    "public.shell-script",
+    "public.data",
+];
+const DIRECTORY_CONTENT_TYPES: &[&str] = &["public.folder"];
+
+// Open With only lists apps whose bundle declares the document types they handle, so the
+// bundle neovide is running from is the one that gets registered
+fn get_bundle_path() -> Option<PathBuf> {
+    let binary_path = env::current_exe().ok()?;
+    let bundle_path = binary_path.ancestors().find(|path| {
+        path.extension()
+            .map_or(false, |extension| extension == "app")
+    })?;
+    Some(bundle_path.to_owned())
+}
+
+fn run_plist_buddy(info_plist: &Path, commands: &[String]) -> bool {
+    let mut command = Command::new(PLIST_BUDDY);
+    for plist_command in commands {
+        command.arg("-c").arg(plist_command);
+    }
+    command
+        .arg(info_plist)
+        .output()
+        .map_or(false, |output| output.status.success())
+}
+
+fn document_type_commands(name: &str, content_types: &[&str]) -> Vec<String> {
+    let entry = ":CFBundleDocumentTypes:0";
+    let mut commands = vec![
+        format!("Add {} dict", entry),
+        format!("Add {}:CFBundleTypeName string {}", entry, name),
+        format!("Add {}:CFBundleTypeRole string Editor", entry),
+        format!("Add {}:LSHandlerRank string Alternate", entry),
+        format!("Add {}:LSItemContentTypes array", entry),
+    ];
+    commands.extend(
+        content_types.iter().map(|content_type| {
+            format!("Add {}:LSItemContentTypes:0 string {}", entry, content_type)
+        }),
+    );
+    commands
+}
+
+fn refresh_launch_services(bundle_path: &Path) {
+    Command::new(LSREGISTER)
+        .arg("-f")
+        .arg(bundle_path)
+        .status()
+        .ok();
+}
+
+fn register_document_type(name: &str, content_types: &[&str]) -> bool {
+    let bundle_path = match get_bundle_path() {
+        Some(bundle_path) => bundle_path,
+        None => {
+            error!("Neovide is not running from an app bundle");
+            return false;
+        }
+    };
+    let info_plist = bundle_path.join("Contents/Info.plist");
+
+    // Adding the array fails harmlessly when another document type already created it
+    run_plist_buddy(
+        &info_plist,
+        &["Add :CFBundleDocumentTypes array".to_owned()],
+    );
+    let registered = run_plist_buddy(&info_plist, &document_type_commands(name, content_types));
+    refresh_launch_services(&bundle_path);
+    registered
+}
+
+pub fn unregister_rightclick() -> bool {
+    let bundle_path = match get_bundle_path() {
+        Some(bundle_path) => bundle_path,
+        None => return false,
+    };
+    let info_plist = bundle_path.join("Contents/Info.plist");
+
+    let removed = run_plist_buddy(&info_plist, &["Delete :CFBundleDocumentTypes".to_owned()]);
+    refresh_launch_services(&bundle_path);
+    removed
+}
+
+pub fn register_rightclick_directory() -> bool {
+    register_document_type("Folder", DIRECTORY_CONTENT_TYPES)
+}
+
+pub fn register_rightclick_file() -> bool {
+    register_document_type("Text", TEXT_CONTENT_TYPES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_type_commands() {
+        let commands = document_type_commands("Folder", &["public.folder"]);
+
+        assert_eq!(
+            commands.last().unwrap(),
+            "Add :CFBundleDocumentTypes:0:LSItemContentTypes:0 string public.folder"
+        );
+        assert!(commands
+            .contains(&"Add :CFBundleDocumentTypes:0:CFBundleTypeRole string Editor".to_owned()));
+    }
+}
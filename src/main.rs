@@ -16,6 +16,11 @@ mod dimensions;
 mod editor;
 mod error_handling;
 mod event_aggregator;
+#[cfg(target_os = "linux")]
+mod linux_utils;
+#[cfg(target_os = "macos")]
+mod macos_utils;
+mod profiling;
 mod redraw_scheduler;
 mod renderer;
 mod running_tracker;
@@ -31,15 +36,23 @@ extern crate lazy_static;
 use std::env::args;
 
 #[cfg(not(test))]
-use flexi_logger::{Cleanup, Criterion, Duplicate, Logger, Naming};
-use log::trace;
+use flexi_logger::{detailed_format, Duplicate, Logger};
+use log::info;
 
-use bridge::start_bridge;
+use bridge::{start_bridge, SessionSettings, UiCommandSettings, WatchdogSettings};
 use cmd_line::CmdLineSettings;
-use editor::start_editor;
-use renderer::{cursor_renderer::CursorSettings, RendererSettings};
+use dimensions::GridSizeSettings;
+use editor::{start_editor, LinkSettings};
+use error_handling::install_panic_hook;
+use renderer::{
+    cursor_renderer::CursorSettings, CommandLineSettings, FontSettings, PopupMenuSettings,
+    RendererSettings, TablineSettings, TextRenderingSettings, TitlebarSettings,
+};
 use settings::SETTINGS;
-use window::{create_window, KeyboardSettings, WindowSettings};
+use window::{
+    create_window, AccessibilitySettings, KeyRepeatSettings, KeyboardSettings, MouseSettings,
+    TouchSettings, WindowSettings,
+};
 
 pub use channel_utils::*;
 pub use event_aggregator::*;
@@ -123,8 +136,17 @@ fn main() {
 
     #[cfg(not(test))]
     init_logger();
+    install_panic_hook();
+
+    register_settings();
+    if SETTINGS.get::<CmdLineSettings>().list_settings {
+        for name in SETTINGS.all_registered_names() {
+            println!("g:neovide_{}", name);
+        }
+        return;
+    }
 
-    trace!("Neovide version: {}", crate_version!());
+    log_startup_banner();
 
     maybe_disown();
 
@@ -134,37 +156,106 @@ fn main() {
     #[cfg(target_os = "macos")]
     handle_macos();
 
-    WindowSettings::register();
-    RendererSettings::register();
-    CursorSettings::register();
-    KeyboardSettings::register();
-
     // We need to keep the bridge reference around to prevent the tokio runtime from getting freed
     let _bridge = start_bridge();
     start_editor();
     create_window();
 }
 
+fn register_settings() {
+    WindowSettings::register();
+    RendererSettings::register();
+    CursorSettings::register();
+    PopupMenuSettings::register();
+    CommandLineSettings::register();
+    KeyboardSettings::register();
+    KeyRepeatSettings::register();
+    MouseSettings::register();
+    TouchSettings::register();
+    AccessibilitySettings::register();
+    UiCommandSettings::register();
+    WatchdogSettings::register();
+    SessionSettings::register();
+    LinkSettings::register();
+    FontSettings::register();
+    TextRenderingSettings::register();
+    TablineSettings::register();
+    GridSizeSettings::register();
+    TitlebarSettings::register();
+}
+
 #[cfg(not(test))]
 pub fn init_logger() {
+    use std::path::Path;
+
     let settings = SETTINGS.get::<CmdLineSettings>();
 
+    let default_spec = if settings.log_to_file {
+        "neovide"
+    } else {
+        "neovide = error"
+    };
+    let logger = match &settings.log_level {
+        Some(level) => Logger::with_str(format!("neovide = {}", level)),
+        None => Logger::with_env_or_str(default_spec),
+    };
+
     let logger = if settings.log_to_file {
-        Logger::with_env_or_str("neovide")
+        let logger = logger
             .log_to_file()
-            .rotate(
-                Criterion::Size(10_000_000),
-                Naming::Timestamps,
-                Cleanup::KeepLogFiles(1),
-            )
-            .duplicate_to_stderr(Duplicate::Error)
+            .format_for_files(detailed_format)
+            .duplicate_to_stderr(Duplicate::Error);
+        match &settings.log_path {
+            Some(log_path) => {
+                let log_path = Path::new(log_path);
+                let logger = logger
+                    .suppress_timestamp()
+                    .append()
+                    .basename(
+                        log_path
+                            .file_stem()
+                            .map(|stem| stem.to_string_lossy().into_owned())
+                            .unwrap_or_else(|| "neovide".to_owned()),
+                    )
+                    .suffix(
+                        log_path
+                            .extension()
+                            .map(|extension| extension.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                    );
+                match log_path
+                    .parent()
+                    .filter(|parent| !parent.as_os_str().is_empty())
+                {
+                    Some(directory) => logger.directory(directory),
+                    None => logger,
+                }
+            }
+            // Named after the start time, for example neovide_r2021-06-01_12-30-00.log
+            None => match dirs::cache_dir() {
+                Some(cache_dir) => logger.directory(cache_dir.join("neovide")),
+                None => logger,
+            },
+        }
     } else {
-        Logger::with_env_or_str("neovide = error")
+        logger
     };
 
     logger.start().expect("Could not start logger");
 }
 
+// The first lines of a log attached to a bug report. The neovim version is logged by the bridge
+// once it is connected and the render backend once the window is created.
+fn log_startup_banner() {
+    info!(
+        "Neovide {} on {} {}",
+        crate_version!(),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    info!("Arguments: {:?}", args().collect::<Vec<String>>());
+}
+
 fn maybe_disown() {
     use std::{env, process};
 
@@ -221,3 +312,45 @@ fn windows_attach_to_console() {
         AttachConsole(ATTACH_PARENT_PROCESS);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_setting_names_are_unique() {
+        let setting_names = [
+            WindowSettings::setting_names(),
+            RendererSettings::setting_names(),
+            CursorSettings::setting_names(),
+            PopupMenuSettings::setting_names(),
+            CommandLineSettings::setting_names(),
+            KeyboardSettings::setting_names(),
+            KeyRepeatSettings::setting_names(),
+            MouseSettings::setting_names(),
+            TouchSettings::setting_names(),
+            AccessibilitySettings::setting_names(),
+            UiCommandSettings::setting_names(),
+            WatchdogSettings::setting_names(),
+            SessionSettings::setting_names(),
+            LinkSettings::setting_names(),
+            FontSettings::setting_names(),
+            TextRenderingSettings::setting_names(),
+            TablineSettings::setting_names(),
+            GridSizeSettings::setting_names(),
+            TitlebarSettings::setting_names(),
+        ]
+        .concat();
+
+        let mut seen_names = HashSet::new();
+        for name in setting_names {
+            assert!(
+                seen_names.insert(name),
+                "g:neovide_{} is registered twice",
+                name
+            );
+        }
+    }
+}
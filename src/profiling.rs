@@ -0,0 +1,318 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use rmpv::Value;
+
+const SAMPLE_COUNT: usize = 120;
+// How often frame stats are reported to neovim
+pub const FRAME_STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    pub static ref PROFILER: Profiler = Profiler::new();
+}
+
+// A fixed size history of samples, dropping the oldest sample once full
+pub struct RingBuffer {
+    samples: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> RingBuffer {
+        RingBuffer {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn last(&self) -> Option<f32> {
+        self.samples.back().copied()
+    }
+
+    pub fn samples(&self) -> Vec<f32> {
+        self.samples.iter().copied().collect()
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct RedrawBatchTiming {
+    pub milliseconds: f32,
+    pub draw_commands: usize,
+}
+
+// Measurements shown by the profiler overlay. Nothing is recorded unless the overlay is enabled
+// so that the cost of profiling is a single atomic load otherwise.
+pub struct Profiler {
+    enabled: AtomicBool,
+    frame_times: Mutex<RingBuffer>,
+    ping_latencies: Mutex<RingBuffer>,
+    input_latencies: Mutex<RingBuffer>,
+    present_intervals: Mutex<RingBuffer>,
+    last_redraw_batch: Mutex<RedrawBatchTiming>,
+    shape_cache_stats: Mutex<(u64, u64)>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            enabled: AtomicBool::new(false),
+            frame_times: Mutex::new(RingBuffer::new(SAMPLE_COUNT)),
+            ping_latencies: Mutex::new(RingBuffer::new(SAMPLE_COUNT)),
+            input_latencies: Mutex::new(RingBuffer::new(SAMPLE_COUNT)),
+            present_intervals: Mutex::new(RingBuffer::new(SAMPLE_COUNT)),
+            last_redraw_batch: Mutex::new(RedrawBatchTiming::default()),
+            shape_cache_stats: Mutex::new((0, 0)),
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn record_frame_time(&self, dt: f32) {
+        if self.is_enabled() {
+            self.frame_times.lock().unwrap().push(dt * 1000.0);
+        }
+    }
+
+    pub fn record_redraw_batch(&self, duration: Duration, draw_commands: usize) {
+        if self.is_enabled() {
+            *self.last_redraw_batch.lock().unwrap() = RedrawBatchTiming {
+                milliseconds: duration.as_secs_f32() * 1000.0,
+                draw_commands,
+            };
+        }
+    }
+
+    pub fn record_ping(&self, latency: Duration) {
+        if self.is_enabled() {
+            self.ping_latencies
+                .lock()
+                .unwrap()
+                .push(latency.as_secs_f32() * 1000.0);
+        }
+    }
+
+    // The time from sending keys to neovim until the redraw answering them was presented, which
+    // is what typing latency measurements see minus the time the os took to deliver the keys
+    pub fn record_input_latency(&self, latency: Duration) {
+        if self.is_enabled() {
+            self.input_latencies
+                .lock()
+                .unwrap()
+                .push(latency.as_secs_f32() * 1000.0);
+        }
+    }
+
+    // The time between two frames reaching the screen, which shows whether frames are paced evenly
+    pub fn record_present_interval(&self, interval: Duration) {
+        if self.is_enabled() {
+            self.present_intervals
+                .lock()
+                .unwrap()
+                .push(interval.as_secs_f32() * 1000.0);
+        }
+    }
+
+    pub fn record_shape_cache_stats(&self, hits: u64, misses: u64) {
+        if self.is_enabled() {
+            *self.shape_cache_stats.lock().unwrap() = (hits, misses);
+        }
+    }
+
+    // Frame times in milliseconds, oldest first
+    pub fn frame_times(&self) -> Vec<f32> {
+        self.frame_times.lock().unwrap().samples()
+    }
+
+    pub fn last_redraw_batch(&self) -> RedrawBatchTiming {
+        *self.last_redraw_batch.lock().unwrap()
+    }
+
+    pub fn last_ping(&self) -> Option<f32> {
+        self.ping_latencies.lock().unwrap().last()
+    }
+
+    // The average over the recorded samples, single keystrokes vary too much with where in the
+    // frame they land
+    pub fn average_input_latency(&self) -> Option<f32> {
+        let samples = self.input_latencies.lock().unwrap().samples();
+        if samples.is_empty() {
+            None
+        } else {
+            Some(samples.iter().sum::<f32>() / samples.len() as f32)
+        }
+    }
+
+    pub fn last_present_interval(&self) -> Option<f32> {
+        self.present_intervals.lock().unwrap().last()
+    }
+
+    // The fraction of shaped text lookups served from the cache
+    pub fn shape_cache_hit_rate(&self) -> Option<f32> {
+        let (hits, misses) = *self.shape_cache_stats.lock().unwrap();
+        let lookups = hits + misses;
+        if lookups == 0 {
+            None
+        } else {
+            Some(hits as f32 / lookups as f32)
+        }
+    }
+}
+
+// A summary of the frames drawn over one reporting interval, times are in milliseconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameStats {
+    pub fps: f32,
+    pub average_frame_time: f32,
+    pub max_frame_time: f32,
+}
+
+impl FrameStats {
+    pub fn to_value(self) -> Value {
+        Value::Map(vec![
+            (Value::from("fps"), Value::from(self.fps)),
+            (
+                Value::from("average_frame_time"),
+                Value::from(self.average_frame_time),
+            ),
+            (
+                Value::from("max_frame_time"),
+                Value::from(self.max_frame_time),
+            ),
+        ])
+    }
+}
+
+// Collects how long each frame took to draw and hands out a summary once per interval. The fps is
+// the number of frames drawn over the interval, so an idle window reports a low rate rather than
+// the refresh rate it could reach.
+pub struct FrameStatsCollector {
+    interval_start: Instant,
+    frames: u32,
+    total_frame_time: Duration,
+    max_frame_time: Duration,
+}
+
+impl FrameStatsCollector {
+    pub fn new(now: Instant) -> FrameStatsCollector {
+        FrameStatsCollector {
+            interval_start: now,
+            frames: 0,
+            total_frame_time: Duration::ZERO,
+            max_frame_time: Duration::ZERO,
+        }
+    }
+
+    pub fn record_frame(&mut self, frame_time: Duration) {
+        self.frames += 1;
+        self.total_frame_time += frame_time;
+        self.max_frame_time = self.max_frame_time.max(frame_time);
+    }
+
+    // Returns the summary of the interval once it is over and starts the next one
+    pub fn take_summary(&mut self, now: Instant) -> Option<FrameStats> {
+        let elapsed = now.saturating_duration_since(self.interval_start);
+        if elapsed < FRAME_STATS_INTERVAL {
+            return None;
+        }
+
+        let average_frame_time = if self.frames == 0 {
+            Duration::ZERO
+        } else {
+            self.total_frame_time / self.frames
+        };
+        let stats = FrameStats {
+            fps: self.frames as f32 / elapsed.as_secs_f32(),
+            average_frame_time: average_frame_time.as_secs_f32() * 1000.0,
+            max_frame_time: self.max_frame_time.as_secs_f32() * 1000.0,
+        };
+        *self = FrameStatsCollector::new(now);
+        Some(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer_drops_oldest_sample() {
+        let mut buffer = RingBuffer::new(3);
+        for sample in 1..=5 {
+            buffer.push(sample as f32);
+        }
+
+        assert_eq!(buffer.samples(), vec![3.0, 4.0, 5.0]);
+        assert_eq!(buffer.last(), Some(5.0));
+    }
+
+    #[test]
+    fn test_disabled_profiler_records_nothing() {
+        let profiler = Profiler::new();
+        profiler.record_frame_time(0.016);
+        profiler.record_ping(Duration::from_millis(3));
+
+        assert!(profiler.frame_times().is_empty());
+        assert_eq!(profiler.last_ping(), None);
+
+        profiler.set_enabled(true);
+        profiler.record_frame_time(0.016);
+
+        assert_eq!(profiler.frame_times().len(), 1);
+    }
+
+    #[test]
+    fn test_input_latency_is_averaged() {
+        let profiler = Profiler::new();
+        profiler.record_input_latency(Duration::from_millis(30));
+        assert_eq!(profiler.average_input_latency(), None);
+
+        profiler.set_enabled(true);
+        profiler.record_input_latency(Duration::from_millis(10));
+        profiler.record_input_latency(Duration::from_millis(20));
+        let average = profiler.average_input_latency().unwrap();
+        assert!((average - 15.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_frame_stats_summarize_an_interval() {
+        let start = Instant::now();
+        let mut collector = FrameStatsCollector::new(start);
+        collector.record_frame(Duration::from_millis(2));
+        collector.record_frame(Duration::from_millis(6));
+
+        assert_eq!(
+            collector.take_summary(start + FRAME_STATS_INTERVAL / 2),
+            None
+        );
+
+        let end = start + FRAME_STATS_INTERVAL * 2;
+        let stats = collector.take_summary(end).unwrap();
+        assert_eq!(stats.fps, 1.0);
+        assert!((stats.average_frame_time - 4.0).abs() < 0.001);
+        assert!((stats.max_frame_time - 6.0).abs() < 0.001);
+
+        // The next interval starts empty
+        let stats = collector.take_summary(end + FRAME_STATS_INTERVAL).unwrap();
+        assert_eq!(stats.fps, 0.0);
+        assert_eq!(stats.max_frame_time, 0.0);
+    }
+}
@@ -6,6 +6,7 @@ use std::{
     time::Instant,
 };
 
+use glutin::event_loop::EventLoopProxy;
 use log::trace;
 
 lazy_static! {
@@ -15,6 +16,10 @@ lazy_static! {
 pub struct RedrawScheduler {
     scheduled_frame: Mutex<Option<Instant>>,
     frame_queued: AtomicBool,
+    frame_forced: AtomicBool,
+    // When keys were sent to neovim which haven't been answered by a drawn redraw yet
+    input_sent: Mutex<Option<Instant>>,
+    event_loop_proxy: Mutex<Option<EventLoopProxy<()>>>,
 }
 
 impl RedrawScheduler {
@@ -22,6 +27,9 @@ impl RedrawScheduler {
         RedrawScheduler {
             scheduled_frame: Mutex::new(None),
             frame_queued: AtomicBool::new(true),
+            frame_forced: AtomicBool::new(false),
+            input_sent: Mutex::new(None),
+            event_loop_proxy: Mutex::new(None),
         }
     }
 
@@ -43,6 +51,51 @@ impl RedrawScheduler {
         self.frame_queued.store(true, Ordering::Relaxed);
     }
 
+    // Used when new content arrives from neovim. The window loop is woken up so the frame is drawn
+    // right away instead of waiting out the idle refresh rate
+    pub fn force_next_frame(&self) {
+        trace!("Next frame forced");
+        self.frame_queued.store(true, Ordering::Relaxed);
+        self.frame_forced.store(true, Ordering::Relaxed);
+        if let Some(proxy) = self.event_loop_proxy.lock().unwrap().as_ref() {
+            proxy.send_event(()).ok();
+        }
+    }
+
+    // Keeps the time of the first keys since the last redraw was drawn, later keys are answered by
+    // the same redraw
+    pub fn input_sent(&self) {
+        self.input_sent
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Instant::now);
+    }
+
+    pub fn awaiting_echo(&self) -> bool {
+        self.input_sent.lock().unwrap().is_some()
+    }
+
+    // Called once a redraw reached the screen, returning when the keys it answers were sent
+    pub fn take_input_sent(&self) -> Option<Instant> {
+        self.input_sent.lock().unwrap().take()
+    }
+
+    pub fn set_event_loop_proxy(&self, proxy: EventLoopProxy<()>) {
+        *self.event_loop_proxy.lock().unwrap() = Some(proxy);
+    }
+
+    pub fn take_forced_frame(&self) -> bool {
+        self.frame_forced.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn frame_queued(&self) -> bool {
+        self.frame_queued.load(Ordering::Relaxed)
+    }
+
+    pub fn next_scheduled_frame(&self) -> Option<Instant> {
+        *self.scheduled_frame.lock().unwrap()
+    }
+
     pub fn should_draw(&self) -> bool {
         if self.frame_queued.load(Ordering::Relaxed) {
             self.frame_queued.store(false, Ordering::Relaxed);
@@ -0,0 +1,50 @@
+use skia_safe::{Canvas, Color, Font, Paint, Rect, Typeface};
+
+const FONT_SIZE: f32 = 14.0;
+const MARGIN: f32 = 10.0;
+const PADDING: f32 = 8.0;
+
+// A line of text drawn across the top of the window on top of everything else, for messages
+// about neovide itself rather than the editor
+pub struct BannerOverlay {
+    font: Font,
+}
+
+impl BannerOverlay {
+    pub fn new() -> BannerOverlay {
+        BannerOverlay {
+            font: Font::from_typeface(Typeface::default(), FONT_SIZE),
+        }
+    }
+
+    pub fn draw(&self, root_canvas: &mut Canvas, text: &str) {
+        let canvas_width = root_canvas.base_layer_size().width as f32;
+        let (text_width, _) = self.font.measure_str(text, None);
+        let width = (text_width + PADDING * 2.0).min(canvas_width - MARGIN * 2.0);
+        let height = FONT_SIZE + PADDING * 2.0;
+        let rect = Rect::from_xywh(
+            ((canvas_width - width) / 2.0).max(0.0),
+            MARGIN,
+            width,
+            height,
+        );
+
+        root_canvas.save();
+        root_canvas.reset_matrix();
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        paint.set_color(Color::from_argb(220, 60, 60, 60));
+        root_canvas.draw_round_rect(&rect, 4.0, 4.0, &paint);
+
+        paint.set_color(Color::WHITE);
+        root_canvas.draw_str(
+            text,
+            (rect.left + PADDING, rect.bottom - PADDING - 2.0),
+            &self.font,
+            &paint,
+        );
+
+        root_canvas.restore();
+    }
+}
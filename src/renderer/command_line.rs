@@ -0,0 +1,322 @@
+use std::sync::Arc;
+
+use skia_safe::{paint::Style as PaintStyle, Canvas, Paint, Point, RRect, Rect};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{dimensions::Dimensions, editor::Style, renderer::GridRenderer, settings::*};
+
+#[derive(SettingGroup, Clone, Default)]
+pub struct CommandLineSettings {
+    pub cmdline: bool,
+}
+
+pub type StyledLine = Vec<(Option<Arc<Style>>, String)>;
+
+#[derive(Clone, Debug)]
+pub struct CommandLineLevel {
+    pub content: StyledLine,
+    // Byte offset of the cursor into the content
+    pub position: u64,
+    pub first_character: String,
+    pub prompt: String,
+    pub indent: u64,
+    pub special_character: Option<(String, bool)>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CommandLineContents {
+    pub levels: Vec<CommandLineLevel>,
+    pub block: Vec<StyledLine>,
+}
+
+// Where the command line was drawn this frame, so that the cursor and the command line completion
+// menu can follow it
+pub struct CommandLinePlacement {
+    pub cursor_destination: Point,
+    pub content_origin: (u64, u64),
+}
+
+fn text_width(text: &str) -> u64 {
+    text.graphemes(true).count() as u64
+}
+
+fn line_width(line: &[(Option<Arc<Style>>, String)]) -> u64 {
+    line.iter().map(|(_, text)| text_width(text)).sum()
+}
+
+fn split_line_at(
+    line: &[(Option<Arc<Style>>, String)],
+    position: usize,
+) -> (StyledLine, StyledLine) {
+    let mut before = Vec::new();
+    let mut after = Vec::new();
+    let mut remaining = position;
+
+    for (style, text) in line.iter() {
+        if remaining >= text.len() {
+            remaining -= text.len();
+            before.push((style.clone(), text.clone()));
+        } else {
+            let mut split = remaining;
+            while !text.is_char_boundary(split) {
+                split -= 1;
+            }
+            if split > 0 {
+                before.push((style.clone(), text[..split].to_owned()));
+            }
+            after.push((style.clone(), text[split..].to_owned()));
+            remaining = 0;
+        }
+    }
+
+    (before, after)
+}
+
+fn drop_first_grapheme(line: &mut StyledLine) {
+    if let Some((_, text)) = line.iter_mut().find(|(_, text)| !text.is_empty()) {
+        let first_length = text.graphemes(true).next().map(str::len).unwrap_or(0);
+        text.drain(..first_length);
+    }
+}
+
+// Builds the displayed line for a command line level along with the column its cursor sits in
+// and the column its content starts at
+fn level_line(level: &CommandLineLevel) -> (StyledLine, u64, u64) {
+    let prefix = format!(
+        "{}{}{}",
+        level.prompt,
+        level.first_character,
+        " ".repeat(level.indent as usize)
+    );
+    let prefix_width = text_width(&prefix);
+
+    let (before, mut after) = split_line_at(&level.content, level.position as usize);
+    let cursor_column = prefix_width + line_width(&before);
+
+    let mut line = vec![(None, prefix)];
+    line.extend(before);
+    if let Some((character, shift)) = &level.special_character {
+        // Unshifted special characters are drawn over the character under the cursor
+        if !shift {
+            drop_first_grapheme(&mut after);
+        }
+        line.push((None, character.clone()));
+    }
+    line.extend(after);
+
+    (line, cursor_column, prefix_width)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct CommandLineLayout {
+    left: u64,
+    top: u64,
+    width: u64,
+    height: u64,
+}
+
+fn compute_layout(line_widths: &[u64], grid_size: Dimensions) -> CommandLineLayout {
+    let max_inner_width = grid_size.width.saturating_sub(4);
+    let inner_width = line_widths
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(grid_size.width / 2)
+        .min(max_inner_width);
+    let width = inner_width + 2;
+    let height = (line_widths.len() as u64).min(grid_size.height);
+
+    CommandLineLayout {
+        left: grid_size.width.saturating_sub(width) / 2,
+        top: (grid_size.height / 3).min(grid_size.height - height),
+        width,
+        height,
+    }
+}
+
+pub struct CommandLineRenderer {
+    contents: CommandLineContents,
+}
+
+impl CommandLineRenderer {
+    pub fn new() -> CommandLineRenderer {
+        CommandLineRenderer {
+            contents: CommandLineContents::default(),
+        }
+    }
+
+    pub fn update(&mut self, contents: CommandLineContents) {
+        self.contents = contents;
+    }
+
+    pub fn draw(
+        &mut self,
+        root_canvas: &mut Canvas,
+        grid_renderer: &mut GridRenderer,
+        grid_size: Option<Dimensions>,
+    ) -> Option<CommandLinePlacement> {
+        let grid_size = grid_size?;
+        if self.contents.levels.is_empty() {
+            return None;
+        }
+
+        // Block lines come first, followed by each level so that recursive command lines stack
+        // underneath the command line they were opened from
+        let mut lines: Vec<StyledLine> = self.contents.block.clone();
+        let mut active_cursor = (0, 0);
+        for level in self.contents.levels.iter() {
+            let (line, cursor_column, prefix_width) = level_line(level);
+            active_cursor = (cursor_column, prefix_width);
+            lines.push(line);
+        }
+        let line_widths: Vec<u64> = lines.iter().map(|line| line_width(line)).collect();
+        let layout = compute_layout(&line_widths, grid_size);
+        if layout.height == 0 {
+            return None;
+        }
+
+        // Only the lines closest to the active level are shown if they don't all fit
+        let first_line = lines.len() - layout.height as usize;
+        let inner_width = layout.width - 2;
+
+        let font_dimensions = grid_renderer.font_dimensions;
+        let font_width = font_dimensions.width as f32;
+        let font_height = font_dimensions.height as f32;
+        let origin = Point::new(
+            layout.left as f32 * font_width,
+            layout.top as f32 * font_height,
+        );
+
+        let default_style = grid_renderer.default_style.clone();
+        let box_rect = Rect::new(
+            0.0,
+            0.0,
+            layout.width as f32 * font_width,
+            layout.height as f32 * font_height,
+        );
+        let corner_radius = font_height / 4.0;
+        let box_rrect = RRect::new_rect_xy(box_rect, corner_radius, corner_radius);
+
+        root_canvas.save();
+        root_canvas.translate(origin);
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        paint.set_color(default_style.background(&default_style.colors).to_color());
+        root_canvas.draw_rrect(&box_rrect, &paint);
+
+        root_canvas.save();
+        root_canvas.clip_rrect(&box_rrect, None, Some(true));
+        for (row, line) in lines.into_iter().skip(first_line).enumerate() {
+            let mut column = 1;
+            for (style, text) in line {
+                let width = text_width(&text);
+                if width > 0 && column <= inner_width {
+                    grid_renderer.draw_foreground(
+                        root_canvas,
+                        text,
+                        (column, row as u64),
+                        width.min(inner_width + 1 - column),
+                        (1, 1),
+                        &style,
+                    );
+                }
+                column += width;
+            }
+        }
+        root_canvas.restore();
+
+        paint.set_style(PaintStyle::Stroke);
+        paint.set_stroke_width(1.0);
+        paint.set_color(
+            default_style
+                .foreground(&default_style.colors)
+                .to_color()
+                .with_a(100),
+        );
+        root_canvas.draw_rrect(&box_rrect, &paint);
+
+        root_canvas.restore();
+
+        let (cursor_column, prefix_width) = active_cursor;
+        let active_row = layout.top + layout.height - 1;
+        let cursor_left = layout.left + 1 + cursor_column.min(inner_width);
+        Some(CommandLinePlacement {
+            cursor_destination: Point::new(
+                cursor_left as f32 * font_width,
+                active_row as f32 * font_height,
+            ),
+            content_origin: (layout.left + 1 + prefix_width, active_row),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(content: &[&str], position: u64) -> CommandLineLevel {
+        CommandLineLevel {
+            content: content
+                .iter()
+                .map(|text| (None, text.to_string()))
+                .collect(),
+            position,
+            first_character: ":".to_owned(),
+            prompt: "".to_owned(),
+            indent: 0,
+            special_character: None,
+        }
+    }
+
+    fn line_text(line: &[(Option<Arc<Style>>, String)]) -> String {
+        line.iter().map(|(_, text)| text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_level_line_cursor_column() {
+        let (line, cursor_column, prefix_width) = level_line(&level(&["ec", "ho"], 3));
+
+        assert_eq!(line_text(&line), ":echo");
+        assert_eq!(cursor_column, 4);
+        assert_eq!(prefix_width, 1);
+    }
+
+    #[test]
+    fn test_level_line_shifted_special_character() {
+        let mut level = level(&["ab"], 1);
+        level.special_character = Some(("^".to_owned(), true));
+
+        let (line, cursor_column, _) = level_line(&level);
+
+        assert_eq!(line_text(&line), ":a^b");
+        assert_eq!(cursor_column, 2);
+    }
+
+    #[test]
+    fn test_level_line_unshifted_special_character() {
+        let mut level = level(&["ab"], 1);
+        level.special_character = Some(("^".to_owned(), false));
+
+        let (line, _, _) = level_line(&level);
+
+        assert_eq!(line_text(&line), ":a^");
+    }
+
+    #[test]
+    fn test_layout_is_centered() {
+        let layout = compute_layout(
+            &[10, 12],
+            Dimensions {
+                width: 100,
+                height: 30,
+            },
+        );
+
+        assert_eq!(layout.width, 52);
+        assert_eq!(layout.left, 24);
+        assert_eq!(layout.top, 10);
+        assert_eq!(layout.height, 2);
+    }
+}
@@ -24,7 +24,14 @@ impl BlinkStatus {
         }
     }
 
-    pub fn update_status(&mut self, new_cursor: &Cursor) -> bool {
+    // The cursor stays solid while it is paused, for example while it animates or the window is
+    // unfocused, and waits for blinkwait again once it is resumed
+    pub fn update_status(&mut self, new_cursor: &Cursor, paused: bool) -> bool {
+        if paused {
+            self.previous_cursor = None;
+            return true;
+        }
+
         if self.previous_cursor.is_none() || new_cursor != self.previous_cursor.as_ref().unwrap() {
             self.previous_cursor = Some(new_cursor.clone());
             self.last_transition = Instant::now();
@@ -79,3 +86,29 @@ impl BlinkStatus {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blinking_cursor() -> Cursor {
+        let mut cursor = Cursor::new();
+        cursor.blinkwait = Some(10);
+        cursor.blinkon = Some(10);
+        cursor.blinkoff = Some(10);
+        cursor
+    }
+
+    #[test]
+    fn test_paused_cursor_stays_solid() {
+        let cursor = blinking_cursor();
+        let mut blink_status = BlinkStatus::new();
+        blink_status.update_status(&cursor, false);
+        blink_status.state = BlinkState::Off;
+
+        assert!(blink_status.update_status(&cursor, true));
+        // Resuming starts over with blinkwait
+        assert!(blink_status.update_status(&cursor, false));
+        assert!(matches!(blink_status.state, BlinkState::Waiting));
+    }
+}
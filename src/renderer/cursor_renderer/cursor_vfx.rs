@@ -18,6 +18,8 @@ pub trait CursorVfx {
         dt: f32,
     ) -> bool;
     fn restart(&mut self, position: Point);
+    // Moves the effect to the position without playing it, for when the cells changed size
+    fn jump_to(&mut self, position: Point);
     fn render(
         &self,
         settings: &CursorSettings,
@@ -125,6 +127,11 @@ impl CursorVfx for PointHighlight {
         self.center_position = position;
     }
 
+    fn jump_to(&mut self, position: Point) {
+        self.t = 1.0;
+        self.center_position = position;
+    }
+
     fn render(
         &self,
         settings: &CursorSettings,
@@ -315,6 +322,12 @@ impl CursorVfx for ParticleTrail {
 
     fn restart(&mut self, _position: Point) {}
 
+    // The particles are laid out for the old cells
+    fn jump_to(&mut self, position: Point) {
+        self.particles.clear();
+        self.previous_cursor_dest = position;
+    }
+
     fn render(
         &self,
         settings: &CursorSettings,
@@ -1,12 +1,17 @@
 mod blink;
 mod cursor_vfx;
+mod overrides;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use skia_safe::{Canvas, Paint, Path, Point};
+use futures::FutureExt;
+use log::error;
+use skia_safe::{Canvas, Color, Paint, PaintStyle, Path, Point};
+use tokio::sync::watch;
 
 use crate::{
     bridge::EditorMode,
+    dimensions::Dimensions,
     editor::{Cursor, CursorShape},
     redraw_scheduler::REDRAW_SCHEDULER,
     renderer::animation_utils::*,
@@ -15,8 +20,11 @@ use crate::{
 };
 
 use blink::*;
+use overrides::{apply_overrides, BlinkOverride, ShapeOverride};
 
 const DEFAULT_CELL_PERCENTAGE: f32 = 1.0 / 8.0;
+// How close to a cell edge a pixel position has to be to count as being on it
+const CELL_EDGE_TOLERANCE: f32 = 0.001;
 
 const STANDARD_CORNERS: &[(f32, f32); 4] = &[(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)];
 
@@ -25,36 +33,121 @@ const STANDARD_CORNERS: &[(f32, f32); 4] = &[(-0.5, -0.5), (0.5, -0.5), (0.5, 0.
 #[derive(Clone)]
 pub struct CursorSettings {
     antialiasing: bool,
+    animate: bool,
     animation_length: f32,
+    // Insert mode overrides, negative to use the setting above
+    animation_length_insert: f32,
     distance_length_adjust: bool,
     animate_in_insert_mode: bool,
     animate_command_line: bool,
+    // Whether the cursor morphs between shapes instead of switching straight away
+    animate_shape: bool,
     trail_size: f32,
+    // Disabled drops the effect entirely, so no particles are simulated
     vfx_mode: cursor_vfx::VfxMode,
+    #[setting(min = 0.0, max = 255.0)]
     vfx_opacity: f32,
+    // Seconds a particle lives, particles fade out relative to it
+    #[setting(min = 0.1, max = 10.0)]
     vfx_particle_lifetime: f32,
+    // Particles spawned per cell the cursor travels, which is what the simulation cost scales with
+    #[setting(min = 0.0, max = 100.0)]
     vfx_particle_density: f32,
+    vfx_particle_density_insert: f32,
     vfx_particle_speed: f32,
     vfx_particle_phase: f32,
     vfx_particle_curl: f32,
+    // Width in pixels of the hollow cursor drawn while the window is unfocused
+    unfocused_outline_width: f32,
+    // A #rrggbb color used instead of the highlight colors, empty to follow the highlight
+    color: String,
+    opacity: f32,
+    // Shapes used instead of the ones from 'guicursor', auto to keep neovim's
+    shape_normal: ShapeOverride,
+    shape_insert: ShapeOverride,
+    shape_visual: ShapeOverride,
+    shape_replace: ShapeOverride,
+    shape_cmdline: ShapeOverride,
+    // Uses the blink timings below in every mode instead of the ones from 'guicursor'
+    blink_override: bool,
+    #[setting(max = 10000)]
+    blink_wait: u64,
+    #[setting(max = 10000)]
+    blink_on: u64,
+    #[setting(max = 10000)]
+    blink_off: u64,
 }
 
 impl Default for CursorSettings {
     fn default() -> Self {
         CursorSettings {
             antialiasing: true,
+            animate: true,
             animation_length: 0.06,
+            animation_length_insert: -1.0,
             distance_length_adjust: true,
             animate_in_insert_mode: true,
             animate_command_line: true,
+            animate_shape: true,
             trail_size: 0.7,
             vfx_mode: cursor_vfx::VfxMode::Disabled,
             vfx_opacity: 200.0,
             vfx_particle_lifetime: 1.2,
             vfx_particle_density: 7.0,
+            vfx_particle_density_insert: -1.0,
             vfx_particle_speed: 10.0,
             vfx_particle_phase: 1.5,
             vfx_particle_curl: 1.0,
+            unfocused_outline_width: 1.0,
+            color: String::new(),
+            opacity: 1.0,
+            shape_normal: ShapeOverride::Auto,
+            shape_insert: ShapeOverride::Auto,
+            shape_visual: ShapeOverride::Auto,
+            shape_replace: ShapeOverride::Auto,
+            shape_cmdline: ShapeOverride::Auto,
+            blink_override: false,
+            blink_wait: 700,
+            blink_on: 400,
+            blink_off: 250,
+        }
+    }
+}
+
+impl CursorSettings {
+    // Applies the overrides for the given mode which are set
+    fn for_mode(mut self, mode: &EditorMode) -> CursorSettings {
+        if let EditorMode::Insert = mode {
+            if self.animation_length_insert >= 0.0 {
+                self.animation_length = self.animation_length_insert;
+            }
+            if self.vfx_particle_density_insert >= 0.0 {
+                self.vfx_particle_density = self.vfx_particle_density_insert;
+            }
+        }
+        self
+    }
+
+    fn shape_for_mode(&self, mode: &EditorMode) -> ShapeOverride {
+        match mode {
+            EditorMode::Normal => self.shape_normal,
+            EditorMode::Insert => self.shape_insert,
+            EditorMode::Visual => self.shape_visual,
+            EditorMode::Replace => self.shape_replace,
+            EditorMode::CmdLine => self.shape_cmdline,
+            EditorMode::Unknown(_) => ShapeOverride::Auto,
+        }
+    }
+
+    fn blink(&self) -> Option<BlinkOverride> {
+        if self.blink_override {
+            Some(BlinkOverride {
+                wait: self.blink_wait,
+                on: self.blink_on,
+                off: self.blink_off,
+            })
+        } else {
+            None
         }
     }
 }
@@ -81,6 +174,13 @@ impl Corner {
         }
     }
 
+    // Moves the pixel positions along with the cells when the cell size changed
+    fn rescale(&mut self, from: Dimensions, to: Dimensions) {
+        self.start_position = rescale_point(self.start_position, from, to);
+        self.current_position = rescale_point(self.current_position, from, to);
+        self.previous_destination = rescale_point(self.previous_destination, from, to);
+    }
+
     pub fn update(
         &mut self,
         settings: &CursorSettings,
@@ -108,14 +208,7 @@ impl Corner {
             return false;
         }
 
-        // Calculate window-space destination for corner
-        let relative_scaled_position: Point = (
-            self.relative_position.x * font_dimensions.x,
-            self.relative_position.y * font_dimensions.y,
-        )
-            .into();
-
-        let corner_destination = destination + relative_scaled_position;
+        let corner_destination = self.corner_destination(font_dimensions, destination);
 
         if immediate_movement {
             self.t = 1.0;
@@ -163,6 +256,45 @@ impl Corner {
 
         true
     }
+
+    // Animates the corner towards its place in a new cursor shape. A shape change on its own lasts
+    // the animation length, one during a move takes the remainder of the move, and either way the
+    // morph restarts from wherever the corner is drawn so quick toggles can't strand it mid-shape.
+    fn morph_to(&mut self, relative_position: Point) {
+        if self.t >= 1.0 {
+            self.length_multiplier = 1.0;
+        }
+        self.relative_position = relative_position;
+        self.start_position = self.current_position;
+        self.t = 0.0;
+    }
+
+    // Switches the corner to a new cursor shape straight away while keeping any move in flight
+    fn snap_to(&mut self, font_dimensions: Point, relative_position: Point) {
+        let offset = relative_position - self.relative_position;
+        let offset = Point::new(offset.x * font_dimensions.x, offset.y * font_dimensions.y);
+        self.start_position += offset;
+        self.current_position += offset;
+        self.relative_position = relative_position;
+    }
+
+    // Moves the corner straight to its destination without any of the animation bookkeeping
+    pub fn jump_to(&mut self, font_dimensions: Point, destination: Point) {
+        self.t = 1.0;
+        self.previous_destination = destination;
+        self.current_position = self.corner_destination(font_dimensions, destination);
+    }
+
+    // Calculate window-space destination for corner
+    fn corner_destination(&self, font_dimensions: Point, destination: Point) -> Point {
+        let relative_scaled_position: Point = (
+            self.relative_position.x * font_dimensions.x,
+            self.relative_position.y * font_dimensions.y,
+        )
+            .into();
+
+        destination + relative_scaled_position
+    }
 }
 
 pub struct CursorRenderer {
@@ -170,14 +302,92 @@ pub struct CursorRenderer {
     cursor: Cursor,
     destination: Point,
     blink_status: BlinkStatus,
-    previous_cursor_shape: Option<CursorShape>,
+    // The shape and cell percentage the corners were last laid out for
+    previous_cursor_shape: Option<(CursorShape, f32)>,
     previous_editor_mode: EditorMode,
     cursor_vfx: Option<Box<dyn cursor_vfx::CursorVfx>>,
     previous_vfx_mode: cursor_vfx::VfxMode,
+    // Set when the cells changed size, the effects are skipped for the next frame
+    metrics_changed: bool,
+    window_focused: bool,
+    settings: watch::Receiver<CursorSettings>,
+    // The settings with the overrides for settings_mode applied, only rebuilt when either changes
+    mode_settings: Arc<CursorSettings>,
+    settings_mode: EditorMode,
+    // The parsed cursor color setting, only parsed again when the settings change
+    forced_color: Option<Color>,
+}
+
+// Converts a pixel position for cells of one size to the same place in the grid for cells of
+// another size. Positions on a cell edge are snapped to it, so that float error can't leave a
+// settled cursor a fraction of a pixel off its new destination and start an animation.
+fn rescale_point(point: Point, from: Dimensions, to: Dimensions) -> Point {
+    let rescale = |pixels: f32, from: u64, to: u64| {
+        let cells = pixels / from as f32;
+        let cells = if (cells - cells.round()).abs() < CELL_EDGE_TOLERANCE {
+            cells.round()
+        } else {
+            cells
+        };
+        cells * to as f32
+    };
+    Point::new(
+        rescale(point.x, from.width, to.width),
+        rescale(point.y, from.height, to.height),
+    )
+}
+
+// Parses the g:neovide_cursor_color setting
+fn parse_cursor_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some(Color::from_rgb(
+        (rgb >> 16) as u8,
+        (rgb >> 8) as u8,
+        rgb as u8,
+    ))
+}
+
+// The color from the cursor color setting, None when it is empty and the highlight decides. An
+// invalid one is reported once here rather than on every frame.
+fn forced_cursor_color(color: &str) -> Option<Color> {
+    if color.is_empty() {
+        return None;
+    }
+    let parsed = parse_cursor_color(color);
+    if parsed.is_none() {
+        error!("Expected a #rrggbb cursor color, but received {}", color);
+    }
+    parsed
+}
+
+// Where the corner with the index sits in a cell for the shape, relative to the cell's center
+fn shape_corner(cursor_shape: &CursorShape, cell_percentage: f32, index: usize) -> Point {
+    let (x, y) = STANDARD_CORNERS[index];
+    match cursor_shape {
+        CursorShape::Block => (x, y).into(),
+        // Transform the x position so that the right side is translated over to the BAR_WIDTH
+        // position
+        CursorShape::Vertical => ((x + 0.5) * cell_percentage - 0.5, y).into(),
+        // Do the same as above, but flip the y coordinate and then flip the result so that the
+        // horizontal bar is at the bottom of the character space instead of the top.
+        CursorShape::Horizontal => (x, -((-y + 0.5) * cell_percentage - 0.5)).into(),
+    }
+}
+
+fn with_opacity(color: Color, opacity: f32) -> Color {
+    let alpha = color.a() as f32 * opacity.max(0.0).min(1.0);
+    color.with_a(alpha as u8)
 }
 
 impl CursorRenderer {
     pub fn new() -> CursorRenderer {
+        let settings = SETTINGS.subscribe::<CursorSettings>();
+        let mode_settings = Arc::new(settings.borrow().clone().for_mode(&EditorMode::Normal));
+        let forced_color = forced_cursor_color(&settings.borrow().color);
         let mut renderer = CursorRenderer {
             corners: vec![Corner::new(); 4],
             cursor: Cursor::new(),
@@ -187,43 +397,50 @@ impl CursorRenderer {
             previous_editor_mode: EditorMode::Normal,
             cursor_vfx: None,
             previous_vfx_mode: cursor_vfx::VfxMode::Disabled,
+            metrics_changed: false,
+            window_focused: true,
+            settings,
+            mode_settings,
+            settings_mode: EditorMode::Normal,
+            forced_color,
         };
-        renderer.set_cursor_shape(&CursorShape::Block, DEFAULT_CELL_PERCENTAGE);
+        renderer.set_cursor_shape(
+            &CursorShape::Block,
+            DEFAULT_CELL_PERCENTAGE,
+            Point::new(0.0, 0.0),
+            false,
+        );
         renderer
     }
 
+    pub fn set_window_focused(&mut self, window_focused: bool) {
+        self.window_focused = window_focused;
+    }
+
+    // The grid the cursor is in
+    pub fn grid_id(&self) -> u64 {
+        self.cursor.parent_window_id
+    }
+
     pub fn update_cursor(&mut self, new_cursor: Cursor) {
         self.cursor = new_cursor;
     }
 
-    fn set_cursor_shape(&mut self, cursor_shape: &CursorShape, cell_percentage: f32) {
-        self.corners = self
-            .corners
-            .clone()
-            .into_iter()
-            .enumerate()
-            .map(|(i, corner)| {
-                let (x, y) = STANDARD_CORNERS[i];
-
-                Corner {
-                    relative_position: match cursor_shape {
-                        CursorShape::Block => (x, y).into(),
-                        // Transform the x position so that the right side is translated over to
-                        // the BAR_WIDTH position
-                        CursorShape::Vertical => ((x + 0.5) * cell_percentage - 0.5, y).into(),
-                        // Do the same as above, but flip the y coordinate and then flip the result
-                        // so that the horizontal bar is at the bottom of the character space
-                        // instead of the top.
-                        CursorShape::Horizontal => {
-                            (x, -((-y + 0.5) * cell_percentage - 0.5)).into()
-                        }
-                    },
-                    t: 0.0,
-                    start_position: corner.current_position,
-                    ..corner
-                }
-            })
-            .collect::<Vec<Corner>>();
+    fn set_cursor_shape(
+        &mut self,
+        cursor_shape: &CursorShape,
+        cell_percentage: f32,
+        font_dimensions: Point,
+        animate: bool,
+    ) {
+        for (i, corner) in self.corners.iter_mut().enumerate() {
+            let relative_position = shape_corner(cursor_shape, cell_percentage, i);
+            if animate {
+                corner.morph_to(relative_position);
+            } else {
+                corner.snap_to(font_dimensions, relative_position);
+            }
+        }
     }
 
     pub fn update_cursor_destination(
@@ -255,6 +472,37 @@ impl CursorRenderer {
         }
     }
 
+    pub fn destination(&self) -> Point {
+        self.destination
+    }
+
+    pub fn set_destination(&mut self, destination: Point) {
+        self.destination = destination;
+    }
+
+    fn current_settings(&mut self, current_mode: &EditorMode) -> Arc<CursorSettings> {
+        let settings_changed = self.settings.changed().now_or_never().is_some();
+        if settings_changed {
+            self.forced_color = forced_cursor_color(&self.settings.borrow().color);
+        }
+        if settings_changed || &self.settings_mode != current_mode {
+            self.mode_settings = Arc::new(self.settings.borrow().clone().for_mode(current_mode));
+            self.settings_mode = current_mode.clone();
+        }
+        self.mode_settings.clone()
+    }
+
+    // Keeps the cursor and an animation in flight on the same cells after a font or scale factor
+    // change. The effects are started over at the new position on the next frame instead of
+    // spawning a trail from the old pixel position.
+    pub fn rescale(&mut self, from: Dimensions, to: Dimensions) {
+        for corner in self.corners.iter_mut() {
+            corner.rescale(from, to);
+        }
+        self.destination = rescale_point(self.destination, from, to);
+        self.metrics_changed = true;
+    }
+
     pub fn draw(
         &mut self,
         grid_renderer: &mut GridRenderer,
@@ -262,8 +510,7 @@ impl CursorRenderer {
         canvas: &mut Canvas,
         dt: f32,
     ) {
-        let render = self.blink_status.update_status(&self.cursor);
-        let settings = SETTINGS.get::<CursorSettings>();
+        let settings = self.current_settings(current_mode);
 
         if settings.vfx_mode != self.previous_vfx_mode {
             self.cursor_vfx = cursor_vfx::new_cursor_vfx(&settings.vfx_mode);
@@ -273,10 +520,16 @@ impl CursorRenderer {
         let mut paint = Paint::new(skia_safe::colors::WHITE, None);
         paint.set_anti_alias(settings.antialiasing);
 
-        let character = self.cursor.character.clone();
+        // The settings are read every frame, so a changed override shows on the next one
+        let cursor = apply_overrides(
+            &self.cursor,
+            settings.shape_for_mode(current_mode),
+            settings.blink(),
+        );
+        let character = cursor.character.clone();
 
         let mut cursor_width = grid_renderer.font_dimensions.width;
-        if self.cursor.double_width && self.cursor.shape == CursorShape::Block {
+        if cursor.double_width && cursor.shape == CursorShape::Block {
             cursor_width *= 2;
         }
 
@@ -292,16 +545,14 @@ impl CursorRenderer {
             ^ matches!(current_mode, EditorMode::CmdLine);
 
         let center_destination = self.destination + cursor_dimensions * 0.5;
-        let new_cursor = Some(self.cursor.shape.clone());
+        let cell_percentage = cursor.cell_percentage.unwrap_or(DEFAULT_CELL_PERCENTAGE);
+        let new_cursor = Some((cursor.shape.clone(), cell_percentage));
 
         if self.previous_cursor_shape != new_cursor {
-            self.previous_cursor_shape = new_cursor.clone();
-            self.set_cursor_shape(
-                &new_cursor.unwrap(),
-                self.cursor
-                    .cell_percentage
-                    .unwrap_or(DEFAULT_CELL_PERCENTAGE),
-            );
+            self.previous_cursor_shape = new_cursor;
+            let shape = cursor.shape.clone();
+            let animate_shape = settings.animate && settings.animate_shape;
+            self.set_cursor_shape(&shape, cell_percentage, cursor_dimensions, animate_shape);
 
             if let Some(vfx) = self.cursor_vfx.as_mut() {
                 vfx.restart(center_destination);
@@ -309,8 +560,13 @@ impl CursorRenderer {
         }
 
         let mut animating = false;
+        let metrics_changed = std::mem::take(&mut self.metrics_changed);
 
-        if !center_destination.is_zero() {
+        if !center_destination.is_zero() && !settings.animate {
+            for corner in self.corners.iter_mut() {
+                corner.jump_to(cursor_dimensions, center_destination);
+            }
+        } else if !center_destination.is_zero() {
             for corner in self.corners.iter_mut() {
                 let immediate_movement = !settings.animate_in_insert_mode && in_insert_mode
                     || !settings.animate_command_line && !changed_to_from_cmdline;
@@ -326,10 +582,13 @@ impl CursorRenderer {
                 animating |= corner_animating;
             }
 
-            let vfx_animating = if let Some(vfx) = self.cursor_vfx.as_mut() {
-                vfx.update(&settings, center_destination, cursor_dimensions, dt)
-            } else {
-                false
+            let vfx_animating = match self.cursor_vfx.as_mut() {
+                Some(vfx) if metrics_changed => {
+                    vfx.jump_to(center_destination);
+                    false
+                }
+                Some(vfx) => vfx.update(&settings, center_destination, cursor_dimensions, dt),
+                None => false,
             };
 
             animating |= vfx_animating;
@@ -341,13 +600,18 @@ impl CursorRenderer {
             self.previous_editor_mode = current_mode.clone();
         }
 
-        if self.cursor.enabled && render {
+        let render = self
+            .blink_status
+            .update_status(&cursor, animating || !self.window_focused);
+
+        if cursor.enabled && render {
             // Draw Background
-            let background_color = self
-                .cursor
-                .background(&grid_renderer.default_style.colors)
-                .to_color();
-            paint.set_color(background_color);
+            let background_color = self.forced_color.unwrap_or_else(|| {
+                cursor
+                    .background(&grid_renderer.default_style.colors)
+                    .to_color()
+            });
+            paint.set_color(with_opacity(background_color, settings.opacity));
 
             // The cursor is made up of four points, so I create a path with each of the four
             // corners.
@@ -359,11 +623,19 @@ impl CursorRenderer {
             path.line_to(self.corners[3].current_position);
             path.close();
 
+            // Like terminals, an unfocused window only outlines the cursor so that the text
+            // under it stays readable
+            if !self.window_focused {
+                paint.set_style(PaintStyle::Stroke);
+                paint.set_stroke_width(settings.unfocused_outline_width);
+                canvas.draw_path(&path, &paint);
+                return;
+            }
+
             canvas.draw_path(&path, &paint);
 
             // Draw foreground
-            let foreground_color = self
-                .cursor
+            let foreground_color = cursor
                 .foreground(&grid_renderer.default_style.colors)
                 .to_color();
             paint.set_color(foreground_color);
@@ -385,8 +657,140 @@ impl CursorRenderer {
             canvas.restore();
 
             if let Some(vfx) = self.cursor_vfx.as_ref() {
-                vfx.render(&settings, canvas, grid_renderer, &self.cursor);
+                vfx.render(&settings, canvas, grid_renderer, &cursor);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rescaled_points_stay_on_their_cells() {
+        let dimensions = |width, height| Dimensions { width, height };
+
+        // The top left corner of cell (7, 3), and a point a third of the way into the next cell
+        let corner = Point::new(70.0, 60.0);
+        let inside = Point::new(80.0 + 10.0 / 3.0, 60.0);
+        assert_eq!(
+            rescale_point(corner, dimensions(10, 20), dimensions(12, 25)),
+            Point::new(84.0, 75.0)
+        );
+        let rescaled = rescale_point(inside, dimensions(10, 20), dimensions(12, 25));
+        assert!((rescaled.x - 100.0).abs() < 0.001);
+
+        // Going back and forth between odd sizes doesn't drift off the cell
+        let mut point = Point::new(7.0 * 9.0, 3.0 * 19.0);
+        for _ in 0..100 {
+            point = rescale_point(point, dimensions(9, 19), dimensions(7, 15));
+            point = rescale_point(point, dimensions(7, 15), dimensions(9, 19));
+        }
+        assert_eq!(point, Point::new(63.0, 57.0));
+    }
+
+    #[test]
+    fn test_corner_rescale_keeps_animation_progress() {
+        let mut corner = Corner::new();
+        corner.start_position = Point::new(10.0, 20.0);
+        corner.current_position = Point::new(15.0, 30.0);
+        corner.previous_destination = Point::new(20.0, 40.0);
+        corner.t = 0.5;
+
+        corner.rescale(
+            Dimensions {
+                width: 10,
+                height: 20,
+            },
+            Dimensions {
+                width: 20,
+                height: 10,
+            },
+        );
+
+        assert_eq!(corner.start_position, Point::new(20.0, 10.0));
+        assert_eq!(corner.current_position, Point::new(30.0, 15.0));
+        assert_eq!(corner.previous_destination, Point::new(40.0, 20.0));
+        assert_eq!(corner.t, 0.5);
+    }
+
+    fn settle(corner: &mut Corner, settings: &CursorSettings, destination: Point) -> Point {
+        for _ in 0..100 {
+            corner.update(settings, Point::new(10.0, 20.0), destination, 0.01, false);
+        }
+        corner.current_position
+    }
+
+    #[test]
+    fn test_corner_morphs_to_the_last_shape() {
+        let settings = CursorSettings::default();
+        let destination = Point::new(100.0, 100.0);
+        let mut corner = Corner::new();
+        corner.relative_position = shape_corner(&CursorShape::Block, 1.0 / 8.0, 1);
+        corner.jump_to(Point::new(10.0, 20.0), destination);
+
+        corner.morph_to(shape_corner(&CursorShape::Vertical, 1.0 / 8.0, 1));
+        corner.update(&settings, Point::new(10.0, 20.0), destination, 0.01, false);
+        assert!(corner.current_position.x < 105.0 && corner.current_position.x > 96.25);
+
+        // Toggling back mid-morph ends on the block again
+        corner.morph_to(shape_corner(&CursorShape::Block, 1.0 / 8.0, 1));
+        let settled = settle(&mut corner, &settings, destination);
+        assert!((settled - Point::new(105.0, 90.0)).length() < 0.001);
+    }
+
+    #[test]
+    fn test_corner_snaps_shape_during_a_move() {
+        let settings = CursorSettings::default();
+        let mut corner = Corner::new();
+        corner.relative_position = shape_corner(&CursorShape::Block, 1.0 / 8.0, 1);
+        corner.jump_to(Point::new(10.0, 20.0), Point::new(100.0, 100.0));
+        corner.update(
+            &settings,
+            Point::new(10.0, 20.0),
+            Point::new(200.0, 100.0),
+            0.01,
+            false,
+        );
+
+        corner.snap_to(
+            Point::new(10.0, 20.0),
+            shape_corner(&CursorShape::Vertical, 1.0 / 8.0, 1),
+        );
+        assert!(corner.t < 1.0, "The move keeps going");
+        let settled = settle(&mut corner, &settings, Point::new(200.0, 100.0));
+        assert!((settled - Point::new(196.25, 90.0)).length() < 0.001);
+    }
+
+    #[test]
+    fn test_insert_mode_overrides() {
+        let settings = CursorSettings {
+            animation_length_insert: 0.0,
+            ..CursorSettings::default()
+        };
+
+        let insert = settings.clone().for_mode(&EditorMode::Insert);
+        assert_eq!(insert.animation_length, 0.0);
+        assert_eq!(insert.vfx_particle_density, settings.vfx_particle_density);
+
+        let normal = settings.clone().for_mode(&EditorMode::Normal);
+        assert_eq!(normal.animation_length, settings.animation_length);
+    }
+
+    #[test]
+    fn test_parse_cursor_color() {
+        assert_eq!(
+            parse_cursor_color("#ff8000"),
+            Some(Color::from_rgb(255, 128, 0))
+        );
+        assert_eq!(parse_cursor_color("ff8000"), None);
+        assert_eq!(parse_cursor_color("#ff80"), None);
+        assert_eq!(parse_cursor_color("#gg8000"), None);
+        assert_eq!(forced_cursor_color(""), None);
+        assert_eq!(
+            forced_cursor_color("#000000"),
+            Some(Color::from_rgb(0, 0, 0))
+        );
+    }
+}
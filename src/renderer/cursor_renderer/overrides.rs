@@ -0,0 +1,123 @@
+use log::error;
+use rmpv::Value;
+
+use crate::{
+    editor::{Cursor, CursorShape},
+    settings::FromValue,
+};
+
+// The shape a mode's cursor is drawn with, auto keeps the one neovim reports from 'guicursor'
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShapeOverride {
+    Auto,
+    Block,
+    Bar,
+    Underline,
+}
+
+impl ShapeOverride {
+    fn cursor_shape(&self) -> Option<CursorShape> {
+        match self {
+            ShapeOverride::Auto => None,
+            ShapeOverride::Block => Some(CursorShape::Block),
+            ShapeOverride::Bar => Some(CursorShape::Vertical),
+            ShapeOverride::Underline => Some(CursorShape::Horizontal),
+        }
+    }
+}
+
+impl FromValue for ShapeOverride {
+    fn from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "auto" => ShapeOverride::Auto,
+                "block" => ShapeOverride::Block,
+                "bar" => ShapeOverride::Bar,
+                "underline" => ShapeOverride::Underline,
+                value => {
+                    error!("Expected a cursor shape, but received {:?}", value);
+                    return;
+                }
+            };
+        } else {
+            error!("Expected a cursor shape string, but received {:?}", value);
+        }
+    }
+}
+
+impl From<ShapeOverride> for Value {
+    fn from(shape: ShapeOverride) -> Self {
+        match shape {
+            ShapeOverride::Auto => Value::from("auto"),
+            ShapeOverride::Block => Value::from("block"),
+            ShapeOverride::Bar => Value::from("bar"),
+            ShapeOverride::Underline => Value::from("underline"),
+        }
+    }
+}
+
+// Blink timings in milliseconds used instead of the ones from 'guicursor', zero stops the
+// blinking like it does there
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlinkOverride {
+    pub wait: u64,
+    pub on: u64,
+    pub off: u64,
+}
+
+// The cursor as it is drawn. A shape other than the reported one gets the default cell
+// percentage, since neovim's is sized for its own shape.
+pub fn apply_overrides(
+    cursor: &Cursor,
+    shape: ShapeOverride,
+    blink: Option<BlinkOverride>,
+) -> Cursor {
+    let mut cursor = cursor.clone();
+    if let Some(shape) = shape.cursor_shape() {
+        if cursor.shape != shape {
+            cursor.shape = shape;
+            cursor.cell_percentage = None;
+        }
+    }
+    if let Some(blink) = blink {
+        cursor.blinkwait = Some(blink.wait);
+        cursor.blinkon = Some(blink.on);
+        cursor.blinkoff = Some(blink.off);
+    }
+    cursor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_keeps_the_reported_shape() {
+        let mut cursor = Cursor::new();
+        cursor.shape = CursorShape::Vertical;
+        cursor.cell_percentage = Some(0.25);
+        cursor.blinkon = Some(100);
+
+        assert_eq!(apply_overrides(&cursor, ShapeOverride::Auto, None), cursor);
+        assert_eq!(apply_overrides(&cursor, ShapeOverride::Bar, None), cursor);
+
+        let underline = apply_overrides(&cursor, ShapeOverride::Underline, None);
+        assert_eq!(underline.shape, CursorShape::Horizontal);
+        assert_eq!(underline.cell_percentage, None);
+        assert_eq!(underline.blinkon, Some(100));
+    }
+
+    #[test]
+    fn test_blink_overrides_replace_guicursor() {
+        let blink = BlinkOverride {
+            wait: 500,
+            on: 0,
+            off: 200,
+        };
+        let cursor = apply_overrides(&Cursor::new(), ShapeOverride::Auto, Some(blink));
+        assert_eq!(
+            (cursor.blinkwait, cursor.blinkon, cursor.blinkoff),
+            (Some(500), Some(0), Some(200))
+        );
+    }
+}
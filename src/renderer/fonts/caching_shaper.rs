@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use log::trace;
+use log::{trace, warn};
 use lru::LruCache;
 use skia_safe::{TextBlob, TextBlobBuilder};
 use swash::{
@@ -13,7 +13,10 @@ use swash::{
 };
 use unicode_segmentation::UnicodeSegmentation;
 
-use crate::renderer::fonts::{font_loader::*, font_options::*};
+use crate::{
+    renderer::fonts::{font_loader::*, font_options::*, text_rendering::TextRendering},
+    settings::SETTINGS,
+};
 
 #[derive(new, Clone, Hash, PartialEq, Eq, Debug)]
 struct ShapeKey {
@@ -22,10 +25,31 @@ struct ShapeKey {
     pub italic: bool,
 }
 
+// The font names of g:neovide_font_fallbacks, in the order they are tried
+fn parse_fallback_fonts(fallback_setting: &str) -> Vec<String> {
+    fallback_setting
+        .split(',')
+        .map(str::trim)
+        .filter(|font_name| !font_name.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
 pub struct CachingShaper {
     options: FontOptions,
+    // Fonts from guifontwide, tried before the default font for characters guifont doesn't cover
+    wide_font_list: Vec<String>,
+    // The g:neovide_font_fallbacks setting, and the fonts from it which could be loaded
+    fallback_setting: String,
+    fallback_list: Vec<String>,
+    // Extra pixels between lines from the linespace option, split above and below the text
+    linespace: u64,
+    text_rendering: TextRendering,
     font_loader: FontLoader,
     blob_cache: LruCache<ShapeKey, Vec<TextBlob>>,
+    // Lookups into the blob cache since it was last cleared, shown by the profiler
+    cache_hits: u64,
+    cache_misses: u64,
     shape_context: ShapeContext,
     scale_factor: f32,
     fudge_factor: f32,
@@ -37,8 +61,15 @@ impl CachingShaper {
         let font_size = options.size * scale_factor;
         let mut shaper = CachingShaper {
             options,
-            font_loader: FontLoader::new(font_size),
+            wide_font_list: Vec::new(),
+            fallback_setting: String::new(),
+            fallback_list: Vec::new(),
+            linespace: 0,
+            text_rendering: TextRendering::default(),
+            font_loader: FontLoader::new(font_size, TextRendering::default()),
             blob_cache: LruCache::new(10000),
+            cache_hits: 0,
+            cache_misses: 0,
             shape_context: ShapeContext::new(),
             scale_factor,
             fudge_factor: 1.0,
@@ -73,7 +104,8 @@ impl CachingShaper {
     pub fn update_font(&mut self, guifont_setting: &str) {
         trace!("Updating font: {}", guifont_setting);
 
-        let options = FontOptions::parse(guifont_setting);
+        let mut options = FontOptions::parse(guifont_setting);
+        options.constrain_size(self.options.size, &SETTINGS.get::<FontSettings>());
         let font_key = FontKey::from(&options);
 
         if self.font_loader.get_or_load(&font_key).is_some() {
@@ -85,6 +117,68 @@ impl CachingShaper {
         }
     }
 
+    pub fn update_wide_font(&mut self, guifontwide_setting: &str) {
+        trace!("Updating wide font: {}", guifontwide_setting);
+        self.wide_font_list = FontOptions::parse(guifontwide_setting).font_list;
+        self.blob_cache.clear();
+    }
+
+    // Returns whether the fallback fonts changed
+    pub fn update_fallback_fonts(&mut self, fallback_setting: &str) -> bool {
+        if self.fallback_setting == fallback_setting {
+            return false;
+        }
+        self.fallback_setting = fallback_setting.to_owned();
+        self.reload_fallback_fonts();
+        true
+    }
+
+    // Looks the fallback fonts up again, for example after installing one. Fonts which can't be
+    // found are left out of the chain rather than tried for every character.
+    pub fn reload_fallback_fonts(&mut self) {
+        trace!("Loading fallback fonts: {}", self.fallback_setting);
+        let font_names = parse_fallback_fonts(&self.fallback_setting);
+        self.fallback_list = font_names
+            .into_iter()
+            .filter(|font_name| {
+                let font_key = FontKey {
+                    italic: false,
+                    bold: false,
+                    font_selection: font_name.into(),
+                };
+                let found = self.font_loader.get_or_load(&font_key).is_some();
+                if !found {
+                    warn!(
+                        "Fallback font {} could not be found, skipping it",
+                        font_name
+                    );
+                }
+                found
+            })
+            .collect();
+        self.blob_cache.clear();
+    }
+
+    pub fn fallback_fonts(&self) -> &[String] {
+        &self.fallback_list
+    }
+
+    pub fn update_linespace(&mut self, linespace: u64) {
+        trace!("Updating linespace: {}", linespace);
+        self.linespace = linespace;
+    }
+
+    // Returns whether the fonts had to be reloaded, hinting can change the cell size
+    pub fn update_text_rendering(&mut self, text_rendering: TextRendering) -> bool {
+        if self.text_rendering == text_rendering {
+            return false;
+        }
+        trace!("Updating text rendering: {:?}", text_rendering);
+        self.text_rendering = text_rendering;
+        self.reset_font_loader();
+        true
+    }
+
     fn reset_font_loader(&mut self) {
         // Calculate the new fudge factor required to scale the font width to the nearest exact pixel
         // NOTE: This temporarily loads the font without any fudge factor, since the interface
@@ -92,7 +186,7 @@ impl CachingShaper {
         self.fudge_factor = 1.0;
         let mut font_size = self.current_size();
         trace!("Using font_size: {:.2}px", font_size);
-        self.font_loader = FontLoader::new(font_size);
+        self.font_loader = FontLoader::new(font_size, self.text_rendering.clone());
         let (metrics, font_width) = self.info();
         trace!(
             "Font width: {:.2}px {:.2}px",
@@ -105,8 +199,20 @@ impl CachingShaper {
         trace!("Fudged font size: {:.2}px", font_size);
         trace!("Fudged font width: {:.2}px", self.info().1);
 
-        self.font_loader = FontLoader::new(font_size);
+        self.font_loader = FontLoader::new(font_size, self.text_rendering.clone());
         self.blob_cache.clear();
+        self.cache_hits = 0;
+        self.cache_misses = 0;
+    }
+
+    // The blobs are made again the next time their text is drawn, after the gpu context they
+    // were uploaded to is gone
+    pub fn clear_blob_cache(&mut self) {
+        self.blob_cache.clear();
+    }
+
+    pub fn cache_stats(&self) -> (u64, u64) {
+        (self.cache_hits, self.cache_misses)
     }
 
     pub fn font_names(&self) -> Vec<String> {
@@ -139,19 +245,20 @@ impl CachingShaper {
 
     pub fn font_base_dimensions(&mut self) -> (u64, u64) {
         let (metrics, glyph_advance) = self.info();
-        let font_height = (metrics.ascent + metrics.descent + metrics.leading).ceil() as u64;
+        let font_height =
+            (metrics.ascent + metrics.descent + metrics.leading).ceil() as u64 + self.linespace;
         let font_width = (glyph_advance + 0.5).floor() as u64;
 
         (font_width, font_height)
     }
 
     pub fn underline_position(&mut self) -> u64 {
-        self.metrics().underline_offset as u64
+        self.metrics().underline_offset as u64 + (self.linespace - self.linespace / 2)
     }
 
     pub fn y_adjustment(&mut self) -> u64 {
         let metrics = self.metrics();
-        (metrics.ascent + metrics.leading).ceil() as u64
+        (metrics.ascent + metrics.leading).ceil() as u64 + self.linespace / 2
     }
 
     fn build_clusters(
@@ -190,12 +297,19 @@ impl CachingShaper {
             // Create font fallback list
             let mut font_fallback_keys = Vec::new();
 
-            // Add parsed fonts from guifont
-            font_fallback_keys.extend(self.options.font_list.iter().map(|font_name| FontKey {
-                italic: self.options.italic || italic,
-                bold: self.options.bold || bold,
-                font_selection: font_name.into(),
-            }));
+            // Add parsed fonts from guifont, followed by the ones from guifontwide and the fallbacks
+            font_fallback_keys.extend(
+                self.options
+                    .font_list
+                    .iter()
+                    .chain(self.wide_font_list.iter())
+                    .chain(self.fallback_list.iter())
+                    .map(|font_name| FontKey {
+                        italic: self.options.italic || italic,
+                        bold: self.options.bold || bold,
+                        font_selection: font_name.into(),
+                    }),
+            );
 
             // Add default font
             font_fallback_keys.push(FontKey {
@@ -316,10 +430,18 @@ impl CachingShaper {
         resulting_blobs
     }
 
+    pub fn is_cached(&self, text: &str, bold: bool, italic: bool) -> bool {
+        self.blob_cache
+            .contains(&ShapeKey::new(text.to_owned(), bold, italic))
+    }
+
     pub fn shape_cached(&mut self, text: String, bold: bool, italic: bool) -> &Vec<TextBlob> {
         let key = ShapeKey::new(text.clone(), bold, italic);
 
-        if !self.blob_cache.contains(&key) {
+        if self.blob_cache.contains(&key) {
+            self.cache_hits += 1;
+        } else {
+            self.cache_misses += 1;
             let blobs = self.shape(text, bold, italic);
             self.blob_cache.put(key.clone(), blobs);
         }
@@ -327,3 +449,84 @@ impl CachingShaper {
         self.blob_cache.get(&key).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_cache_hits_repeated_text() {
+        let mut shaper = CachingShaper::new(1.0);
+        shaper.shape_cached("let".to_owned(), false, false);
+        shaper.shape_cached("let".to_owned(), false, false);
+        shaper.shape_cached("let".to_owned(), true, false);
+
+        assert_eq!(shaper.cache_stats(), (1, 2));
+    }
+
+    #[test]
+    fn test_blob_cache_invalidated_on_font_change() {
+        FontSettings::register();
+        let mut shaper = CachingShaper::new(1.0);
+        shaper.shape_cached("let".to_owned(), false, false);
+
+        shaper.update_font("");
+        assert_eq!(shaper.cache_stats(), (0, 0));
+        shaper.shape_cached("let".to_owned(), false, false);
+        assert_eq!(shaper.cache_stats(), (0, 1));
+
+        shaper.update_scale_factor(2.0);
+        shaper.shape_cached("let".to_owned(), false, false);
+        assert_eq!(shaper.cache_stats(), (0, 1));
+    }
+
+    #[test]
+    fn test_blob_cache_invalidated_on_text_rendering_change() {
+        let mut shaper = CachingShaper::new(1.0);
+        shaper.shape_cached("let".to_owned(), false, false);
+
+        assert!(!shaper.update_text_rendering(TextRendering::default()));
+        assert_eq!(shaper.cache_stats(), (0, 1));
+
+        let lighter = TextRendering {
+            gamma: 0.8,
+            ..TextRendering::default()
+        };
+        assert!(shaper.update_text_rendering(lighter));
+        assert_eq!(shaper.cache_stats(), (0, 0));
+    }
+
+    #[test]
+    fn test_linespace_adds_to_line_height() {
+        let mut shaper = CachingShaper::new(1.0);
+        let (width, height) = shaper.font_base_dimensions();
+        let y_adjustment = shaper.y_adjustment();
+
+        shaper.update_linespace(4);
+
+        assert_eq!(shaper.font_base_dimensions(), (width, height + 4));
+        assert_eq!(shaper.y_adjustment(), y_adjustment + 2);
+    }
+
+    #[test]
+    fn test_fallback_fonts_keep_their_order() {
+        assert_eq!(
+            parse_fallback_fonts("Noto Color Emoji, Noto Sans CJK JP,,"),
+            vec!["Noto Color Emoji", "Noto Sans CJK JP"]
+        );
+        assert!(parse_fallback_fonts("").is_empty());
+    }
+
+    #[test]
+    fn test_missing_fallback_fonts_are_skipped() {
+        let mut shaper = CachingShaper::new(1.0);
+        shaper.shape_cached("let".to_owned(), false, false);
+
+        assert!(shaper.update_fallback_fonts("Not An Installed Font 7f3a"));
+        assert!(shaper.fallback_fonts().is_empty());
+        // The text is shaped again with the new chain
+        shaper.shape_cached("let".to_owned(), false, false);
+        assert_eq!(shaper.cache_stats(), (0, 2));
+        assert!(!shaper.update_fallback_fonts("Not An Installed Font 7f3a"));
+    }
+}
@@ -1,9 +1,11 @@
 use std::sync::Arc;
 
 use lru::LruCache;
-use skia_safe::{font::Edging, Data, Font, FontHinting, FontMgr, FontStyle, Typeface};
+use skia_safe::{Data, Font, FontMgr, FontStyle, Typeface};
 
-use crate::renderer::fonts::{font_options::FontOptions, swash_font::SwashFont};
+use crate::renderer::fonts::{
+    font_options::FontOptions, swash_font::SwashFont, text_rendering::TextRendering,
+};
 
 static DEFAULT_FONT: &[u8] = include_bytes!("../../../assets/fonts/FiraCode-Regular.ttf");
 static LAST_RESORT_FONT: &[u8] = include_bytes!("../../../assets/fonts/LastResort-Regular.ttf");
@@ -14,10 +16,10 @@ pub struct FontPair {
 }
 
 impl FontPair {
-    fn new(mut skia_font: Font) -> Option<FontPair> {
+    fn new(mut skia_font: Font, text_rendering: &TextRendering) -> Option<FontPair> {
         skia_font.set_subpixel(true);
-        skia_font.set_hinting(FontHinting::Full);
-        skia_font.set_edging(Edging::AntiAlias);
+        skia_font.set_hinting(text_rendering.hinting);
+        skia_font.set_edging(text_rendering.edging);
 
         let (font_data, index) = skia_font.typeface().unwrap().to_font_data().unwrap();
         let swash_font = SwashFont::from_data(font_data, index)?;
@@ -39,6 +41,7 @@ pub struct FontLoader {
     font_mgr: FontMgr,
     cache: LruCache<FontKey, Arc<FontPair>>,
     font_size: f32,
+    text_rendering: TextRendering,
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
@@ -105,11 +108,12 @@ impl From<char> for FontSelection {
 }
 
 impl FontLoader {
-    pub fn new(font_size: f32) -> FontLoader {
+    pub fn new(font_size: f32, text_rendering: TextRendering) -> FontLoader {
         FontLoader {
             font_mgr: FontMgr::new(),
             cache: LruCache::new(10),
             font_size,
+            text_rendering,
         }
     }
 
@@ -124,7 +128,10 @@ impl FontLoader {
         match font_key.font_selection {
             FontSelection::Name(name) => {
                 let typeface = self.font_mgr.match_family_style(name, font_style)?;
-                FontPair::new(Font::from_typeface(typeface, self.font_size))
+                FontPair::new(
+                    Font::from_typeface(typeface, self.font_size),
+                    &self.text_rendering,
+                )
             }
             FontSelection::Character(character) => {
                 let typeface = self.font_mgr.match_family_style_character(
@@ -133,17 +140,26 @@ impl FontLoader {
                     &[],
                     character as i32,
                 )?;
-                FontPair::new(Font::from_typeface(typeface, self.font_size))
+                FontPair::new(
+                    Font::from_typeface(typeface, self.font_size),
+                    &self.text_rendering,
+                )
             }
             FontSelection::Default => {
                 let data = Data::new_copy(DEFAULT_FONT);
                 let typeface = Typeface::from_data(data, 0).unwrap();
-                FontPair::new(Font::from_typeface(typeface, self.font_size))
+                FontPair::new(
+                    Font::from_typeface(typeface, self.font_size),
+                    &self.text_rendering,
+                )
             }
             FontSelection::LastResort => {
                 let data = Data::new_copy(LAST_RESORT_FONT);
                 let typeface = Typeface::from_data(data, 0).unwrap();
-                FontPair::new(Font::from_typeface(typeface, self.font_size))
+                FontPair::new(
+                    Font::from_typeface(typeface, self.font_size),
+                    &self.text_rendering,
+                )
             }
         }
     }
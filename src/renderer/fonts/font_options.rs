@@ -1,7 +1,32 @@
+use log::{info, warn};
+
 use super::font_loader::FontSelection;
+use crate::settings::*;
 
 const DEFAULT_FONT_SIZE: f32 = 14.0;
 
+#[derive(Clone, SettingGroup)]
+#[setting_prefix = "font"]
+pub struct FontSettings {
+    // Limits in points for the size guifont asks for, so that a stray setting can't make the
+    // window unusable
+    pub size_min: f32,
+    pub size_max: f32,
+    // Comma separated fonts tried in order for characters guifont and guifontwide don't cover,
+    // before the bundled fonts and the system's fallback
+    pub fallbacks: String,
+}
+
+impl Default for FontSettings {
+    fn default() -> Self {
+        Self {
+            size_min: 1.0,
+            size_max: 200.0,
+            fallbacks: String::new(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct FontOptions {
     pub font_list: Vec<String>,
@@ -51,6 +76,31 @@ impl FontOptions {
         }
     }
 
+    // Sizes which can't be drawn fall back to the previous size, anything else is kept within the
+    // configured limits
+    pub fn constrain_size(&mut self, previous_size: f32, settings: &FontSettings) {
+        if !(self.size > 0.0 && self.size.is_finite()) {
+            warn!(
+                "Font size {} is not positive, keeping the previous size",
+                self.size
+            );
+            self.size = previous_size;
+            return;
+        }
+
+        let constrained_size = self
+            .size
+            .min(points_to_pixels(settings.size_max))
+            .max(points_to_pixels(settings.size_min));
+        if (constrained_size - self.size).abs() > std::f32::EPSILON {
+            info!(
+                "Font size clamped to {}px, between g:neovide_font_size_min and g:neovide_font_size_max",
+                constrained_size
+            );
+            self.size = constrained_size;
+        }
+    }
+
     pub fn primary_font(&self) -> FontSelection {
         self.font_list
             .first()
@@ -99,3 +149,33 @@ fn points_to_pixels(value: f32) -> f32 {
         value * (pixels_per_inch / points_per_inch)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constrain_size() {
+        let settings = FontSettings {
+            size_min: 6.0,
+            size_max: 30.0,
+        };
+        let previous_size = points_to_pixels(12.0);
+
+        let mut options = FontOptions::parse("Fira Code:h500");
+        options.constrain_size(previous_size, &settings);
+        assert_eq!(options.size, points_to_pixels(30.0));
+
+        let mut options = FontOptions::parse("Fira Code:h2");
+        options.constrain_size(previous_size, &settings);
+        assert_eq!(options.size, points_to_pixels(6.0));
+
+        let mut options = FontOptions::parse("Fira Code:h0");
+        options.constrain_size(previous_size, &settings);
+        assert_eq!(options.size, previous_size);
+
+        let mut options = FontOptions::parse("Fira Code:h-4");
+        options.constrain_size(previous_size, &settings);
+        assert_eq!(options.size, previous_size);
+    }
+}
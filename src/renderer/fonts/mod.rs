@@ -1,4 +1,5 @@
 pub mod caching_shaper;
 mod font_loader;
-mod font_options;
+pub mod font_options;
 mod swash_font;
+pub mod text_rendering;
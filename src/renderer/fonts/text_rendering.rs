@@ -0,0 +1,180 @@
+use log::error;
+use rmpv::Value;
+use skia_safe::{font::Edging, table_mask_filter, FontHinting, MaskFilter};
+
+use crate::settings::*;
+
+// How strongly glyph outlines are fit to the pixel grid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HintingLevel {
+    None,
+    Slight,
+    Normal,
+    Full,
+}
+
+impl HintingLevel {
+    fn font_hinting(&self) -> FontHinting {
+        match self {
+            HintingLevel::None => FontHinting::None,
+            HintingLevel::Slight => FontHinting::Slight,
+            HintingLevel::Normal => FontHinting::Normal,
+            HintingLevel::Full => FontHinting::Full,
+        }
+    }
+}
+
+impl FromValue for HintingLevel {
+    fn from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "none" => HintingLevel::None,
+                "slight" => HintingLevel::Slight,
+                "normal" => HintingLevel::Normal,
+                "full" => HintingLevel::Full,
+                value => {
+                    error!("Expected a hinting level, but received {:?}", value);
+                    return;
+                }
+            };
+        } else {
+            error!("Expected a hinting level string, but received {:?}", value);
+        }
+    }
+}
+
+impl From<HintingLevel> for Value {
+    fn from(hinting: HintingLevel) -> Self {
+        match hinting {
+            HintingLevel::None => Value::from("none"),
+            HintingLevel::Slight => Value::from("slight"),
+            HintingLevel::Normal => Value::from("normal"),
+            HintingLevel::Full => Value::from("full"),
+        }
+    }
+}
+
+#[derive(Clone, SettingGroup)]
+pub struct TextRenderingSettings {
+    // LCD antialiasing, which only applies while the window is opaque
+    pub subpixel_aa: bool,
+    // Above 1 thickens the glyphs' edges, below 1 thins them
+    #[setting(min = 0.1, max = 4.0)]
+    pub text_gamma: f32,
+    // Pushes partly covered pixels towards fully covered or empty
+    #[setting(min = 0.0, max = 1.0)]
+    pub text_contrast: f32,
+    pub font_hinting: HintingLevel,
+}
+
+impl Default for TextRenderingSettings {
+    fn default() -> Self {
+        Self {
+            subpixel_aa: false,
+            text_gamma: 1.0,
+            text_contrast: 0.0,
+            font_hinting: HintingLevel::Full,
+        }
+    }
+}
+
+// How the glyphs of the grid are rasterized. Fonts and the text blobs shaped from them carry the
+// edging and hinting, so a change means loading the fonts again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextRendering {
+    pub edging: Edging,
+    pub hinting: FontHinting,
+    pub gamma: f32,
+    pub contrast: f32,
+}
+
+impl Default for TextRendering {
+    fn default() -> Self {
+        TextRendering::new(&TextRenderingSettings::default(), true)
+    }
+}
+
+impl TextRendering {
+    // LCD filtering assumes the glyph lands on an opaque background of a known color, over a
+    // translucent window the colored fringes show through instead of blending away
+    pub fn new(settings: &TextRenderingSettings, opaque: bool) -> TextRendering {
+        let edging = if settings.subpixel_aa && opaque {
+            Edging::SubpixelAntiAlias
+        } else {
+            Edging::AntiAlias
+        };
+
+        TextRendering {
+            edging,
+            hinting: settings.font_hinting.font_hinting(),
+            gamma: settings.text_gamma,
+            contrast: settings.text_contrast,
+        }
+    }
+
+    // Maps the coverage of each glyph pixel, or None when it is left as skia rasterized it
+    pub fn coverage_table(&self) -> Option<[u8; 256]> {
+        if (self.gamma - 1.0).abs() < f32::EPSILON && self.contrast <= 0.0 {
+            return None;
+        }
+
+        let mut table = [0; 256];
+        for (index, entry) in table.iter_mut().enumerate() {
+            let coverage = (index as f32 / 255.0).powf(1.0 / self.gamma);
+            let coverage = 0.5 + (coverage - 0.5) * (1.0 + self.contrast);
+            *entry = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        Some(table)
+    }
+
+    // Glyphs drawn with a mask filter skip skia's glyph atlas, so it is only built when the
+    // coverage is actually changed
+    pub fn mask_filter(&self) -> Option<MaskFilter> {
+        self.coverage_table()
+            .and_then(|table| table_mask_filter::new(&table))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subpixel_needs_an_opaque_window() {
+        let settings = TextRenderingSettings {
+            subpixel_aa: true,
+            ..TextRenderingSettings::default()
+        };
+        assert_eq!(
+            TextRendering::new(&settings, true).edging,
+            Edging::SubpixelAntiAlias
+        );
+        assert_eq!(
+            TextRendering::new(&settings, false).edging,
+            Edging::AntiAlias
+        );
+    }
+
+    #[test]
+    fn test_coverage_table() {
+        assert_eq!(TextRendering::default().coverage_table(), None);
+
+        let bolder = TextRendering {
+            gamma: 2.0,
+            ..TextRendering::default()
+        }
+        .coverage_table()
+        .unwrap();
+        assert_eq!((bolder[0], bolder[255]), (0, 255));
+        assert!(bolder[64] > 64);
+
+        let sharper = TextRendering {
+            contrast: 1.0,
+            ..TextRendering::default()
+        }
+        .coverage_table()
+        .unwrap();
+        assert_eq!((sharper[32], sharper[224]), (0, 255));
+        assert!(sharper[96] < 96 && sharper[160] > 160);
+    }
+}
@@ -1,13 +1,16 @@
 use std::sync::Arc;
 
-use glutin::dpi::PhysicalSize;
+use glutin::dpi::{PhysicalPosition, PhysicalSize};
 use log::trace;
-use skia_safe::{colors, dash_path_effect, BlendMode, Canvas, Color, Paint, Rect, HSV};
+use skia_safe::{
+    colors, dash_path_effect, BlendMode, Canvas, Color, MaskFilter, Paint, Point, Rect, HSV,
+};
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
     dimensions::Dimensions,
     editor::{Colors, Style},
-    renderer::{CachingShaper, RendererSettings},
+    renderer::{CachingShaper, RendererSettings, TextRendering},
     settings::*,
     window::WindowSettings,
 };
@@ -15,10 +18,14 @@ use crate::{
 pub struct GridRenderer {
     pub shaper: CachingShaper,
     pub paint: Paint,
+    // Applied to the glyphs only, from the text gamma and contrast
+    text_mask_filter: Option<MaskFilter>,
     pub default_style: Arc<Style>,
     pub font_dimensions: Dimensions,
     pub scale_factor: f64,
     pub is_ready: bool,
+    // What is left of this frame's glyph budget, None without a limit
+    glyph_budget: Option<u64>,
 }
 
 impl GridRenderer {
@@ -36,13 +43,43 @@ impl GridRenderer {
         GridRenderer {
             shaper,
             paint,
+            text_mask_filter: None,
             default_style,
             font_dimensions,
             scale_factor,
             is_ready: false,
+            glyph_budget: None,
         }
     }
 
+    pub fn reset_glyph_budget(&mut self, glyph_budget: u64) {
+        self.glyph_budget = if glyph_budget == 0 {
+            None
+        } else {
+            Some(glyph_budget)
+        };
+    }
+
+    // Whether the text may be drawn this frame. Text which is shaped already costs nothing, other
+    // text uses up the budget by its glyphs. The text which goes over the budget is still drawn
+    // so that even a line longer than the budget makes progress.
+    pub fn take_glyph_budget(&mut self, text: &str, style: &Option<Arc<Style>>) -> bool {
+        let remaining = match self.glyph_budget {
+            Some(remaining) => remaining,
+            None => return true,
+        };
+        let style = style.as_ref().unwrap_or(&self.default_style);
+        if self.shaper.is_cached(text, style.bold, style.italic) {
+            return true;
+        }
+        if remaining == 0 {
+            return false;
+        }
+        let glyphs = text.graphemes(true).count() as u64;
+        self.glyph_budget = Some(remaining.saturating_sub(glyphs));
+        true
+    }
+
     pub fn font_names(&self) -> Vec<String> {
         self.shaper.font_names()
     }
@@ -57,6 +94,28 @@ impl GridRenderer {
         (grid * self.font_dimensions).into()
     }
 
+    /// Convert a pixel position relative to the grid to the cell under it. The cell size changes
+    /// with the scale factor, so anything mapping pixels to cells should go through here.
+    pub fn convert_physical_to_cell(
+        &self,
+        position: PhysicalPosition<f32>,
+    ) -> PhysicalPosition<u32> {
+        PhysicalPosition::new(
+            (position.x as u64 / self.font_dimensions.width) as u32,
+            (position.y as u64 / self.font_dimensions.height) as u32,
+        )
+    }
+
+    /// The pixel rect of the cell starting at the given position
+    pub fn cell_rect(&self, position: Point) -> Rect {
+        Rect::from_xywh(
+            position.x,
+            position.y,
+            self.font_dimensions.width as f32,
+            self.font_dimensions.height as f32,
+        )
+    }
+
     pub fn handle_scale_factor_update(&mut self, scale_factor: f64) {
         self.shaper.update_scale_factor(scale_factor as f32);
         self.update_font_dimensions();
@@ -67,6 +126,30 @@ impl GridRenderer {
         self.update_font_dimensions();
     }
 
+    pub fn update_wide_font(&mut self, guifontwide_setting: &str) {
+        self.shaper.update_wide_font(guifontwide_setting);
+    }
+
+    // Returns whether the fallback fonts changed, in which case the grids need to be drawn again
+    pub fn update_fallback_fonts(&mut self, fallback_setting: &str) -> bool {
+        self.shaper.update_fallback_fonts(fallback_setting)
+    }
+
+    pub fn update_linespace(&mut self, linespace: u64) {
+        self.shaper.update_linespace(linespace);
+        self.update_font_dimensions();
+    }
+
+    // Returns whether the glyphs changed, in which case the grids need to be drawn again
+    pub fn update_text_rendering(&mut self, text_rendering: TextRendering) -> bool {
+        if !self.shaper.update_text_rendering(text_rendering.clone()) {
+            return false;
+        }
+        self.text_mask_filter = text_rendering.mask_filter();
+        self.update_font_dimensions();
+        true
+    }
+
     fn update_font_dimensions(&mut self) {
         self.font_dimensions = self.shaper.font_base_dimensions().into();
         self.is_ready = true;
@@ -80,6 +163,14 @@ impl GridRenderer {
         Rect::new(x as f32, y as f32, (x + width) as f32, (y + height) as f32)
     }
 
+    pub fn background_color(&self, style: &Option<Arc<Style>>) -> Color {
+        style
+            .as_ref()
+            .unwrap_or(&self.default_style)
+            .background(&self.default_style.colors)
+            .to_color()
+    }
+
     pub fn get_default_background(&self) -> Color {
         self.default_style.colors.background.unwrap().to_color()
     }
@@ -106,7 +197,7 @@ impl GridRenderer {
                 .set_color(style.background(&self.default_style.colors).to_color());
         }
 
-        if (SETTINGS.get::<WindowSettings>().transparency - 1.0).abs() > f32::EPSILON
+        if !SETTINGS.get::<WindowSettings>().is_opaque()
             && self.paint.color() == self.get_default_background()
         {
             if is_floating {
@@ -125,6 +216,7 @@ impl GridRenderer {
         text: String,
         grid_position: (u64, u64),
         cell_width: u64,
+        (overflow_left, overflow_right): (u64, u64),
         style: &Option<Arc<Style>>,
     ) {
         let (x, y) = grid_position * self.font_dimensions;
@@ -134,11 +226,13 @@ impl GridRenderer {
 
         canvas.save();
 
-        // We don't want to clip text in the x position, only the y so we add a buffer of 1
-        // character on either side of the region so that we clip vertically but not horizontally
+        // The text is clipped vertically, horizontally the glyphs may overhang into the given
+        // number of cells on either side
         let (grid_x, grid_y) = grid_position;
-        let clip_position = (grid_x.saturating_sub(1), grid_y);
-        let region = self.compute_text_region(clip_position, cell_width + 2);
+        let overflow_left = overflow_left.min(grid_x);
+        let clip_position = (grid_x - overflow_left, grid_y);
+        let region =
+            self.compute_text_region(clip_position, cell_width + overflow_left + overflow_right);
 
         canvas.clip_rect(region, None, Some(false));
 
@@ -183,6 +277,7 @@ impl GridRenderer {
                 .set_color(style.foreground(&self.default_style.colors).to_color());
         }
         self.paint.set_anti_alias(false);
+        self.paint.set_mask_filter(self.text_mask_filter.clone());
 
         for blob in self
             .shaper
@@ -191,6 +286,7 @@ impl GridRenderer {
         {
             canvas.draw_text_blob(blob, (x as f32, (y + y_adjustment) as f32), &self.paint);
         }
+        self.paint.set_mask_filter(None);
 
         if style.strikethrough {
             let line_position = region.center_y();
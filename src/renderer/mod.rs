@@ -1,8 +1,14 @@
 pub mod animation_utils;
+mod banner;
+mod command_line;
 pub mod cursor_renderer;
 mod fonts;
 pub mod grid_renderer;
+mod popup_menu;
+mod profiler;
 mod rendered_window;
+mod tabline;
+mod titlebar;
 
 use std::{
     cmp::Ordering,
@@ -10,40 +16,129 @@ use std::{
     sync::Arc,
 };
 
+use glutin::dpi::PhysicalPosition;
 use log::error;
-use skia_safe::Canvas;
+use rmpv::Value;
+use skia_safe::{Canvas, Paint, Rect};
 use tokio::sync::mpsc::UnboundedReceiver;
 
 use crate::{
     bridge::EditorMode,
-    editor::{Cursor, Style},
+    dimensions::Dimensions,
+    editor::{Cursor, LinkSpan, Style},
     event_aggregator::EVENT_AGGREGATOR,
+    profiling::PROFILER,
     settings::*,
+    window::Padding,
     WindowSettings,
 };
 
+use banner::BannerOverlay;
+use command_line::CommandLineRenderer;
+pub use command_line::{CommandLineContents, CommandLineLevel, CommandLineSettings, StyledLine};
 use cursor_renderer::CursorRenderer;
-pub use fonts::caching_shaper::CachingShaper;
+pub use fonts::{
+    caching_shaper::CachingShaper,
+    font_options::FontSettings,
+    text_rendering::{TextRendering, TextRenderingSettings},
+};
 pub use grid_renderer::GridRenderer;
+use popup_menu::PopupMenuRenderer;
+pub use popup_menu::{PopupMenuDrawCommand, PopupMenuSettings, PopupMenuStyles};
+use profiler::ProfilerOverlay;
 pub use rendered_window::{LineFragment, RenderedWindow, WindowDrawCommand, WindowDrawDetails};
+use tabline::TablineRenderer;
+pub use tabline::{TablineContents, TablineHit, TablineSettings};
+use titlebar::TitlebarRenderer;
+pub use titlebar::{is_double_click, TitlebarHit, TitlebarSettings};
+
+// The easing curve of the scroll animation. Each one slows down towards the destination.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScrollEasing {
+    Linear,
+    Quad,
+    Cubic,
+    Expo,
+}
+
+impl ScrollEasing {
+    fn function(&self) -> fn(f32) -> f32 {
+        match self {
+            ScrollEasing::Linear => animation_utils::ease_linear,
+            ScrollEasing::Quad => animation_utils::ease_out_quad,
+            ScrollEasing::Cubic => animation_utils::ease_out_cubic,
+            ScrollEasing::Expo => animation_utils::ease_out_expo,
+        }
+    }
+}
+
+impl FromValue for ScrollEasing {
+    fn from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "linear" => ScrollEasing::Linear,
+                "quad" => ScrollEasing::Quad,
+                "cubic" => ScrollEasing::Cubic,
+                "expo" => ScrollEasing::Expo,
+                value => {
+                    error!("Expected a scroll easing name, but received {:?}", value);
+                    return;
+                }
+            };
+        } else {
+            error!("Expected a scroll easing string, but received {:?}", value);
+        }
+    }
+}
+
+impl From<ScrollEasing> for Value {
+    fn from(easing: ScrollEasing) -> Self {
+        match easing {
+            ScrollEasing::Linear => Value::from("linear"),
+            ScrollEasing::Quad => Value::from("quad"),
+            ScrollEasing::Cubic => Value::from("cubic"),
+            ScrollEasing::Expo => Value::from("expo"),
+        }
+    }
+}
 
 #[derive(SettingGroup, Clone)]
 pub struct RendererSettings {
     position_animation_length: f32,
+    smooth_scroll: bool,
     scroll_animation_length: f32,
+    scroll_animation_easing: ScrollEasing,
+    // The most lines a scroll animates through, longer jumps such as G start closer to their
+    // destination. Zero animates every jump in full.
+    scroll_animation_far_lines: u64,
     floating_opacity: f32,
     floating_blur: bool,
     debug_renderer: bool,
+    debug_damage: bool,
+    // Lets wide glyphs such as sign column icons spill into an empty cell after them instead of
+    // clipping every glyph to its cells
+    glyph_overflow: bool,
+    // The most glyphs shaped and rasterized in a frame. Text over the budget shows only its
+    // background until a later frame gets to it. Zero removes the limit.
+    glyph_budget: u64,
+    profiler: bool,
 }
 
 impl Default for RendererSettings {
     fn default() -> Self {
         Self {
             position_animation_length: 0.15,
+            smooth_scroll: true,
             scroll_animation_length: 0.3,
+            scroll_animation_easing: ScrollEasing::Expo,
+            scroll_animation_far_lines: 0,
             floating_opacity: 0.7,
             floating_blur: true,
             debug_renderer: false,
+            debug_damage: false,
+            glyph_overflow: true,
+            glyph_budget: 20000,
+            profiler: false,
         }
     }
 }
@@ -57,12 +152,32 @@ pub enum DrawCommand {
     },
     UpdateCursor(Cursor),
     FontChanged(String),
+    WideFontChanged(String),
+    LineSpaceChanged(u64),
     DefaultStyleChanged(Style),
     ModeChanged(EditorMode),
+    PopupMenu(PopupMenuDrawCommand),
+    CommandLine(CommandLineContents),
+    Tabline(TablineContents),
+    // The TabLine highlight, which colors the custom titlebar
+    TitlebarStyle(Option<Arc<Style>>),
+    HoverLink(Option<LinkSpan>),
 }
 
 pub struct Renderer {
     cursor_renderer: CursorRenderer,
+    popup_menu_renderer: PopupMenuRenderer,
+    command_line_renderer: CommandLineRenderer,
+    tabline_renderer: TablineRenderer,
+    titlebar_renderer: TitlebarRenderer,
+    profiler_overlay: ProfilerOverlay,
+    banner_overlay: BannerOverlay,
+    banner: Option<String>,
+    hovered_link: Option<LinkSpan>,
+    // Set by the window, which may widen the padding settings for zen mode
+    pub padding: Padding,
+    // The opacity windows without the cursor are drawn with
+    pub inactive_window_opacity: f32,
     pub grid_renderer: GridRenderer,
     current_mode: EditorMode,
 
@@ -70,11 +185,19 @@ pub struct Renderer {
     pub window_regions: Vec<WindowDrawDetails>,
 
     pub batched_draw_command_receiver: UnboundedReceiver<Vec<DrawCommand>>,
+    // Batches taken off the receiver which the next frame applies
+    pending_draw_commands: Vec<DrawCommand>,
 }
 
 impl Renderer {
     pub fn new(scale_factor: f64) -> Self {
         let cursor_renderer = CursorRenderer::new();
+        let popup_menu_renderer = PopupMenuRenderer::new();
+        let command_line_renderer = CommandLineRenderer::new();
+        let tabline_renderer = TablineRenderer::new();
+        let titlebar_renderer = TitlebarRenderer::new();
+        let profiler_overlay = ProfilerOverlay::new();
+        let banner_overlay = BannerOverlay::new();
         let grid_renderer = GridRenderer::new(scale_factor);
         let current_mode = EditorMode::Unknown(String::from(""));
 
@@ -86,32 +209,155 @@ impl Renderer {
         Renderer {
             rendered_windows,
             cursor_renderer,
+            popup_menu_renderer,
+            command_line_renderer,
+            tabline_renderer,
+            titlebar_renderer,
+            profiler_overlay,
+            banner_overlay,
+            banner: None,
+            hovered_link: None,
+            padding: SETTINGS.get::<WindowSettings>().padding(),
+            inactive_window_opacity: 1.0,
             grid_renderer,
             current_mode,
             window_regions,
             batched_draw_command_receiver,
+            pending_draw_commands: Vec::new(),
         }
     }
 
+    // Takes the batches which arrived since the last frame, returning whether there were any
+    pub fn poll_draw_commands(&mut self) -> bool {
+        let mut received = false;
+        while let Ok(draw_commands) = self.batched_draw_command_receiver.try_recv() {
+            self.pending_draw_commands.extend(draw_commands);
+            received = true;
+        }
+        received
+    }
+
     pub fn font_names(&self) -> Vec<String> {
         self.grid_renderer.font_names()
     }
 
+    pub fn set_window_focused(&mut self, window_focused: bool) {
+        self.cursor_renderer.set_window_focused(window_focused);
+    }
+
+    // Shows a message over the grids until it is set back to None
+    pub fn set_banner(&mut self, banner: Option<String>) {
+        self.banner = banner;
+    }
+
+    // The tabline sits directly above the grid, inside the top padding
+    pub fn tabline_height(&self) -> u32 {
+        self.tabline_renderer
+            .height(self.grid_renderer.font_dimensions.height)
+    }
+
+    // The titlebar sits at the very top of the window, above the padding and the tabline
+    pub fn titlebar_height(&self) -> u32 {
+        self.titlebar_renderer
+            .height(self.grid_renderer.font_dimensions.height)
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.titlebar_renderer.set_title(title);
+    }
+
+    // What a position in physical pixels from the top left of the window is on the titlebar
+    pub fn titlebar_hit(
+        &self,
+        position: PhysicalPosition<f64>,
+        window_width: u32,
+    ) -> Option<TitlebarHit> {
+        if position.y < 0.0 || position.y >= self.titlebar_height() as f64 {
+            return None;
+        }
+        Some(self.titlebar_renderer.hit(
+            position.x,
+            window_width as f64,
+            self.grid_renderer.font_dimensions.width,
+        ))
+    }
+
+    // The tab under a position in physical pixels from the top left of the window
+    pub fn tabline_hit(&self, position: PhysicalPosition<f64>) -> Option<TablineHit> {
+        let padding = self.padding;
+        let top = padding.top.saturating_sub(self.tabline_height()) as f64;
+        if position.y < top || position.y >= padding.top as f64 {
+            return None;
+        }
+        let x = position.x - padding.left as f64;
+        if x < 0.0 {
+            return None;
+        }
+        let column = x / self.grid_renderer.font_dimensions.width as f64;
+        self.tabline_renderer.hit(column as u64)
+    }
+
+    // The cell the cursor is headed to in physical pixels, along with the mode it is drawn for
+    pub fn caret(&self) -> (Rect, &EditorMode) {
+        let destination = self.cursor_renderer.destination();
+        let padding = self.padding;
+        let rect = self
+            .grid_renderer
+            .cell_rect(destination)
+            .with_offset((padding.left as f32, padding.top as f32));
+        (rect, &self.current_mode)
+    }
+
+    // Reloads the fonts for a new scale factor
+    pub fn handle_scale_factor_update(&mut self, scale_factor: f64) {
+        let previous_dimensions = self.grid_renderer.font_dimensions;
+        self.grid_renderer.handle_scale_factor_update(scale_factor);
+        self.handle_cell_size_change(previous_dimensions);
+    }
+
+    // The cursor's pixel positions are moved along with the cells so that an animation in flight
+    // carries on instead of jumping. Window positions and scrolling are kept in cells already.
+    fn handle_cell_size_change(&mut self, previous_dimensions: Dimensions) {
+        let dimensions = self.grid_renderer.font_dimensions;
+        if dimensions == previous_dimensions {
+            return;
+        }
+        self.cursor_renderer
+            .rescale(previous_dimensions, dimensions);
+        for window in self.rendered_windows.values_mut() {
+            window.handle_cell_size_change();
+        }
+    }
+
+    // After the window made its skia renderer again, the grids are given surfaces from the new
+    // one. Their contents are gone with the old context, so the editor has to redraw them.
+    pub fn recreate_surfaces(&mut self, root_canvas: &mut Canvas) {
+        self.grid_renderer.shaper.clear_blob_cache();
+        for window in self.rendered_windows.values_mut() {
+            window.recreate_surface(root_canvas, &self.grid_renderer);
+        }
+    }
+
     /// Draws frame
     ///
     /// # Returns
     /// `bool` indicating whether or not font was changed during this frame.
     #[allow(clippy::needless_collect)]
     pub fn draw_frame(&mut self, root_canvas: &mut Canvas, dt: f32) -> bool {
-        let mut draw_commands = Vec::new();
-        while let Ok(draw_command) = self.batched_draw_command_receiver.try_recv() {
-            draw_commands.extend(draw_command);
-        }
+        self.poll_draw_commands();
+        let draw_commands = std::mem::take(&mut self.pending_draw_commands);
 
         let mut font_changed = false;
 
+        // Text left over from earlier frames goes first, it is older than the new commands
+        self.grid_renderer
+            .reset_glyph_budget(SETTINGS.get::<RendererSettings>().glyph_budget);
+        for window in self.rendered_windows.values_mut() {
+            window.draw_deferred_text(&mut self.grid_renderer, false);
+        }
+
         for draw_command in draw_commands.into_iter() {
-            if let DrawCommand::FontChanged(_) = draw_command {
+            if let DrawCommand::FontChanged(_) | DrawCommand::LineSpaceChanged(_) = draw_command {
                 font_changed = true;
             }
             self.handle_draw_command(root_canvas, draw_command);
@@ -120,11 +366,37 @@ impl Renderer {
         let default_background = self.grid_renderer.get_default_background();
         let font_dimensions = self.grid_renderer.font_dimensions;
 
-        let transparency = { SETTINGS.get::<WindowSettings>().transparency };
-        root_canvas.clear(default_background.with_a((255.0 * transparency) as u8));
+        let window_settings = SETTINGS.get::<WindowSettings>();
+        let background_alpha = window_settings.background_alpha();
+        // The padding is left cleared to the default background
+        let padding = self.padding;
+        root_canvas.clear(default_background.with_a(background_alpha));
         root_canvas.save();
         root_canvas.reset_matrix();
 
+        let titlebar_height = self.titlebar_height() as f32;
+        if titlebar_height > 0.0 {
+            let window_width = root_canvas.base_layer_size().width as f32;
+            let bar = Rect::from_xywh(0.0, 0.0, window_width, titlebar_height);
+            self.titlebar_renderer
+                .draw(root_canvas, &mut self.grid_renderer, bar);
+        }
+
+        root_canvas.translate((padding.left as f32, padding.top as f32));
+
+        let tabline_height = self.tabline_height() as f32;
+        if tabline_height > 0.0 {
+            let window_width = root_canvas.base_layer_size().width as f32;
+            let bar = Rect::from_xywh(
+                -(padding.left as f32),
+                -tabline_height,
+                window_width,
+                tabline_height,
+            );
+            self.tabline_renderer
+                .draw(root_canvas, &mut self.grid_renderer, bar);
+        }
+
         if let Some(root_window) = self.rendered_windows.get(&1) {
             let clip_rect = root_window.pixel_region(font_dimensions);
             root_canvas.clip_rect(&clip_rect, None, Some(false));
@@ -152,28 +424,76 @@ impl Renderer {
         };
 
         let settings = SETTINGS.get::<RendererSettings>();
+        let inactive_alpha = (255.0 * self.inactive_window_opacity.max(0.0).min(1.0)) as u8;
+        let active_grid = self.cursor_renderer.grid_id();
         self.window_regions = windows
             .into_iter()
             .map(|window| {
-                window.draw(
+                let dimmed = inactive_alpha < 255 && window.id != active_grid;
+                if dimmed {
+                    root_canvas.save_layer_alpha(None, inactive_alpha.into());
+                }
+                let details = window.draw(
                     root_canvas,
                     &settings,
-                    default_background.with_a((255.0 * transparency) as u8),
+                    default_background.with_a(background_alpha),
                     font_dimensions,
                     dt,
-                )
+                );
+                if dimmed {
+                    root_canvas.restore();
+                }
+                details
             })
             .collect();
 
+        if let Some(link) = self.hovered_link.clone() {
+            self.draw_link_underline(root_canvas, &link);
+        }
+
+        let root_grid_size = self
+            .rendered_windows
+            .get(&1)
+            .map(|root_window| root_window.grid_size);
+        let command_line_placement =
+            self.command_line_renderer
+                .draw(root_canvas, &mut self.grid_renderer, root_grid_size);
+        self.popup_menu_renderer.draw(
+            root_canvas,
+            &mut self.grid_renderer,
+            root_grid_size,
+            command_line_placement
+                .as_ref()
+                .map(|placement| placement.content_origin),
+            dt,
+        );
+
         let windows = &self.rendered_windows;
         self.cursor_renderer
             .update_cursor_destination(font_dimensions.into(), windows);
+        if let Some(placement) = command_line_placement {
+            // The external command line has its own cursor position separate from the grids
+            self.cursor_renderer
+                .set_destination(placement.cursor_destination);
+        }
 
         self.cursor_renderer
             .draw(&mut self.grid_renderer, &self.current_mode, root_canvas, dt);
 
         root_canvas.restore();
 
+        if let Some(banner) = &self.banner {
+            self.banner_overlay.draw(root_canvas, banner);
+        }
+
+        PROFILER.set_enabled(settings.profiler);
+        if settings.profiler {
+            PROFILER.record_frame_time(dt);
+            let (hits, misses) = self.grid_renderer.shaper.cache_stats();
+            PROFILER.record_shape_cache_stats(hits, misses);
+            self.profiler_overlay.draw(root_canvas);
+        }
+
         font_changed
     }
 
@@ -217,7 +537,17 @@ impl Renderer {
                 self.cursor_renderer.update_cursor(new_cursor);
             }
             DrawCommand::FontChanged(new_font) => {
+                let previous_dimensions = self.grid_renderer.font_dimensions;
                 self.grid_renderer.update_font(&new_font);
+                self.handle_cell_size_change(previous_dimensions);
+            }
+            DrawCommand::WideFontChanged(new_wide_font) => {
+                self.grid_renderer.update_wide_font(&new_wide_font);
+            }
+            DrawCommand::LineSpaceChanged(new_linespace) => {
+                let previous_dimensions = self.grid_renderer.font_dimensions;
+                self.grid_renderer.update_linespace(new_linespace);
+                self.handle_cell_size_change(previous_dimensions);
             }
             DrawCommand::DefaultStyleChanged(new_style) => {
                 self.grid_renderer.default_style = Arc::new(new_style);
@@ -225,9 +555,57 @@ impl Renderer {
             DrawCommand::ModeChanged(new_mode) => {
                 self.current_mode = new_mode;
             }
+            DrawCommand::PopupMenu(popup_menu_command) => {
+                self.popup_menu_renderer
+                    .handle_draw_command(popup_menu_command);
+            }
+            DrawCommand::CommandLine(command_line_contents) => {
+                self.command_line_renderer.update(command_line_contents);
+            }
+            DrawCommand::Tabline(tabline_contents) => {
+                self.tabline_renderer.update(tabline_contents);
+            }
+            DrawCommand::TitlebarStyle(style) => self.titlebar_renderer.set_style(style),
+            DrawCommand::HoverLink(link) => self.hovered_link = link,
             _ => {}
         }
     }
+
+    // Underlines the link under the pointer in the default foreground color
+    fn draw_link_underline(&mut self, root_canvas: &mut Canvas, link: &LinkSpan) {
+        let region = match self
+            .window_regions
+            .iter()
+            .find(|details| details.id == link.grid_id)
+        {
+            Some(details) => details.region,
+            None => return,
+        };
+
+        let font_dimensions = self.grid_renderer.font_dimensions;
+        let baseline = region.top + ((link.row + 1) * font_dimensions.height) as f32
+            - self.grid_renderer.shaper.underline_position() as f32;
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        paint.set_stroke_width(self.grid_renderer.shaper.current_size() / 10.0);
+        paint.set_color(
+            self.grid_renderer
+                .default_style
+                .foreground(&self.grid_renderer.default_style.colors)
+                .to_color(),
+        );
+        root_canvas.draw_line(
+            (
+                region.left + (link.start * font_dimensions.width) as f32,
+                baseline,
+            ),
+            (
+                region.left + (link.end * font_dimensions.width) as f32,
+                baseline,
+            ),
+            &paint,
+        );
+    }
 }
 
 /// Defines how floating windows are sorted.
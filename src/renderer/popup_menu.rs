@@ -0,0 +1,790 @@
+use std::{collections::HashMap, sync::Arc};
+
+use log::error;
+use rmpv::Value;
+use skia_safe::{Canvas, Paint, Point, RRect, Rect};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    bridge::PopupMenuItem,
+    dimensions::Dimensions,
+    editor::Style,
+    renderer::{animation_utils::*, GridRenderer, RendererSettings},
+    settings::*,
+};
+
+// Maps completion kinds such as "Function" to the text drawn in their place, usually a nerd font
+// icon. Kinds without an entry are drawn as is.
+#[derive(Clone, Default)]
+pub struct KindIcons(HashMap<String, String>);
+
+impl FromValue for KindIcons {
+    fn from_value(&mut self, value: Value) {
+        if let Value::Map(entries) = value {
+            let mut icons = HashMap::new();
+            for (kind, icon) in entries {
+                match (kind.as_str(), icon.as_str()) {
+                    (Some(kind), Some(icon)) => {
+                        icons.insert(kind.to_owned(), icon.to_owned());
+                    }
+                    _ => {
+                        error!(
+                            "Expected kind icons to map strings to strings, but received {:?}: {:?}",
+                            kind, icon
+                        );
+                        return;
+                    }
+                }
+            }
+            self.0 = icons;
+        } else {
+            error!("Setting expected a dictionary, but received {:?}", value);
+        }
+    }
+}
+
+impl From<KindIcons> for Value {
+    fn from(icons: KindIcons) -> Self {
+        Value::Map(
+            icons
+                .0
+                .into_iter()
+                .map(|(kind, icon)| (Value::from(kind), Value::from(icon)))
+                .collect(),
+        )
+    }
+}
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "popupmenu"]
+pub struct PopupMenuSettings {
+    kind_icons: KindIcons,
+    // The largest the panel with the selected item's info may be, as fractions of the window
+    info_max_width: f32,
+    info_max_height: f32,
+}
+
+impl Default for PopupMenuSettings {
+    fn default() -> Self {
+        Self {
+            kind_icons: KindIcons::default(),
+            info_max_width: 0.4,
+            info_max_height: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PopupMenuStyles {
+    pub normal: Option<Arc<Style>>,
+    pub selected: Option<Arc<Style>>,
+    pub scrollbar: Option<Arc<Style>>,
+    pub thumb: Option<Arc<Style>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum PopupMenuDrawCommand {
+    Show {
+        items: Vec<PopupMenuItem>,
+        selected: Option<u64>,
+        // Global grid position of the cell the menu is attached to
+        anchor: (f64, f64),
+        // Command line menus are anchored to the command line rather than a grid. The column of
+        // the anchor is then relative to the start of the command line text
+        cmdline: bool,
+        styles: PopupMenuStyles,
+    },
+    Select {
+        selected: Option<u64>,
+    },
+    Hide,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct PopupMenuLayout {
+    left: u64,
+    top: u64,
+    width: u64,
+    height: u64,
+    word_width: u64,
+    kind_width: u64,
+    scrollbar: bool,
+}
+
+// Where the info panel goes, in grid cells, with the info wrapped to fit inside its padding
+#[derive(Clone, Debug, PartialEq)]
+struct InfoLayout {
+    left: u64,
+    top: u64,
+    width: u64,
+    lines: Vec<String>,
+}
+
+// Cells a grapheme takes up, two for wide ones such as CJK the way neovim's grid lays them out
+fn grapheme_width(grapheme: &str) -> u64 {
+    (grapheme.width() as u64).max(1).min(2)
+}
+
+fn text_width(text: &str) -> u64 {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+// Splits the text into runs the shaper can lay out a cell per grapheme. Like the grid, every wide
+// grapheme gets a run of its own covering both of its cells. Returns the column each run starts
+// at within the text along with its width.
+fn cell_runs(text: &str) -> Vec<(u64, String, u64)> {
+    let mut runs: Vec<(u64, String, u64)> = Vec::new();
+    let mut column = 0;
+    let mut extends_run = false;
+    for grapheme in text.graphemes(true) {
+        let width = grapheme_width(grapheme);
+        match runs.last_mut() {
+            Some((_, run, run_width)) if extends_run && width == 1 => {
+                run.push_str(grapheme);
+                *run_width += 1;
+            }
+            _ => runs.push((column, grapheme.to_owned(), width)),
+        }
+        extends_run = width == 1;
+        column += width;
+    }
+    runs
+}
+
+// Draws the text from the cell at most max_width cells wide, with wide graphemes on two cells
+fn draw_text(
+    grid_renderer: &mut GridRenderer,
+    canvas: &mut Canvas,
+    text: &str,
+    (column, row): (u64, u64),
+    max_width: u64,
+    style: &Option<Arc<Style>>,
+) {
+    for (offset, run, width) in cell_runs(text) {
+        if offset >= max_width {
+            break;
+        }
+        grid_renderer.draw_foreground(
+            canvas,
+            run,
+            (column + offset, row),
+            width.min(max_width - offset),
+            (1, 1),
+            style,
+        );
+    }
+}
+
+fn column_width<'a>(texts: impl Iterator<Item = &'a str>) -> u64 {
+    texts.map(text_width).max().unwrap_or(0)
+}
+
+fn kind_text<'a>(kind: &'a str, kind_icons: &'a KindIcons) -> &'a str {
+    kind_icons.0.get(kind).map(String::as_str).unwrap_or(kind)
+}
+
+fn compute_layout(
+    items: &[PopupMenuItem],
+    kind_icons: &KindIcons,
+    (anchor_column, anchor_row): (u64, u64),
+    open_above: bool,
+    grid_size: Dimensions,
+) -> PopupMenuLayout {
+    let item_count = items.len() as u64;
+
+    // Prefer opening below the anchor, but flip above it when the items don't fit and there is
+    // more room above
+    let space_below = grid_size.height.saturating_sub(anchor_row + 1);
+    let space_above = anchor_row;
+    let (top, height) = if !open_above && (item_count <= space_below || space_below >= space_above)
+    {
+        (anchor_row + 1, item_count.min(space_below))
+    } else {
+        let height = item_count.min(space_above);
+        (anchor_row - height, height)
+    };
+
+    let word_width = column_width(items.iter().map(|item| item.word.as_str()));
+    let kind_width = column_width(items.iter().map(|item| kind_text(&item.kind, kind_icons)));
+    let menu_width = column_width(items.iter().map(|item| item.menu.as_str()));
+    let scrollbar = height < item_count;
+
+    let mut width = 1 + word_width + 1;
+    if kind_width > 0 {
+        width += kind_width + 1;
+    }
+    if menu_width > 0 {
+        width += menu_width + 1;
+    }
+    if scrollbar {
+        width += 1;
+    }
+    let width = width.min(grid_size.width);
+
+    // The padding column sits to the left of the anchor so that the item text lines up with the
+    // text being completed
+    let left = anchor_column
+        .saturating_sub(1)
+        .min(grid_size.width.saturating_sub(width));
+
+    PopupMenuLayout {
+        left,
+        top,
+        width,
+        height,
+        word_width,
+        kind_width,
+        scrollbar,
+    }
+}
+
+// Wraps the text at spaces into lines at most width cells wide. Wide graphemes are drawn on two
+// cells and the others on one, so cells are what the text advances by. Words longer than a line
+// are broken wherever they reach the edge.
+fn wrap_text(text: &str, width: u64) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut line_width = 0;
+        for word in paragraph.split(' ') {
+            let word_width = text_width(word);
+            if line_width > 0 && line_width + 1 + word_width > width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            if line_width > 0 {
+                line.push(' ');
+                line_width += 1;
+            }
+            for grapheme in word.graphemes(true) {
+                let grapheme_width = grapheme_width(grapheme);
+                if line_width > 0 && line_width + grapheme_width > width {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0;
+                }
+                line.push_str(grapheme);
+                line_width += grapheme_width;
+            }
+        }
+        lines.push(line);
+    }
+    // Docs often end with a blank line, which would only pad the panel
+    while lines.last().map_or(false, |line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+// Places the info panel beside the menu, on the right unless there is more room on the left, and
+// no larger than the given fractions of the grid
+fn compute_info_layout(
+    info: &str,
+    menu: &PopupMenuLayout,
+    grid_size: Dimensions,
+    (max_width, max_height): (f32, f32),
+) -> Option<InfoLayout> {
+    // A column of padding on either side of the text
+    let wanted_width = info.lines().map(text_width).max().unwrap_or(0) + 2;
+    let max_width = (grid_size.width as f32 * max_width.max(0.0).min(1.0)) as u64;
+    let max_height = (grid_size.height as f32 * max_height.max(0.0).min(1.0)) as u64;
+
+    let room_right = grid_size.width.saturating_sub(menu.left + menu.width);
+    let room_left = menu.left;
+    let on_right = room_right >= wanted_width.min(max_width) || room_right >= room_left;
+    let room = if on_right { room_right } else { room_left };
+
+    let width = wanted_width.min(max_width).min(room);
+    if width < 3 {
+        return None;
+    }
+
+    let mut lines = wrap_text(info, width - 2);
+    lines.truncate(max_height.min(grid_size.height.saturating_sub(menu.top)) as usize);
+    if lines.is_empty() {
+        return None;
+    }
+
+    Some(InfoLayout {
+        left: if on_right {
+            menu.left + menu.width
+        } else {
+            menu.left - width
+        },
+        top: menu.top,
+        width,
+        lines,
+    })
+}
+
+pub struct PopupMenuRenderer {
+    items: Vec<PopupMenuItem>,
+    selected: Option<u64>,
+    anchor: (f64, f64),
+    cmdline: bool,
+    styles: PopupMenuStyles,
+    visible: bool,
+    drawn_last_frame: bool,
+    scroll_offset: u64,
+
+    start_position: Point,
+    current_position: Point,
+    destination: Point,
+    t: f32,
+}
+
+impl PopupMenuRenderer {
+    pub fn new() -> PopupMenuRenderer {
+        PopupMenuRenderer {
+            items: Vec::new(),
+            selected: None,
+            anchor: (0.0, 0.0),
+            cmdline: false,
+            styles: PopupMenuStyles::default(),
+            visible: false,
+            drawn_last_frame: false,
+            scroll_offset: 0,
+
+            start_position: Point::new(0.0, 0.0),
+            current_position: Point::new(0.0, 0.0),
+            destination: Point::new(0.0, 0.0),
+            t: 1.0,
+        }
+    }
+
+    pub fn handle_draw_command(&mut self, command: PopupMenuDrawCommand) {
+        match command {
+            PopupMenuDrawCommand::Show {
+                items,
+                selected,
+                anchor,
+                cmdline,
+                styles,
+            } => {
+                self.items = items;
+                self.selected = selected;
+                self.anchor = anchor;
+                self.cmdline = cmdline;
+                self.styles = styles;
+                self.scroll_offset = 0;
+                self.visible = true;
+            }
+            PopupMenuDrawCommand::Select { selected } => {
+                self.selected = selected;
+            }
+            PopupMenuDrawCommand::Hide => {
+                self.visible = false;
+            }
+        }
+    }
+
+    fn update_position(&mut self, destination: Point, was_drawn: bool, dt: f32) {
+        if !was_drawn {
+            // Newly shown menus appear in place rather than sliding in from the last location
+            self.start_position = destination;
+            self.current_position = destination;
+            self.destination = destination;
+            self.t = 1.0;
+            return;
+        }
+
+        if destination != self.destination {
+            self.start_position = self.current_position;
+            self.destination = destination;
+            self.t = 0.0;
+        }
+
+        let animation_length = SETTINGS.get::<RendererSettings>().position_animation_length;
+        if self.t < 1.0 && animation_length > 0.0 {
+            self.t = (self.t + dt / animation_length).min(1.0);
+            self.current_position =
+                ease_point(ease_out_expo, self.start_position, self.destination, self.t);
+        } else {
+            self.t = 1.0;
+            self.current_position = self.destination;
+        }
+    }
+
+    fn update_scroll_offset(&mut self, height: u64) {
+        if let Some(selected) = self.selected {
+            if selected < self.scroll_offset {
+                self.scroll_offset = selected;
+            } else if selected >= self.scroll_offset + height {
+                self.scroll_offset = selected + 1 - height;
+            }
+        }
+        let max_offset = (self.items.len() as u64).saturating_sub(height);
+        self.scroll_offset = self.scroll_offset.min(max_offset);
+    }
+
+    pub fn draw(
+        &mut self,
+        root_canvas: &mut Canvas,
+        grid_renderer: &mut GridRenderer,
+        grid_size: Option<Dimensions>,
+        command_line_origin: Option<(u64, u64)>,
+        dt: f32,
+    ) {
+        let was_drawn = self.drawn_last_frame;
+        self.drawn_last_frame = false;
+        let grid_size = match grid_size {
+            Some(grid_size) if self.visible && !self.items.is_empty() => grid_size,
+            _ => return,
+        };
+
+        let settings = SETTINGS.get::<PopupMenuSettings>();
+        let kind_icons = settings.kind_icons;
+        let mut anchor = (self.anchor.0.max(0.0) as u64, self.anchor.1.max(0.0) as u64);
+        let mut open_above = self.cmdline;
+        if let (true, Some((origin_left, origin_top))) = (self.cmdline, command_line_origin) {
+            // The floating command line sits near the top of the window, so its completions open
+            // below it starting from the completed column
+            anchor = (origin_left + anchor.0, origin_top);
+            open_above = false;
+        }
+        let layout = compute_layout(&self.items, &kind_icons, anchor, open_above, grid_size);
+        if layout.height == 0 || layout.width == 0 {
+            return;
+        }
+        self.update_scroll_offset(layout.height);
+
+        let font_dimensions = grid_renderer.font_dimensions;
+        let font_width = font_dimensions.width as f32;
+        let font_height = font_dimensions.height as f32;
+        let destination = Point::new(
+            layout.left as f32 * font_width,
+            layout.top as f32 * font_height,
+        );
+        self.update_position(destination, was_drawn, dt);
+
+        let default_style = grid_renderer.default_style.clone();
+        let background_of = |style: &Option<Arc<Style>>| {
+            style
+                .as_ref()
+                .unwrap_or(&default_style)
+                .background(&default_style.colors)
+                .to_color()
+        };
+
+        root_canvas.save();
+        root_canvas.translate(self.current_position);
+
+        let menu_rect = Rect::new(
+            0.0,
+            0.0,
+            layout.width as f32 * font_width,
+            layout.height as f32 * font_height,
+        );
+        let corner_radius = font_height / 4.0;
+        root_canvas.clip_rrect(
+            RRect::new_rect_xy(menu_rect, corner_radius, corner_radius),
+            None,
+            Some(true),
+        );
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(false);
+        paint.set_color(background_of(&self.styles.normal));
+        root_canvas.draw_rect(menu_rect, &paint);
+
+        let content_width = layout.width - if layout.scrollbar { 1 } else { 0 };
+        for row in 0..layout.height {
+            let index = self.scroll_offset + row;
+            let item = &self.items[index as usize];
+
+            let style = if self.selected == Some(index) {
+                let row_rect = Rect::new(
+                    0.0,
+                    row as f32 * font_height,
+                    content_width as f32 * font_width,
+                    (row + 1) as f32 * font_height,
+                );
+                paint.set_color(background_of(&self.styles.selected));
+                root_canvas.draw_rect(row_rect, &paint);
+                self.styles.selected.clone()
+            } else {
+                self.styles.normal.clone()
+            };
+
+            let mut column = 1;
+            let columns = [
+                (item.word.as_str(), layout.word_width),
+                (kind_text(&item.kind, &kind_icons), layout.kind_width),
+                (item.menu.as_str(), 0),
+            ];
+            for (text, width) in columns.iter() {
+                if !text.is_empty() && column < content_width {
+                    draw_text(
+                        grid_renderer,
+                        root_canvas,
+                        text,
+                        (column, row),
+                        content_width - column,
+                        &style,
+                    );
+                }
+                if *width > 0 {
+                    column += width + 1;
+                }
+            }
+        }
+
+        if layout.scrollbar {
+            let item_count = self.items.len() as f32;
+            let scrollbar_left = (layout.width - 1) as f32 * font_width;
+            let scrollbar_rect = Rect::new(
+                scrollbar_left,
+                0.0,
+                scrollbar_left + font_width,
+                menu_rect.bottom,
+            );
+            paint.set_color(background_of(&self.styles.scrollbar));
+            root_canvas.draw_rect(scrollbar_rect, &paint);
+
+            let thumb_top = self.scroll_offset as f32 / item_count * menu_rect.bottom;
+            let thumb_height = (layout.height as f32 / item_count * menu_rect.bottom).max(1.0);
+            let thumb_rect = Rect::new(
+                scrollbar_left,
+                thumb_top,
+                scrollbar_left + font_width,
+                thumb_top + thumb_height,
+            );
+            paint.set_color(background_of(&self.styles.thumb));
+            root_canvas.draw_rect(thumb_rect, &paint);
+        }
+
+        root_canvas.restore();
+
+        let info = self
+            .selected
+            .and_then(|selected| self.items.get(selected as usize))
+            .map(|item| item.info.as_str())
+            .unwrap_or("");
+        let info_layout = compute_info_layout(
+            info,
+            &layout,
+            grid_size,
+            (settings.info_max_width, settings.info_max_height),
+        );
+        if let Some(info_layout) = info_layout {
+            // The panel is drawn relative to the menu so that it moves along with it
+            root_canvas.save();
+            root_canvas.translate(self.current_position);
+            root_canvas.translate((
+                (info_layout.left as f32 - layout.left as f32) * font_width,
+                (info_layout.top as f32 - layout.top as f32) * font_height,
+            ));
+
+            let info_rect = Rect::new(
+                0.0,
+                0.0,
+                info_layout.width as f32 * font_width,
+                info_layout.lines.len() as f32 * font_height,
+            );
+            root_canvas.clip_rrect(
+                RRect::new_rect_xy(info_rect, corner_radius, corner_radius),
+                None,
+                Some(true),
+            );
+            paint.set_color(background_of(&self.styles.normal));
+            root_canvas.draw_rect(info_rect, &paint);
+
+            for (row, line) in info_layout.lines.iter().enumerate() {
+                if !line.is_empty() {
+                    draw_text(
+                        grid_renderer,
+                        root_canvas,
+                        line,
+                        (1, row as u64),
+                        text_width(line),
+                        &self.styles.normal,
+                    );
+                }
+            }
+            root_canvas.restore();
+        }
+
+        self.drawn_last_frame = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(count: usize) -> Vec<PopupMenuItem> {
+        (0..count)
+            .map(|index| PopupMenuItem {
+                word: format!("word{}", index),
+                kind: "f".to_owned(),
+                menu: "".to_owned(),
+                info: "".to_owned(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_layout_opens_below_anchor() {
+        let layout = compute_layout(
+            &items(3),
+            &KindIcons::default(),
+            (4, 2),
+            false,
+            Dimensions {
+                width: 80,
+                height: 20,
+            },
+        );
+
+        assert_eq!(layout.top, 3);
+        assert_eq!(layout.height, 3);
+        assert_eq!(layout.left, 3);
+        assert_eq!(layout.width, 1 + 5 + 1 + 1 + 1);
+        assert!(!layout.scrollbar);
+    }
+
+    #[test]
+    fn test_layout_flips_above_near_bottom_edge() {
+        let layout = compute_layout(
+            &items(5),
+            &KindIcons::default(),
+            (4, 18),
+            false,
+            Dimensions {
+                width: 80,
+                height: 20,
+            },
+        );
+
+        assert_eq!(layout.top, 13);
+        assert_eq!(layout.height, 5);
+    }
+
+    #[test]
+    fn test_layout_shifts_left_near_right_edge() {
+        let layout = compute_layout(
+            &items(3),
+            &KindIcons::default(),
+            (78, 2),
+            false,
+            Dimensions {
+                width: 80,
+                height: 20,
+            },
+        );
+
+        assert_eq!(layout.left + layout.width, 80);
+    }
+
+    #[test]
+    fn test_layout_adds_scrollbar_when_items_overflow() {
+        let layout = compute_layout(
+            &items(30),
+            &KindIcons::default(),
+            (0, 4),
+            false,
+            Dimensions {
+                width: 80,
+                height: 20,
+            },
+        );
+
+        assert_eq!(layout.top, 5);
+        assert_eq!(layout.height, 15);
+        assert!(layout.scrollbar);
+    }
+
+    #[test]
+    fn test_layout_cmdline_opens_above() {
+        let layout = compute_layout(
+            &items(3),
+            &KindIcons::default(),
+            (2, 19),
+            true,
+            Dimensions {
+                width: 80,
+                height: 20,
+            },
+        );
+
+        assert_eq!(layout.top, 16);
+        assert_eq!(layout.height, 3);
+    }
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(
+            wrap_text("fn foo(bar: u64)\n\nReturns the answer\n", 10),
+            vec!["fn", "foo(bar:", "u64)", "", "Returns", "the answer"]
+        );
+        assert_eq!(wrap_text("abcdefgh", 3), vec!["abc", "def", "gh"]);
+        assert_eq!(wrap_text("日本語です", 5), vec!["日本", "語で", "す"]);
+    }
+
+    #[test]
+    fn test_wide_text_is_measured_in_cells() {
+        assert_eq!(text_width("abc"), 3);
+        assert_eq!(text_width("日本語"), 6);
+        assert_eq!(text_width("e\u{301}"), 1);
+        assert_eq!(
+            cell_runs("ab日本c"),
+            vec![
+                (0, "ab".to_owned(), 2),
+                (2, "日".to_owned(), 2),
+                (4, "本".to_owned(), 2),
+                (6, "c".to_owned(), 1),
+            ]
+        );
+
+        let mut wide_items = items(1);
+        wide_items[0].word = "関数".to_owned();
+        let grid_size = Dimensions {
+            width: 80,
+            height: 20,
+        };
+        let layout = compute_layout(&wide_items, &KindIcons::default(), (4, 2), false, grid_size);
+        assert_eq!(layout.word_width, 4);
+    }
+
+    #[test]
+    fn test_info_layout_flips_near_right_edge() {
+        let grid_size = Dimensions {
+            width: 80,
+            height: 20,
+        };
+        let menu = compute_layout(&items(3), &KindIcons::default(), (4, 2), false, grid_size);
+        let info = compute_info_layout("some docs", &menu, grid_size, (0.5, 0.5)).unwrap();
+        assert_eq!(info.left, menu.left + menu.width);
+        assert_eq!(info.top, menu.top);
+        assert_eq!(info.width, 11);
+
+        let menu = compute_layout(&items(3), &KindIcons::default(), (75, 2), false, grid_size);
+        let info = compute_info_layout("some docs", &menu, grid_size, (0.5, 0.5)).unwrap();
+        assert_eq!(info.left + info.width, menu.left);
+
+        assert_eq!(compute_info_layout("", &menu, grid_size, (0.5, 0.5)), None);
+    }
+
+    #[test]
+    fn test_info_layout_is_capped() {
+        let grid_size = Dimensions {
+            width: 80,
+            height: 20,
+        };
+        let menu = compute_layout(&items(3), &KindIcons::default(), (4, 2), false, grid_size);
+        let info = "word ".repeat(200);
+        let info = compute_info_layout(&info, &menu, grid_size, (0.25, 0.25)).unwrap();
+        assert_eq!(info.width, 20);
+        assert_eq!(info.lines.len(), 5);
+        assert!(info.lines.iter().all(|line| text_width(line) <= 18));
+    }
+
+    #[test]
+    fn test_kind_icons_replace_kinds() {
+        let mut kind_icons = KindIcons::default();
+        kind_icons.from_value(Value::Map(vec![(Value::from("f"), Value::from("ƒ"))]));
+
+        assert_eq!(kind_text("f", &kind_icons), "ƒ");
+        assert_eq!(kind_text("v", &kind_icons), "v");
+    }
+}
@@ -0,0 +1,102 @@
+use skia_safe::{paint::Style as PaintStyle, Canvas, Color, Font, Paint, Path, Rect, Typeface};
+
+use crate::{
+    bridge::{REDRAW_QUEUE, UI_COMMAND_STATS},
+    profiling::PROFILER,
+};
+
+const WIDTH: f32 = 220.0;
+const HEIGHT: f32 = 165.0;
+const MARGIN: f32 = 10.0;
+const LINE_HEIGHT: f32 = 15.0;
+const GRAPH_HEIGHT: f32 = 50.0;
+
+// Frame times are graphed against at least a 30fps frame so that a steady 60fps doesn't fill the
+// graph with noise
+const MIN_GRAPH_SCALE: f32 = 1000.0 / 30.0;
+
+pub struct ProfilerOverlay {
+    font: Font,
+}
+
+impl ProfilerOverlay {
+    pub fn new() -> ProfilerOverlay {
+        ProfilerOverlay {
+            font: Font::from_typeface(Typeface::default(), 12.0),
+        }
+    }
+
+    pub fn draw(&self, root_canvas: &mut Canvas) {
+        let canvas_width = root_canvas.base_layer_size().width as f32;
+        let left = (canvas_width - WIDTH - MARGIN).max(0.0);
+        let rect = Rect::from_xywh(left, MARGIN, WIDTH, HEIGHT);
+
+        root_canvas.save();
+        root_canvas.reset_matrix();
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        paint.set_color(Color::from_argb(200, 0, 0, 0));
+        root_canvas.draw_rect(&rect, &paint);
+
+        let frame_times = PROFILER.frame_times();
+        let redraw_batch = PROFILER.last_redraw_batch();
+
+        paint.set_color(Color::WHITE);
+        let lines = [
+            format!("frame: {:.2}ms", frame_times.last().copied().unwrap_or(0.0)),
+            format!(
+                "redraw batch: {:.2}ms ({} commands)",
+                redraw_batch.milliseconds, redraw_batch.draw_commands
+            ),
+            match PROFILER.last_ping() {
+                Some(latency) => format!("rpc ping: {:.2}ms", latency),
+                None => "rpc ping: waiting".to_owned(),
+            },
+            match PROFILER.average_input_latency() {
+                Some(latency) => format!("input to screen: {:.2}ms", latency),
+                None => "input to screen: waiting".to_owned(),
+            },
+            match PROFILER.last_present_interval() {
+                Some(interval) => format!("present interval: {:.2}ms", interval),
+                None => "present interval: waiting".to_owned(),
+            },
+            match PROFILER.shape_cache_hit_rate() {
+                Some(hit_rate) => format!("shape cache: {:.1}% hits", hit_rate * 100.0),
+                None => "shape cache: empty".to_owned(),
+            },
+            format!(
+                "queued: {}/{} redraws, {} ui commands",
+                REDRAW_QUEUE.depth(),
+                REDRAW_QUEUE.capacity(),
+                UI_COMMAND_STATS.pending()
+            ),
+        ];
+        for (index, line) in lines.iter().enumerate() {
+            let baseline = rect.top + LINE_HEIGHT * (index + 1) as f32;
+            root_canvas.draw_str(line, (rect.left + 5.0, baseline), &self.font, &paint);
+        }
+
+        let graph_bottom = rect.bottom - 5.0;
+        let scale = frame_times.iter().copied().fold(MIN_GRAPH_SCALE, f32::max);
+        let step = WIDTH / frame_times.len().max(1) as f32;
+        let mut path = Path::new();
+        for (index, frame_time) in frame_times.iter().enumerate() {
+            let point = (
+                rect.left + step * index as f32,
+                graph_bottom - frame_time / scale * GRAPH_HEIGHT,
+            );
+            if index == 0 {
+                path.move_to(point);
+            } else {
+                path.line_to(point);
+            }
+        }
+        paint.set_style(PaintStyle::Stroke);
+        paint.set_stroke_width(1.0);
+        paint.set_color(Color::GREEN);
+        root_canvas.draw_path(&path, &paint);
+
+        root_canvas.restore();
+    }
+}
@@ -1,20 +1,95 @@
 use std::{collections::VecDeque, sync::Arc};
 
 use skia_safe::{
-    canvas::{SaveLayerRec, SrcRectConstraint},
-    gpu::SurfaceOrigin,
-    image_filters::blur,
-    BlendMode, Budgeted, Canvas, Color, Image, ImageInfo, Paint, Point, Rect, SamplingOptions,
-    Surface, SurfaceProps, SurfacePropsFlags,
+    canvas::SaveLayerRec, gpu::SurfaceOrigin, image_filters::blur, BlendMode, Budgeted, Canvas,
+    Color, IRect, Image, ImageInfo, Paint, Point, Rect, SamplingOptions, Surface, SurfaceProps,
+    SurfacePropsFlags,
 };
 
+// How long the damaged regions of a window stay highlighted when debug_damage is set
+const DAMAGE_FLASH_LENGTH: f32 = 0.25;
+
 use crate::{
     dimensions::Dimensions,
     editor::Style,
     redraw_scheduler::REDRAW_SCHEDULER,
     renderer::{animation_utils::*, GridRenderer, RendererSettings},
+    settings::SETTINGS,
 };
 
+// Joins neighbouring fragments with the same background into one run, so that a line such as a
+// statusline is filled with a single rect instead of one per highlight
+fn merge_background_runs<'a>(
+    line_fragments: &'a [LineFragment],
+    background_color: impl Fn(&Option<Arc<Style>>) -> Color,
+) -> Vec<((u64, u64), u64, &'a Option<Arc<Style>>)> {
+    let mut runs: Vec<((u64, u64), u64, &Option<Arc<Style>>)> = Vec::new();
+    let mut previous_color = None;
+    for fragment in line_fragments {
+        let color = background_color(&fragment.style);
+        if let Some(((left, top), width, _)) = runs.last_mut() {
+            if previous_color == Some(color)
+                && *top == fragment.window_top
+                && *left + *width == fragment.window_left
+            {
+                *width += fragment.width;
+                continue;
+            }
+        }
+        runs.push((
+            (fragment.window_left, fragment.window_top),
+            fragment.width,
+            &fragment.style,
+        ));
+        previous_color = Some(color);
+    }
+    runs
+}
+
+// Whether the glyphs of a fragment may spill over into the cell after it. That is only allowed
+// when the cell is an empty one with the same background, the way sign columns pad their icons,
+// so that a wide icon isn't cut off while regular text never draws over its neighbour.
+fn overflows_into_next(
+    fragment: &LineFragment,
+    next: Option<&LineFragment>,
+    background_color: impl Fn(&Option<Arc<Style>>) -> Color,
+) -> bool {
+    next.map_or(false, |next| {
+        next.window_top == fragment.window_top
+            && next.window_left == fragment.window_left + fragment.width
+            && next.text.starts_with(' ')
+            && background_color(&next.style) == background_color(&fragment.style)
+    })
+}
+
+// The cells of a scrolled region which are still inside it after the scroll, as (left, top, right,
+// bottom). Only these are copied, the lines the scroll exposes are drawn by the grid_line events
+// which follow it.
+fn scroll_source(
+    (top, bottom, left, right): (u64, u64, u64, u64),
+    rows: i64,
+    cols: i64,
+) -> Option<(i64, i64, i64, i64)> {
+    let (top, bottom, left, right) = (top as i64, bottom as i64, left as i64, right as i64);
+    let (source_top, source_bottom) = (top + rows.max(0), bottom + rows.min(0));
+    let (source_left, source_right) = (left + cols.max(0), right + cols.min(0));
+    if source_top >= source_bottom || source_left >= source_right {
+        None
+    } else {
+        Some((source_left, source_top, source_right, source_bottom))
+    }
+}
+
+// Where a scroll animation to the destination starts, so that it covers at most far_lines lines
+fn scroll_animation_start(current_scroll: f32, destination: f32, far_lines: u64) -> f32 {
+    let distance = destination - current_scroll;
+    if far_lines == 0 || distance.abs() <= far_lines as f32 {
+        current_scroll
+    } else {
+        destination - distance.signum() * far_lines as f32
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct LineFragment {
     pub text: String,
@@ -24,6 +99,21 @@ pub struct LineFragment {
     pub style: Option<Arc<Style>>,
 }
 
+impl LineFragment {
+    fn overlaps(&self, other: &LineFragment) -> bool {
+        self.window_top == other.window_top
+            && self.window_left < other.window_left + other.width
+            && other.window_left < self.window_left + self.width
+    }
+}
+
+// The text of a fragment which went over the frame's glyph budget. Its background is drawn
+// already, the glyphs follow on a later frame.
+struct DeferredText {
+    fragment: LineFragment,
+    overflow: (u64, u64),
+}
+
 #[derive(Clone, Debug)]
 pub enum WindowDrawCommand {
     Position {
@@ -140,6 +230,11 @@ pub struct RenderedWindow {
     pub current_scroll: f32,
     scroll_destination: f32,
     scroll_t: f32,
+
+    // Regions of the current surface that were re-rasterized recently along with how much longer
+    // they should be highlighted for
+    damaged_regions: Vec<(Rect, f32)>,
+    deferred_text: Vec<DeferredText>,
 }
 
 #[derive(Clone, Debug)]
@@ -177,13 +272,18 @@ impl RenderedWindow {
             current_scroll: 0.0,
             scroll_destination: 0.0,
             scroll_t: 2.0, // 2.0 is out of the 0.0 to 1.0 range and stops animation
+
+            damaged_regions: Vec::new(),
+            deferred_text: Vec::new(),
         }
     }
 
     pub fn pixel_region(&self, font_dimensions: Dimensions) -> Rect {
+        // Animated positions fall between pixels, which blurs the window and leaves hairline
+        // seams against its neighbours on fractional scale factors, so they snap to whole pixels
         let current_pixel_position = Point::new(
-            self.grid_current_position.x * font_dimensions.width as f32,
-            self.grid_current_position.y * font_dimensions.height as f32,
+            (self.grid_current_position.x * font_dimensions.width as f32).round(),
+            (self.grid_current_position.y * font_dimensions.height as f32).round(),
         );
 
         let image_size: (i32, i32) = (self.grid_size * font_dimensions).into();
@@ -191,6 +291,24 @@ impl RenderedWindow {
         Rect::from_point_and_size(current_pixel_position, image_size)
     }
 
+    // The scroll snapshots were drawn for the old cells, so a scroll in flight ends where it is
+    // headed instead of showing them at the wrong size
+    pub fn handle_cell_size_change(&mut self) {
+        self.snapshots.clear();
+        self.current_scroll = self.scroll_destination;
+        self.scroll_t = 2.0;
+    }
+
+    pub fn recreate_surface(&mut self, parent_canvas: &mut Canvas, grid_renderer: &GridRenderer) {
+        self.handle_cell_size_change();
+        self.current_surface = LocatedSurface::new(
+            parent_canvas,
+            grid_renderer,
+            self.grid_size,
+            self.current_surface.top_line,
+        );
+    }
+
     pub fn update(&mut self, settings: &RendererSettings, dt: f32) -> bool {
         let mut animating = false;
 
@@ -213,7 +331,7 @@ impl RenderedWindow {
         }
 
         {
-            if 1.0 - self.scroll_t < std::f32::EPSILON {
+            if 1.0 - self.scroll_t < std::f32::EPSILON || !settings.smooth_scroll {
                 // We are at destination, move t out of 0-1 range to stop the animation
                 self.scroll_t = 2.0;
                 self.snapshots.clear();
@@ -223,7 +341,7 @@ impl RenderedWindow {
             }
 
             self.current_scroll = ease(
-                ease_out_expo,
+                settings.scroll_animation_easing.function(),
                 self.start_scroll,
                 self.scroll_destination,
                 self.scroll_t,
@@ -285,8 +403,9 @@ impl RenderedWindow {
 
         // Draw scrolling snapshots
         for snapshot in self.snapshots.iter_mut().rev() {
-            let scroll_offset = (snapshot.top_line * font_height) as f32
-                - (self.current_scroll * font_height as f32);
+            let scroll_offset = ((snapshot.top_line * font_height) as f32
+                - (self.current_scroll * font_height as f32))
+                .round();
             let image = &mut snapshot.image;
             root_canvas.draw_image_rect(
                 image,
@@ -296,8 +415,9 @@ impl RenderedWindow {
             );
         }
         // Draw current surface
-        let scroll_offset = (self.current_surface.top_line * font_height) as f32
-            - (self.current_scroll * font_height as f32);
+        let scroll_offset = ((self.current_surface.top_line * font_height) as f32
+            - (self.current_scroll * font_height as f32))
+            .round();
         let snapshot = self.current_surface.surface.image_snapshot();
         root_canvas.draw_image_rect(
             snapshot,
@@ -312,6 +432,12 @@ impl RenderedWindow {
             root_canvas.restore();
         }
 
+        if settings.debug_damage {
+            self.draw_damaged_regions(root_canvas, pixel_region, scroll_offset, dt);
+        } else {
+            self.damaged_regions.clear();
+        }
+
         root_canvas.restore();
 
         WindowDrawDetails {
@@ -321,6 +447,74 @@ impl RenderedWindow {
         }
     }
 
+    fn draw_damaged_regions(
+        &mut self,
+        root_canvas: &mut Canvas,
+        pixel_region: Rect,
+        scroll_offset: f32,
+        dt: f32,
+    ) {
+        let mut paint = Paint::default();
+        for (region, remaining) in self.damaged_regions.iter_mut() {
+            let alpha = (*remaining / DAMAGE_FLASH_LENGTH).max(0.0).min(1.0) * 120.0;
+            paint.set_color(Color::from_argb(alpha as u8, 255, 0, 0));
+            root_canvas.draw_rect(
+                region.with_offset((pixel_region.left, pixel_region.top + scroll_offset)),
+                &paint,
+            );
+            *remaining -= dt;
+        }
+
+        self.damaged_regions
+            .retain(|(_, remaining)| *remaining > 0.0);
+        if !self.damaged_regions.is_empty() {
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+    }
+
+    fn mark_damaged(&mut self, region: Rect) {
+        self.damaged_regions.push((region, DAMAGE_FLASH_LENGTH));
+    }
+
+    // Draws as much of the deferred text as the budget allows, all of it when ignore_budget is
+    // set because the surface is about to be moved around
+    pub fn draw_deferred_text(&mut self, grid_renderer: &mut GridRenderer, ignore_budget: bool) {
+        if self.deferred_text.is_empty() {
+            return;
+        }
+        let deferred_text = std::mem::take(&mut self.deferred_text);
+        let canvas = self.current_surface.surface.canvas();
+        for deferred in deferred_text {
+            let DeferredText { fragment, overflow } = deferred;
+            if !ignore_budget && !grid_renderer.take_glyph_budget(&fragment.text, &fragment.style) {
+                self.deferred_text.push(DeferredText { fragment, overflow });
+                continue;
+            }
+            let Dimensions {
+                width: font_width,
+                height: font_height,
+            } = grid_renderer.font_dimensions;
+            let region = Rect::from_xywh(
+                (fragment.window_left * font_width) as f32,
+                (fragment.window_top * font_height) as f32,
+                (fragment.width * font_width) as f32,
+                font_height as f32,
+            );
+            grid_renderer.draw_foreground(
+                canvas,
+                fragment.text,
+                (fragment.window_left, fragment.window_top),
+                fragment.width,
+                overflow,
+                &fragment.style,
+            );
+            self.damaged_regions.push((region, DAMAGE_FLASH_LENGTH));
+        }
+        if !self.deferred_text.is_empty() {
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+    }
+
     pub fn handle_window_draw_command(
         &mut self,
         grid_renderer: &mut GridRenderer,
@@ -379,28 +573,51 @@ impl RenderedWindow {
                 }
             }
             WindowDrawCommand::DrawLine(line_fragments) => {
+                // The new line replaces whatever was still waiting to be drawn in its cells
+                self.deferred_text.retain(|deferred| {
+                    !line_fragments
+                        .iter()
+                        .any(|fragment| deferred.fragment.overlaps(fragment))
+                });
                 let canvas = self.current_surface.surface.canvas();
 
                 canvas.save();
-                for line_fragment in line_fragments.iter() {
-                    let LineFragment {
-                        window_left,
-                        window_top,
-                        width,
-                        style,
-                        ..
-                    } = line_fragment;
-                    let grid_position = (*window_left, *window_top);
+                let background_runs = merge_background_runs(&line_fragments, |style| {
+                    grid_renderer.background_color(style)
+                });
+                for (grid_position, width, style) in background_runs {
                     grid_renderer.draw_background(
                         canvas,
                         grid_position,
-                        *width,
+                        width,
                         style,
                         self.floating_order.is_some(),
                     );
                 }
 
-                for line_fragment in line_fragments.into_iter() {
+                let Dimensions {
+                    width: font_width,
+                    height: font_height,
+                } = grid_renderer.font_dimensions;
+                let mut damaged_region: Option<Rect> = None;
+                let glyph_overflow = SETTINGS.get::<RendererSettings>().glyph_overflow;
+                // The cells on the left and right the glyphs of each fragment aren't clipped to
+                let overflow: Vec<(u64, u64)> = line_fragments
+                    .iter()
+                    .enumerate()
+                    .map(|(index, fragment)| {
+                        if !glyph_overflow {
+                            return (0, 0);
+                        }
+                        let next = line_fragments.get(index + 1);
+                        let into_next = overflows_into_next(fragment, next, |style| {
+                            grid_renderer.background_color(style)
+                        });
+                        (1, into_next as u64)
+                    })
+                    .collect();
+
+                for (line_fragment, overflow) in line_fragments.into_iter().zip(overflow) {
                     let LineFragment {
                         text,
                         window_left,
@@ -408,10 +625,48 @@ impl RenderedWindow {
                         width,
                         style,
                     } = line_fragment;
+                    let fragment_region = Rect::from_xywh(
+                        (window_left * font_width) as f32,
+                        (window_top * font_height) as f32,
+                        (width * font_width) as f32,
+                        font_height as f32,
+                    );
+                    damaged_region = Some(match damaged_region {
+                        Some(region) => Rect::join2(region, fragment_region),
+                        None => fragment_region,
+                    });
+
+                    if !grid_renderer.take_glyph_budget(&text, &style) {
+                        self.deferred_text.push(DeferredText {
+                            fragment: LineFragment {
+                                text,
+                                window_left,
+                                window_top,
+                                width,
+                                style,
+                            },
+                            overflow,
+                        });
+                        continue;
+                    }
                     let grid_position = (window_left, window_top);
-                    grid_renderer.draw_foreground(canvas, text, grid_position, width, &style);
+                    grid_renderer.draw_foreground(
+                        canvas,
+                        text,
+                        grid_position,
+                        width,
+                        overflow,
+                        &style,
+                    );
                 }
                 canvas.restore();
+
+                if let Some(damaged_region) = damaged_region {
+                    self.mark_damaged(damaged_region);
+                }
+                if !self.deferred_text.is_empty() {
+                    REDRAW_SCHEDULER.queue_next_frame();
+                }
             }
             WindowDrawCommand::Scroll {
                 top,
@@ -421,6 +676,8 @@ impl RenderedWindow {
                 rows,
                 cols,
             } => {
+                // The deferred text is placed by its cells, which are about to move
+                self.draw_deferred_text(grid_renderer, true);
                 let Dimensions {
                     width: font_width,
                     height: font_height,
@@ -432,28 +689,36 @@ impl RenderedWindow {
                     (bottom * font_height) as f32,
                 );
 
-                let mut translated_region = scrolled_region;
-                translated_region.offset((
-                    -cols as f32 * font_width as f32,
-                    -rows as f32 * font_height as f32,
-                ));
-
-                let snapshot = self.current_surface.surface.image_snapshot();
-                let canvas = self.current_surface.surface.canvas();
-
-                canvas.save();
-
-                canvas.clip_rect(scrolled_region, None, Some(false));
-                canvas.draw_image_rect(
-                    snapshot,
-                    Some((&scrolled_region, SrcRectConstraint::Fast)),
-                    translated_region,
-                    &grid_renderer.paint,
-                );
+                // Copying only what stays in view keeps the snapshot down to the moved pixels
+                // instead of the whole surface
+                if let Some((source_left, source_top, source_right, source_bottom)) =
+                    scroll_source((top, bottom, left, right), rows, cols)
+                {
+                    let (font_width, font_height) = (font_width as i64, font_height as i64);
+                    let source = IRect::new(
+                        (source_left * font_width) as i32,
+                        (source_top * font_height) as i32,
+                        (source_right * font_width) as i32,
+                        (source_bottom * font_height) as i32,
+                    );
+                    let surface = &mut self.current_surface.surface;
+                    if let Some(snapshot) = surface.image_snapshot_with_bounds(&source) {
+                        let destination = (
+                            ((source_left - cols) * font_width) as f32,
+                            ((source_top - rows) * font_height) as f32,
+                        );
+                        surface.canvas().draw_image(
+                            snapshot,
+                            destination,
+                            Some(&grid_renderer.paint),
+                        );
+                    }
+                }
 
-                canvas.restore();
+                self.mark_damaged(scrolled_region);
             }
             WindowDrawCommand::Clear => {
+                self.deferred_text.clear();
                 self.current_surface.surface = build_window_surface_with_grid_size(
                     self.current_surface.surface.canvas(),
                     grid_renderer,
@@ -461,6 +726,10 @@ impl RenderedWindow {
                 );
 
                 self.snapshots.clear();
+                self.damaged_regions.clear();
+                self.mark_damaged(Rect::from_size(
+                    (self.grid_size * grid_renderer.font_dimensions).into(),
+                ));
             }
             WindowDrawCommand::Show => {
                 if self.hidden {
@@ -482,7 +751,11 @@ impl RenderedWindow {
                     self.current_surface.top_line = top_line as u64;
 
                     // Set new target viewport position and initialize animation timer
-                    self.start_scroll = self.current_scroll;
+                    let far_lines = SETTINGS
+                        .get::<RendererSettings>()
+                        .scroll_animation_far_lines;
+                    self.start_scroll =
+                        scroll_animation_start(self.current_scroll, top_line as f32, far_lines);
                     self.scroll_destination = top_line as f32;
                     self.scroll_t = 0.0;
                 }
@@ -491,3 +764,132 @@ impl RenderedWindow {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment(window_left: u64, width: u64, style: Option<Arc<Style>>) -> LineFragment {
+        LineFragment {
+            text: String::new(),
+            window_left,
+            window_top: 3,
+            width,
+            style,
+        }
+    }
+
+    #[test]
+    fn test_scroll_source() {
+        // Scrolling a 10 line region up by 3 moves the bottom 7 lines
+        assert_eq!(scroll_source((0, 10, 0, 80), 3, 0), Some((0, 3, 80, 10)));
+        assert_eq!(scroll_source((2, 10, 0, 80), -3, 0), Some((0, 2, 80, 7)));
+        assert_eq!(scroll_source((0, 10, 4, 80), 0, -4), Some((4, 0, 76, 10)));
+        // Everything scrolls out of the region, so there is nothing to copy
+        assert_eq!(scroll_source((0, 10, 0, 80), 10, 0), None);
+        assert_eq!(scroll_source((0, 10, 0, 80), -12, 0), None);
+    }
+
+    #[test]
+    fn test_new_fragments_replace_deferred_text_in_their_cells() {
+        let deferred = fragment(10, 5, None);
+        assert!(deferred.overlaps(&fragment(12, 1, None)));
+        assert!(deferred.overlaps(&fragment(0, 11, None)));
+        assert!(!deferred.overlaps(&fragment(15, 4, None)));
+        assert!(!deferred.overlaps(&fragment(0, 10, None)));
+        assert!(!deferred.overlaps(&LineFragment {
+            window_top: 4,
+            ..fragment(10, 5, None)
+        }));
+    }
+
+    #[test]
+    fn test_glyph_budget_defers_text_once_used_up() {
+        let mut grid_renderer = GridRenderer::new(1.0);
+        grid_renderer.reset_glyph_budget(5);
+        assert!(grid_renderer.take_glyph_budget("abc", &None));
+        // Going over the budget still draws the text which does it
+        assert!(grid_renderer.take_glyph_budget("defgh", &None));
+        assert!(!grid_renderer.take_glyph_budget("ijk", &None));
+
+        grid_renderer.reset_glyph_budget(0);
+        assert!(grid_renderer.take_glyph_budget(&"x".repeat(100), &None));
+    }
+
+    #[test]
+    fn test_merge_background_runs() {
+        let colors =
+            crate::editor::Colors::new(Some(Color::WHITE.into()), Some(Color::BLACK.into()), None);
+        let bold = Arc::new(Style {
+            bold: true,
+            ..Style::new(colors.clone())
+        });
+        let red = Arc::new(Style {
+            colors: crate::editor::Colors::new(None, Some(Color::RED.into()), None),
+            ..Style::new(colors.clone())
+        });
+        let line_fragments = vec![
+            fragment(0, 4, None),
+            fragment(4, 2, Some(bold)),
+            fragment(6, 3, Some(red)),
+            fragment(9, 1, None),
+        ];
+
+        let runs = merge_background_runs(&line_fragments, |style| {
+            style
+                .as_ref()
+                .and_then(|style| style.colors.background)
+                .unwrap_or_else(|| Color::BLACK.into())
+                .to_color()
+        });
+
+        let runs: Vec<((u64, u64), u64)> = runs
+            .into_iter()
+            .map(|(position, width, _)| (position, width))
+            .collect();
+        assert_eq!(runs, vec![((0, 3), 6), ((6, 3), 3), ((9, 3), 1)]);
+    }
+
+    #[test]
+    fn test_overflows_into_next() {
+        let red = Arc::new(Style {
+            colors: crate::editor::Colors::new(None, Some(Color::RED.into()), None),
+            ..Style::new(crate::editor::Colors::new(None, None, None))
+        });
+        let background_color = |style: &Option<Arc<Style>>| {
+            style
+                .as_ref()
+                .and_then(|style| style.colors.background)
+                .unwrap_or_else(|| Color::BLACK.into())
+                .to_color()
+        };
+        let with_text = |fragment: LineFragment, text: &str| LineFragment {
+            text: text.to_owned(),
+            ..fragment
+        };
+
+        let sign = with_text(fragment(0, 1, None), "x");
+        let padding = with_text(fragment(1, 1, None), " ");
+        assert!(overflows_into_next(&sign, Some(&padding), background_color));
+        assert!(!overflows_into_next(&sign, None, background_color));
+
+        let text = with_text(fragment(1, 1, None), "y");
+        assert!(!overflows_into_next(&sign, Some(&text), background_color));
+
+        let red_padding = with_text(fragment(1, 1, Some(red)), " ");
+        assert!(!overflows_into_next(
+            &sign,
+            Some(&red_padding),
+            background_color
+        ));
+    }
+
+    #[test]
+    fn test_scroll_animation_start_caps_far_jumps() {
+        assert_eq!(scroll_animation_start(10.0, 14.0, 0), 10.0);
+        assert_eq!(scroll_animation_start(10.0, 1000.0, 0), 10.0);
+        assert_eq!(scroll_animation_start(10.0, 14.0, 20), 10.0);
+        assert_eq!(scroll_animation_start(10.0, 1000.0, 20), 980.0);
+        assert_eq!(scroll_animation_start(1000.0, 0.0, 20), 20.0);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{path::Path, sync::Arc};
+
+use skia_safe::{Canvas, Paint, Rect};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{bridge::TablineTab, editor::Style, renderer::GridRenderer, settings::*};
+
+// Labels longer than this are cut short, so that a long path doesn't push the other tabs away
+const MAX_LABEL_WIDTH: usize = 24;
+const CLOSE_BUTTON: &str = "×";
+
+#[derive(SettingGroup, Clone)]
+pub struct TablineSettings {
+    // Draws the tabs natively instead of leaving neovim to draw them as a line of text
+    pub tabline: bool,
+    // Keeps the tabline shown with a single tab, like showtabline=2
+    pub tabline_always: bool,
+    // In multiples of the cell height
+    #[setting(min = 1.0, max = 3.0)]
+    pub tabline_height: f32,
+}
+
+impl Default for TablineSettings {
+    fn default() -> Self {
+        Self {
+            tabline: false,
+            tabline_always: false,
+            tabline_height: 1.5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TablineContents {
+    pub current_tab: u64,
+    pub tabs: Vec<TablineTab>,
+    // TabLine, TabLineSel and TabLineFill
+    pub normal: Option<Arc<Style>>,
+    pub selected: Option<Arc<Style>>,
+    pub fill: Option<Arc<Style>>,
+}
+
+// What a click on the tabline landed on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TablineHit {
+    Tab(u64),
+    Close(u64),
+}
+
+// Tabs are named after the file shown in them, the directories are left out
+fn tab_label(name: &str) -> String {
+    let name = Path::new(name)
+        .file_name()
+        .map_or(name.into(), |file_name| file_name.to_string_lossy());
+    if name.is_empty() {
+        return "[No Name]".to_owned();
+    }
+
+    let graphemes: Vec<&str> = name.graphemes(true).collect();
+    if graphemes.len() > MAX_LABEL_WIDTH {
+        format!("{}…", graphemes[..MAX_LABEL_WIDTH - 1].concat())
+    } else {
+        name.into_owned()
+    }
+}
+
+// A tab in columns from the left of the grid. It is drawn as its label between a cell of space on
+// either side, followed by the close button and another cell of space.
+#[derive(Clone, Debug, PartialEq)]
+struct TabLayout {
+    tab: u64,
+    label: String,
+    left: u64,
+    width: u64,
+}
+
+impl TabLayout {
+    fn label_width(&self) -> u64 {
+        self.width - 4
+    }
+
+    fn close_column(&self) -> u64 {
+        self.left + self.width - 2
+    }
+}
+
+fn compute_layout(tabs: &[TablineTab]) -> Vec<TabLayout> {
+    let mut left = 0;
+    tabs.iter()
+        .map(|tab| {
+            let label = tab_label(&tab.name);
+            let width = label.graphemes(true).count() as u64 + 4;
+            let layout = TabLayout {
+                tab: tab.tab,
+                label,
+                left,
+                width,
+            };
+            left += width;
+            layout
+        })
+        .collect()
+}
+
+fn hit_test(layout: &[TabLayout], column: u64) -> Option<TablineHit> {
+    let tab = layout
+        .iter()
+        .find(|tab| column >= tab.left && column < tab.left + tab.width)?;
+    if column >= tab.close_column() {
+        Some(TablineHit::Close(tab.tab))
+    } else {
+        Some(TablineHit::Tab(tab.tab))
+    }
+}
+
+pub struct TablineRenderer {
+    contents: TablineContents,
+    layout: Vec<TabLayout>,
+}
+
+impl TablineRenderer {
+    pub fn new() -> TablineRenderer {
+        TablineRenderer {
+            contents: TablineContents::default(),
+            layout: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, contents: TablineContents) {
+        self.layout = compute_layout(&contents.tabs);
+        self.contents = contents;
+    }
+
+    // The height in pixels the tabline takes from the top of the grid, zero while it is hidden
+    pub fn height(&self, font_height: u64) -> u32 {
+        let settings = SETTINGS.get::<TablineSettings>();
+        let shown = settings.tabline
+            && (self.contents.tabs.len() > 1
+                || settings.tabline_always && !self.contents.tabs.is_empty());
+        if shown {
+            (font_height as f32 * settings.tabline_height).round() as u32
+        } else {
+            0
+        }
+    }
+
+    pub fn hit(&self, column: u64) -> Option<TablineHit> {
+        hit_test(&self.layout, column)
+    }
+
+    // The tabs start at the origin's column, the fill covers the whole bar including the padding
+    // on either side
+    pub fn draw(&self, root_canvas: &mut Canvas, grid_renderer: &mut GridRenderer, bar: Rect) {
+        let height = bar.height();
+        if height <= 0.0 {
+            return;
+        }
+
+        let font_width = grid_renderer.font_dimensions.width as f32;
+        let font_height = grid_renderer.font_dimensions.height as f32;
+        let mut paint = Paint::default();
+
+        root_canvas.save();
+        root_canvas.clip_rect(&bar, None, Some(false));
+        paint.set_color(grid_renderer.background_color(&self.contents.fill));
+        root_canvas.draw_rect(&bar, &paint);
+
+        for tab in self.layout.iter() {
+            let style = if tab.tab == self.contents.current_tab {
+                &self.contents.selected
+            } else {
+                &self.contents.normal
+            };
+
+            paint.set_color(grid_renderer.background_color(style));
+            root_canvas.draw_rect(
+                Rect::from_xywh(
+                    tab.left as f32 * font_width,
+                    bar.top,
+                    tab.width as f32 * font_width,
+                    height,
+                ),
+                &paint,
+            );
+
+            // The text is centered in the taller tabline
+            root_canvas.save();
+            root_canvas.translate((0.0, bar.top + (height - font_height) / 2.0));
+            grid_renderer.draw_foreground(
+                root_canvas,
+                tab.label.clone(),
+                (tab.left + 1, 0),
+                tab.label_width(),
+                (0, 0),
+                style,
+            );
+            grid_renderer.draw_foreground(
+                root_canvas,
+                CLOSE_BUTTON.to_owned(),
+                (tab.close_column(), 0),
+                1,
+                (0, 0),
+                style,
+            );
+            root_canvas.restore();
+        }
+
+        root_canvas.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tab(tab: u64, name: &str) -> TablineTab {
+        TablineTab {
+            tab,
+            name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_tab_labels() {
+        assert_eq!(tab_label("/home/user/src/main.rs"), "main.rs");
+        assert_eq!(tab_label(""), "[No Name]");
+        assert_eq!(tab_label("term://~//42:/bin/zsh"), "zsh");
+
+        let long_label = tab_label(&"a".repeat(40));
+        assert_eq!(long_label.graphemes(true).count(), MAX_LABEL_WIDTH);
+        assert!(long_label.ends_with('…'));
+    }
+
+    #[test]
+    fn test_clicks_find_the_tab_or_its_close_button() {
+        let layout = compute_layout(&[tab(1, "a.rs"), tab(4, "main.rs")]);
+        assert_eq!(layout[0].width, 8);
+        assert_eq!(layout[1].left, 8);
+
+        assert_eq!(hit_test(&layout, 0), Some(TablineHit::Tab(1)));
+        assert_eq!(hit_test(&layout, 5), Some(TablineHit::Tab(1)));
+        assert_eq!(hit_test(&layout, 6), Some(TablineHit::Close(1)));
+        assert_eq!(hit_test(&layout, 8), Some(TablineHit::Tab(4)));
+        assert_eq!(hit_test(&layout, 17), Some(TablineHit::Close(4)));
+        assert_eq!(hit_test(&layout, 19), None);
+    }
+
+    #[test]
+    fn test_the_tabline_hides_with_a_single_tab() {
+        SETTINGS.set(&TablineSettings {
+            tabline: true,
+            ..TablineSettings::default()
+        });
+        let mut renderer = TablineRenderer::new();
+        renderer.update(TablineContents {
+            current_tab: 1,
+            tabs: vec![tab(1, "a.rs")],
+            ..TablineContents::default()
+        });
+        assert_eq!(renderer.height(20), 0);
+
+        SETTINGS.set(&TablineSettings {
+            tabline: true,
+            tabline_always: true,
+            ..TablineSettings::default()
+        });
+        assert_eq!(renderer.height(20), 30);
+
+        SETTINGS.set(&TablineSettings::default());
+        assert_eq!(renderer.height(20), 0);
+    }
+}
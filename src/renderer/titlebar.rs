@@ -0,0 +1,198 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use skia_safe::{Canvas, Paint, Rect};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{editor::Style, renderer::GridRenderer, settings::*};
+
+// In cells, each button is its glyph with a cell of space on either side
+const BUTTON_WIDTH: u64 = 3;
+// From the right edge of the window
+const BUTTONS: [(TitlebarHit, &str); 3] = [
+    (TitlebarHit::Close, "×"),
+    (TitlebarHit::Maximize, "□"),
+    (TitlebarHit::Minimize, "—"),
+];
+// Two presses on the strip closer together than this count as a double click
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(500);
+
+#[derive(SettingGroup, Clone)]
+pub struct TitlebarSettings {
+    // Draws a strip with the title and window buttons in place of the system title bar
+    pub custom_titlebar: bool,
+    // In multiples of the cell height
+    #[setting(min = 1.0, max = 3.0)]
+    pub titlebar_height: f32,
+}
+
+impl Default for TitlebarSettings {
+    fn default() -> Self {
+        Self {
+            custom_titlebar: false,
+            titlebar_height: 1.5,
+        }
+    }
+}
+
+// What a press on the titlebar landed on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TitlebarHit {
+    Drag,
+    Minimize,
+    Maximize,
+    Close,
+}
+
+// The buttons sit in the right end of the strip, everything else grabs the window
+fn hit_test(column_from_right: u64) -> TitlebarHit {
+    BUTTONS
+        .get((column_from_right / BUTTON_WIDTH) as usize)
+        .map_or(TitlebarHit::Drag, |(hit, _)| *hit)
+}
+
+// The title is centered in the window, so it may use the columns between the buttons and the
+// same width on the left
+fn title_label(title: &str, columns: u64) -> String {
+    let available = columns.saturating_sub(2 * (BUTTONS.len() as u64 * BUTTON_WIDTH + 1)) as usize;
+    let graphemes: Vec<&str> = title.graphemes(true).collect();
+    if graphemes.len() <= available {
+        title.to_owned()
+    } else if available == 0 {
+        String::new()
+    } else {
+        format!("{}…", graphemes[..available - 1].concat())
+    }
+}
+
+pub fn is_double_click(previous_press: Option<Instant>, now: Instant) -> bool {
+    previous_press.map_or(false, |previous_press| {
+        now.saturating_duration_since(previous_press) < DOUBLE_CLICK_TIME
+    })
+}
+
+pub struct TitlebarRenderer {
+    title: String,
+    // TabLine, the default style standing in for Normal while it is unset
+    style: Option<Arc<Style>>,
+}
+
+impl TitlebarRenderer {
+    pub fn new() -> TitlebarRenderer {
+        TitlebarRenderer {
+            title: String::new(),
+            style: None,
+        }
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_owned();
+    }
+
+    pub fn set_style(&mut self, style: Option<Arc<Style>>) {
+        self.style = style;
+    }
+
+    // The height in pixels the titlebar takes from the top of the window, zero while it is off
+    pub fn height(&self, font_height: u64) -> u32 {
+        let settings = SETTINGS.get::<TitlebarSettings>();
+        if settings.custom_titlebar {
+            (font_height as f32 * settings.titlebar_height).round() as u32
+        } else {
+            0
+        }
+    }
+
+    pub fn hit(&self, x: f64, window_width: f64, font_width: u64) -> TitlebarHit {
+        let from_right = (window_width - x).max(0.0);
+        hit_test((from_right / font_width as f64) as u64)
+    }
+
+    // The strip spans the whole width of the window, above the padding
+    pub fn draw(&self, root_canvas: &mut Canvas, grid_renderer: &mut GridRenderer, bar: Rect) {
+        let height = bar.height();
+        if height <= 0.0 {
+            return;
+        }
+
+        let font_width = grid_renderer.font_dimensions.width;
+        let font_height = grid_renderer.font_dimensions.height as f32;
+        let columns = (bar.width() / font_width as f32) as u64;
+        let mut paint = Paint::default();
+
+        root_canvas.save();
+        root_canvas.clip_rect(&bar, None, Some(false));
+        paint.set_color(grid_renderer.background_color(&self.style));
+        root_canvas.draw_rect(&bar, &paint);
+
+        // The text is centered in the taller strip, and the buttons are aligned to the right edge
+        // rather than to the cells
+        root_canvas.translate((bar.left, bar.top + (height - font_height) / 2.0));
+        let label = title_label(&self.title, columns);
+        let label_width = label.graphemes(true).count() as u64;
+        grid_renderer.draw_foreground(
+            root_canvas,
+            label,
+            ((columns.saturating_sub(label_width)) / 2, 0),
+            label_width,
+            (0, 0),
+            &self.style,
+        );
+
+        let right_edge_offset = bar.width() - (columns * font_width) as f32;
+        root_canvas.translate((right_edge_offset, 0.0));
+        for (index, (_, glyph)) in BUTTONS.iter().enumerate() {
+            let column = columns.saturating_sub((index as u64 + 1) * BUTTON_WIDTH) + 1;
+            grid_renderer.draw_foreground(
+                root_canvas,
+                (*glyph).to_owned(),
+                (column, 0),
+                1,
+                (0, 0),
+                &self.style,
+            );
+        }
+
+        root_canvas.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presses_find_the_buttons_from_the_right_edge() {
+        assert_eq!(hit_test(0), TitlebarHit::Close);
+        assert_eq!(hit_test(2), TitlebarHit::Close);
+        assert_eq!(hit_test(3), TitlebarHit::Maximize);
+        assert_eq!(hit_test(8), TitlebarHit::Minimize);
+        assert_eq!(hit_test(9), TitlebarHit::Drag);
+
+        let renderer = TitlebarRenderer::new();
+        assert_eq!(renderer.hit(795.0, 800.0, 10), TitlebarHit::Close);
+        assert_eq!(renderer.hit(730.0, 800.0, 10), TitlebarHit::Minimize);
+        assert_eq!(renderer.hit(10.0, 800.0, 10), TitlebarHit::Drag);
+    }
+
+    #[test]
+    fn test_long_titles_leave_room_for_the_buttons() {
+        assert_eq!(title_label("main.rs - NVIM", 80), "main.rs - NVIM");
+
+        let label = title_label(&"a".repeat(100), 40);
+        assert_eq!(label.graphemes(true).count(), 20);
+        assert!(label.ends_with('…'));
+
+        assert_eq!(title_label("main.rs", 10), "");
+    }
+
+    #[test]
+    fn test_double_clicks() {
+        let start = Instant::now();
+        assert!(!is_double_click(None, start));
+        assert!(is_double_click(Some(start), start + DOUBLE_CLICK_TIME / 2));
+        assert!(!is_double_click(Some(start), start + DOUBLE_CLICK_TIME * 2));
+    }
+}
@@ -1,31 +1,91 @@
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicI32, Ordering},
     Arc,
 };
 
 use log::info;
+use tokio::sync::watch;
+
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
 
 lazy_static! {
     pub static ref RUNNING_TRACKER: RunningTracker = RunningTracker::new();
 }
 
+// Fires once when neovide quits. Unlike the running flag it wakes tasks which are waiting on a
+// channel, so that an idle session doesn't wait for one more message before it can exit.
+pub struct ShutdownSignal {
+    sender: watch::Sender<bool>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        Self { sender, receiver }
+    }
+
+    pub fn trigger(&self) {
+        // The signal holds a receiver itself, so sending can't fail
+        self.sender.send(true).ok();
+    }
+
+    // Completes right away once the signal fired, however late it is awaited
+    pub async fn triggered(&self) {
+        let mut receiver = self.receiver.clone();
+        while !*receiver.borrow() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl Default for ShutdownSignal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct RunningTracker {
     running: Arc<AtomicBool>,
+    exit_code: AtomicI32,
+    shutdown: Arc<ShutdownSignal>,
 }
 
 impl RunningTracker {
     fn new() -> Self {
         Self {
             running: Arc::new(AtomicBool::new(true)),
+            exit_code: AtomicI32::new(0),
+            shutdown: Arc::new(ShutdownSignal::new()),
         }
     }
 
     pub fn quit(&self, reason: &str) {
         self.running.store(false, Ordering::Relaxed);
+        self.shutdown.trigger();
         info!("Quit {}", reason);
+        // Wake up the window so that it closes right away rather than on its next frame
+        REDRAW_SCHEDULER.force_next_frame();
+    }
+
+    // Neovide exits with the same code as neovim so that callers like git can tell when editing
+    // was aborted
+    pub fn quit_with_code(&self, exit_code: i32, reason: &str) {
+        self.exit_code.store(exit_code, Ordering::Relaxed);
+        self.quit(reason);
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code.load(Ordering::Relaxed)
     }
 
     pub fn is_running(&self) -> bool {
         self.running.load(Ordering::Relaxed)
     }
+
+    pub fn shutdown_signal(&self) -> Arc<ShutdownSignal> {
+        self.shutdown.clone()
+    }
 }
@@ -8,6 +8,51 @@ pub trait FromValue {
     fn from_value(&mut self, value: Value);
 }
 
+// What the SettingGroup and SettingMap derives require of each field's type: reading it from the
+// variable and writing it back. Every type with a FromValue impl and a conversion into Value gets
+// it, so a new setting type only needs those two, and a missing one is reported at the field.
+pub trait SettingValue: FromValue {
+    fn to_value(self) -> Value;
+}
+
+impl<T: FromValue + Into<Value>> SettingValue for T {
+    fn to_value(self) -> Value {
+        self.into()
+    }
+}
+
+// Variables arrive in the shape they were set in, which depends on whether they were set from
+// vimscript or lua. A float without a fractional part such as 1.0 is made an integer, so that it
+// counts for an integer or 0/1 boolean setting. Lua can't tell an empty table from an empty list,
+// and neovim sends it as a list, which is made the empty dictionary the setting meant.
+pub fn normalize_value(value: Value) -> Value {
+    match value {
+        Value::F32(number) => normalize_float(number as f64),
+        Value::F64(number) => normalize_float(number),
+        Value::Array(values) if values.is_empty() => Value::Map(Vec::new()),
+        Value::Array(values) => Value::Array(values.into_iter().map(normalize_value).collect()),
+        Value::Map(entries) => Value::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, normalize_value(value)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+// Only floats which convert to an integer without losing anything
+fn normalize_float(number: f64) -> Value {
+    const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0;
+    if number.fract() != 0.0 || number.abs() > MAX_EXACT_INTEGER {
+        Value::from(number)
+    } else if number >= 0.0 {
+        Value::from(number as u64)
+    } else {
+        Value::from(number as i64)
+    }
+}
+
 // FromValue implementations for most typical types
 impl FromValue for f32 {
     fn from_value(&mut self, value: Value) {
@@ -158,6 +203,53 @@ mod tests {
         assert_eq!(v0, v1p, "v0 should equal {} but is actually {}", v1p, v0);
     }
 
+    #[test]
+    fn test_setting_values_round_trip() {
+        fn round_trip<T: SettingValue + Clone>(default: T, value: T) -> Value {
+            let mut setting = default;
+            setting.from_value(value.clone().to_value());
+            setting.to_value()
+        }
+
+        assert_eq!(round_trip(0u64, 42), Value::from(42u64));
+        assert_eq!(round_trip(0.0f32, 0.5), Value::from(0.5f32));
+        assert_eq!(round_trip(false, true), Value::from(true));
+        assert_eq!(
+            round_trip(String::new(), "vsplit".to_owned()),
+            Value::from("vsplit")
+        );
+    }
+
+    #[test]
+    fn test_normalize_value() {
+        assert_eq!(normalize_value(Value::from(1.0)), Value::from(1u64));
+        assert_eq!(normalize_value(Value::from(-2.0)), Value::from(-2i64));
+        assert_eq!(normalize_value(Value::from(0.5)), Value::from(0.5));
+        assert_eq!(normalize_value(Value::from(1e300)), Value::from(1e300));
+        assert_eq!(
+            normalize_value(Value::from(f64::NAN))
+                .as_f64()
+                .map(f64::is_nan),
+            Some(true)
+        );
+        assert_eq!(normalize_value(Value::from("1.0")), Value::from("1.0"));
+
+        assert_eq!(
+            normalize_value(Value::Array(Vec::new())),
+            Value::Map(Vec::new())
+        );
+        assert_eq!(
+            normalize_value(Value::Map(vec![
+                (Value::from("opacity"), Value::from(200.0)),
+                (Value::from("kinds"), Value::Array(Vec::new())),
+            ])),
+            Value::Map(vec![
+                (Value::from("opacity"), Value::from(200u64)),
+                (Value::from("kinds"), Value::Map(Vec::new())),
+            ])
+        );
+    }
+
     #[test]
     fn test_from_value_bool() {
         let mut v0: bool = false;
@@ -1,21 +1,27 @@
 mod from_value;
 mod window_geometry;
 
-use log::trace;
+use log::{trace, warn};
 use nvim_rs::Neovim;
 use parking_lot::RwLock;
 use rmpv::Value;
 use std::{
     any::{Any, TypeId},
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     convert::TryInto,
 };
+use tokio::sync::watch;
 
-use crate::{bridge::TxWrapper, error_handling::ResultPanicExplanation};
-pub use from_value::FromValue;
+use crate::{
+    bridge::{ParallelCommand, TxWrapper, UiCommand, UiCommandTarget},
+    error_handling::ResultPanicExplanation,
+    event_aggregator::EVENT_AGGREGATOR,
+};
+use from_value::normalize_value;
+pub use from_value::{FromValue, SettingValue};
 pub use window_geometry::{
-    load_last_window_settings, parse_window_geometry, save_window_geometry,
-    PersistentWindowSettings, DEFAULT_WINDOW_GEOMETRY,
+    clamp_position_to_monitors, load_last_window_settings, parse_window_geometry,
+    save_window_geometry, PersistentWindowSettings, DEFAULT_WINDOW_GEOMETRY,
 };
 
 lazy_static! {
@@ -29,6 +35,8 @@ pub trait SettingGroup {
 // Function types to handle settings updates
 type UpdateHandlerFunc = fn(Value);
 type ReaderFunc = fn() -> Value;
+// The receiver is kept so that sending never fails and new subscribers can be cloned from it
+type Watcher<T> = (watch::Sender<T>, watch::Receiver<T>);
 
 // The Settings struct acts as a global container where each of Neovide's subsystems can store
 // their own settings. It will also coordinate updates between Neovide and nvim to make sure the
@@ -41,6 +49,16 @@ pub struct Settings {
     settings: RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
     listeners: RwLock<HashMap<String, UpdateHandlerFunc>>,
     readers: RwLock<HashMap<String, ReaderFunc>>,
+    // The setting group each name was registered by, to report names registered twice
+    owners: RwLock<HashMap<String, &'static str>>,
+    // Settings which follow a vim option instead of a g:neovide_ variable
+    option_listeners: RwLock<HashMap<String, UpdateHandlerFunc>>,
+    // A Watcher<T> for each setting group something subscribed to
+    watchers: RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
+    // The former names of renamed settings, each with the name it has now
+    aliases: RwLock<HashMap<String, String>>,
+    // The former names which were used already, the deprecation warning is only shown once
+    warned_aliases: RwLock<HashSet<String>>,
 }
 
 impl Settings {
@@ -49,15 +67,32 @@ impl Settings {
             settings: RwLock::new(HashMap::new()),
             listeners: RwLock::new(HashMap::new()),
             readers: RwLock::new(HashMap::new()),
+            owners: RwLock::new(HashMap::new()),
+            option_listeners: RwLock::new(HashMap::new()),
+            watchers: RwLock::new(HashMap::new()),
+            aliases: RwLock::new(HashMap::new()),
+            warned_aliases: RwLock::new(HashSet::new()),
         }
     }
 
     pub fn set_setting_handlers(
         &self,
         property_name: &str,
+        owner: &'static str,
         update_func: UpdateHandlerFunc,
         reader_func: ReaderFunc,
     ) {
+        // The later registration replaces the earlier one, which is almost certainly a mistake
+        if let Some(previous_owner) = self
+            .owners
+            .write()
+            .insert(String::from(property_name), owner)
+        {
+            warn!(
+                "g:neovide_{} is registered by both {} and {}",
+                property_name, previous_owner, owner
+            );
+        }
         self.listeners
             .write()
             .insert(String::from(property_name), update_func);
@@ -66,6 +101,88 @@ impl Settings {
             .insert(String::from(property_name), reader_func);
     }
 
+    // Keeps g:neovide_<alias> working for the setting now called property_name. It is read and
+    // watched like the setting itself, but values are only ever reported under the new name.
+    pub fn set_setting_alias(&self, alias: &str, property_name: &str) {
+        self.aliases
+            .write()
+            .insert(String::from(alias), String::from(property_name));
+    }
+
+    // The name an alias stands for, with the warning to show the first time it is used
+    fn resolve_alias(&self, name: &str) -> Option<(String, Option<String>)> {
+        let property_name = self.aliases.read().get(name)?.clone();
+        let warning = self
+            .warned_aliases
+            .write()
+            .insert(String::from(name))
+            .then(|| {
+                format!(
+                    "g:neovide_{} is deprecated, use g:neovide_{} instead",
+                    name, property_name
+                )
+            });
+        Some((property_name, warning))
+    }
+
+    pub fn all_registered_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.listeners.read().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    // Reads the named settings through their readers, so the values are the live ones. Names
+    // which aren't registered are left out.
+    pub fn snapshot(&self, names: &[&str]) -> Value {
+        let readers: Vec<(String, ReaderFunc)> = {
+            let registered = self.readers.read();
+            names
+                .iter()
+                .filter_map(|name| {
+                    registered
+                        .get(*name)
+                        .map(|reader| (name.to_string(), *reader))
+                })
+                .collect()
+        };
+        Value::Map(
+            readers
+                .into_iter()
+                .map(|(name, reader)| (Value::from(name), reader()))
+                .collect(),
+        )
+    }
+
+    // Every registered setting, grouped by the setting group which registered it, for example to
+    // attach a user's configuration to a bug report
+    pub fn snapshot_all(&self) -> Value {
+        let mut groups: Vec<(&'static str, Vec<String>)> = Vec::new();
+        for (name, owner) in self.owners.read().iter() {
+            match groups.iter_mut().find(|(group, _)| group == owner) {
+                Some((_, names)) => names.push(name.clone()),
+                None => groups.push((owner, vec![name.clone()])),
+            }
+        }
+        groups.sort();
+
+        Value::Map(
+            groups
+                .into_iter()
+                .map(|(group, mut names)| {
+                    names.sort();
+                    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+                    (Value::from(group), self.snapshot(&names))
+                })
+                .collect(),
+        )
+    }
+
+    pub fn set_option_handler(&self, option_name: &str, update_func: UpdateHandlerFunc) {
+        self.option_listeners
+            .write()
+            .insert(String::from(option_name), update_func);
+    }
+
     pub fn set<T: Clone + Send + Sync + 'static>(&self, t: &T) {
         let type_id: TypeId = TypeId::of::<T>();
         let t: T = (*t).clone();
@@ -73,7 +190,28 @@ impl Settings {
             self.settings.force_unlock_write();
         }
         let mut write_lock = self.settings.write();
-        write_lock.insert(type_id, Box::new(t));
+        write_lock.insert(type_id, Box::new(t.clone()));
+        drop(write_lock);
+
+        if let Some(watcher) = self.watchers.read().get(&type_id) {
+            if let Some((sender, _)) = watcher.downcast_ref::<Watcher<T>>() {
+                sender.send(t).ok();
+            }
+        }
+    }
+
+    // Returns a receiver which is updated with the new value each time the group is set. Reading
+    // it only borrows the value, so hot paths like the render loop can hold on to one instead of
+    // cloning the group with get on every access.
+    pub fn subscribe<T: Clone + Send + Sync + 'static>(&self) -> watch::Receiver<T> {
+        let mut watchers = self.watchers.write();
+        let watcher = watchers
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(watch::channel(self.get::<T>())));
+        let (_, receiver) = watcher
+            .downcast_ref::<Watcher<T>>()
+            .expect("Attempted to subscribe to a settings object of the wrong type");
+        receiver.clone()
     }
 
     pub fn get<T: Clone + Send + Sync + 'static>(&'_ self) -> T {
@@ -87,26 +225,63 @@ impl Settings {
         (*value).clone()
     }
 
-    pub async fn read_initial_values(&self, nvim: &Neovim<TxWrapper>) {
+    // Applies every registered variable and option neovim already has, and stores the default of
+    // each variable which isn't set so that scripts can read it. Run after the change listeners
+    // are set up, so that a variable set in between isn't missed.
+    pub async fn read_initial_values<T: UiCommandTarget>(&self, nvim: &T) {
+        // Former names are applied first, so that the current name wins when both are set
+        let aliases: Vec<String> = self.aliases.read().keys().cloned().collect();
+        for alias in aliases {
+            if let Some(value) =
+                get_value(nvim, "nvim_get_var", &format!("neovide_{}", alias)).await
+            {
+                if let Some((name, warning)) = self.resolve_alias(&alias) {
+                    if let Some(warning) = warning {
+                        nvim.err_writeln(&warning).await.ok();
+                    }
+                    if let Some(update_func) = self.listeners.read().get(&name) {
+                        update_func(normalize_value(value));
+                    }
+                }
+            }
+        }
+
         let keys: Vec<String> = self.listeners.read().keys().cloned().collect();
 
         for name in keys {
             let variable_name = format!("neovide_{}", name);
-            match nvim.get_var(&variable_name).await {
-                Ok(value) => {
-                    self.listeners.read().get(&name).unwrap()(value);
+            match get_value(nvim, "nvim_get_var", &variable_name).await {
+                Some(value) => {
+                    self.listeners.read().get(&name).unwrap()(normalize_value(value));
                 }
-                Err(error) => {
-                    trace!("Initial value load failed for {}: {}", name, error);
+                None => {
                     let setting = self.readers.read().get(&name).unwrap()();
-                    nvim.set_var(&variable_name, setting).await.ok();
+                    nvim.call(
+                        "nvim_set_var",
+                        vec![Value::from(variable_name.as_str()), setting],
+                    )
+                    .await
+                    .ok();
                 }
             }
         }
+
+        let option_names: Vec<String> = self.option_listeners.read().keys().cloned().collect();
+        for name in option_names {
+            if let Some(value) = get_value(nvim, "nvim_get_option", &name).await {
+                self.option_listeners.read().get(&name).unwrap()(value);
+            }
+        }
     }
 
     pub async fn setup_changed_listeners(&self, nvim: &Neovim<TxWrapper>) {
-        let keys: Vec<String> = self.listeners.read().keys().cloned().collect();
+        let keys: Vec<String> = self
+            .listeners
+            .read()
+            .keys()
+            .chain(self.aliases.read().keys())
+            .cloned()
+            .collect();
 
         for name in keys {
             let vimscript = format!(
@@ -126,23 +301,77 @@ impl Settings {
                     name
                 ));
         }
+
+        let option_names: Vec<String> = self.option_listeners.read().keys().cloned().collect();
+        for name in option_names {
+            let autocmd = format!(
+                "autocmd OptionSet {0} call rpcnotify(1, 'option_changed', '{0}', &{0})",
+                name
+            );
+            nvim.command(&autocmd)
+                .await
+                .unwrap_or_explained_panic(&format!(
+                    "Could not setup option notifier for {}",
+                    name
+                ));
+        }
     }
 
     pub fn handle_changed_notification(&self, arguments: Vec<Value>) {
         let mut arguments = arguments.into_iter();
         let (name, value) = (arguments.next().unwrap(), arguments.next().unwrap());
 
+        let name: Result<String, _> = name.try_into();
+        let mut name = name.unwrap();
+
+        if let Some((property_name, warning)) = self.resolve_alias(&name) {
+            if let Some(warning) = warning {
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::ShowError(warning)));
+            }
+            name = property_name;
+        }
+
+        self.listeners.read().get(&name).unwrap()(normalize_value(value));
+    }
+
+    pub fn handle_option_changed_notification(&self, arguments: Vec<Value>) {
+        let mut arguments = arguments.into_iter();
+        let (name, value) = (arguments.next().unwrap(), arguments.next().unwrap());
+
         let name: Result<String, _> = name.try_into();
         let name = name.unwrap();
 
-        self.listeners.read().get(&name).unwrap()(value);
+        if let Some(update_func) = self.option_listeners.read().get(&name) {
+            update_func(value);
+        }
+    }
+}
+
+// The value of a variable or option, None when neovim doesn't have it
+async fn get_value<T: UiCommandTarget>(nvim: &T, method: &str, name: &str) -> Option<Value> {
+    match nvim.call(method, vec![Value::from(name)]).await {
+        Ok(Ok(value)) => Some(value),
+        Ok(Err(error)) => {
+            trace!("Initial value load failed for {}: {}", name, error);
+            None
+        }
+        Err(error) => {
+            trace!("Initial value load failed for {}: {}", name, error);
+            None
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
+
     use async_trait::async_trait;
-    use nvim_rs::{Handler, Neovim};
+    use futures::FutureExt;
+    use nvim_rs::{
+        error::{CallError, EncodeError},
+        Handler, Neovim,
+    };
     use tokio;
 
     use super::*;
@@ -179,7 +408,7 @@ mod tests {
             Value::Nil
         }
 
-        settings.set_setting_handlers(property_name, noop_update, noop_read);
+        settings.set_setting_handlers(property_name, "Test", noop_update, noop_read);
         let listeners = settings.listeners.read();
         let readers = settings.readers.read();
         let listener = listeners.get(property_name).unwrap();
@@ -188,6 +417,218 @@ mod tests {
         assert_eq!(&(noop_read as ReaderFunc), reader);
     }
 
+    #[test]
+    fn test_field_prefix_nests_under_struct_prefix() {
+        #[derive(Clone, Default, SettingGroup)]
+        #[setting_prefix = "test_group"]
+        struct PrefixedSettings {
+            plain: bool,
+            #[setting(prefix = "nested")]
+            grouped: bool,
+        }
+
+        PrefixedSettings::register();
+
+        let listeners = SETTINGS.listeners.read();
+        assert!(listeners.contains_key("test_group_plain"));
+        assert!(listeners.contains_key("test_group_nested_grouped"));
+    }
+
+    #[test]
+    fn test_bounded_fields_reject_values_out_of_range() {
+        #[derive(Clone, SettingGroup)]
+        #[setting_prefix = "test_bounds"]
+        struct BoundedSettings {
+            #[setting(min = 0.1, max = 10.0)]
+            lifetime: f32,
+            #[setting(min = 1)]
+            count: u64,
+        }
+
+        impl Default for BoundedSettings {
+            fn default() -> Self {
+                BoundedSettings {
+                    lifetime: 1.2,
+                    count: 5,
+                }
+            }
+        }
+
+        BoundedSettings::register();
+        let update = |name: &str, value: Value| {
+            SETTINGS.handle_changed_notification(vec![Value::from(name), value])
+        };
+
+        update("test_bounds_lifetime", Value::from(20.0));
+        update("test_bounds_count", Value::from(0));
+        let settings = SETTINGS.get::<BoundedSettings>();
+        assert_eq!(settings.lifetime, 1.2);
+        assert_eq!(settings.count, 5);
+
+        update("test_bounds_lifetime", Value::from(10.0));
+        update("test_bounds_count", Value::from(100));
+        let settings = SETTINGS.get::<BoundedSettings>();
+        assert_eq!(settings.lifetime, 10.0);
+        assert_eq!(settings.count, 100);
+    }
+
+    #[test]
+    fn test_vimscript_and_lua_values_set_the_same_settings() {
+        #[derive(Clone, Default, SettingGroup)]
+        #[setting_prefix = "test_shapes"]
+        struct ShapeSettings {
+            enabled: bool,
+            opacity: f32,
+        }
+
+        ShapeSettings::register();
+        let update = |name: &str, value: Value| {
+            SETTINGS.handle_changed_notification(vec![Value::from(name), value])
+        };
+        let enabled = || SETTINGS.get::<ShapeSettings>().enabled;
+        let opacity = || SETTINGS.get::<ShapeSettings>().opacity;
+
+        // let g:neovide_test_shapes_enabled = 1, v:false and 1.0
+        update("test_shapes_enabled", Value::from(1));
+        assert!(enabled());
+        update("test_shapes_enabled", Value::from(false));
+        assert!(!enabled());
+        update("test_shapes_enabled", Value::from(1.0));
+        assert!(enabled());
+        // vim.g.neovide_test_shapes_enabled = false and true
+        update("test_shapes_enabled", Value::from(false));
+        assert!(!enabled());
+        update("test_shapes_enabled", Value::from(true));
+        assert!(enabled());
+
+        // let g:neovide_test_shapes_opacity = 0.5 and 1
+        update("test_shapes_opacity", Value::from(0.5));
+        assert_eq!(opacity(), 0.5);
+        update("test_shapes_opacity", Value::from(1));
+        assert_eq!(opacity(), 1.0);
+        // vim.g.neovide_test_shapes_opacity = 0.25 and 2, which neovim sends as an integer
+        update("test_shapes_opacity", Value::from(0.25));
+        assert_eq!(opacity(), 0.25);
+        update("test_shapes_opacity", Value::from(2));
+        assert_eq!(opacity(), 2.0);
+    }
+
+    #[test]
+    fn test_snapshot_reads_live_values() {
+        #[derive(Clone, Default, SettingGroup)]
+        #[setting_prefix = "test_snapshot"]
+        struct SnapshotSettings {
+            enabled: bool,
+            count: u64,
+        }
+
+        SnapshotSettings::register();
+        SETTINGS
+            .handle_changed_notification(vec![Value::from("test_snapshot_count"), Value::from(3)]);
+
+        assert_eq!(
+            SnapshotSettings::snapshot(),
+            Value::Map(vec![
+                (Value::from("test_snapshot_enabled"), Value::from(false)),
+                (Value::from("test_snapshot_count"), Value::from(3u64)),
+            ])
+        );
+
+        let all = SETTINGS.snapshot_all();
+        let group = all
+            .as_map()
+            .unwrap()
+            .iter()
+            .find(|(group, _)| group.as_str() == Some("SnapshotSettings"))
+            .map(|(_, values)| values.clone());
+        assert_eq!(
+            group,
+            Some(Value::Map(vec![
+                (Value::from("test_snapshot_count"), Value::from(3u64)),
+                (Value::from("test_snapshot_enabled"), Value::from(false)),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_option_fields_follow_vim_options() {
+        #[derive(Clone, Default, SettingGroup)]
+        #[setting_prefix = "test_options"]
+        struct MixedSettings {
+            variable: bool,
+            #[setting(option = "textwidth")]
+            text_width: u64,
+        }
+
+        MixedSettings::register();
+        assert!(SETTINGS
+            .listeners
+            .read()
+            .contains_key("test_options_variable"));
+        assert!(!SETTINGS
+            .listeners
+            .read()
+            .contains_key("test_options_text_width"));
+
+        SETTINGS
+            .handle_option_changed_notification(vec![Value::from("textwidth"), Value::from(80)]);
+        assert_eq!(SETTINGS.get::<MixedSettings>().text_width, 80);
+    }
+
+    #[test]
+    fn test_map_fields_round_trip() {
+        #[derive(Clone, Debug, PartialEq, SettingMap)]
+        struct VfxMap {
+            mode: String,
+            opacity: f32,
+        }
+
+        #[derive(Clone, SettingGroup)]
+        #[setting_prefix = "test_map"]
+        struct MapSettings {
+            vfx: VfxMap,
+        }
+
+        impl Default for MapSettings {
+            fn default() -> Self {
+                MapSettings {
+                    vfx: VfxMap {
+                        mode: String::new(),
+                        opacity: 200.0,
+                    },
+                }
+            }
+        }
+
+        MapSettings::register();
+        let update = |value: Value| {
+            SETTINGS.handle_changed_notification(vec![Value::from("test_map_vfx"), value])
+        };
+
+        update(Value::Map(vec![(
+            Value::from("mode"),
+            Value::from("torpedo"),
+        )]));
+        let expected = VfxMap {
+            mode: "torpedo".to_owned(),
+            opacity: 200.0,
+        };
+        assert_eq!(SETTINGS.get::<MapSettings>().vfx, expected);
+
+        // Malformed dictionaries are ignored
+        update(Value::Map(vec![(Value::from("speed"), Value::from(1))]));
+        update(Value::from("railgun"));
+        assert_eq!(SETTINGS.get::<MapSettings>().vfx, expected);
+
+        let reader = *SETTINGS.readers.read().get("test_map_vfx").unwrap();
+        let mut read_back = VfxMap {
+            mode: String::new(),
+            opacity: 0.0,
+        };
+        read_back.from_value(reader());
+        assert_eq!(read_back, expected);
+    }
+
     #[test]
     fn test_set() {
         let settings = Settings::new();
@@ -214,6 +655,21 @@ mod tests {
         assert_eq!(v2, *r3);
     }
 
+    #[test]
+    fn test_subscribe() {
+        let settings = Settings::new();
+        settings.set(&1u32);
+
+        let mut receiver = settings.subscribe::<u32>();
+        assert_eq!(*receiver.borrow(), 1);
+
+        settings.set(&2u32);
+        assert!(receiver.changed().now_or_never().is_some());
+        assert_eq!(*receiver.borrow(), 2);
+        assert!(receiver.changed().now_or_never().is_none());
+        assert_eq!(*settings.subscribe::<u32>().borrow(), 2);
+    }
+
     #[test]
     fn test_get() {
         let settings = Settings::new();
@@ -238,6 +694,180 @@ mod tests {
         assert_eq!(v2, r2);
     }
 
+    // Answers nvim_get_var and nvim_set_var from a map of preset variables
+    #[derive(Default)]
+    struct PresetVariables {
+        variables: Mutex<HashMap<String, Value>>,
+        // What was written with err_writeln
+        errors: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl UiCommandTarget for PresetVariables {
+        async fn input(&self, _keys: &str) -> Result<i64, Box<CallError>> {
+            unimplemented!()
+        }
+
+        async fn input_mouse(
+            &self,
+            _button: &str,
+            _action: &str,
+            _modifier: &str,
+            _grid: i64,
+            _row: i64,
+            _col: i64,
+        ) -> Result<(), Box<CallError>> {
+            unimplemented!()
+        }
+
+        async fn command(&self, _command: &str) -> Result<(), Box<CallError>> {
+            unimplemented!()
+        }
+
+        async fn eval(&self, _expr: &str) -> Result<Value, Box<CallError>> {
+            unimplemented!()
+        }
+
+        async fn call(
+            &self,
+            method: &str,
+            args: Vec<Value>,
+        ) -> Result<Result<Value, Value>, Box<EncodeError>> {
+            let mut args = args.into_iter();
+            let name = args.next().unwrap().as_str().unwrap().to_owned();
+            let mut variables = self.variables.lock().unwrap();
+            Ok(match method {
+                "nvim_get_var" => variables
+                    .get(&name)
+                    .cloned()
+                    .ok_or_else(|| Value::from("Key not found")),
+                "nvim_set_var" => {
+                    variables.insert(name, args.next().unwrap());
+                    Ok(Value::Nil)
+                }
+                _ => Err(Value::from("Unknown method")),
+            })
+        }
+
+        async fn err_writeln(&self, message: &str) -> Result<(), Box<CallError>> {
+            self.errors.lock().unwrap().push(message.to_owned());
+            Ok(())
+        }
+
+        async fn ui_try_resize(&self, _width: i64, _height: i64) -> Result<(), Box<CallError>> {
+            unimplemented!()
+        }
+
+        async fn ui_set_option(&self, _name: &str, _value: Value) -> Result<(), Box<CallError>> {
+            unimplemented!()
+        }
+
+        async fn ui_detach(&self) -> Result<(), Box<CallError>> {
+            unimplemented!()
+        }
+
+        async fn paste(
+            &self,
+            _data: &str,
+            _crlf: bool,
+            _phase: i64,
+        ) -> Result<bool, Box<CallError>> {
+            unimplemented!()
+        }
+    }
+
+    #[derive(Clone, SettingGroup)]
+    #[setting_prefix = "test_renamed"]
+    struct RenamedSettings {
+        #[setting(alias = "test_old_speed", alias = "test_older_speed")]
+        speed: u64,
+    }
+
+    impl Default for RenamedSettings {
+        fn default() -> Self {
+            RenamedSettings { speed: 1 }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_aliases_set_the_renamed_setting_once_warned() {
+        RenamedSettings::register();
+        let nvim = PresetVariables::default();
+        nvim.variables
+            .lock()
+            .unwrap()
+            .insert("neovide_test_old_speed".to_owned(), Value::from(5));
+
+        SETTINGS.read_initial_values(&nvim).await;
+        assert_eq!(SETTINGS.get::<RenamedSettings>().speed, 5);
+        // The value is stored under the new name only
+        assert_eq!(
+            nvim.variables
+                .lock()
+                .unwrap()
+                .get("neovide_test_renamed_speed"),
+            Some(&Value::from(5))
+        );
+        assert_eq!(
+            *nvim.errors.lock().unwrap(),
+            vec![
+                "g:neovide_test_old_speed is deprecated, use g:neovide_test_renamed_speed instead"
+            ]
+        );
+
+        SETTINGS.handle_changed_notification(vec![Value::from("test_older_speed"), Value::from(7)]);
+        assert_eq!(SETTINGS.get::<RenamedSettings>().speed, 7);
+        assert!(SETTINGS
+            .resolve_alias("test_older_speed")
+            .unwrap()
+            .1
+            .is_none());
+        assert_eq!(RenamedSettings::setting_names(), vec!["test_renamed_speed"]);
+        assert!(!SETTINGS
+            .all_registered_names()
+            .contains(&"test_old_speed".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn test_initial_values_apply_preset_variables() {
+        #[derive(Clone, SettingGroup)]
+        #[setting_prefix = "test_initial"]
+        struct InitialSettings {
+            refresh_rate: u64,
+            smooth: bool,
+        }
+
+        impl Default for InitialSettings {
+            fn default() -> Self {
+                InitialSettings {
+                    refresh_rate: 60,
+                    smooth: true,
+                }
+            }
+        }
+
+        InitialSettings::register();
+        let nvim = PresetVariables::default();
+        nvim.variables.lock().unwrap().insert(
+            "neovide_test_initial_refresh_rate".to_owned(),
+            Value::from(140),
+        );
+
+        SETTINGS.read_initial_values(&nvim).await;
+
+        let settings = SETTINGS.get::<InitialSettings>();
+        assert_eq!(settings.refresh_rate, 140);
+        assert!(settings.smooth);
+        // Variables neovim didn't have are set to their defaults
+        assert_eq!(
+            nvim.variables
+                .lock()
+                .unwrap()
+                .get("neovide_test_initial_smooth"),
+            Some(&Value::from(true))
+        );
+    }
+
     #[tokio::test]
     async fn test_read_initial_values() {
         let settings = Settings::new();
@@ -252,7 +882,7 @@ mod tests {
         //TODO: this sets a static variable. Can this have side effects on other tests?
         SETTINGS.set::<CmdLineSettings>(&CmdLineSettings::default());
 
-        let (nvim, _) = create::new_child_cmd(&mut create_nvim_command(), NeovimHandler())
+        let (nvim, _, _) = create::new_child_cmd(&mut create_nvim_command(), NeovimHandler())
             .await
             .unwrap_or_explained_panic("Could not locate or start the neovim process");
         nvim.set_var(&v4, Value::from(v2.clone())).await.ok();
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use glutin::dpi::PhysicalPosition;
+use glutin::dpi::{PhysicalPosition, PhysicalSize};
 use serde::{Deserialize, Serialize};
 
 use crate::{dimensions::Dimensions, settings::SETTINGS, window::WindowSettings};
@@ -108,6 +108,25 @@ pub fn save_window_geometry(
     std::fs::write(settings_path, json).unwrap();
 }
 
+// A position saved while another monitor was connected could leave the window off screen, so it is
+// moved onto the first monitor unless it lies within one of the given monitor bounds
+pub fn clamp_position_to_monitors(
+    position: PhysicalPosition<i32>,
+    monitors: &[(PhysicalPosition<i32>, PhysicalSize<u32>)],
+) -> PhysicalPosition<i32> {
+    let on_monitor = monitors.iter().any(|(monitor_position, monitor_size)| {
+        position.x >= monitor_position.x
+            && position.y >= monitor_position.y
+            && position.x < monitor_position.x + monitor_size.width as i32
+            && position.y < monitor_position.y + monitor_size.height as i32
+    });
+
+    match monitors.first() {
+        Some((monitor_position, _)) if !on_monitor => *monitor_position,
+        _ => position,
+    }
+}
+
 pub fn parse_window_geometry(geometry: Option<String>) -> Result<Dimensions, String> {
     let saved_window_size = load_last_window_settings()
         .and_then(|window_settings| {
@@ -150,3 +169,33 @@ pub fn parse_window_geometry(geometry: Option<String>) -> Result<Dimensions, Str
             .map_err(|msg| msg.to_owned())
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_position_to_monitors() {
+        let monitors = [
+            (PhysicalPosition::new(0, 0), PhysicalSize::new(1920, 1080)),
+            (
+                PhysicalPosition::new(1920, 0),
+                PhysicalSize::new(1280, 1024),
+            ),
+        ];
+
+        let on_second_monitor = PhysicalPosition::new(2000, 100);
+        assert_eq!(
+            clamp_position_to_monitors(on_second_monitor, &monitors),
+            on_second_monitor
+        );
+        assert_eq!(
+            clamp_position_to_monitors(PhysicalPosition::new(4000, 100), &monitors),
+            PhysicalPosition::new(0, 0)
+        );
+        assert_eq!(
+            clamp_position_to_monitors(PhysicalPosition::new(-50, 20), &[]),
+            PhysicalPosition::new(-50, 20)
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use glutin::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    window::Window,
+};
+use log::trace;
+
+use crate::{bridge::EditorMode, renderer::Renderer, settings::*};
+
+#[derive(Clone, Default, SettingGroup)]
+pub struct AccessibilitySettings {
+    // Reporting the caret is still experimental, so it has to be turned on with g:neovide_a11y
+    pub a11y: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaretState {
+    pub position: PhysicalPosition<i32>,
+    pub size: PhysicalSize<u32>,
+    pub mode: EditorMode,
+}
+
+impl CaretState {
+    fn from_renderer(renderer: &Renderer) -> CaretState {
+        let (rect, mode) = renderer.caret();
+        CaretState {
+            position: PhysicalPosition::new(rect.left as i32, rect.top as i32),
+            size: PhysicalSize::new(rect.width() as u32, rect.height() as u32),
+            mode: mode.clone(),
+        }
+    }
+}
+
+// Each platform layer the caret is reported to lives behind this trait, so that for example an
+// accessibility tree can be added next to the ime position without touching the window
+pub trait AccessibilityBackend {
+    fn report_caret(&mut self, window: &Window, caret: &CaretState);
+}
+
+// Input methods and most screen magnifiers follow the ime position, which is the one caret api
+// every platform winit supports has
+struct ImeBackend;
+
+impl AccessibilityBackend for ImeBackend {
+    fn report_caret(&mut self, window: &Window, caret: &CaretState) {
+        // The composition window is placed below the given position, so the bottom of the cell is
+        // reported to keep it from covering the text being typed
+        window.set_ime_position(PhysicalPosition::new(
+            caret.position.x,
+            caret.position.y + caret.size.height as i32,
+        ));
+        // Winit has no widget state to put the mode in, so it is only visible in the logs until
+        // a backend with an accessibility tree exists
+        trace!("Caret in {:?} mode", caret.mode);
+    }
+}
+
+pub struct AccessibilityManager {
+    backends: Vec<Box<dyn AccessibilityBackend>>,
+    reported_caret: Option<CaretState>,
+}
+
+impl AccessibilityManager {
+    pub fn new() -> AccessibilityManager {
+        AccessibilityManager {
+            backends: vec![Box::new(ImeBackend)],
+            reported_caret: None,
+        }
+    }
+
+    pub fn update(&mut self, renderer: &Renderer, window: &Window) {
+        if !SETTINGS.get::<AccessibilitySettings>().a11y {
+            self.reported_caret = None;
+            return;
+        }
+
+        if let Some(caret) = self.caret_change(CaretState::from_renderer(renderer)) {
+            for backend in self.backends.iter_mut() {
+                backend.report_caret(window, &caret);
+            }
+        }
+    }
+
+    // The caret is only reported when it moved or the mode changed since it was last reported
+    fn caret_change(&mut self, caret: CaretState) -> Option<CaretState> {
+        if self.reported_caret.as_ref() == Some(&caret) {
+            return None;
+        }
+        self.reported_caret = Some(caret.clone());
+        Some(caret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caret(x: i32, mode: EditorMode) -> CaretState {
+        CaretState {
+            position: PhysicalPosition::new(x, 20),
+            size: PhysicalSize::new(8, 16),
+            mode,
+        }
+    }
+
+    #[test]
+    fn test_caret_only_reported_when_changed() {
+        let mut manager = AccessibilityManager::new();
+
+        assert!(manager.caret_change(caret(0, EditorMode::Normal)).is_some());
+        assert!(manager.caret_change(caret(0, EditorMode::Normal)).is_none());
+        assert!(manager.caret_change(caret(8, EditorMode::Normal)).is_some());
+        assert!(manager.caret_change(caret(8, EditorMode::Insert)).is_some());
+    }
+}
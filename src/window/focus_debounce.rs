@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+// Holds a change of focus back until the window kept it for the debounce interval, since every
+// report runs the FocusGained or FocusLost autocommands in neovim. Alt-tabbing through windows or
+// a tiling window manager moving the focus around reports a burst of changes, and only where the
+// focus ended up is sent. A change back to the focus neovim was last told about cancels the
+// pending one, so the same state is never reported twice.
+#[derive(Debug)]
+pub struct FocusDebounce {
+    reported: bool,
+    pending: Option<(bool, Instant)>,
+}
+
+impl FocusDebounce {
+    pub fn new(reported: bool) -> FocusDebounce {
+        FocusDebounce {
+            reported,
+            pending: None,
+        }
+    }
+
+    // Records the focus the window has now, returning it if it is to be reported right away
+    pub fn changed(&mut self, focused: bool, now: Instant, interval: Duration) -> Option<bool> {
+        if focused == self.reported {
+            self.pending = None;
+            None
+        } else if interval == Duration::ZERO {
+            self.pending = None;
+            self.reported = focused;
+            Some(focused)
+        } else {
+            self.pending = Some((focused, now));
+            None
+        }
+    }
+
+    // Returns the pending focus once the window kept it for the interval
+    pub fn settled(&mut self, now: Instant, interval: Duration) -> Option<bool> {
+        match self.pending {
+            Some((focused, since)) if now.saturating_duration_since(since) >= interval => {
+                self.pending = None;
+                self.reported = focused;
+                Some(focused)
+            }
+            _ => None,
+        }
+    }
+
+    // When the pending focus is due, for waking the event loop
+    pub fn deadline(&self, interval: Duration) -> Option<Instant> {
+        self.pending.map(|(_, since)| since + interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn test_only_the_focus_a_burst_ends_with_is_reported() {
+        let start = Instant::now();
+        let step = Duration::from_millis(10);
+        let mut focus = FocusDebounce::new(true);
+
+        for change in 0..6 {
+            let now = start + step * change;
+            assert_eq!(focus.changed(change % 2 == 0, now, INTERVAL), None);
+            assert_eq!(focus.settled(now, INTERVAL), None);
+        }
+        // The burst ended unfocused
+        let last_change = start + step * 5;
+        assert_eq!(focus.deadline(INTERVAL), Some(last_change + INTERVAL));
+        assert_eq!(focus.settled(last_change + INTERVAL / 2, INTERVAL), None);
+        assert_eq!(focus.settled(last_change + INTERVAL, INTERVAL), Some(false));
+        assert_eq!(focus.settled(last_change + INTERVAL * 2, INTERVAL), None);
+    }
+
+    #[test]
+    fn test_returning_to_the_reported_focus_sends_nothing() {
+        let start = Instant::now();
+        let mut focus = FocusDebounce::new(true);
+
+        assert_eq!(focus.changed(false, start, INTERVAL), None);
+        assert_eq!(focus.changed(true, start + INTERVAL / 2, INTERVAL), None);
+        assert_eq!(focus.deadline(INTERVAL), None);
+        assert_eq!(focus.settled(start + INTERVAL * 2, INTERVAL), None);
+    }
+
+    #[test]
+    fn test_changes_are_sent_right_away_without_an_interval() {
+        let start = Instant::now();
+        let mut focus = FocusDebounce::new(true);
+
+        assert_eq!(focus.changed(false, start, Duration::ZERO), Some(false));
+        assert_eq!(focus.changed(false, start, Duration::ZERO), None);
+        assert_eq!(focus.changed(true, start, Duration::ZERO), Some(true));
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    ffi::c_void,
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+
+use crate::{profiling::PROFILER, window::windowed_context::WindowedContext};
+
+// How much of each new sample goes into the running estimate. Low enough that a single late frame
+// doesn't throw off the pacing, high enough to follow a change in how much there is to draw.
+const ESTIMATE_WEIGHT: f32 = 0.1;
+
+// Turns waiting for vblank on or off for the buffer swaps of the current context. glutin only
+// takes the swap interval when the context is created, so it is changed through the platform's
+// own opengl call instead, which keeps the window and context as they are.
+pub fn set_swap_interval(windowed_context: &WindowedContext, vsync: bool) {
+    let interval = if vsync { 1 } else { 0 };
+    let applied = unsafe { platform_swap_interval(windowed_context, interval) };
+    if applied {
+        info!("Vsync {}", if vsync { "enabled" } else { "disabled" });
+    } else {
+        warn!("Could not change the swap interval, vsync stays as the driver set it");
+    }
+}
+
+fn load(windowed_context: &WindowedContext, name: &str) -> Option<*const c_void> {
+    let address = windowed_context.get_proc_address(name);
+    if address.is_null() {
+        None
+    } else {
+        Some(address)
+    }
+}
+
+#[cfg(target_os = "windows")]
+unsafe fn platform_swap_interval(windowed_context: &WindowedContext, interval: i32) -> bool {
+    match load(windowed_context, "wglSwapIntervalEXT") {
+        Some(address) => {
+            let swap_interval: extern "system" fn(i32) -> i32 = std::mem::transmute(address);
+            swap_interval(interval) != 0
+        }
+        None => false,
+    }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn platform_swap_interval(_windowed_context: &WindowedContext, interval: i32) -> bool {
+    const CGL_CP_SWAP_INTERVAL: i32 = 222;
+
+    #[link(name = "OpenGL", kind = "framework")]
+    extern "C" {
+        fn CGLGetCurrentContext() -> *mut c_void;
+        fn CGLSetParameter(context: *mut c_void, parameter: i32, value: *const i32) -> i32;
+    }
+
+    let context = CGLGetCurrentContext();
+    !context.is_null() && CGLSetParameter(context, CGL_CP_SWAP_INTERVAL, &interval) == 0
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+unsafe fn platform_swap_interval(windowed_context: &WindowedContext, interval: i32) -> bool {
+    use glutin::platform::ContextTraitExt;
+
+    if let Some(display) = windowed_context.get_egl_display() {
+        return match load(windowed_context, "eglSwapInterval") {
+            Some(address) => {
+                let swap_interval: extern "system" fn(*const c_void, i32) -> u32 =
+                    std::mem::transmute(address);
+                swap_interval(display, interval) != 0
+            }
+            None => false,
+        };
+    }
+
+    // The mesa extension is the only glx one that applies to the current drawable and can also
+    // turn vsync back off
+    match load(windowed_context, "glXSwapIntervalMESA") {
+        Some(address) => {
+            let swap_interval: extern "C" fn(u32) -> i32 = std::mem::transmute(address);
+            swap_interval(interval as u32) == 0
+        }
+        None => false,
+    }
+}
+
+fn update_estimate(estimate: &mut Option<f32>, sample: f32) {
+    *estimate = Some(match *estimate {
+        Some(previous) => previous + (sample - previous) * ESTIMATE_WEIGHT,
+        None => sample,
+    });
+}
+
+// Decides when to start drawing the next frame. With vsync the buffer swap blocks until the
+// display takes the frame, so frames simply start one frame length after the previous one. Without
+// it, the time a frame takes to draw and present is estimated from the frames so far, and the next
+// frame starts early enough that its present lands one frame length after the last one. The event
+// loop sleeps until then, so nothing spins.
+pub struct FramePacer {
+    vsync: bool,
+    last_present: Option<Instant>,
+    // A running estimate of how long a frame takes to draw and present, in seconds
+    frame_cost: Option<f32>,
+}
+
+impl FramePacer {
+    pub fn new(vsync: bool) -> FramePacer {
+        FramePacer {
+            vsync,
+            last_present: None,
+            frame_cost: None,
+        }
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.vsync
+    }
+
+    pub fn set_vsync(&mut self, vsync: bool) {
+        // The estimates were made with the other swap behavior
+        *self = FramePacer::new(vsync);
+    }
+
+    // Records a frame which started drawing at frame_start and was presented at present
+    pub fn record_present(&mut self, frame_start: Instant, present: Instant) {
+        let frame_cost = present.saturating_duration_since(frame_start);
+        update_estimate(&mut self.frame_cost, frame_cost.as_secs_f32());
+
+        if let Some(last_present) = self.last_present {
+            PROFILER.record_present_interval(present.saturating_duration_since(last_present));
+        }
+        self.last_present = Some(present);
+    }
+
+    pub fn next_frame_start(
+        &self,
+        previous_frame_start: Instant,
+        frame_length: Duration,
+    ) -> Instant {
+        match (self.vsync, self.last_present, self.frame_cost) {
+            // Only a present of the previous frame says when the next one is due, frames which
+            // had nothing to draw weren't presented
+            (false, Some(last_present), Some(frame_cost))
+                if last_present >= previous_frame_start =>
+            {
+                let frame_cost = Duration::from_secs_f32(frame_cost).min(frame_length);
+                last_present + frame_length - frame_cost
+            }
+            _ => previous_frame_start + frame_length,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Instant, expected: Instant) {
+        let difference = if actual > expected {
+            actual - expected
+        } else {
+            expected - actual
+        };
+        assert!(difference < Duration::from_micros(10), "{:?}", difference);
+    }
+
+    #[test]
+    fn test_frame_pacer_starts_early_by_the_frame_cost() {
+        let frame_length = Duration::from_millis(16);
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(false);
+        assert_eq!(
+            pacer.next_frame_start(start, frame_length),
+            start + frame_length
+        );
+
+        let cost = Duration::from_millis(4);
+        for frame in 0..10 {
+            let frame_start = start + frame_length * frame;
+            pacer.record_present(frame_start, frame_start + cost);
+        }
+        // The last frame is presented late, so the next one is paced from its present rather than
+        // from when it started. The estimate moves a tenth of the way to the late frame's cost.
+        let last_start = start + frame_length * 10;
+        let late_cost = Duration::from_millis(10);
+        pacer.record_present(last_start, last_start + late_cost);
+        let estimate = cost + (late_cost - cost) / 10;
+        let next_start = pacer.next_frame_start(last_start, frame_length);
+        assert_close(next_start, last_start + late_cost + frame_length - estimate);
+        assert!(next_start > last_start + frame_length + Duration::from_millis(1));
+
+        let mut vsync_pacer = FramePacer::new(true);
+        vsync_pacer.record_present(start, start + cost);
+        assert_eq!(
+            vsync_pacer.next_frame_start(start, frame_length),
+            start + frame_length
+        );
+    }
+}
@@ -0,0 +1,47 @@
+// The modifier keys the keyboard has held down right now. Winit only reports modifier changes
+// while the window has focus, so this is asked when focus comes back. Platforms without a way to
+// ask return None, and their modifiers count as released until winit reports otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HeldModifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+#[cfg(target_os = "windows")]
+pub fn held_modifiers() -> Option<HeldModifiers> {
+    use winapi::um::winuser::{GetAsyncKeyState, VK_CONTROL, VK_LWIN, VK_MENU, VK_RWIN, VK_SHIFT};
+
+    // The most significant bit is set while the key is down
+    let is_down = |key: i32| unsafe { GetAsyncKeyState(key) as u16 & 0x8000 != 0 };
+    Some(HeldModifiers {
+        shift: is_down(VK_SHIFT),
+        ctrl: is_down(VK_CONTROL),
+        alt: is_down(VK_MENU),
+        logo: is_down(VK_LWIN) || is_down(VK_RWIN),
+    })
+}
+
+#[cfg(target_os = "macos")]
+pub fn held_modifiers() -> Option<HeldModifiers> {
+    use objc::{class, msg_send, sel, sel_impl};
+
+    const SHIFT: u64 = 1 << 17;
+    const CONTROL: u64 = 1 << 18;
+    const OPTION: u64 = 1 << 19;
+    const COMMAND: u64 = 1 << 20;
+
+    let flags: u64 = unsafe { msg_send![class!(NSEvent), modifierFlags] };
+    Some(HeldModifiers {
+        shift: flags & SHIFT != 0,
+        ctrl: flags & CONTROL != 0,
+        alt: flags & OPTION != 0,
+        logo: flags & COMMAND != 0,
+    })
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub fn held_modifiers() -> Option<HeldModifiers> {
+    None
+}
@@ -0,0 +1,216 @@
+// The names neovim's key notation has for keys, without the <> and modifiers around them which
+// the keyboard manager adds. Keys neovim has no name for, such as media keys, have no entry here:
+// anything sent for them would arrive as literal text.
+use glutin::keyboard::Key;
+
+// Keys which never produce text
+pub fn is_control_key(key: Key<'static>) -> Option<&str> {
+    match key {
+        Key::Backspace => Some("BS"),
+        Key::Escape => Some("Esc"),
+        Key::Delete => Some("Del"),
+        Key::ArrowUp => Some("Up"),
+        Key::ArrowDown => Some("Down"),
+        Key::ArrowLeft => Some("Left"),
+        Key::ArrowRight => Some("Right"),
+        Key::F1 => Some("F1"),
+        Key::F2 => Some("F2"),
+        Key::F3 => Some("F3"),
+        Key::F4 => Some("F4"),
+        Key::F5 => Some("F5"),
+        Key::F6 => Some("F6"),
+        Key::F7 => Some("F7"),
+        Key::F8 => Some("F8"),
+        Key::F9 => Some("F9"),
+        Key::F10 => Some("F10"),
+        Key::F11 => Some("F11"),
+        Key::F12 => Some("F12"),
+        Key::F13 => Some("F13"),
+        Key::F14 => Some("F14"),
+        Key::F15 => Some("F15"),
+        Key::F16 => Some("F16"),
+        Key::F17 => Some("F17"),
+        Key::F18 => Some("F18"),
+        Key::F19 => Some("F19"),
+        Key::F20 => Some("F20"),
+        Key::F21 => Some("F21"),
+        Key::F22 => Some("F22"),
+        Key::F23 => Some("F23"),
+        Key::F24 => Some("F24"),
+        Key::Insert => Some("Insert"),
+        Key::Home => Some("Home"),
+        Key::End => Some("End"),
+        Key::PageUp => Some("PageUp"),
+        Key::PageDown => Some("PageDown"),
+        Key::Tab => Some("Tab"),
+        Key::Help => Some("Help"),
+        Key::Undo => Some("Undo"),
+        _ => None,
+    }
+}
+
+// Keys on the numpad. The logical key already reflects numlock, so it decides between digits and
+// navigation.
+pub fn keypad_key(key: Key<'static>) -> Option<&str> {
+    match key {
+        Key::Character(text) => match text {
+            "0" => Some("k0"),
+            "1" => Some("k1"),
+            "2" => Some("k2"),
+            "3" => Some("k3"),
+            "4" => Some("k4"),
+            "5" => Some("k5"),
+            "6" => Some("k6"),
+            "7" => Some("k7"),
+            "8" => Some("k8"),
+            "9" => Some("k9"),
+            "+" => Some("kPlus"),
+            "-" => Some("kMinus"),
+            "*" => Some("kMultiply"),
+            "/" => Some("kDivide"),
+            "." => Some("kPoint"),
+            "," => Some("kComma"),
+            "=" => Some("kEqual"),
+            _ => None,
+        },
+        Key::Enter => Some("kEnter"),
+        // Navigation keys are what the keypad sends with numlock off
+        Key::Home => Some("kHome"),
+        Key::End => Some("kEnd"),
+        Key::PageUp => Some("kPageUp"),
+        Key::PageDown => Some("kPageDown"),
+        Key::ArrowUp => Some("kUp"),
+        Key::ArrowDown => Some("kDown"),
+        Key::ArrowLeft => Some("kLeft"),
+        Key::ArrowRight => Some("kRight"),
+        Key::Insert => Some("kInsert"),
+        Key::Delete => Some("kDel"),
+        Key::Clear => Some("kOrigin"),
+        _ => None,
+    }
+}
+
+// A key which is neither text nor one of the keys with a name in neovim's key notation
+pub fn is_unknown_key(key: Key<'static>) -> bool {
+    match key {
+        Key::Character(_) | Key::Dead(_) | Key::Space | Key::Enter => false,
+        _ => is_control_key(key).is_none() && keypad_key(key).is_none(),
+    }
+}
+
+// Text which has to be sent by name since it means something else in key notation
+pub fn is_special(text: &str) -> Option<&str> {
+    match text {
+        " " => Some("Space"),
+        "<" => Some("lt"),
+        "\\" => Some("Bslash"),
+        "|" => Some("Bar"),
+        "\t" => Some("Tab"),
+        "\n" => Some("CR"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_keys() {
+        let keys = [
+            (Key::Backspace, "BS"),
+            (Key::Escape, "Esc"),
+            (Key::Delete, "Del"),
+            (Key::ArrowUp, "Up"),
+            (Key::ArrowDown, "Down"),
+            (Key::ArrowLeft, "Left"),
+            (Key::ArrowRight, "Right"),
+            (Key::F1, "F1"),
+            (Key::F12, "F12"),
+            (Key::F13, "F13"),
+            (Key::F24, "F24"),
+            (Key::Insert, "Insert"),
+            (Key::Home, "Home"),
+            (Key::End, "End"),
+            (Key::PageUp, "PageUp"),
+            (Key::PageDown, "PageDown"),
+            (Key::Tab, "Tab"),
+            (Key::Help, "Help"),
+            (Key::Undo, "Undo"),
+        ];
+        for (key, name) in keys.iter() {
+            assert_eq!(is_control_key(*key), Some(*name), "{:?}", key);
+        }
+        assert_eq!(is_control_key(Key::Character("a")), None);
+        assert_eq!(is_control_key(Key::Enter), None);
+    }
+
+    #[test]
+    fn test_keypad_keys() {
+        let keys = [
+            (Key::Character("0"), "k0"),
+            (Key::Character("1"), "k1"),
+            (Key::Character("2"), "k2"),
+            (Key::Character("3"), "k3"),
+            (Key::Character("4"), "k4"),
+            (Key::Character("5"), "k5"),
+            (Key::Character("6"), "k6"),
+            (Key::Character("7"), "k7"),
+            (Key::Character("8"), "k8"),
+            (Key::Character("9"), "k9"),
+            (Key::Character("+"), "kPlus"),
+            (Key::Character("-"), "kMinus"),
+            (Key::Character("*"), "kMultiply"),
+            (Key::Character("/"), "kDivide"),
+            (Key::Character("."), "kPoint"),
+            (Key::Character(","), "kComma"),
+            (Key::Character("="), "kEqual"),
+            (Key::Enter, "kEnter"),
+            (Key::Home, "kHome"),
+            (Key::End, "kEnd"),
+            (Key::PageUp, "kPageUp"),
+            (Key::PageDown, "kPageDown"),
+            (Key::ArrowUp, "kUp"),
+            (Key::ArrowDown, "kDown"),
+            (Key::ArrowLeft, "kLeft"),
+            (Key::ArrowRight, "kRight"),
+            (Key::Insert, "kInsert"),
+            (Key::Delete, "kDel"),
+            (Key::Clear, "kOrigin"),
+        ];
+        for (key, name) in keys.iter() {
+            assert_eq!(keypad_key(*key), Some(*name), "{:?}", key);
+        }
+        assert_eq!(keypad_key(Key::Character("a")), None);
+        assert_eq!(keypad_key(Key::Tab), None);
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        assert!(is_unknown_key(Key::AudioVolumeUp));
+        assert!(is_unknown_key(Key::MediaPlayPause));
+        assert!(!is_unknown_key(Key::Character("a")));
+        assert!(!is_unknown_key(Key::Space));
+        assert!(!is_unknown_key(Key::Enter));
+        assert!(!is_unknown_key(Key::F5));
+        assert!(!is_unknown_key(Key::F13));
+        assert!(!is_unknown_key(Key::Clear));
+    }
+
+    #[test]
+    fn test_special_text() {
+        let texts = [
+            (" ", "Space"),
+            ("<", "lt"),
+            ("\\", "Bslash"),
+            ("|", "Bar"),
+            ("\t", "Tab"),
+            ("\n", "CR"),
+        ];
+        for (text, name) in texts.iter() {
+            assert_eq!(is_special(text), Some(*name));
+        }
+        assert_eq!(is_special("a"), None);
+        assert_eq!(is_special(">"), None);
+    }
+}
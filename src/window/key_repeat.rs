@@ -0,0 +1,166 @@
+use std::time::{Duration, Instant};
+
+use glutin::keyboard::{Key, KeyCode};
+use log::info;
+
+use crate::settings::*;
+
+// The most repeats sent at once after the event loop was blocked for a while
+const MAX_REPEAT_BURST: u32 = 10;
+
+#[derive(Clone, SettingGroup)]
+#[setting_prefix = "key_repeat"]
+pub struct KeyRepeatSettings {
+    // Repeats per second neovide sends for a held key when the platform sends none, for example
+    // on wayland compositors which leave repeating to the client. 0 turns it off.
+    pub rate: f32,
+    // Seconds a key has to be held before it starts repeating
+    pub delay: f32,
+}
+
+impl Default for KeyRepeatSettings {
+    fn default() -> Self {
+        Self {
+            rate: 0.0,
+            delay: 0.5,
+        }
+    }
+}
+
+// Modifiers on their own never produce input, so holding them must not either
+pub fn is_modifier_key(key: Key) -> bool {
+    matches!(
+        key,
+        Key::Shift
+            | Key::Control
+            | Key::Alt
+            | Key::AltGraph
+            | Key::Super
+            | Key::Hyper
+            | Key::Meta
+            | Key::CapsLock
+    )
+}
+
+struct HeldKey {
+    key: KeyCode,
+    keybinding: String,
+    next_repeat: Instant,
+}
+
+// Repeats the keybinding of the last key pressed until it is released. As soon as the platform
+// sends a repeat of its own this turns itself off for good, so keys never repeat at double speed.
+#[derive(Default)]
+pub struct KeyRepeater {
+    held_key: Option<HeldKey>,
+    platform_repeats: bool,
+}
+
+impl KeyRepeater {
+    pub fn press(&mut self, key: KeyCode, keybinding: String, now: Instant, delay: f32) {
+        self.held_key = Some(HeldKey {
+            key,
+            keybinding,
+            next_repeat: now + Duration::from_secs_f32(delay.max(0.0)),
+        });
+    }
+
+    pub fn release(&mut self, key: KeyCode) {
+        if matches!(&self.held_key, Some(held_key) if held_key.key == key) {
+            self.held_key = None;
+        }
+    }
+
+    // Focus loss means the release may never arrive
+    pub fn clear(&mut self) {
+        self.held_key = None;
+    }
+
+    pub fn platform_repeated(&mut self) {
+        if !self.platform_repeats {
+            info!("The platform repeats held keys, neovide won't repeat them itself");
+            self.platform_repeats = true;
+        }
+        self.held_key = None;
+    }
+
+    // The keybindings to send for the repeats which are due
+    pub fn due_repeats(&mut self, now: Instant, rate: f32) -> Vec<String> {
+        let held_key = match &mut self.held_key {
+            Some(held_key) if !self.platform_repeats && rate > 0.0 => held_key,
+            _ => return Vec::new(),
+        };
+
+        let interval = Duration::from_secs_f32(1.0 / rate);
+        let mut repeats = Vec::new();
+        while held_key.next_repeat <= now && repeats.len() < MAX_REPEAT_BURST as usize {
+            repeats.push(held_key.keybinding.clone());
+            held_key.next_repeat += interval;
+        }
+        // Skip whatever is left instead of catching up on it over the next frames
+        if held_key.next_repeat <= now {
+            held_key.next_repeat = now + interval;
+        }
+        repeats
+    }
+
+    // When the next repeat is due, so that the event loop wakes up for it
+    pub fn next_repeat(&self, rate: f32) -> Option<Instant> {
+        match &self.held_key {
+            Some(held_key) if !self.platform_repeats && rate > 0.0 => Some(held_key.next_repeat),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_held_key_repeats_after_the_delay() {
+        let start = Instant::now();
+        let mut repeater = KeyRepeater::default();
+        repeater.press(KeyCode::KeyJ, "j".to_owned(), start, 0.5);
+
+        assert!(repeater.due_repeats(start, 10.0).is_empty());
+        assert!(repeater
+            .due_repeats(start + Duration::from_millis(400), 10.0)
+            .is_empty());
+        assert_eq!(
+            repeater.due_repeats(start + Duration::from_millis(650), 10.0),
+            vec!["j".to_owned(), "j".to_owned()]
+        );
+
+        repeater.release(KeyCode::KeyK);
+        assert_eq!(
+            repeater.due_repeats(start + Duration::from_millis(700), 10.0),
+            vec!["j".to_owned()]
+        );
+        repeater.release(KeyCode::KeyJ);
+        assert!(repeater
+            .due_repeats(start + Duration::from_secs(1), 10.0)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_platform_repeats_turn_it_off() {
+        let start = Instant::now();
+        let mut repeater = KeyRepeater::default();
+        repeater.platform_repeated();
+        repeater.press(KeyCode::KeyJ, "j".to_owned(), start, 0.0);
+
+        assert!(repeater
+            .due_repeats(start + Duration::from_secs(1), 10.0)
+            .is_empty());
+        assert_eq!(repeater.next_repeat(10.0), None);
+    }
+
+    #[test]
+    fn test_modifiers_are_recognized() {
+        assert!(is_modifier_key(Key::Shift));
+        assert!(is_modifier_key(Key::Control));
+        assert!(!is_modifier_key(Key::Character("j")));
+        assert!(!is_modifier_key(Key::Enter));
+    }
+}
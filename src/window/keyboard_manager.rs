@@ -1,20 +1,53 @@
+use std::time::{Duration, Instant};
+
 use glutin::{
     event::{ElementState, Event, KeyEvent, WindowEvent},
-    keyboard::{Key, Key::Dead},
+    keyboard::{Key, Key::Dead, KeyCode, KeyLocation},
     platform::modifier_supplement::KeyEventExtModifierSupplement,
 };
+use log::trace;
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
-    bridge::{SerialCommand, UiCommand},
+    bridge::{ParallelCommand, SerialCommand, ShortcutAction, UiCommand},
+    editor::EditorCommand,
     event_aggregator::EVENT_AGGREGATOR,
     settings::SETTINGS,
-    window::KeyboardSettings,
+    window::{
+        held_modifiers::{held_modifiers, HeldModifiers},
+        key_notation::{is_control_key, is_special, is_unknown_key, keypad_key},
+        key_repeat::{is_modifier_key, KeyRepeatSettings, KeyRepeater},
+        standard_shortcuts::standard_shortcut,
+        KeyboardSettings,
+    },
 };
 
 enum InputEvent {
     KeyEvent(KeyEvent),
     ImeInput(String),
 }
+
+// Drops the auto repeats of a held key which come faster than the allowed rate. The press itself
+// always goes through and starts the timing over.
+#[derive(Default)]
+struct RepeatThrottle {
+    last_sent: Option<(KeyCode, Instant)>,
+}
+
+impl RepeatThrottle {
+    fn should_send(&mut self, key: KeyCode, repeat: bool, now: Instant, max_rate: f32) -> bool {
+        if repeat && max_rate > 0.0 {
+            if let Some((last_key, last_sent)) = self.last_sent {
+                let interval = Duration::from_secs_f32(1.0 / max_rate);
+                if last_key == key && now.saturating_duration_since(last_sent) < interval {
+                    return false;
+                }
+            }
+        }
+        self.last_sent = Some((key, now));
+        true
+    }
+}
 pub struct KeyboardManager {
     shift: bool,
     ctrl: bool,
@@ -23,6 +56,11 @@ pub struct KeyboardManager {
     logo: bool,
     ignore_input_this_frame: bool,
     queued_input_events: Vec<InputEvent>,
+    // Text which may only be part of a grapheme, such as the first half of a flag emoji. It is
+    // held back until the rest of the grapheme arrives.
+    pending_text: String,
+    repeat_throttle: RepeatThrottle,
+    key_repeater: KeyRepeater,
 }
 
 impl KeyboardManager {
@@ -35,18 +73,24 @@ impl KeyboardManager {
             logo: false,
             ignore_input_this_frame: false,
             queued_input_events: Vec::new(),
+            pending_text: String::new(),
+            repeat_throttle: RepeatThrottle::default(),
+            key_repeater: KeyRepeater::default(),
         }
     }
 
     pub fn handle_event(&mut self, event: &Event<()>) {
         match event {
             Event::WindowEvent {
-                event: WindowEvent::Focused(_focused),
+                event: WindowEvent::Focused(focused),
                 ..
             } => {
                 // When window is just focused or lost it's focus, ignore keyboard events
                 // that were submitted this frame
                 self.ignore_input_this_frame = true;
+                self.key_repeater.clear();
+                let held = if *focused { held_modifiers() } else { None };
+                self.sync_modifiers(held);
             }
             Event::WindowEvent {
                 event:
@@ -73,47 +117,119 @@ impl KeyboardManager {
             } => {
                 // Record the modifer states so that we can properly add them to the keybinding
                 // text
-                self.shift = modifiers.shift_key();
-                self.ctrl = modifiers.control_key();
-                self.alt = modifiers.alt_key();
-                self.logo = modifiers.super_key();
+                self.set_modifiers(HeldModifiers {
+                    shift: modifiers.shift_key(),
+                    ctrl: modifiers.control_key(),
+                    alt: modifiers.alt_key(),
+                    logo: modifiers.super_key(),
+                });
             }
             Event::MainEventsCleared => {
                 // And the window wasn't just focused.
                 let settings = SETTINGS.get::<KeyboardSettings>();
+                let repeat_settings = SETTINGS.get::<KeyRepeatSettings>();
 
                 if !self.should_ignore_input(&settings) {
                     // If we have a keyboard event this frame
-                    for input_event in self.queued_input_events.iter() {
+                    let input_events = std::mem::take(&mut self.queued_input_events);
+                    for input_event in input_events.iter() {
                         let mut next_dead_key = self.prev_dead_key;
                         match input_event {
                             InputEvent::KeyEvent(key_event) => {
                                 // And a key was pressed
                                 if key_event.state == ElementState::Pressed {
-                                    if let Some(keybinding) = self.maybe_get_keybinding(key_event) {
-                                        EVENT_AGGREGATOR.send(UiCommand::Serial(
-                                            SerialCommand::Keyboard(keybinding),
+                                    if key_event.repeat {
+                                        self.key_repeater.platform_repeated();
+                                    }
+                                    if !self.repeat_throttle.should_send(
+                                        key_event.physical_key,
+                                        key_event.repeat,
+                                        Instant::now(),
+                                        settings.max_repeat_rate,
+                                    ) {
+                                        continue;
+                                    }
+                                    if let Some(action) =
+                                        self.standard_shortcut(&settings, key_event)
+                                    {
+                                        // Holding the keys doesn't save or paste over and over
+                                        if !key_event.repeat {
+                                            self.flush_pending_text(false);
+                                            EVENT_AGGREGATOR.send(UiCommand::Serial(
+                                                SerialCommand::Shortcut(action),
+                                            ));
+                                        }
+                                    } else if settings.copy_screen_shortcut
+                                        && self.is_primary_shortcut(key_event, "c")
+                                    {
+                                        self.flush_pending_text(false);
+                                        EVENT_AGGREGATOR
+                                            .send(EditorCommand::CopyScreen { highlights: false });
+                                    } else if settings.new_window_shortcut
+                                        && self.is_primary_shortcut(key_event, "n")
+                                    {
+                                        self.flush_pending_text(false);
+                                        EVENT_AGGREGATOR.send(UiCommand::Parallel(
+                                            ParallelCommand::NewWindow {
+                                                cwd: None,
+                                                files: Vec::new(),
+                                            },
                                         ));
+                                    } else if !settings.forward_unknown_keys
+                                        && is_unknown_key(key_event.logical_key)
+                                    {
+                                        trace!("Dropped unknown key {:?}", key_event.logical_key);
+                                    } else if let Some(keybinding) =
+                                        self.maybe_get_keybinding(key_event)
+                                    {
+                                        if !key_event.repeat
+                                            && !is_modifier_key(key_event.logical_key)
+                                        {
+                                            self.key_repeater.press(
+                                                key_event.physical_key,
+                                                keybinding.clone(),
+                                                Instant::now(),
+                                                repeat_settings.delay,
+                                            );
+                                        }
+                                        self.send_keybinding(keybinding);
                                     }
                                     next_dead_key = None;
                                 } else if key_event.state == ElementState::Released {
+                                    self.key_repeater.release(key_event.physical_key);
                                     // dead key detect here
-                                    if let Dead(dead_key) = key_event.logical_key {
+                                    if let (Dead(dead_key), false) =
+                                        (key_event.logical_key, self.is_macos_meta())
+                                    {
                                         // should wait for the next input text_with_all_modifiers, and ignore the next ime.
                                         next_dead_key = dead_key;
                                     }
                                 }
                             }
+                            // Committed text spanning several lines is a paste, for example from a
+                            // clipboard manager, and is inserted as one edit so that mappings and
+                            // auto indent don't apply to it
+                            InputEvent::ImeInput(raw_input) if raw_input.contains('\n') => {
+                                self.flush_pending_text(false);
+                                EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Paste(
+                                    raw_input.to_owned(),
+                                )));
+                            }
                             InputEvent::ImeInput(raw_input) => {
                                 if self.prev_dead_key.is_none() {
-                                    EVENT_AGGREGATOR.send(UiCommand::Serial(
-                                        SerialCommand::Keyboard(raw_input.to_string()),
-                                    ));
+                                    self.pending_text.push_str(raw_input);
                                 }
                             }
                         }
                         self.prev_dead_key = next_dead_key;
                     }
+                    for keybinding in self
+                        .key_repeater
+                        .due_repeats(Instant::now(), repeat_settings.rate)
+                    {
+                        self.send_keybinding(keybinding);
+                    }
+                    self.flush_pending_text(true);
                 }
 
                 // Regardless of whether this was a valid keyboard input or not, rest ignoring and
@@ -125,11 +241,107 @@ impl KeyboardManager {
         }
     }
 
+    // Plain text is joined with the text around it so that graphemes split across several
+    // events reach neovim whole, anything else is sent after the text typed before it
+    fn send_keybinding(&mut self, keybinding: String) {
+        // Text which isn't plain, such as "<" itself, is always sent as a bracketed keybinding
+        if keybinding.starts_with('<') {
+            self.flush_pending_text(false);
+            EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Keyboard(keybinding)));
+        } else {
+            self.pending_text.push_str(&keybinding);
+        }
+    }
+
+    fn flush_pending_text(&mut self, keep_incomplete: bool) {
+        let text = if keep_incomplete {
+            take_complete_graphemes(&mut self.pending_text)
+        } else {
+            std::mem::take(&mut self.pending_text)
+        };
+        if !text.is_empty() {
+            EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Keyboard(text)));
+        }
+    }
+
+    pub fn alt_pressed(&self) -> bool {
+        self.alt
+    }
+
+    pub fn ctrl_pressed(&self) -> bool {
+        self.ctrl
+    }
+
+    // When the held key repeats next, the event loop has to wake up for it even when idle
+    pub fn next_key_repeat(&self) -> Option<Instant> {
+        self.key_repeater
+            .next_repeat(SETTINGS.get::<KeyRepeatSettings>().rate)
+    }
+
+    // Cmd+Shift and the letter on macos and Ctrl+Shift elsewhere, where most apps put shortcuts
+    // such as the one for a new window
+    fn is_primary_shortcut(&self, key_event: &KeyEvent, letter: &str) -> bool {
+        let primary_modifier = if cfg!(target_os = "macos") {
+            self.logo && !self.ctrl
+        } else {
+            self.ctrl && !self.logo
+        };
+        primary_modifier
+            && self.shift
+            && !self.alt
+            && matches!(key_event.key_without_modifiers(), Key::Character(key) if key.eq_ignore_ascii_case(letter))
+    }
+
+    // Cmd and the letter alone, other modifiers leave the keys to neovim mappings
+    fn standard_shortcut(
+        &self,
+        settings: &KeyboardSettings,
+        key_event: &KeyEvent,
+    ) -> Option<ShortcutAction> {
+        if !settings.standard_shortcuts || !self.logo || self.shift || self.ctrl || self.alt {
+            return None;
+        }
+        match key_event.key_without_modifiers() {
+            Key::Character(key) => standard_shortcut(key),
+            _ => None,
+        }
+    }
+
+    pub fn shift_held(&self) -> bool {
+        self.shift
+    }
+
+    fn set_modifiers(&mut self, held: HeldModifiers) {
+        self.shift = held.shift;
+        self.ctrl = held.ctrl;
+        self.alt = held.alt;
+        self.logo = held.logo;
+    }
+
+    // Modifiers released while another window has focus are never reported, so without this
+    // they would stay held and turn every key after switching back into a chord. The state is
+    // taken from the platform when it can tell, otherwise the modifiers start out released.
+    fn sync_modifiers(&mut self, held: Option<HeldModifiers>) {
+        self.set_modifiers(held.unwrap_or_default());
+    }
+
     fn should_ignore_input(&self, settings: &KeyboardSettings) -> bool {
         self.ignore_input_this_frame || (self.logo && !settings.use_logo)
     }
 
     fn maybe_get_keybinding(&self, key_event: &KeyEvent) -> Option<String> {
+        // Keypad keys get their own notation so that they can be mapped separately. The logical
+        // key already reflects numlock, so it decides between digits and navigation.
+        if key_event.location == KeyLocation::Numpad {
+            if let Some(key_text) = keypad_key(key_event.logical_key) {
+                return Some(self.format_keybinding_string(true, true, key_text));
+            }
+        }
+
+        if let Some(keybinding) = self.ctrl_chord_keybinding(key_event.key_without_modifiers()) {
+            return Some(keybinding);
+        }
+
         // Determine if this key event represents a key which won't ever
         // present text.
         if let Some(key_text) = is_control_key(key_event.logical_key) {
@@ -140,6 +352,8 @@ impl KeyboardManager {
             } else {
                 Some(self.format_keybinding_string(true, true, key_text))
             }
+        } else if let Some(meta_text) = self.macos_meta_text(key_event) {
+            Some(self.format_text_keybinding(&meta_text))
         } else {
             let key_text = if self.prev_dead_key.is_none() {
                 key_event.text
@@ -155,19 +369,70 @@ impl KeyboardManager {
                 }
                 // This is not a control key, so we rely upon winit to determine if
                 // this is a deadkey or not.
-                let keybinding_string = if let Some(escaped_text) = is_special(key_text) {
-                    self.format_keybinding_string(true, false, escaped_text)
-                } else {
-                    self.format_keybinding_string(false, false, key_text)
-                };
-
-                Some(keybinding_string)
+                Some(self.format_text_keybinding(key_text))
             } else {
                 None
             }
         }
     }
 
+    // The text of these chords is a control character (or nothing) which doesn't say which key was
+    // pressed, so they are told apart by the key instead. Shift changes what the characters are,
+    // so only space keeps its chord with shift held.
+    fn ctrl_chord_keybinding(&self, key: Key<'static>) -> Option<String> {
+        if !self.ctrl {
+            return None;
+        }
+        let key_text = match key {
+            Key::Space | Key::Character(" ") => "Space",
+            Key::Character("/") if !self.shift => "/",
+            // What terminals send for ctrl+6, the alternate file mapping
+            Key::Character("6") if !self.shift => "^",
+            _ => return None,
+        };
+        Some(self.format_keybinding_string(true, true, key_text))
+    }
+
+    fn is_macos_meta(&self) -> bool {
+        cfg!(target_os = "macos") && use_alt(self.alt)
+    }
+
+    // Option composes characters on macos, so when it is used as meta the key it was held with is
+    // sent instead of whatever it composed
+    fn macos_meta_text(&self, key_event: &KeyEvent) -> Option<String> {
+        if !self.is_macos_meta() {
+            return None;
+        }
+        match key_event.key_without_modifiers() {
+            Key::Character(text) if self.shift => Some(text.to_uppercase()),
+            Key::Character(text) => Some(text.to_owned()),
+            _ => None,
+        }
+    }
+
+    fn format_text_keybinding(&self, key_text: &str) -> String {
+        if let Some(escaped_text) = is_special(key_text) {
+            self.format_keybinding_string(true, false, escaped_text)
+        } else if let Some(letter) = self.shifted_logo_letter(key_text) {
+            self.format_keybinding_string(true, true, &letter)
+        } else {
+            self.format_keybinding_string(false, false, key_text)
+        }
+    }
+
+    // Shifted letters are normally sent as the uppercase letter, but neovim expects shifted
+    // mappings such as <D-S-s> to use the lowercase letter with an explicit shift when the logo
+    // key is held
+    fn shifted_logo_letter(&self, key_text: &str) -> Option<String> {
+        let mut characters = key_text.chars();
+        match (characters.next(), characters.next()) {
+            (Some(letter), None) if self.logo && self.shift && letter.is_alphabetic() => {
+                Some(letter.to_lowercase().collect())
+            }
+            _ => None,
+        }
+    }
+
     fn format_keybinding_string(&self, special: bool, use_shift: bool, text: &str) -> String {
         let special = special || self.ctrl || use_alt(self.alt) || self.logo;
 
@@ -194,10 +459,10 @@ fn use_alt(alt: bool) -> bool {
 }
 
 // The option or alt key is used on Macos for character set changes
-// and does not operate the same as other systems.
+// and does not operate the same as other systems unless it is set to act as meta.
 #[cfg(target_os = "macos")]
-fn use_alt(_: bool) -> bool {
-    false
+fn use_alt(alt: bool) -> bool {
+    alt && SETTINGS.get::<KeyboardSettings>().macos_alt_is_meta
 }
 
 fn or_empty(condition: bool, text: &str) -> &str {
@@ -208,45 +473,180 @@ fn or_empty(condition: bool, text: &str) -> &str {
     }
 }
 
-fn is_control_key(key: Key<'static>) -> Option<&str> {
-    match key {
-        Key::Backspace => Some("BS"),
-        Key::Escape => Some("Esc"),
-        Key::Delete => Some("Del"),
-        Key::ArrowUp => Some("Up"),
-        Key::ArrowDown => Some("Down"),
-        Key::ArrowLeft => Some("Left"),
-        Key::ArrowRight => Some("Right"),
-        Key::F1 => Some("F1"),
-        Key::F2 => Some("F2"),
-        Key::F3 => Some("F3"),
-        Key::F4 => Some("F4"),
-        Key::F5 => Some("F5"),
-        Key::F6 => Some("F6"),
-        Key::F7 => Some("F7"),
-        Key::F8 => Some("F8"),
-        Key::F9 => Some("F9"),
-        Key::F10 => Some("F10"),
-        Key::F11 => Some("F11"),
-        Key::F12 => Some("F12"),
-        Key::Insert => Some("Insert"),
-        Key::Home => Some("Home"),
-        Key::End => Some("End"),
-        Key::PageUp => Some("PageUp"),
-        Key::PageDown => Some("PageDown"),
-        Key::Tab => Some("Tab"),
-        _ => None,
+fn is_regional_indicator(character: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&character)
+}
+
+// A grapheme which the next event could still extend. Flags are made of two regional indicators
+// and emoji sequences are joined with a zero width joiner, so a lone regional indicator or a
+// trailing joiner means more is coming.
+fn is_incomplete_grapheme(grapheme: &str) -> bool {
+    let mut characters = grapheme.chars();
+    match (characters.next(), characters.next()) {
+        (Some(character), None) if is_regional_indicator(character) => true,
+        _ => grapheme.ends_with('\u{200D}'),
+    }
+}
+
+// Takes every whole grapheme from the pending text and leaves the last one behind if it is
+// incomplete
+fn take_complete_graphemes(pending_text: &mut String) -> String {
+    let incomplete_start = pending_text
+        .grapheme_indices(true)
+        .last()
+        .filter(|(_, grapheme)| is_incomplete_grapheme(grapheme))
+        .map(|(index, _)| index);
+
+    match incomplete_start {
+        Some(index) => {
+            let incomplete = pending_text.split_off(index);
+            std::mem::replace(pending_text, incomplete)
+        }
+        None => std::mem::take(pending_text),
     }
 }
 
-fn is_special(text: &str) -> Option<&str> {
-    match text {
-        " " => Some("Space"),
-        "<" => Some("lt"),
-        "\\" => Some("Bslash"),
-        "|" => Some("Bar"),
-        "\t" => Some("Tab"),
-        "\n" => Some("CR"),
-        _ => None,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logo_keyboard_manager(shift: bool) -> KeyboardManager {
+        let mut keyboard_manager = KeyboardManager::new();
+        keyboard_manager.logo = true;
+        keyboard_manager.shift = shift;
+        keyboard_manager
+    }
+
+    #[test]
+    fn test_logo_keybindings() {
+        assert_eq!(
+            logo_keyboard_manager(false).format_text_keybinding("s"),
+            "<D-s>"
+        );
+        assert_eq!(
+            logo_keyboard_manager(true).format_text_keybinding("S"),
+            "<S-D-s>"
+        );
+        assert_eq!(
+            logo_keyboard_manager(true).format_text_keybinding("<"),
+            "<D-lt>"
+        );
+    }
+
+    #[test]
+    fn test_focus_changes_resync_modifiers() {
+        let mut keyboard_manager = KeyboardManager::new();
+        keyboard_manager.ctrl = true;
+        keyboard_manager.alt = true;
+
+        keyboard_manager.sync_modifiers(None);
+        assert_eq!(keyboard_manager.format_modifier_string(true), "");
+
+        keyboard_manager.sync_modifiers(Some(HeldModifiers {
+            shift: true,
+            ..HeldModifiers::default()
+        }));
+        assert_eq!(keyboard_manager.format_modifier_string(true), "S-");
+    }
+
+    #[test]
+    fn test_repeats_are_throttled() {
+        let start = Instant::now();
+        let mut throttle = RepeatThrottle::default();
+
+        assert!(throttle.should_send(KeyCode::KeyJ, false, start, 10.0));
+        assert!(!throttle.should_send(
+            KeyCode::KeyJ,
+            true,
+            start + Duration::from_millis(30),
+            10.0
+        ));
+        assert!(throttle.should_send(
+            KeyCode::KeyJ,
+            true,
+            start + Duration::from_millis(100),
+            10.0
+        ));
+        // Presses and other keys are never held back
+        assert!(throttle.should_send(
+            KeyCode::KeyJ,
+            false,
+            start + Duration::from_millis(110),
+            10.0
+        ));
+        assert!(throttle.should_send(
+            KeyCode::KeyK,
+            true,
+            start + Duration::from_millis(120),
+            10.0
+        ));
+        // Without a limit every repeat goes through
+        assert!(throttle.should_send(KeyCode::KeyK, true, start + Duration::from_millis(121), 0.0));
+    }
+
+    #[test]
+    fn test_keypad_keybindings() {
+        let mut keyboard_manager = KeyboardManager::new();
+        assert_eq!(
+            keyboard_manager.format_keybinding_string(true, true, "k0"),
+            "<k0>"
+        );
+        keyboard_manager.ctrl = true;
+        assert_eq!(
+            keyboard_manager.format_keybinding_string(true, true, "kEnter"),
+            "<C-kEnter>"
+        );
+    }
+
+    #[test]
+    fn test_ctrl_chords() {
+        let mut keyboard_manager = KeyboardManager::new();
+        keyboard_manager.ctrl = true;
+        let chords = [
+            (Key::Space, Some("<C-Space>")),
+            (Key::Character(" "), Some("<C-Space>")),
+            (Key::Character("/"), Some("<C-/>")),
+            (Key::Character("6"), Some("<C-^>")),
+            (Key::Character("a"), None),
+        ];
+        for (key, expected) in chords.iter() {
+            assert_eq!(
+                keyboard_manager.ctrl_chord_keybinding(*key).as_deref(),
+                *expected,
+                "{:?}",
+                key
+            );
+        }
+
+        keyboard_manager.shift = true;
+        assert_eq!(
+            keyboard_manager
+                .ctrl_chord_keybinding(Key::Space)
+                .as_deref(),
+            Some("<S-C-Space>")
+        );
+        assert_eq!(
+            keyboard_manager.ctrl_chord_keybinding(Key::Character("/")),
+            None
+        );
+
+        keyboard_manager.ctrl = false;
+        assert_eq!(keyboard_manager.ctrl_chord_keybinding(Key::Space), None);
+    }
+
+    #[test]
+    fn test_split_graphemes_are_joined() {
+        let mut pending_text = String::from("a\u{1F1EB}");
+        assert_eq!(take_complete_graphemes(&mut pending_text), "a");
+
+        pending_text.push('\u{1F1F7}');
+        assert_eq!(
+            take_complete_graphemes(&mut pending_text),
+            "\u{1F1EB}\u{1F1F7}"
+        );
+        assert!(pending_text.is_empty());
+
+        let mut pending_text = String::from("e\u{301}");
+        assert_eq!(take_complete_graphemes(&mut pending_text), "e\u{301}");
     }
 }
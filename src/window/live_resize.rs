@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+// How long the window size has to stay the same for a live resize to count as finished
+const RESIZE_SETTLE_TIME: Duration = Duration::from_millis(200);
+
+// Tells a single resize, such as maximizing or snapping the window, apart from the user dragging
+// its edge. Winit reports neither the start nor the end of a drag, so a resize arriving before the
+// previous one settled counts as part of one. The first size of a drag is sent right away, the
+// ones after it are held back until the window stays still, since neovim redrawing the whole grid
+// for every step only makes it fall behind. The grid keeps its size and is letterboxed until then.
+#[derive(Debug, Default)]
+pub struct LiveResize {
+    last_resize: Option<Instant>,
+}
+
+impl LiveResize {
+    // Records a change of the window size, returning whether the grid size should be sent now
+    pub fn resized(&mut self, now: Instant) -> bool {
+        self.last_resize.replace(now).is_none()
+    }
+
+    // Returns true once when the window has been still for long enough after a resize, so that
+    // the final size is sent exactly once even if it was sent already
+    pub fn settled(&mut self, now: Instant) -> bool {
+        match self.last_resize {
+            Some(last_resize)
+                if now.saturating_duration_since(last_resize) > RESIZE_SETTLE_TIME =>
+            {
+                self.last_resize = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_resizes_are_sent_right_away() {
+        let start = Instant::now();
+        let mut live_resize = LiveResize::default();
+
+        assert!(live_resize.resized(start));
+        assert!(!live_resize.settled(start + RESIZE_SETTLE_TIME / 2));
+        assert!(live_resize.settled(start + RESIZE_SETTLE_TIME * 2));
+        assert!(!live_resize.settled(start + RESIZE_SETTLE_TIME * 3));
+
+        assert!(live_resize.resized(start + RESIZE_SETTLE_TIME * 4));
+    }
+
+    #[test]
+    fn test_drags_wait_until_the_window_is_still() {
+        let start = Instant::now();
+        let step = Duration::from_millis(16);
+        let mut live_resize = LiveResize::default();
+
+        assert!(live_resize.resized(start));
+        for frame in 1..20 {
+            assert!(!live_resize.resized(start + step * frame));
+            assert!(!live_resize.settled(start + step * frame));
+        }
+
+        let last_step = start + step * 19;
+        assert!(!live_resize.settled(last_step + RESIZE_SETTLE_TIME / 2));
+        assert!(live_resize.settled(last_step + RESIZE_SETTLE_TIME * 2));
+    }
+}
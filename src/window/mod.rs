@@ -1,66 +1,170 @@
+mod accessibility;
+mod focus_debounce;
+mod frame_pacer;
+mod held_modifiers;
+mod key_notation;
+mod key_repeat;
 mod keyboard_manager;
+mod live_resize;
 mod mouse_manager;
 mod renderer;
 mod settings;
+mod size_animation;
+mod standard_shortcuts;
+mod taskbar;
+mod touch_manager;
+mod windowed_context;
 
-use std::time::{Duration, Instant};
+use std::{
+    convert::TryInto,
+    time::{Duration, Instant},
+};
 
 use glutin::{
     self,
-    dpi::PhysicalSize,
-    event::{Event, WindowEvent},
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, Event, MouseButton, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::{self, Fullscreen, Icon},
-    ContextBuilder, GlProfile, WindowedContext,
 };
-use log::trace;
+use log::{info, trace, warn};
 use tokio::sync::mpsc::UnboundedReceiver;
 
+#[cfg(target_os = "macos")]
+use glutin::platform::macos::WindowBuilderExtMacOS;
 #[cfg(target_os = "linux")]
 use glutin::platform::unix::WindowBuilderExtUnix;
 
+use accessibility::AccessibilityManager;
+use focus_debounce::FocusDebounce;
+use frame_pacer::{set_swap_interval, FramePacer};
+use held_modifiers::held_modifiers;
 use image::{load_from_memory, GenericImageView, Pixel};
 use keyboard_manager::KeyboardManager;
+use live_resize::LiveResize;
 use mouse_manager::MouseManager;
-use renderer::SkiaRenderer;
+use renderer::{create_skia_renderer, ContextLosses, SkiaRenderer};
+use size_animation::{SizeAnimation, SIZE_ANIMATION_LENGTH};
+use taskbar::{create_taskbar_indicator, set_icon_text, TaskbarIndicator};
+use touch_manager::TouchManager;
+use windowed_context::{ContextConfig, WindowedContext};
 
 use crate::{
-    bridge::{ParallelCommand, UiCommand},
+    bridge::{
+        is_remote, set_render_backend, set_scale_factor, ChromeState, ParallelCommand,
+        RenderBackend, UiCommand,
+    },
     cmd_line::CmdLineSettings,
     dimensions::Dimensions,
     editor::EditorCommand,
     event_aggregator::EVENT_AGGREGATOR,
+    profiling::{FrameStatsCollector, PROFILER},
     redraw_scheduler::REDRAW_SCHEDULER,
-    renderer::Renderer,
+    renderer::{
+        is_double_click, CommandLineSettings, FontSettings, Renderer, TablineHit, TablineSettings,
+        TextRendering, TextRenderingSettings, TitlebarHit, TitlebarSettings,
+    },
     running_tracker::*,
     settings::{
-        load_last_window_settings, save_window_geometry, PersistentWindowSettings, SETTINGS,
+        clamp_position_to_monitors, load_last_window_settings, save_window_geometry,
+        PersistentWindowSettings, SETTINGS,
     },
 };
-pub use settings::{KeyboardSettings, WindowSettings};
+pub use accessibility::AccessibilitySettings;
+pub use key_repeat::KeyRepeatSettings;
+pub use settings::{Frame, KeyboardSettings, MouseSettings, Padding, WindowSettings};
+pub use taskbar::parse_progress;
+pub use touch_manager::TouchSettings;
 
 static ICON: &[u8] = include_bytes!("../../assets/neovide.ico");
 
+const DEFAULT_TITLE: &str = "Neovide";
 const MIN_WINDOW_WIDTH: u64 = 20;
 const MIN_WINDOW_HEIGHT: u64 = 6;
+const MIN_FONT_ZOOM: f64 = 0.5;
+const MAX_FONT_ZOOM: f64 = 4.0;
 
 #[derive(Clone, Debug)]
 pub enum WindowCommand {
     TitleChanged(String),
+    IconChanged(String),
     SetMouseEnabled(bool),
     ListAvailableFonts,
+    SetOpacity(f32),
+    SetGridSize(Dimensions),
+    GridSizeSettled {
+        requested: Dimensions,
+        settled: Dimensions,
+    },
+    SetNeovimResponding(bool),
+    ToggleZen,
+    SetProgress(Option<f64>),
+    SetBadge(Option<String>),
+    Suspend,
+    ReloadFallbackFonts,
+    RecreateRenderer,
+    SetChromeState(ChromeState),
 }
 
 pub struct GlutinWindowWrapper {
-    windowed_context: WindowedContext<glutin::PossiblyCurrent>,
-    skia_renderer: SkiaRenderer,
+    windowed_context: WindowedContext,
+    skia_renderer: Box<dyn SkiaRenderer>,
+    render_backend: RenderBackend,
+    context_losses: ContextLosses,
     renderer: Renderer,
     keyboard_manager: KeyboardManager,
     mouse_manager: MouseManager,
+    touch_manager: TouchManager,
+    accessibility_manager: AccessibilityManager,
+    // Pinching scales the font on top of the monitor's scale factor
+    font_zoom: f64,
+    // Set when the window moved to a monitor with another scale factor, so that the grid size is
+    // recalculated with the new font metrics once the resize that comes with it has happened
+    scale_factor_changed: bool,
     title: String,
+    // Shown in the title while chrome_indicator is set
+    chrome_state: ChromeState,
+    chrome_indicator: bool,
+    // The text neovim sets from 'iconstring', empty while it follows the title
+    icon_text: String,
     fullscreen: bool,
+    maximized: bool,
+    focused: bool,
+    // Winit has no minimize or occlusion events here, a minimized window is resized to nothing
+    // instead
+    minimized: bool,
+    // Set while the window is minimized for neovim's suspend, until it is brought back
+    suspended: bool,
+    // The focus neovim was last told about. A minimized window counts as unfocused, and neither
+    // change is sent twice in a row.
+    focus_debounce: FocusDebounce,
+    frame: Frame,
+    background_alpha: u8,
+    padding: Padding,
+    external_command_line: bool,
+    external_tabline: bool,
+    // Where the pointer is in the window, for clicks on the tabline and the titlebar
+    pointer_position: PhysicalPosition<f64>,
+    // For telling double clicks on the titlebar apart
+    last_titlebar_press: Option<Instant>,
     saved_inner_size: PhysicalSize<u32>,
     saved_grid_size: Option<Dimensions>,
+    live_resize: LiveResize,
+    // Set while the window animates to a size neovim asked for. The grid keeps its size and is
+    // letterboxed until the animation ends, rather than reflowing on every step.
+    size_animation: Option<SizeAnimation>,
+    // Cleared by the bridge's watchdog while neovim doesn't answer its pings
+    neovim_responding: bool,
+    // Set by the first close while neovim isn't responding, the second one quits without it
+    quit_requested: bool,
+    frame_stats: FrameStatsCollector,
+    frame_pacer: FramePacer,
+    taskbar: Box<dyn TaskbarIndicator>,
+    // Zen mode is layered over the window settings rather than changing them, so turning it off
+    // returns to whatever they are. zen_setting is the last seen g:neovide_zen, so that the rpc
+    // toggle isn't undone until the variable itself changes.
+    zen: bool,
+    zen_setting: bool,
     window_command_receiver: UnboundedReceiver<WindowCommand>,
 }
 
@@ -78,11 +182,130 @@ impl GlutinWindowWrapper {
     }
 
     pub fn synchronize_settings(&mut self) {
-        let fullscreen = { SETTINGS.get::<WindowSettings>().fullscreen };
+        let zen = { SETTINGS.get::<WindowSettings>().zen };
+
+        if self.zen_setting != zen {
+            self.zen_setting = zen;
+            self.set_zen(zen);
+        }
+
+        let fullscreen = { SETTINGS.get::<WindowSettings>().fullscreen } || self.zen;
 
         if self.fullscreen != fullscreen {
             self.toggle_fullscreen();
         }
+
+        let maximized = { SETTINGS.get::<WindowSettings>().maximized };
+
+        if self.maximized != maximized {
+            self.maximized = maximized;
+            self.windowed_context.window().set_maximized(maximized);
+        }
+
+        // The custom titlebar takes the place of the system one
+        let frame = if SETTINGS.get::<TitlebarSettings>().custom_titlebar {
+            Frame::None
+        } else {
+            SETTINGS.get::<WindowSettings>().frame
+        };
+
+        if self.frame != frame {
+            self.frame = frame;
+            self.windowed_context
+                .window()
+                .set_decorations(frame.has_decorations());
+        }
+
+        let chrome_indicator = { SETTINGS.get::<WindowSettings>().chrome_indicator };
+
+        if self.chrome_indicator != chrome_indicator {
+            self.chrome_indicator = chrome_indicator;
+            self.update_title();
+        }
+
+        let vsync = { SETTINGS.get::<WindowSettings>().vsync };
+
+        if self.frame_pacer.vsync() != vsync {
+            set_swap_interval(&self.windowed_context, vsync);
+            self.frame_pacer.set_vsync(vsync);
+        }
+
+        let background_alpha = { SETTINGS.get::<WindowSettings>().background_alpha() };
+
+        if self.background_alpha != background_alpha {
+            // Cells with the default background are drawn with the transparency baked in, so the
+            // grids need to be redrawn for the change to show
+            self.background_alpha = background_alpha;
+            EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
+        }
+
+        let fallback_fonts = { SETTINGS.get::<FontSettings>().fallbacks };
+
+        if self
+            .renderer
+            .grid_renderer
+            .update_fallback_fonts(&fallback_fonts)
+        {
+            // Glyphs already drawn keep the font they were drawn with until their lines are
+            // drawn again
+            EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
+        }
+
+        let text_rendering = TextRendering::new(
+            &SETTINGS.get::<TextRenderingSettings>(),
+            SETTINGS.get::<WindowSettings>().is_opaque(),
+        );
+
+        if self
+            .renderer
+            .grid_renderer
+            .update_text_rendering(text_rendering)
+        {
+            // The hinting can change the cell size, and the glyphs already drawn keep the old
+            // rendering until their lines are drawn again
+            self.handle_new_grid_size(self.windowed_context.window().inner_size());
+            EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
+        }
+
+        let inner_size = self.windowed_context.window().inner_size();
+        let padding = self.padding_for(inner_size);
+
+        if self.padding != padding {
+            // The window keeps its size, so the grid shrinks or grows to fill what is left
+            self.handle_new_grid_size(inner_size);
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+
+        let inactive_window_opacity = if self.zen {
+            SETTINGS.get::<WindowSettings>().zen_inactive_opacity
+        } else {
+            1.0
+        };
+
+        if self.renderer.inactive_window_opacity != inactive_window_opacity {
+            self.renderer.inactive_window_opacity = inactive_window_opacity;
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+
+        let external_command_line = { SETTINGS.get::<CommandLineSettings>().cmdline };
+
+        if self.external_command_line != external_command_line {
+            self.external_command_line = external_command_line;
+            EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::SetUiOption {
+                name: String::from("ext_cmdline"),
+                value: external_command_line,
+            }));
+        }
+
+        let external_tabline = { SETTINGS.get::<TablineSettings>().tabline };
+
+        if self.external_tabline != external_tabline {
+            self.external_tabline = external_tabline;
+            EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::SetUiOption {
+                name: String::from("ext_tabline"),
+                value: external_tabline,
+            }));
+        }
     }
 
     #[allow(clippy::needless_collect)]
@@ -90,17 +313,273 @@ impl GlutinWindowWrapper {
         while let Ok(window_command) = self.window_command_receiver.try_recv() {
             match window_command {
                 WindowCommand::TitleChanged(new_title) => self.handle_title_changed(new_title),
+                WindowCommand::IconChanged(new_icon) => self.handle_icon_changed(new_icon),
                 WindowCommand::SetMouseEnabled(mouse_enabled) => {
-                    self.mouse_manager.enabled = mouse_enabled
+                    self.mouse_manager.set_enabled(mouse_enabled)
                 }
                 WindowCommand::ListAvailableFonts => self.send_font_names(),
+                WindowCommand::SetOpacity(opacity) => self.handle_set_opacity(opacity),
+                WindowCommand::SetGridSize(grid_size) => self.handle_set_grid_size(grid_size),
+                WindowCommand::GridSizeSettled { requested, settled } => {
+                    self.handle_grid_size_settled(requested, settled)
+                }
+                WindowCommand::SetNeovimResponding(responding) => {
+                    self.handle_neovim_responding(responding)
+                }
+                WindowCommand::ToggleZen => self.set_zen(!self.zen),
+                WindowCommand::SetProgress(progress) => self.taskbar.set_progress(progress),
+                WindowCommand::SetBadge(badge) => self.taskbar.set_badge(badge.as_deref()),
+                WindowCommand::Suspend => self.handle_suspend(),
+                WindowCommand::ReloadFallbackFonts => self.reload_fallback_fonts(),
+                WindowCommand::RecreateRenderer => self.recreate_skia_renderer(),
+                WindowCommand::SetChromeState(state) => {
+                    self.chrome_state = state;
+                    self.update_title();
+                }
+            }
+        }
+    }
+
+    // Files are usually dragged in from another application, so the window may not have seen the
+    // shift key go down. The platform is asked where it can tell.
+    fn handle_file_drop(&mut self, path: String) {
+        let shift = held_modifiers().map_or(self.keyboard_manager.shift_held(), |held| held.shift);
+        let settings = SETTINGS.get::<WindowSettings>();
+        let mode = if shift {
+            settings.file_drop_shift_mode
+        } else {
+            settings.file_drop_mode
+        };
+        EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::FileDrop {
+            path,
+            mode,
+        }));
+    }
+
+    // The fullscreen, padding and opacity changes are picked up by synchronize_settings
+    pub fn set_zen(&mut self, zen: bool) {
+        if self.zen != zen {
+            info!("Zen mode {}", if zen { "on" } else { "off" });
+            self.zen = zen;
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+    }
+
+    // The padding settings, widened in zen mode to center a column of zen_width cells. The titlebar
+    // and the tabline are drawn in the top padding, so the grid shrinks by their height.
+    fn padding_for(&self, window_size: PhysicalSize<u32>) -> Padding {
+        let window_settings = SETTINGS.get::<WindowSettings>();
+        let mut padding = window_settings.padding();
+        if self.zen {
+            let column_width =
+                self.renderer.grid_renderer.font_dimensions.width * window_settings.zen_width;
+            padding = padding.centered(window_size, column_width.try_into().unwrap_or(u32::MAX));
+        }
+
+        padding.top += self.renderer.titlebar_height() + self.renderer.tabline_height();
+        padding
+    }
+
+    // Clicks on the native tabline select or close tabs, and clicks on the custom titlebar move or
+    // size the window, instead of reaching neovim as mouse input. Returns whether the event was
+    // taken by either of them.
+    fn handle_bar_event(&mut self, event: &Event<()>) -> bool {
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                ..
+            } => {
+                self.pointer_position = *position;
+                false
             }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        button,
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                // Releases still go to the mouse manager, which may be ending a drag
+                let window_width = self.windowed_context.window().inner_size().width;
+                if let Some(hit) = self
+                    .renderer
+                    .titlebar_hit(self.pointer_position, window_width)
+                {
+                    if *button == MouseButton::Left {
+                        self.handle_titlebar_press(hit);
+                    }
+                    return true;
+                }
+
+                let hit = match self.renderer.tabline_hit(self.pointer_position) {
+                    Some(hit) => hit,
+                    None => return false,
+                };
+                let command = match (button, hit) {
+                    (MouseButton::Left, TablineHit::Tab(tab)) => ParallelCommand::TabSelect(tab),
+                    (MouseButton::Left, TablineHit::Close(tab))
+                    | (MouseButton::Middle, TablineHit::Tab(tab) | TablineHit::Close(tab)) => {
+                        ParallelCommand::TabClose(tab)
+                    }
+                    _ => return true,
+                };
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(command));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn handle_titlebar_press(&mut self, hit: TitlebarHit) {
+        let now = Instant::now();
+        let double_click = is_double_click(self.last_titlebar_press.take(), now);
+        let window = self.windowed_context.window();
+        match hit {
+            TitlebarHit::Drag if double_click => self.toggle_maximized(),
+            TitlebarHit::Drag => {
+                self.last_titlebar_press = Some(now);
+                window.drag_window().ok();
+            }
+            TitlebarHit::Minimize => window.set_minimized(true),
+            TitlebarHit::Maximize => self.toggle_maximized(),
+            TitlebarHit::Close => self.handle_quit(),
         }
     }
 
+    // Goes through g:neovide_maximized like the other window settings, so that
+    // synchronize_settings doesn't undo it
+    fn toggle_maximized(&mut self) {
+        let mut window_settings = SETTINGS.get::<WindowSettings>();
+        window_settings.maximized = !self.windowed_context.window().is_maximized();
+        SETTINGS.set(&window_settings);
+    }
+
     pub fn handle_title_changed(&mut self, new_title: String) {
-        self.title = new_title;
-        self.windowed_context.window().set_title(&self.title);
+        // Neovim sends an empty title when 'title' is unset, which would leave a blank title bar
+        self.title = if new_title.is_empty() {
+            String::from(DEFAULT_TITLE)
+        } else {
+            new_title
+        };
+        self.update_title();
+        if self.icon_text.is_empty() {
+            set_icon_text(self.windowed_context.window(), &self.title);
+        }
+    }
+
+    fn update_title(&mut self) {
+        let title = if self.chrome_indicator {
+            self.chrome_state.decorate_title(&self.title)
+        } else {
+            self.title.clone()
+        };
+        self.windowed_context.window().set_title(&title);
+        self.renderer.set_title(&title);
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    // Neovim sends an empty icon when 'icon' is unset, in which case the icon shows the title
+    pub fn handle_icon_changed(&mut self, new_icon: String) {
+        self.icon_text = new_icon;
+        let icon_text = if self.icon_text.is_empty() {
+            &self.title
+        } else {
+            &self.icon_text
+        };
+        set_icon_text(self.windowed_context.window(), icon_text);
+    }
+
+    // The opacity is stored as the transparency setting, so that synchronize_settings applies it
+    // the same way as a change to g:neovide_transparency
+    pub fn handle_set_opacity(&mut self, opacity: f32) {
+        let mut window_settings = SETTINGS.get::<WindowSettings>();
+        window_settings.transparency = opacity.max(0.0).min(1.0);
+        SETTINGS.set(&window_settings);
+    }
+
+    // Resizes the window to fit the grid size neovim asked for, for example with :set columns=120.
+    // The resize then reaches neovim the same way as one made by the user.
+    pub fn handle_set_grid_size(&mut self, grid_size: Dimensions) {
+        if self.fullscreen || self.maximized {
+            trace!(
+                "Ignoring grid size {:?} for a fullscreen or maximized window",
+                grid_size
+            );
+            return;
+        }
+
+        let window = self.windowed_context.window();
+        let mut new_size = self.padding.window_size(
+            self.renderer
+                .grid_renderer
+                .convert_grid_to_physical(grid_size),
+        );
+        if let Some(monitor) = window.current_monitor() {
+            let monitor_size = monitor.size();
+            new_size.width = new_size.width.min(monitor_size.width);
+            new_size.height = new_size.height.min(monitor_size.height);
+        }
+
+        if window.inner_size() == new_size {
+            return;
+        }
+
+        if SETTINGS.get::<WindowSettings>().window_resize_animation {
+            self.size_animation = Some(SizeAnimation::new(
+                window.inner_size(),
+                new_size,
+                Instant::now(),
+            ));
+            REDRAW_SCHEDULER.queue_next_frame();
+        } else {
+            window.set_inner_size(new_size);
+        }
+    }
+
+    // Whether a window of this size is still part of the size animation. It ends once the window
+    // reached the target, or when the user resized the window to something the animation
+    // wouldn't have.
+    fn is_size_animating(&mut self, size: PhysicalSize<u32>) -> bool {
+        let animating = self.size_animation.as_ref().map_or(false, |animation| {
+            size != animation.to && animation.made(size)
+        });
+        if !animating {
+            self.size_animation = None;
+        }
+        animating
+    }
+
+    fn step_size_animation(&mut self) {
+        let animation = match &self.size_animation {
+            Some(animation) => animation,
+            None => return,
+        };
+
+        if animation.elapsed() >= SIZE_ANIMATION_LENGTH * 2 {
+            // The window manager kept the window from reaching the target, so the grid is fit to
+            // the size it got instead
+            self.size_animation = None;
+            let size = self.windowed_context.window().inner_size();
+            self.handle_new_grid_size(size);
+            return;
+        }
+
+        let size = animation.size_at(Instant::now());
+        self.windowed_context.window().set_inner_size(size);
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    // Frames are always counted, the summary is only sent while g:neovide_report_frame_stats is set
+    fn report_frame_stats(&mut self) {
+        if let Some(stats) = self.frame_stats.take_summary(Instant::now()) {
+            if SETTINGS.get::<WindowSettings>().report_frame_stats {
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::ReportFrameStats(
+                    stats,
+                )));
+            }
+        }
     }
 
     pub fn send_font_names(&self) {
@@ -110,31 +589,200 @@ impl GlutinWindowWrapper {
         )));
     }
 
+    // Looks the fallback fonts up again and reports the ones which were found
+    fn reload_fallback_fonts(&mut self) {
+        let shaper = &mut self.renderer.grid_renderer.shaper;
+        shaper.reload_fallback_fonts();
+        EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::ShowFallbackFonts(
+            shaper.fallback_fonts().to_vec(),
+        )));
+        EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
+    }
+
+    // The context is current on this thread only, so the renderer is always made again here. The
+    // grids keep their places and only their contents are drawn again.
+    fn recreate_skia_renderer(&mut self) {
+        let backend = if self.context_losses.lost(Instant::now())
+            && self.render_backend != RenderBackend::Raster
+        {
+            warn!("The gpu context keeps getting lost, falling back to the raster renderer");
+            RenderBackend::Raster
+        } else {
+            self.render_backend
+        };
+
+        // The context the gpu reset can't be drawn with at all any more, so the window gets a new
+        // one along with the renderer
+        self.skia_renderer.abandon();
+        let backend = if self.windowed_context.recreate_context() {
+            info!("Recreated the opengl context");
+            // The new context starts out with the swap interval vsync had at startup
+            set_swap_interval(&self.windowed_context, self.frame_pacer.vsync());
+            backend
+        } else {
+            warn!("Keeping the old opengl context, falling back to the raster renderer");
+            RenderBackend::Raster
+        };
+
+        let (skia_renderer, backend) = create_skia_renderer(&self.windowed_context, backend);
+        info!("Recreated the {} rendering backend", backend.name());
+        self.skia_renderer = skia_renderer;
+        self.render_backend = backend;
+        set_render_backend(backend);
+
+        self.renderer.recreate_surfaces(self.skia_renderer.canvas());
+        EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    pub fn handle_neovim_responding(&mut self, responding: bool) {
+        self.neovim_responding = responding;
+        self.quit_requested = false;
+        self.renderer.set_banner(if responding {
+            None
+        } else {
+            Some(String::from("Neovim is not responding..."))
+        });
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
     pub fn handle_quit(&mut self) {
-        if SETTINGS.get::<CmdLineSettings>().remote_tcp.is_none() {
-            EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::Quit));
+        // A quit command would only wait in line behind whatever is blocking neovim, so closing a
+        // hung neovim asks for confirmation and then closes the window without it
+        if !self.neovim_responding {
+            if self.quit_requested {
+                RUNNING_TRACKER.quit("window closed while neovim was not responding");
+            } else {
+                self.quit_requested = true;
+                self.renderer.set_banner(Some(String::from(
+                    "Neovim is not responding, close the window again to quit",
+                )));
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            return;
+        }
+
+        // A remote server may be shared with other clients, so closing the window only detaches
+        // this ui and leaves the server running
+        let command = if is_remote() {
+            ParallelCommand::Detach
+        } else if SETTINGS.get::<WindowSettings>().confirm_quit {
+            ParallelCommand::ConfirmQuit
         } else {
-            RUNNING_TRACKER.quit("window closed");
+            ParallelCommand::Quit
+        };
+        EVENT_AGGREGATOR.send(UiCommand::Parallel(command));
+    }
+
+    fn focus_debounce_interval() -> Duration {
+        Duration::from_millis(SETTINGS.get::<WindowSettings>().focus_debounce)
+    }
+
+    fn send_focus(focus: Option<bool>) {
+        if let Some(focused) = focus {
+            EVENT_AGGREGATOR.send(UiCommand::Parallel(if focused {
+                ParallelCommand::FocusGained
+            } else {
+                ParallelCommand::FocusLost
+            }));
         }
     }
 
+    fn report_focus(&mut self, focused: bool) {
+        let focus =
+            self.focus_debounce
+                .changed(focused, Instant::now(), Self::focus_debounce_interval());
+        Self::send_focus(focus);
+    }
+
+    pub fn report_settled_focus(&mut self) {
+        let focus = self
+            .focus_debounce
+            .settled(Instant::now(), Self::focus_debounce_interval());
+        Self::send_focus(focus);
+    }
+
+    pub fn next_focus_report(&self) -> Option<Instant> {
+        self.focus_debounce
+            .deadline(Self::focus_debounce_interval())
+    }
+
     pub fn handle_focus_lost(&mut self) {
-        EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::FocusLost));
+        self.focused = false;
+        self.renderer.set_window_focused(false);
+        self.report_focus(false);
+        // The cursor is redrawn as an outline
+        REDRAW_SCHEDULER.queue_next_frame();
     }
 
     pub fn handle_focus_gained(&mut self) {
-        EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::FocusGained));
+        self.focused = true;
+        self.renderer.set_window_focused(true);
+        if !self.minimized {
+            self.report_focus(true);
+        }
+        if std::mem::take(&mut self.suspended) {
+            // Not every platform resizes a minimized window to nothing, so the restore may only
+            // show up as the focus coming back
+            EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
+        }
         REDRAW_SCHEDULER.queue_next_frame();
     }
 
+    // Neovim keeps running, it only asks the ui to get out of the way. Restoring the window
+    // reports the focus again, which is what resuming looks like to neovim.
+    fn handle_suspend(&mut self) {
+        if !SETTINGS.get::<WindowSettings>().minimize_on_suspend {
+            trace!("Ignoring suspend");
+            return;
+        }
+        self.suspended = true;
+        // Neovim is told about the suspend right away, the window is gone
+        let focus = self
+            .focus_debounce
+            .changed(false, Instant::now(), Duration::ZERO);
+        Self::send_focus(focus);
+        self.windowed_context.window().set_minimized(true);
+    }
+
+    fn handle_minimized(&mut self, minimized: bool) {
+        if self.minimized == minimized {
+            return;
+        }
+        self.minimized = minimized;
+        if minimized {
+            self.report_focus(false);
+        } else {
+            self.report_focus(self.focused);
+            // Nothing was drawn while the window was minimized
+            EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
+        }
+    }
+
     pub fn handle_event(&mut self, event: Event<()>) {
         self.keyboard_manager.handle_event(&event);
-        self.mouse_manager.handle_event(
+        if !self.handle_bar_event(&event) {
+            self.mouse_manager.handle_event(
+                &event,
+                &self.keyboard_manager,
+                &self.renderer,
+                &self.windowed_context,
+                self.frame,
+            );
+        }
+        self.mouse_manager
+            .update_drag_autoscroll(&self.keyboard_manager);
+        self.touch_manager.handle_event(
             &event,
+            &mut self.mouse_manager,
             &self.keyboard_manager,
             &self.renderer,
             &self.windowed_context,
+            self.frame,
         );
+        if let Some(zoom) = self.touch_manager.take_zoom() {
+            self.handle_font_zoom(zoom);
+        }
         match event {
             Event::LoopDestroyed => {
                 self.handle_quit();
@@ -153,13 +801,16 @@ impl GlutinWindowWrapper {
                 ..
             } => {
                 self.handle_scale_factor_update(scale_factor);
+                self.scale_factor_changed = true;
+                // The grids were drawn with glyphs rasterized for the previous scale factor
+                EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
             }
             Event::WindowEvent {
                 event: WindowEvent::DroppedFile(path),
                 ..
             } => {
                 let file_path = path.into_os_string().into_string().unwrap();
-                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::FileDrop(file_path)));
+                self.handle_file_drop(file_path);
             }
             Event::WindowEvent {
                 event: WindowEvent::Focused(focus),
@@ -171,6 +822,13 @@ impl GlutinWindowWrapper {
                     self.handle_focus_lost();
                 }
             }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => {
+                self.handle_minimized(size.width == 0 || size.height == 0);
+                REDRAW_SCHEDULER.queue_next_frame()
+            }
             Event::RedrawRequested(..) | Event::WindowEvent { .. } => {
                 REDRAW_SCHEDULER.queue_next_frame()
             }
@@ -179,13 +837,40 @@ impl GlutinWindowWrapper {
     }
 
     pub fn draw_frame(&mut self, dt: f32) {
+        if self.minimized && SETTINGS.get::<WindowSettings>().suspend_when_hidden {
+            return;
+        }
+
         let window = self.windowed_context.window();
         let mut font_changed = false;
 
         if REDRAW_SCHEDULER.should_draw() || SETTINGS.get::<WindowSettings>().no_idle {
+            let drawing_started = Instant::now();
+            let mut updated = self.renderer.poll_draw_commands();
             font_changed = self.renderer.draw_frame(self.skia_renderer.canvas(), dt);
-            self.skia_renderer.gr_context.flush(None);
+            // The answer to a keystroke which arrived while drawing goes out with this frame
+            // rather than waiting a whole frame for the next one
+            if REDRAW_SCHEDULER.awaiting_echo() && self.renderer.poll_draw_commands() {
+                font_changed |= self.renderer.draw_frame(self.skia_renderer.canvas(), 0.0);
+                updated = true;
+            }
+            self.skia_renderer.flush();
+            if self.skia_renderer.context_lost() {
+                warn!("The gpu context was lost, recreating the renderer");
+                self.recreate_skia_renderer();
+                return;
+            }
+            // Swapping waits for vsync when it's on, which would hide how long the drawing itself took
+            self.frame_stats.record_frame(drawing_started.elapsed());
             self.windowed_context.swap_buffers().unwrap();
+            self.frame_pacer
+                .record_present(drawing_started, Instant::now());
+            if updated {
+                if let Some(input_sent) = REDRAW_SCHEDULER.take_input_sent() {
+                    PROFILER.record_input_latency(input_sent.elapsed());
+                }
+            }
+            self.accessibility_manager.update(&self.renderer, window);
         }
 
         // Wait until fonts are loaded, so we can set proper window size.
@@ -201,9 +886,11 @@ impl GlutinWindowWrapper {
 
         if self.saved_grid_size.is_none() && !resized_at_startup {
             window.set_inner_size(
-                self.renderer
-                    .grid_renderer
-                    .convert_grid_to_physical(settings.geometry),
+                self.padding.window_size(
+                    self.renderer
+                        .grid_renderer
+                        .convert_grid_to_physical(settings.geometry),
+                ),
             );
             self.saved_grid_size = Some(settings.geometry);
             // Font change at startup is ignored, so grid size (and startup screen) could be preserved.
@@ -211,39 +898,115 @@ impl GlutinWindowWrapper {
             font_changed = false;
         }
 
-        if self.saved_inner_size != new_size || font_changed {
+        let scale_factor_changed = std::mem::take(&mut self.scale_factor_changed);
+        if self.saved_inner_size != new_size || font_changed || scale_factor_changed {
             self.saved_inner_size = new_size;
-            self.handle_new_grid_size(new_size);
+            if !self.is_size_animating(new_size) {
+                // A font or scale factor change is never part of a drag
+                if self.live_resize.resized(Instant::now()) || font_changed || scale_factor_changed
+                {
+                    self.handle_new_grid_size(new_size);
+                } else {
+                    self.update_grid_size(new_size);
+                }
+            }
             self.skia_renderer.resize(&self.windowed_context);
+
+            // Fill the new surface right away, otherwise it shows uninitialized contents (white on
+            // windows) until the next frame
+            self.renderer.draw_frame(self.skia_renderer.canvas(), 0.0);
+            self.skia_renderer.flush();
+            self.windowed_context.swap_buffers().unwrap();
+        } else if self.live_resize.settled(Instant::now()) {
+            self.handle_resize_settled();
+        }
+
+        self.step_size_animation();
+        self.report_frame_stats();
+    }
+
+    fn handle_resize_settled(&mut self) {
+        if let Some(grid_size) = self.saved_grid_size {
+            trace!("Resize settled at {:?}", grid_size);
+            EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::Resize {
+                width: grid_size.width,
+                height: grid_size.height,
+            }));
+        }
+    }
+
+    // Neovim can settle on another grid size than the window asked for, for example when a smaller
+    // ui is attached as well. Keeping its size means the next resize to the asked size is sent
+    // again instead of being skipped as unchanged. A size the window has moved on from since is
+    // left alone.
+    fn handle_grid_size_settled(&mut self, requested: Dimensions, settled: Dimensions) {
+        if self.saved_grid_size == Some(requested) {
+            trace!("Neovim settled on {:?} instead of {:?}", settled, requested);
+            self.saved_grid_size = Some(settled);
         }
     }
 
     fn handle_new_grid_size(&mut self, new_size: PhysicalSize<u32>) {
+        if let Some(grid_size) = self.update_grid_size(new_size) {
+            EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::Resize {
+                width: grid_size.width,
+                height: grid_size.height,
+            }));
+        }
+    }
+
+    // Fits the grid to the window without telling neovim, returning the grid size if it changed
+    fn update_grid_size(&mut self, new_size: PhysicalSize<u32>) -> Option<Dimensions> {
+        self.padding = self.padding_for(new_size);
+        self.renderer.padding = self.padding;
+
         let grid_size = self
             .renderer
             .grid_renderer
-            .convert_physical_to_grid(new_size);
+            .convert_physical_to_grid(self.padding.content_size(new_size));
 
         // Have a minimum size
         if grid_size.width < MIN_WINDOW_WIDTH || grid_size.height < MIN_WINDOW_HEIGHT {
-            return;
+            return None;
         }
 
         if self.saved_grid_size == Some(grid_size) {
             trace!("Grid matched saved size, skip update.");
-            return;
+            return None;
         }
         self.saved_grid_size = Some(grid_size);
-        EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::Resize {
-            width: grid_size.width,
-            height: grid_size.height,
-        }));
+        Some(grid_size)
+    }
+
+    // Frames are drawn less often while the window is in the background or nothing is animating
+    // to save power
+    pub fn refresh_rate(&self) -> u64 {
+        let settings = SETTINGS.get::<WindowSettings>();
+        if self.focused && (REDRAW_SCHEDULER.frame_queued() || settings.no_idle) {
+            settings.refresh_rate
+        } else {
+            settings.refresh_rate_idle
+        }
+        .max(1)
     }
 
     fn handle_scale_factor_update(&mut self, scale_factor: f64) {
+        set_scale_factor(scale_factor);
         self.renderer
-            .grid_renderer
-            .handle_scale_factor_update(scale_factor);
+            .handle_scale_factor_update(scale_factor * self.font_zoom);
+    }
+
+    fn handle_font_zoom(&mut self, zoom: f64) {
+        self.font_zoom = (self.font_zoom * zoom)
+            .max(MIN_FONT_ZOOM)
+            .min(MAX_FONT_ZOOM);
+        let scale_factor = self.windowed_context.window().scale_factor();
+        self.handle_scale_factor_update(scale_factor);
+
+        // The window keeps its size, so the grid has to change to fit the new font size
+        let inner_size = self.windowed_context.window().inner_size();
+        self.handle_new_grid_size(inner_size);
+        EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
     }
 }
 
@@ -259,10 +1022,12 @@ pub fn create_window() {
     };
 
     let event_loop = EventLoop::new();
+    REDRAW_SCHEDULER.set_event_loop_proxy(event_loop.create_proxy());
 
     let cmd_line_settings = SETTINGS.get::<CmdLineSettings>();
+    let frame = SETTINGS.get::<WindowSettings>().frame;
 
-    let mut maximized = cmd_line_settings.maximized;
+    let mut maximized = SETTINGS.get::<WindowSettings>().maximized;
     let mut previous_position = None;
     if let Ok(last_window_settings) = load_last_window_settings() {
         match last_window_settings {
@@ -270,17 +1035,35 @@ pub fn create_window() {
                 maximized = true;
             }
             PersistentWindowSettings::Windowed { position, .. } => {
-                previous_position = Some(position);
+                let monitors: Vec<_> = event_loop
+                    .available_monitors()
+                    .map(|monitor| (monitor.position(), monitor.size()))
+                    .collect();
+                previous_position = Some(clamp_position_to_monitors(position, &monitors));
             }
         }
     }
 
     let mut winit_window_builder = window::WindowBuilder::new()
-        .with_title("Neovide")
+        .with_title(DEFAULT_TITLE)
         .with_window_icon(Some(icon))
         .with_maximized(maximized)
         .with_transparent(true)
-        .with_decorations(!cmd_line_settings.frameless);
+        .with_decorations(frame.has_decorations());
+
+    #[cfg(target_os = "macos")]
+    let winit_window_builder = match frame {
+        Frame::Buttonless => winit_window_builder
+            .with_title_hidden(true)
+            .with_titlebar_transparent(true)
+            .with_titlebar_buttons_hidden(true)
+            .with_fullsize_content_view(true),
+        Frame::Transparent => winit_window_builder
+            .with_title_hidden(true)
+            .with_titlebar_transparent(true)
+            .with_fullsize_content_view(true),
+        _ => winit_window_builder,
+    };
 
     if let Some(previous_position) = previous_position {
         if !maximized {
@@ -293,25 +1076,48 @@ pub fn create_window() {
         .with_app_id(cmd_line_settings.wayland_app_id)
         .with_class("neovide".to_string(), cmd_line_settings.x11_wm_class);
 
-    let windowed_context = ContextBuilder::new()
-        .with_pixel_format(24, 8)
-        .with_stencil_buffer(8)
-        .with_gl_profile(GlProfile::Core)
-        .with_vsync(false)
-        .with_srgb(cmd_line_settings.srgb)
-        .build_windowed(winit_window_builder, &event_loop)
-        .unwrap();
-    let windowed_context = unsafe { windowed_context.make_current().unwrap() };
+    let vsync = SETTINGS.get::<WindowSettings>().vsync;
+    let build_context = |hardware_acceleration: bool| {
+        let config = ContextConfig {
+            vsync,
+            srgb: cmd_line_settings.srgb,
+            hardware_acceleration,
+        };
+        WindowedContext::new(config, winit_window_builder.clone(), &event_loop)
+    };
+    // Remote desktops and virtual machines often have no gpu to create a context on, in which
+    // case the software implementation of the platform's opengl is used instead. Asking for
+    // software rendering also leaves the drawing to skia's raster backend, which is faster than
+    // skia on software opengl, so the context only copies finished frames.
+    let (windowed_context, backend) = if cmd_line_settings.software_render {
+        (build_context(false), RenderBackend::Raster)
+    } else {
+        match build_context(true) {
+            Ok(windowed_context) => (Ok(windowed_context), RenderBackend::Gpu),
+            Err(error) => {
+                warn!(
+                    "Could not create a gpu context, falling back to software rendering: {}",
+                    error
+                );
+                (build_context(false), RenderBackend::Software)
+            }
+        }
+    };
+    let windowed_context = windowed_context.expect("Could not create an opengl context");
 
     let window = windowed_context.window();
 
     let scale_factor = windowed_context.window().scale_factor();
     let renderer = Renderer::new(scale_factor);
+    set_scale_factor(scale_factor);
     let saved_inner_size = window.inner_size();
 
-    let skia_renderer = SkiaRenderer::new(&windowed_context);
+    let (skia_renderer, backend) = create_skia_renderer(&windowed_context, backend);
+    info!("Using the {} rendering backend", backend.name());
+    set_render_backend(backend);
 
     let window_command_receiver = EVENT_AGGREGATOR.register_event::<WindowCommand>();
+    let taskbar = create_taskbar_indicator(windowed_context.window());
 
     log::info!(
         "window created (scale_factor: {:.4}, font_dimensions: {:?})",
@@ -322,13 +1128,43 @@ pub fn create_window() {
     let mut window_wrapper = GlutinWindowWrapper {
         windowed_context,
         skia_renderer,
+        render_backend: backend,
+        context_losses: ContextLosses::default(),
         renderer,
         keyboard_manager: KeyboardManager::new(),
         mouse_manager: MouseManager::new(),
-        title: String::from("Neovide"),
+        touch_manager: TouchManager::new(),
+        accessibility_manager: AccessibilityManager::new(),
+        font_zoom: 1.0,
+        scale_factor_changed: false,
+        title: String::from(DEFAULT_TITLE),
+        chrome_state: ChromeState::default(),
+        chrome_indicator: SETTINGS.get::<WindowSettings>().chrome_indicator,
+        icon_text: String::new(),
         fullscreen: false,
+        maximized: SETTINGS.get::<WindowSettings>().maximized,
+        focused: true,
+        minimized: false,
+        suspended: false,
+        focus_debounce: FocusDebounce::new(true),
+        frame,
+        background_alpha: SETTINGS.get::<WindowSettings>().background_alpha(),
+        padding: SETTINGS.get::<WindowSettings>().padding(),
+        external_command_line: false,
+        external_tabline: false,
+        pointer_position: PhysicalPosition::new(0.0, 0.0),
+        last_titlebar_press: None,
         saved_inner_size,
         saved_grid_size: None,
+        live_resize: LiveResize::default(),
+        size_animation: None,
+        neovim_responding: true,
+        quit_requested: false,
+        frame_stats: FrameStatsCollector::new(Instant::now()),
+        frame_pacer: FramePacer::new(vsync),
+        taskbar,
+        zen: false,
+        zen_setting: false,
         window_command_receiver,
     };
 
@@ -342,7 +1178,9 @@ pub fn create_window() {
                 window_wrapper.saved_grid_size,
                 window.outer_position().ok(),
             );
-            std::process::exit(0);
+            // The launcher and dock keep showing progress after the process is gone
+            window_wrapper.taskbar.clear();
+            std::process::exit(RUNNING_TRACKER.exit_code());
         }
 
         let frame_start = Instant::now();
@@ -350,18 +1188,42 @@ pub fn create_window() {
         window_wrapper.handle_window_commands();
         window_wrapper.synchronize_settings();
         window_wrapper.handle_event(e);
+        window_wrapper.report_settled_focus();
 
-        let refresh_rate = { SETTINGS.get::<WindowSettings>().refresh_rate as f32 };
+        let refresh_rate = window_wrapper.refresh_rate() as f32;
         let expected_frame_length_seconds = 1.0 / refresh_rate;
         let frame_duration = Duration::from_secs_f32(expected_frame_length_seconds);
+        let frame_due = window_wrapper
+            .frame_pacer
+            .next_frame_start(previous_frame_start, frame_duration);
 
-        if frame_start - previous_frame_start > frame_duration {
+        let scheduled_frame_due = REDRAW_SCHEDULER
+            .next_scheduled_frame()
+            .map_or(false, |scheduled_frame| scheduled_frame <= frame_start);
+
+        if REDRAW_SCHEDULER.take_forced_frame() || scheduled_frame_due || frame_start >= frame_due {
             let dt = previous_frame_start.elapsed().as_secs_f32();
             window_wrapper.draw_frame(dt);
             previous_frame_start = frame_start;
         }
 
-        *control_flow = ControlFlow::WaitUntil(previous_frame_start + frame_duration)
+        // Animations may have queued another frame while drawing, so the rate is checked again
+        let refresh_rate = window_wrapper.refresh_rate() as f32;
+        let mut next_frame_start = window_wrapper.frame_pacer.next_frame_start(
+            previous_frame_start,
+            Duration::from_secs_f32(1.0 / refresh_rate),
+        );
+        if let Some(scheduled_frame) = REDRAW_SCHEDULER.next_scheduled_frame() {
+            next_frame_start = next_frame_start.min(scheduled_frame.max(frame_start));
+        }
+        if let Some(key_repeat) = window_wrapper.keyboard_manager.next_key_repeat() {
+            next_frame_start = next_frame_start.min(key_repeat.max(frame_start));
+        }
+        if let Some(focus_report) = window_wrapper.next_focus_report() {
+            next_frame_start = next_frame_start.min(focus_report.max(frame_start));
+        }
+
+        *control_flow = ControlFlow::WaitUntil(next_frame_start)
     });
 }
 
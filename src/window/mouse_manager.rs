@@ -1,22 +1,33 @@
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    time::{Duration, Instant},
+};
 
 use glutin::{
     self,
     dpi::PhysicalPosition,
     event::{ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent},
-    PossiblyCurrent, WindowedContext,
 };
+use log::trace;
 use skia_safe::Rect;
 
 use crate::{
     bridge::{SerialCommand, UiCommand},
+    editor::{EditorCommand, LinkSettings},
     event_aggregator::EVENT_AGGREGATOR,
+    redraw_scheduler::REDRAW_SCHEDULER,
     renderer::{Renderer, WindowDrawDetails},
     settings::SETTINGS,
     window::keyboard_manager::KeyboardManager,
-    window::WindowSettings,
+    window::windowed_context::WindowedContext,
+    window::{Frame, MouseSettings, WindowSettings},
 };
 
+// Time between scrolls when a drag is held just past the edge of a window. Each additional row past
+// the edge scrolls faster, up to MAX_AUTOSCROLL_SPEEDUP times as fast.
+const AUTOSCROLL_INTERVAL: Duration = Duration::from_millis(100);
+const MAX_AUTOSCROLL_SPEEDUP: u32 = 10;
+
 fn clamp_position(
     position: PhysicalPosition<f32>,
     region: Rect,
@@ -34,14 +45,43 @@ fn clamp_position(
     )
 }
 
-fn to_grid_coords(
+fn cells_past_edge(position: f32, start: f32, end: f32, cell_size: u64) -> i64 {
+    let cell_size = cell_size as f32;
+    if position < start {
+        -(((start - position) / cell_size) as i64 + 1)
+    } else if position >= end {
+        ((position - end) / cell_size) as i64 + 1
+    } else {
+        0
+    }
+}
+
+// How many rows above (negative) or below (positive) the region the position is
+fn rows_past_edge(y: f32, region: Rect, font_height: u64) -> i64 {
+    cells_past_edge(y, region.top, region.bottom, font_height)
+}
+
+// How many columns left (negative) or right (positive) of the region the position is
+fn columns_past_edge(x: f32, region: Rect, font_width: u64) -> i64 {
+    cells_past_edge(x, region.left, region.right, font_width)
+}
+
+// Adds a scroll delta to the partial lines left over from previous events, returning the whole
+// lines to scroll and the fraction to carry over to the next event
+fn accumulate_scroll(accumulated: f32, delta: f32) -> (i64, f32) {
+    let total = accumulated + delta;
+    let lines = total.trunc();
+    (lines as i64, total - lines)
+}
+
+// Whether the pointer moved far enough from where the button was pressed to start a drag
+fn past_drag_threshold(
+    press_position: PhysicalPosition<f32>,
     position: PhysicalPosition<f32>,
-    (font_width, font_height): (u64, u64),
-) -> PhysicalPosition<u32> {
-    PhysicalPosition::new(
-        (position.x as u64 / font_width) as u32,
-        (position.y as u64 / font_height) as u32,
-    )
+    threshold: f32,
+) -> bool {
+    let (x, y) = (position.x - press_position.x, position.y - press_position.y);
+    (x * x + y * y).sqrt() >= threshold
 }
 
 fn mouse_button_to_button_text(mouse_button: &MouseButton) -> Option<String> {
@@ -61,12 +101,31 @@ pub struct MouseManager {
     position: PhysicalPosition<u32>,
     relative_position: PhysicalPosition<u32>,
 
+    // The pointer in pixels relative to the grid, and where it was when the button was pressed.
+    // The drag only starts once it moved past the drag threshold from there.
+    pixel_position: PhysicalPosition<f32>,
+    press_position: PhysicalPosition<f32>,
+    press_cell: PhysicalPosition<u32>,
+    drag_started: bool,
+
+    // A ctrl+click waits for its release, since it only reaches neovim if there is no link under
+    // the pointer. The press is sent right away if it turns into a drag instead.
+    pending_link_press: Option<UiCommand>,
+    // The grid and cell the pointer is over while ctrl is held
+    link_hover: Option<(u64, PhysicalPosition<u32>)>,
+
     scroll_position: PhysicalPosition<f32>,
+    scroll_grid_id: Option<u64>,
+
+    autoscroll_rows: i64,
+    autoscroll_columns: i64,
+    last_autoscroll: Instant,
 
     window_details_under_mouse: Option<WindowDrawDetails>,
 
     mouse_hidden: bool,
-    pub enabled: bool,
+    // Follows neovim's mouse_on and mouse_off events, which reflect the 'mouse' option
+    enabled: bool,
 }
 
 impl MouseManager {
@@ -77,27 +136,67 @@ impl MouseManager {
             position: PhysicalPosition::new(0, 0),
             relative_position: PhysicalPosition::new(0, 0),
             drag_position: PhysicalPosition::new(0, 0),
+            pixel_position: PhysicalPosition::new(0.0, 0.0),
+            press_position: PhysicalPosition::new(0.0, 0.0),
+            press_cell: PhysicalPosition::new(0, 0),
+            drag_started: false,
+            pending_link_press: None,
+            link_hover: None,
             scroll_position: PhysicalPosition::new(0.0, 0.0),
+            scroll_grid_id: None,
+            autoscroll_rows: 0,
+            autoscroll_columns: 0,
+            last_autoscroll: Instant::now(),
             window_details_under_mouse: None,
             mouse_hidden: false,
             enabled: true,
         }
     }
 
-    fn handle_pointer_motion(
+    pub fn set_enabled(&mut self, enabled: bool) {
+        trace!("Mouse {}", if enabled { "on" } else { "off" });
+        self.enabled = enabled;
+        // A drag in progress when 'mouse' is cleared is dropped rather than left half finished
+        if !enabled {
+            self.dragging = None;
+            self.has_moved = false;
+            self.drag_started = false;
+            self.autoscroll_rows = 0;
+            self.autoscroll_columns = 0;
+            self.pending_link_press = None;
+        }
+    }
+
+    pub(super) fn handle_pointer_motion(
         &mut self,
         x: i32,
         y: i32,
         keyboard_manager: &KeyboardManager,
         renderer: &Renderer,
-        windowed_context: &WindowedContext<PossiblyCurrent>,
+        windowed_context: &WindowedContext,
     ) {
         let size = windowed_context.window().inner_size();
-        if x < 0 || x as u32 >= size.width || y < 0 || y as u32 >= size.height {
+        // Drags keep reporting positions outside of the window so that they can scroll it
+        let outside_window = x < 0 || x as u32 >= size.width || y < 0 || y as u32 >= size.height;
+        if outside_window && self.dragging.is_none() {
             return;
         }
 
-        let position: PhysicalPosition<f32> = PhysicalPosition::new(x as f32, y as f32);
+        // Window regions are relative to the grid, which starts after the padding
+        let padding = renderer.padding;
+        let position: PhysicalPosition<f32> = PhysicalPosition::new(
+            (x - padding.left as i32) as f32,
+            (y - padding.top as i32) as f32,
+        );
+        self.pixel_position = position;
+
+        let mut drag_just_started = false;
+        if self.dragging.is_some() && !self.drag_started {
+            let scale_factor = windowed_context.window().scale_factor() as f32;
+            let threshold = SETTINGS.get::<MouseSettings>().drag_threshold * scale_factor;
+            self.drag_started = past_drag_threshold(self.press_position, position, threshold);
+            drag_just_started = self.drag_started;
+        }
 
         // If dragging, the relevant window (the one which we send all commands to) is the one
         // which the mouse drag started on. Otherwise its the top rendered window
@@ -125,37 +224,54 @@ impl MouseManager {
                 .last()
         };
 
+        let content_size = padding.content_size(size);
         let global_bounds = relevant_window_details
             .map(|details| details.region)
-            .unwrap_or_else(|| Rect::from_wh(size.width as f32, size.height as f32));
+            .unwrap_or_else(|| {
+                Rect::from_wh(content_size.width as f32, content_size.height as f32)
+            });
+
+        let font_dimensions = renderer.grid_renderer.font_dimensions;
+        if self.dragging.is_some() && self.drag_started {
+            self.autoscroll_rows =
+                rows_past_edge(position.y, global_bounds, font_dimensions.height);
+            self.autoscroll_columns =
+                columns_past_edge(position.x, global_bounds, font_dimensions.width);
+        } else {
+            self.autoscroll_rows = 0;
+            self.autoscroll_columns = 0;
+        }
         let clamped_position = clamp_position(
             position,
             global_bounds,
             renderer.grid_renderer.font_dimensions.into(),
         );
 
-        self.position = to_grid_coords(
-            clamped_position,
-            renderer.grid_renderer.font_dimensions.into(),
-        );
+        self.position = renderer
+            .grid_renderer
+            .convert_physical_to_cell(clamped_position);
 
         if let Some(relevant_window_details) = relevant_window_details {
             let relative_position = PhysicalPosition::new(
                 clamped_position.x - relevant_window_details.region.left,
                 clamped_position.y - relevant_window_details.region.top,
             );
-            self.relative_position = to_grid_coords(
-                relative_position,
-                renderer.grid_renderer.font_dimensions.into(),
-            );
+            self.relative_position = renderer
+                .grid_renderer
+                .convert_physical_to_cell(relative_position);
 
             let previous_position = self.drag_position;
             self.drag_position = self.relative_position;
 
-            let has_moved = self.drag_position != previous_position;
+            // Jitter below the threshold may already have moved the pointer to another cell, so
+            // the drag is sent as soon as it starts
+            let has_moved = self.drag_started
+                && (self.drag_position != previous_position
+                    || drag_just_started && self.drag_position != self.press_cell);
 
             // If dragging and we haven't already sent a position, send a drag command
             if self.dragging.is_some() && has_moved {
+                self.send_pending_link_press();
                 EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Drag {
                     button: self.dragging.as_ref().unwrap().to_owned(),
                     grid_id: relevant_window_details.id,
@@ -171,12 +287,25 @@ impl MouseManager {
         }
     }
 
-    fn handle_pointer_transition(
+    pub(super) fn handle_pointer_transition(
         &mut self,
         mouse_button: &MouseButton,
         down: bool,
         keyboard_manager: &KeyboardManager,
+        windowed_context: &WindowedContext,
+        frame: Frame,
     ) {
+        // Without a title bar there is nothing to grab the window by, so alt+left click moves the
+        // window instead of clicking in the grid
+        if down
+            && *mouse_button == MouseButton::Left
+            && frame == Frame::None
+            && keyboard_manager.alt_pressed()
+        {
+            windowed_context.window().drag_window().ok();
+            return;
+        }
+
         // For some reason pointer down is handled differently from pointer up and drag.
         // Floating windows: relative coordinates are great.
         // Non floating windows: rather than global coordinates, relative are needed
@@ -195,89 +324,182 @@ impl MouseManager {
                         self.relative_position
                     };
 
-                    EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::MouseButton {
+                    let command = UiCommand::Serial(SerialCommand::MouseButton {
                         button: button_text.clone(),
                         action,
                         grid_id: details.id,
                         position: position.into(),
                         modifier_string: keyboard_manager.format_modifier_string(true),
-                    }));
+                    });
+
+                    let left_button = *mouse_button == MouseButton::Left;
+                    if down
+                        && left_button
+                        && keyboard_manager.ctrl_pressed()
+                        && SETTINGS.get::<LinkSettings>().open_links
+                    {
+                        self.pending_link_press = Some(command);
+                    } else if !down && left_button && self.pending_link_press.is_some() {
+                        let press = self.pending_link_press.take().unwrap();
+                        EVENT_AGGREGATOR.send(EditorCommand::OpenLink {
+                            grid_id: details.id,
+                            column: self.press_cell.x as u64,
+                            row: self.press_cell.y as u64,
+                            fallback: vec![press, command],
+                        });
+                    } else {
+                        self.send_pending_link_press();
+                        EVENT_AGGREGATOR.send(command);
+                    }
                 }
 
                 if down {
                     self.dragging = Some(button_text);
+                    self.press_position = self.pixel_position;
+                    self.press_cell = self.relative_position;
+                    self.drag_started = false;
                 } else {
                     self.dragging = None;
                 }
 
                 if self.dragging.is_none() {
                     self.has_moved = false;
+                    self.autoscroll_rows = 0;
+                    self.autoscroll_columns = 0;
                 }
             }
         }
     }
 
-    fn handle_line_scroll(&mut self, x: f32, y: f32, keyboard_manager: &KeyboardManager) {
-        if !self.enabled {
-            return;
+    fn send_pending_link_press(&mut self) {
+        if let Some(press) = self.pending_link_press.take() {
+            EVENT_AGGREGATOR.send(press);
         }
+    }
+
+    // Tells the editor which cell to look for a link in while ctrl is held over a grid
+    fn update_link_hover(&mut self, keyboard_manager: &KeyboardManager) {
+        let link_hover = if self.enabled
+            && self.dragging.is_none()
+            && keyboard_manager.ctrl_pressed()
+            && SETTINGS.get::<LinkSettings>().underline_links
+        {
+            self.window_details_under_mouse
+                .as_ref()
+                .map(|details| (details.id, self.relative_position))
+        } else {
+            None
+        };
 
-        let previous_y = self.scroll_position.y as i64;
-        self.scroll_position.y += y;
-        let new_y = self.scroll_position.y as i64;
+        if self.link_hover != link_hover {
+            self.link_hover = link_hover;
+            EVENT_AGGREGATOR
+                .send(EditorCommand::HoverLink(link_hover.map(
+                    |(grid_id, position)| (grid_id, position.x as u64, position.y as u64),
+                )));
+        }
+    }
 
-        let vertical_input_type = match new_y.partial_cmp(&previous_y) {
-            Some(Ordering::Greater) => Some("up"),
-            Some(Ordering::Less) => Some("down"),
-            _ => None,
+    // Scrolls the window a drag started in while the pointer is held past one of its edges,
+    // extending the drag to the newly visible text
+    pub fn update_drag_autoscroll(&mut self, keyboard_manager: &KeyboardManager) {
+        let autoscrolling = self.autoscroll_rows != 0 || self.autoscroll_columns != 0;
+        let (button, grid_id) = match (&self.dragging, &self.window_details_under_mouse) {
+            (Some(button), Some(details)) if autoscrolling => (button.clone(), details.id),
+            _ => return,
         };
 
-        if let Some(input_type) = vertical_input_type {
-            let scroll_command: UiCommand = SerialCommand::Scroll {
-                direction: input_type.to_string(),
-                grid_id: self
-                    .window_details_under_mouse
-                    .as_ref()
-                    .map(|details| details.id)
-                    .unwrap_or(0),
+        // Keep frames coming so that this is checked again soon
+        REDRAW_SCHEDULER.queue_next_frame();
+
+        let distance = self
+            .autoscroll_rows
+            .abs()
+            .max(self.autoscroll_columns.abs());
+        let speedup = (distance as u32).min(MAX_AUTOSCROLL_SPEEDUP);
+        if self.last_autoscroll.elapsed() < AUTOSCROLL_INTERVAL / speedup {
+            return;
+        }
+        self.last_autoscroll = Instant::now();
+
+        let vertical_direction = match self.autoscroll_rows.cmp(&0) {
+            Ordering::Less => Some("up"),
+            Ordering::Greater => Some("down"),
+            Ordering::Equal => None,
+        };
+        let horizontal_direction = match self.autoscroll_columns.cmp(&0) {
+            Ordering::Less => Some("left"),
+            Ordering::Greater => Some("right"),
+            Ordering::Equal => None,
+        };
+        let modifier_string = keyboard_manager.format_modifier_string(true);
+        for direction in vertical_direction.iter().chain(horizontal_direction.iter()) {
+            EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Scroll {
+                direction: direction.to_string(),
+                count: 1,
+                grid_id,
                 position: self.drag_position.into(),
-                modifier_string: keyboard_manager.format_modifier_string(true),
-            }
-            .into();
-            for _ in 0..(new_y - previous_y).abs() {
-                EVENT_AGGREGATOR.send(scroll_command.clone());
-            }
+                modifier_string: modifier_string.clone(),
+            }));
         }
+        self.send_pending_link_press();
+        EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Drag {
+            button,
+            grid_id,
+            position: self.drag_position.into(),
+            modifier_string,
+        }));
+    }
 
-        let previous_x = self.scroll_position.x as i64;
-        self.scroll_position.x += x;
-        let new_x = self.scroll_position.x as i64;
+    fn send_scroll(&self, direction: &str, count: i64, keyboard_manager: &KeyboardManager) {
+        EVENT_AGGREGATOR.send(UiCommand::Serial(SerialCommand::Scroll {
+            direction: direction.to_owned(),
+            count: count as u64,
+            grid_id: self
+                .window_details_under_mouse
+                .as_ref()
+                .map(|details| details.id)
+                .unwrap_or(0),
+            position: self.drag_position.into(),
+            modifier_string: keyboard_manager.format_modifier_string(true),
+        }));
+    }
 
-        let horizontal_input_type = match new_x.partial_cmp(&previous_x) {
-            Some(Ordering::Greater) => Some("right"),
-            Some(Ordering::Less) => Some("left"),
-            _ => None,
-        };
+    fn handle_line_scroll(&mut self, x: f32, y: f32, keyboard_manager: &KeyboardManager) {
+        if !self.enabled {
+            return;
+        }
 
-        if let Some(input_type) = horizontal_input_type {
-            let scroll_command: UiCommand = SerialCommand::Scroll {
-                direction: input_type.to_string(),
-                grid_id: self
-                    .window_details_under_mouse
-                    .as_ref()
-                    .map(|details| details.id)
-                    .unwrap_or(0),
-                position: self.drag_position.into(),
-                modifier_string: keyboard_manager.format_modifier_string(true),
-            }
-            .into();
-            for _ in 0..(new_x - previous_x).abs() {
-                EVENT_AGGREGATOR.send(scroll_command.clone());
-            }
+        // Partial lines left over from another window shouldn't scroll this one
+        let grid_id = self
+            .window_details_under_mouse
+            .as_ref()
+            .map(|details| details.id);
+        if grid_id != self.scroll_grid_id {
+            self.scroll_grid_id = grid_id;
+            self.scroll_position = PhysicalPosition::new(0.0, 0.0);
+        }
+
+        let scroll_speed = SETTINGS.get::<WindowSettings>().scroll_speed;
+
+        let (lines, remainder) = accumulate_scroll(self.scroll_position.y, y * scroll_speed);
+        self.scroll_position.y = remainder;
+        match lines.cmp(&0) {
+            Ordering::Greater => self.send_scroll("up", lines, keyboard_manager),
+            Ordering::Less => self.send_scroll("down", -lines, keyboard_manager),
+            Ordering::Equal => {}
+        }
+
+        let (columns, remainder) = accumulate_scroll(self.scroll_position.x, x * scroll_speed);
+        self.scroll_position.x = remainder;
+        match columns.cmp(&0) {
+            Ordering::Greater => self.send_scroll("right", columns, keyboard_manager),
+            Ordering::Less => self.send_scroll("left", -columns, keyboard_manager),
+            Ordering::Equal => {}
         }
     }
 
-    fn handle_pixel_scroll(
+    pub(super) fn handle_pixel_scroll(
         &mut self,
         (font_width, font_height): (u64, u64),
         (pixel_x, pixel_y): (f32, f32),
@@ -295,9 +517,29 @@ impl MouseManager {
         event: &Event<()>,
         keyboard_manager: &KeyboardManager,
         renderer: &Renderer,
-        windowed_context: &WindowedContext<PossiblyCurrent>,
+        windowed_context: &WindowedContext,
+        frame: Frame,
     ) {
+        let settings = SETTINGS.get::<MouseSettings>();
         match event {
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { .. },
+                ..
+            }
+            | Event::WindowEvent {
+                event: WindowEvent::MouseWheel { .. },
+                ..
+            }
+            | Event::WindowEvent {
+                event: WindowEvent::MouseInput { .. },
+                ..
+            } if !settings.enabled => {
+                // Moving the mouse still reveals it, it just isn't sent to neovim
+                if self.mouse_hidden {
+                    windowed_context.window().set_cursor_visible(true);
+                    self.mouse_hidden = false;
+                }
+            }
             Event::WindowEvent {
                 event: WindowEvent::CursorMoved { position, .. },
                 ..
@@ -309,11 +551,16 @@ impl MouseManager {
                     renderer,
                     windowed_context,
                 );
+                self.update_link_hover(keyboard_manager);
                 if self.mouse_hidden {
                     windowed_context.window().set_cursor_visible(true);
                     self.mouse_hidden = false;
                 }
             }
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(_),
+                ..
+            } => self.update_link_hover(keyboard_manager),
             Event::WindowEvent {
                 event:
                     WindowEvent::MouseWheel {
@@ -341,6 +588,8 @@ impl MouseManager {
                 button,
                 state == &ElementState::Pressed,
                 keyboard_manager,
+                windowed_context,
+                frame,
             ),
             Event::WindowEvent {
                 event:
@@ -350,8 +599,7 @@ impl MouseManager {
                 ..
             } => {
                 if key_event.state == ElementState::Pressed {
-                    let window_settings = SETTINGS.get::<WindowSettings>();
-                    if window_settings.hide_mouse_when_typing && !self.mouse_hidden {
+                    if settings.hide_on_keypress && !self.mouse_hidden {
                         windowed_context.window().set_cursor_visible(false);
                         self.mouse_hidden = true;
                     }
@@ -361,3 +609,76 @@ impl MouseManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mouse_off_ends_drag() {
+        let mut manager = MouseManager::new();
+        manager.dragging = Some("left".to_owned());
+        manager.autoscroll_rows = 2;
+
+        manager.set_enabled(false);
+        assert!(manager.dragging.is_none());
+        assert_eq!(manager.autoscroll_rows, 0);
+    }
+
+    #[test]
+    fn test_past_drag_threshold() {
+        let press = PhysicalPosition::new(10.0, 10.0);
+
+        assert!(!past_drag_threshold(
+            press,
+            PhysicalPosition::new(12.0, 11.0),
+            3.0
+        ));
+        assert!(past_drag_threshold(
+            press,
+            PhysicalPosition::new(13.0, 10.0),
+            3.0
+        ));
+        assert!(past_drag_threshold(
+            press,
+            PhysicalPosition::new(8.0, 7.0),
+            3.0
+        ));
+        assert!(past_drag_threshold(press, press, 0.0));
+    }
+
+    #[test]
+    fn test_rows_past_edge() {
+        let region = Rect::from_xywh(0.0, 100.0, 500.0, 200.0);
+
+        assert_eq!(rows_past_edge(150.0, region, 20), 0);
+        assert_eq!(rows_past_edge(95.0, region, 20), -1);
+        assert_eq!(rows_past_edge(50.0, region, 20), -3);
+        assert_eq!(rows_past_edge(300.0, region, 20), 1);
+        assert_eq!(rows_past_edge(345.0, region, 20), 3);
+    }
+
+    #[test]
+    fn test_accumulate_scroll_carries_partial_lines() {
+        let (lines, remainder) = accumulate_scroll(0.0, 0.4);
+        assert_eq!(lines, 0);
+        let (lines, remainder) = accumulate_scroll(remainder, 0.4);
+        assert_eq!(lines, 0);
+        let (lines, remainder) = accumulate_scroll(remainder, 0.4);
+        assert_eq!(lines, 1);
+        assert!((remainder - 0.2).abs() < 0.001);
+
+        let (lines, remainder) = accumulate_scroll(remainder, -2.5);
+        assert_eq!(lines, -2);
+        assert!((remainder + 0.3).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_columns_past_edge() {
+        let region = Rect::from_xywh(100.0, 0.0, 200.0, 500.0);
+
+        assert_eq!(columns_past_edge(150.0, region, 10), 0);
+        assert_eq!(columns_past_edge(85.0, region, 10), -2);
+        assert_eq!(columns_past_edge(305.0, region, 10), 1);
+    }
+}
@@ -1,24 +1,101 @@
-use std::convert::TryInto;
+use std::{
+    collections::VecDeque,
+    convert::TryInto,
+    time::{Duration, Instant},
+};
 
 use gl::types::*;
+use log::warn;
 use skia_safe::{
     gpu::{gl::FramebufferInfo, BackendRenderTarget, DirectContext, SurfaceOrigin},
-    Canvas, ColorType, Surface,
+    AlphaType, Canvas, ColorType, ImageInfo, Surface,
 };
 
-type WindowedContext = glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window>;
+use crate::{bridge::RenderBackend, window::windowed_context::WindowedContext};
+
+// The surface the renderer draws a frame into. The window only talks to it through this trait, so
+// the draw code is the same whether skia draws on the gpu or in memory.
+pub trait SkiaRenderer {
+    fn canvas(&mut self) -> &mut Canvas;
+    fn resize(&mut self, windowed_context: &WindowedContext);
+    // Finishes the frame so that it shows up on the next buffer swap
+    fn flush(&mut self);
+    // Whether the gpu dropped the context, after waking from sleep or switching gpus. Nothing
+    // drawn through it shows up any more, so the renderer has to be made again.
+    fn context_lost(&mut self) -> bool {
+        false
+    }
+    // Lets go of everything made through the context without calling into it, for when the
+    // context is about to be destroyed
+    fn abandon(&mut self);
+}
+
+// This many losses within CONTEXT_LOSS_WINDOW make the window give up on the gpu
+const CONTEXT_LOSS_LIMIT: usize = 3;
+const CONTEXT_LOSS_WINDOW: Duration = Duration::from_secs(60);
+
+// Remembers recent context losses, so that a gpu which keeps failing is left for the raster
+// renderer instead of recreating the context every frame
+#[derive(Debug, Default)]
+pub struct ContextLosses {
+    losses: VecDeque<Instant>,
+}
+
+impl ContextLosses {
+    // Records a loss, returning whether the window should fall back to the raster renderer
+    pub fn lost(&mut self, now: Instant) -> bool {
+        self.losses
+            .retain(|loss| now.saturating_duration_since(*loss) <= CONTEXT_LOSS_WINDOW);
+        self.losses.push_back(now);
+        self.losses.len() >= CONTEXT_LOSS_LIMIT
+    }
+}
+
+// Asks the driver with KHR_robustness where it is available. Contexts created without robust
+// access always report no error, which leaves skia noticing the loss itself.
+fn graphics_reset() -> bool {
+    gl::GetGraphicsResetStatus::is_loaded()
+        && unsafe { gl::GetGraphicsResetStatus() } != gl::NO_ERROR
+}
+
+// Picks the renderer for the window, falling back to drawing in memory when skia can't draw
+// through the context's opengl
+pub fn create_skia_renderer(
+    windowed_context: &WindowedContext,
+    backend: RenderBackend,
+) -> (Box<dyn SkiaRenderer>, RenderBackend) {
+    gl::load_with(|s| windowed_context.get_proc_address(s));
+
+    if backend != RenderBackend::Raster {
+        match GpuRenderer::new(windowed_context) {
+            Some(renderer) => return (Box::new(renderer), backend),
+            None => {
+                warn!("Could not create a skia gpu context, falling back to the raster renderer")
+            }
+        }
+    }
+
+    (
+        Box::new(RasterRenderer::new(windowed_context)),
+        RenderBackend::Raster,
+    )
+}
+
+fn window_size(windowed_context: &WindowedContext) -> (i32, i32) {
+    let size = windowed_context.window().inner_size();
+    (
+        size.width.try_into().expect("Could not convert width"),
+        size.height.try_into().expect("Could not convert height"),
+    )
+}
 
 fn create_surface(
     windowed_context: &WindowedContext,
     gr_context: &mut DirectContext,
     fb_info: FramebufferInfo,
-) -> Surface {
+) -> Option<Surface> {
     let pixel_format = windowed_context.get_pixel_format();
-    let size = windowed_context.window().inner_size();
-    let size = (
-        size.width.try_into().expect("Could not convert width"),
-        size.height.try_into().expect("Could not convert height"),
-    );
+    let size = window_size(windowed_context);
     let backend_render_target = BackendRenderTarget::new_gl(
         size,
         pixel_format
@@ -39,29 +116,24 @@ fn create_surface(
         None,
         None,
     )
-    .expect("Could not create skia surface")
 }
 
-pub struct SkiaRenderer {
-    pub gr_context: DirectContext,
+pub struct GpuRenderer {
+    gr_context: DirectContext,
     fb_info: FramebufferInfo,
     surface: Surface,
 }
 
-impl SkiaRenderer {
-    pub fn new(windowed_context: &WindowedContext) -> SkiaRenderer {
-        gl::load_with(|s| windowed_context.get_proc_address(s));
-
+impl GpuRenderer {
+    fn new(windowed_context: &WindowedContext) -> Option<GpuRenderer> {
         let interface = skia_safe::gpu::gl::Interface::new_load_with(|name| {
             if name == "eglGetCurrentDisplay" {
                 return std::ptr::null();
             }
             windowed_context.get_proc_address(name)
-        })
-        .expect("Could not create interface");
+        })?;
 
-        let mut gr_context = skia_safe::gpu::DirectContext::new_gl(Some(interface), None)
-            .expect("Could not create direct context");
+        let mut gr_context = skia_safe::gpu::DirectContext::new_gl(Some(interface), None)?;
         let fb_info = {
             let mut fboid: GLint = 0;
             unsafe { gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut fboid) };
@@ -71,20 +143,172 @@ impl SkiaRenderer {
                 format: skia_safe::gpu::gl::Format::RGBA8.into(),
             }
         };
-        let surface = create_surface(windowed_context, &mut gr_context, fb_info);
+        let surface = create_surface(windowed_context, &mut gr_context, fb_info)?;
 
-        SkiaRenderer {
+        Some(GpuRenderer {
             gr_context,
             fb_info,
             surface,
+        })
+    }
+}
+
+impl SkiaRenderer for GpuRenderer {
+    fn canvas(&mut self) -> &mut Canvas {
+        self.surface.canvas()
+    }
+
+    fn resize(&mut self, windowed_context: &WindowedContext) {
+        self.surface = create_surface(windowed_context, &mut self.gr_context, self.fb_info)
+            .expect("Could not create skia surface");
+    }
+
+    fn flush(&mut self) {
+        self.gr_context.flush(None);
+    }
+
+    fn context_lost(&mut self) -> bool {
+        if self.gr_context.abandoned() {
+            return true;
+        }
+        if graphics_reset() {
+            // Skia mustn't free its resources through the dead context when it is dropped
+            self.gr_context.abandon();
+            return true;
+        }
+        false
+    }
+
+    fn abandon(&mut self) {
+        self.gr_context.abandon();
+    }
+}
+
+// Draws each frame in memory and copies it to the window's framebuffer through a texture. Only
+// the most basic opengl calls are needed for that, so it works where skia's gpu backend doesn't.
+pub struct RasterRenderer {
+    surface: Surface,
+    texture: GLuint,
+    framebuffer: GLuint,
+}
+
+fn create_raster_surface(windowed_context: &WindowedContext) -> Surface {
+    let size = window_size(windowed_context);
+    windowed_context.resize((size.0 as u32, size.1 as u32).into());
+    let image_info = ImageInfo::new(size, ColorType::RGBA8888, AlphaType::Premul, None);
+    Surface::new_raster(&image_info, None, None).expect("Could not create raster surface")
+}
+
+impl RasterRenderer {
+    fn new(windowed_context: &WindowedContext) -> RasterRenderer {
+        let mut texture = 0;
+        let mut framebuffer = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+            gl::GenFramebuffers(1, &mut framebuffer);
+        }
+
+        RasterRenderer {
+            surface: create_raster_surface(windowed_context),
+            texture,
+            framebuffer,
         }
     }
+}
 
-    pub fn canvas(&mut self) -> &mut Canvas {
+impl SkiaRenderer for RasterRenderer {
+    fn canvas(&mut self) -> &mut Canvas {
         self.surface.canvas()
     }
 
-    pub fn resize(&mut self, windowed_context: &WindowedContext) {
-        self.surface = create_surface(windowed_context, &mut self.gr_context, self.fb_info);
+    fn resize(&mut self, windowed_context: &WindowedContext) {
+        self.surface = create_raster_surface(windowed_context);
+    }
+
+    fn flush(&mut self) {
+        let (width, height) = (self.surface.width(), self.surface.height());
+        let pixmap = match self.surface.peek_pixels() {
+            Some(pixmap) => pixmap,
+            None => return,
+        };
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as GLint,
+                width,
+                height,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixmap.addr(),
+            );
+
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+            gl::FramebufferTexture2D(
+                gl::READ_FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                self.texture,
+                0,
+            );
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+            // The surface's rows go top to bottom while opengl's go bottom to top, so the copy
+            // flips it
+            gl::BlitFramebuffer(
+                0,
+                0,
+                width,
+                height,
+                0,
+                height,
+                width,
+                0,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    // Deleting the name 0 does nothing, so dropping the renderer afterwards leaves the next
+    // context alone
+    fn abandon(&mut self) {
+        self.texture = 0;
+        self.framebuffer = 0;
+    }
+}
+
+impl Drop for RasterRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_losses_fall_back_to_the_raster_renderer() {
+        let start = Instant::now();
+        let mut losses = ContextLosses::default();
+
+        assert!(!losses.lost(start));
+        assert!(!losses.lost(start + CONTEXT_LOSS_WINDOW * 2));
+        assert!(!losses.lost(start + CONTEXT_LOSS_WINDOW * 4));
+
+        let later = start + CONTEXT_LOSS_WINDOW * 6;
+        assert!(!losses.lost(later));
+        assert!(!losses.lost(later + Duration::from_secs(1)));
+        assert!(losses.lost(later + Duration::from_secs(2)));
     }
 }
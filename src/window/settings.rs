@@ -1,15 +1,106 @@
-use crate::{cmd_line::CmdLineSettings, settings::*};
+use glutin::dpi::PhysicalSize;
+use log::error;
+use rmpv::Value;
+
+use crate::{
+    bridge::{DirectoryDropMode, OpenMode},
+    cmd_line::CmdLineSettings,
+    settings::*,
+};
+
+// The window decorations. Buttonless and Transparent are macOS title bar styles which can only be
+// applied when the window is created, elsewhere they behave like Full.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Frame {
+    Full,
+    None,
+    Buttonless,
+    Transparent,
+}
+
+impl Frame {
+    pub fn has_decorations(&self) -> bool {
+        *self != Frame::None
+    }
+}
+
+impl FromValue for Frame {
+    fn from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "full" => Frame::Full,
+                "none" => Frame::None,
+                "buttonless" => Frame::Buttonless,
+                "transparent" => Frame::Transparent,
+                value => {
+                    error!("Expected a frame name, but received {:?}", value);
+                    return;
+                }
+            };
+        } else {
+            error!("Expected a frame string, but received {:?}", value);
+        }
+    }
+}
+
+impl From<Frame> for Value {
+    fn from(frame: Frame) -> Self {
+        match frame {
+            Frame::Full => Value::from("full"),
+            Frame::None => Value::from("none"),
+            Frame::Buttonless => Value::from("buttonless"),
+            Frame::Transparent => Value::from("transparent"),
+        }
+    }
+}
 
 #[derive(Clone, SettingGroup)]
 pub struct WindowSettings {
     pub refresh_rate: u64,
+    pub refresh_rate_idle: u64,
     pub no_idle: bool,
+    // Buffer swaps wait for vblank. Off by default, frames are paced to the refresh rate instead,
+    // which can tear but shows input sooner.
+    pub vsync: bool,
+    // Stops drawing while the window is minimized. Screen capture tools which still show a
+    // minimized window need it off.
+    pub suspend_when_hidden: bool,
+    // Neovim's :suspend and <C-z> minimize the window, the closest a gui has to suspending
+    pub minimize_on_suspend: bool,
     pub transparency: f32,
     pub fullscreen: bool,
+    pub maximized: bool,
     pub iso_layout: bool,
     pub remember_window_size: bool,
     pub remember_window_position: bool,
-    pub hide_mouse_when_typing: bool,
+    pub scroll_speed: f32,
+    // Animates the window to the size neovim asks for when columns or lines are set
+    pub window_resize_animation: bool,
+    // Closing the window asks for confirmation in neovim instead of quitting right away
+    pub confirm_quit: bool,
+    // Marks the title while the current buffer has unsaved changes or can't be written
+    pub chrome_indicator: bool,
+    // Stores how fast frames are drawn in g:neovide_frame_stats once a second
+    pub report_frame_stats: bool,
+    // In milliseconds, how long the focus has to stay before neovim is told about it. Zero
+    // reports every change right away.
+    pub focus_debounce: u64,
+    // Where dropped files are opened, and where while shift is held
+    pub file_drop_mode: OpenMode,
+    pub file_drop_shift_mode: OpenMode,
+    // Dropped directories become the working directory of the tab, the window or all of neovim,
+    // or are opened with a command such as edit or Explore
+    pub file_drop_directory_mode: DirectoryDropMode,
+    pub frame: Frame,
+    pub padding_top: u32,
+    pub padding_bottom: u32,
+    pub padding_left: u32,
+    pub padding_right: u32,
+    // Zen mode goes fullscreen and centers a column of zen_width cells. Windows other than the
+    // one with the cursor are drawn with zen_inactive_opacity.
+    pub zen: bool,
+    pub zen_width: u64,
+    pub zen_inactive_opacity: f32,
 }
 
 impl Default for WindowSettings {
@@ -17,18 +108,217 @@ impl Default for WindowSettings {
         Self {
             transparency: 1.0,
             fullscreen: false,
+            maximized: SETTINGS.get::<CmdLineSettings>().maximized,
             iso_layout: false,
             refresh_rate: 60,
+            refresh_rate_idle: 5,
             no_idle: SETTINGS.get::<CmdLineSettings>().no_idle,
+            vsync: false,
+            suspend_when_hidden: true,
+            minimize_on_suspend: true,
             remember_window_size: true,
             remember_window_position: true,
-            hide_mouse_when_typing: false,
+            scroll_speed: 1.0,
+            window_resize_animation: false,
+            confirm_quit: false,
+            chrome_indicator: false,
+            report_frame_stats: false,
+            focus_debounce: 0,
+            file_drop_mode: OpenMode::Edit,
+            file_drop_shift_mode: OpenMode::Tab,
+            file_drop_directory_mode: DirectoryDropMode::Tab,
+            frame: if SETTINGS.get::<CmdLineSettings>().frameless {
+                Frame::None
+            } else {
+                Frame::Full
+            },
+            padding_top: 0,
+            padding_bottom: 0,
+            padding_left: 0,
+            padding_right: 0,
+            zen: false,
+            zen_width: 100,
+            zen_inactive_opacity: 1.0,
         }
     }
 }
 
-#[derive(Clone, Default, SettingGroup)]
+impl WindowSettings {
+    // Alpha applied to the default background. Values outside of 0.0-1.0 are clamped.
+    pub fn background_alpha(&self) -> u8 {
+        (255.0 * self.transparency.max(0.0).min(1.0)) as u8
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.background_alpha() == 255
+    }
+
+    pub fn padding(&self) -> Padding {
+        Padding {
+            top: self.padding_top,
+            bottom: self.padding_bottom,
+            left: self.padding_left,
+            right: self.padding_right,
+        }
+    }
+}
+
+// Space in physical pixels between the window edges and the grid. It is filled with the default
+// background.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Padding {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+impl Padding {
+    // The part of a window of the given size which is left for the grid
+    pub fn content_size(&self, window_size: PhysicalSize<u32>) -> PhysicalSize<u32> {
+        PhysicalSize::new(
+            window_size.width.saturating_sub(self.left + self.right),
+            window_size.height.saturating_sub(self.top + self.bottom),
+        )
+    }
+
+    // Widens the left and right padding so that at most content_width is left for the grid,
+    // centered in the window
+    pub fn centered(&self, window_size: PhysicalSize<u32>, content_width: u32) -> Padding {
+        let extra = self
+            .content_size(window_size)
+            .width
+            .saturating_sub(content_width)
+            / 2;
+        Padding {
+            left: self.left + extra,
+            right: self.right + extra,
+            ..*self
+        }
+    }
+
+    // The window size needed to fit a grid of the given size
+    pub fn window_size(&self, content_size: PhysicalSize<u32>) -> PhysicalSize<u32> {
+        PhysicalSize::new(
+            content_size.width + self.left + self.right,
+            content_size.height + self.top + self.bottom,
+        )
+    }
+}
+
+#[derive(Clone, SettingGroup)]
 #[setting_prefix = "input"]
 pub struct KeyboardSettings {
     pub use_logo: bool,
+    pub new_window_shortcut: bool,
+    // Makes option send meta keybindings on macos instead of the characters it composes
+    pub macos_alt_is_meta: bool,
+    // The most auto repeats of a held key sent per second, 0 sends every repeat the os makes
+    pub max_repeat_rate: f32,
+    // Keys neovide has no name for, such as media keys, are sent as whatever text the platform
+    // gives them. Turning this off drops them instead.
+    pub forward_unknown_keys: bool,
+    // Cmd+Shift+C on macos and Ctrl+Shift+C elsewhere copies the screen as text. Off by default
+    // since neovim mappings may use it.
+    pub copy_screen_shortcut: bool,
+    // Cmd+C, X, V, A, S and Q copy, cut, paste, select all, save and quit like in other apps
+    // rather than reaching neovim as <D-c> and so on. Cmd is only handled with use_logo set.
+    pub standard_shortcuts: bool,
+}
+
+impl Default for KeyboardSettings {
+    fn default() -> Self {
+        Self {
+            // Cmd is the primary modifier for shortcuts on macos
+            use_logo: cfg!(target_os = "macos"),
+            new_window_shortcut: true,
+            macos_alt_is_meta: false,
+            max_repeat_rate: 0.0,
+            forward_unknown_keys: true,
+            copy_screen_shortcut: false,
+            standard_shortcuts: false,
+        }
+    }
+}
+
+#[derive(Clone, SettingGroup)]
+#[setting_prefix = "mouse"]
+pub struct MouseSettings {
+    // Turning the mouse off here behaves like an empty 'mouse' option, no mouse input reaches
+    // neovim
+    pub enabled: bool,
+    // Formerly g:neovide_hide_mouse_when_typing
+    #[setting(alias = "hide_mouse_when_typing")]
+    pub hide_on_keypress: bool,
+    // How far in logical pixels the pointer has to move with a button held down before it is a
+    // drag rather than a click
+    pub drag_threshold: f32,
+    // A left click or drag made while neovim is in visual mode extends the selection, as if
+    // shift was held
+    pub extend_visual_selection: bool,
+    // Sends the wheel as <ScrollWheelUp> and friends through nvim_input, for mappings of those
+    // keys which the wheel events of nvim_input_mouse don't trigger
+    pub scroll_as_keys: bool,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            hide_on_keypress: false,
+            drag_threshold: 3.0,
+            extend_visual_selection: true,
+            scroll_as_keys: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_padding_sizes() {
+        let padding = Padding {
+            top: 4,
+            bottom: 6,
+            left: 10,
+            right: 2,
+        };
+
+        assert_eq!(
+            padding.content_size(PhysicalSize::new(800, 600)),
+            PhysicalSize::new(788, 590)
+        );
+        assert_eq!(
+            padding.window_size(PhysicalSize::new(788, 590)),
+            PhysicalSize::new(800, 600)
+        );
+        assert_eq!(
+            padding.content_size(PhysicalSize::new(8, 8)),
+            PhysicalSize::new(0, 0)
+        );
+    }
+
+    #[test]
+    fn test_centered_padding() {
+        let padding = Padding {
+            top: 4,
+            bottom: 6,
+            left: 10,
+            right: 2,
+        };
+
+        let centered = padding.centered(PhysicalSize::new(800, 600), 588);
+        assert_eq!(centered.left, 110);
+        assert_eq!(centered.right, 102);
+        assert_eq!(centered.top, 4);
+        assert_eq!(
+            centered.content_size(PhysicalSize::new(800, 600)).width,
+            588
+        );
+
+        // A column wider than the window leaves the padding as it is
+        assert_eq!(padding.centered(PhysicalSize::new(800, 600), 1000), padding);
+    }
 }
@@ -0,0 +1,80 @@
+use std::time::{Duration, Instant};
+
+use glutin::dpi::PhysicalSize;
+
+use crate::renderer::animation_utils::{ease, ease_out_cubic};
+
+pub const SIZE_ANIMATION_LENGTH: Duration = Duration::from_millis(150);
+
+// Grows or shrinks the window towards a size neovim asked for. Every size in between lies in the
+// rectangle spanned by the start and the target, which is how resizes the animation made itself
+// are told apart from the user resizing the window.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SizeAnimation {
+    from: PhysicalSize<u32>,
+    pub to: PhysicalSize<u32>,
+    start: Instant,
+}
+
+fn between(value: u32, a: u32, b: u32) -> bool {
+    value >= a.min(b) && value <= a.max(b)
+}
+
+impl SizeAnimation {
+    pub fn new(from: PhysicalSize<u32>, to: PhysicalSize<u32>, start: Instant) -> SizeAnimation {
+        SizeAnimation { from, to, start }
+    }
+
+    // The size the window should have at the given time
+    pub fn size_at(&self, now: Instant) -> PhysicalSize<u32> {
+        let t = now.saturating_duration_since(self.start).as_secs_f32()
+            / SIZE_ANIMATION_LENGTH.as_secs_f32();
+        if t >= 1.0 {
+            return self.to;
+        }
+
+        let step = |from: u32, to: u32| ease(ease_out_cubic, from as f32, to as f32, t).round();
+        PhysicalSize::new(
+            step(self.from.width, self.to.width) as u32,
+            step(self.from.height, self.to.height) as u32,
+        )
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    // Whether the window having this size could be caused by the animation
+    pub fn made(&self, size: PhysicalSize<u32>) -> bool {
+        between(size.width, self.from.width, self.to.width)
+            && between(size.height, self.from.height, self.to.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_animation() {
+        let start = Instant::now();
+        let animation = SizeAnimation::new(
+            PhysicalSize::new(800, 600),
+            PhysicalSize::new(1000, 500),
+            start,
+        );
+
+        assert_eq!(animation.size_at(start), PhysicalSize::new(800, 600));
+        let halfway = animation.size_at(start + SIZE_ANIMATION_LENGTH / 2);
+        assert!(halfway.width > 800 && halfway.width < 1000);
+        assert!(halfway.height > 500 && halfway.height < 600);
+        assert_eq!(
+            animation.size_at(start + SIZE_ANIMATION_LENGTH),
+            PhysicalSize::new(1000, 500)
+        );
+
+        assert!(animation.made(halfway));
+        assert!(!animation.made(PhysicalSize::new(1200, 550)));
+        assert!(!animation.made(PhysicalSize::new(900, 400)));
+    }
+}
@@ -0,0 +1,32 @@
+use crate::bridge::ShortcutAction;
+
+// The letter held with Cmd for each standard shortcut. Kept as data so that single entries can be
+// overridden from a setting later on.
+const STANDARD_SHORTCUTS: &[(&str, ShortcutAction)] = &[
+    ("c", ShortcutAction::Copy),
+    ("x", ShortcutAction::Cut),
+    ("v", ShortcutAction::Paste),
+    ("a", ShortcutAction::SelectAll),
+    ("s", ShortcutAction::Save),
+    ("q", ShortcutAction::Quit),
+];
+
+pub fn standard_shortcut(key: &str) -> Option<ShortcutAction> {
+    STANDARD_SHORTCUTS
+        .iter()
+        .find(|(shortcut_key, _)| shortcut_key.eq_ignore_ascii_case(key))
+        .map(|(_, action)| *action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_shortcuts() {
+        assert_eq!(standard_shortcut("s"), Some(ShortcutAction::Save));
+        assert_eq!(standard_shortcut("V"), Some(ShortcutAction::Paste));
+        assert_eq!(standard_shortcut("z"), None);
+        assert_eq!(standard_shortcut("ss"), None);
+    }
+}
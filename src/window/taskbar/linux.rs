@@ -0,0 +1,122 @@
+use std::{
+    process::Command,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use crate::window::taskbar::TaskbarIndicator;
+
+// The desktop entry neovide registers, see linux_utils
+const APP_URI: &str = "application://neovide.desktop";
+const OBJECT_PATH: &str = "/com/neovide/Neovide";
+// Each update starts a gdbus process, so a burst of them is shown at most this often
+const MIN_UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+// The Unity launcher api, which docks such as Plank, Dash to Dock and KDE's task manager follow as
+// well. It takes a count rather than text, so only a numeric badge is shown. The update is a dbus
+// signal sent with gdbus, and nothing happens on desktops without it.
+#[derive(Default)]
+pub struct LauncherEntry {
+    progress: Option<f64>,
+    count: Option<i64>,
+    // The thread sending the updates, started with the first one
+    updates: Option<Sender<String>>,
+}
+
+fn emit_update(properties: &str) {
+    Command::new("gdbus")
+        .args(&[
+            "emit",
+            "--session",
+            "--object-path",
+            OBJECT_PATH,
+            "--signal",
+            "com.canonical.Unity.LauncherEntry.Update",
+            &format!("'{}'", APP_URI),
+            properties,
+        ])
+        .output()
+        .ok();
+}
+
+// Sends the updates one at a time and in order, so that a cleared progress can't be overtaken by
+// an older one. Updates which arrive while one is sent are skipped for the newest.
+fn run_updates(receiver: Receiver<String>, interval: Duration, mut emit: impl FnMut(&str)) {
+    while let Ok(properties) = receiver.recv() {
+        let properties = receiver.try_iter().last().unwrap_or(properties);
+        emit(&properties);
+        thread::sleep(interval);
+    }
+}
+
+impl LauncherEntry {
+    fn properties(&self) -> String {
+        format!(
+            "{{'progress': <{:.3}>, 'progress-visible': <{}>, 'count': <int64 {}>, 'count-visible': <{}>}}",
+            self.progress.unwrap_or(0.0),
+            self.progress.is_some(),
+            self.count.unwrap_or(0),
+            self.count.is_some()
+        )
+    }
+
+    fn send_update(&mut self) {
+        let properties = self.properties();
+        // Waited for off the window thread so that it doesn't leave a zombie behind
+        let updates = self.updates.get_or_insert_with(|| {
+            let (sender, receiver) = channel();
+            thread::spawn(move || run_updates(receiver, MIN_UPDATE_INTERVAL, emit_update));
+            sender
+        });
+        updates.send(properties).ok();
+    }
+}
+
+impl TaskbarIndicator for LauncherEntry {
+    fn set_progress(&mut self, progress: Option<f64>) {
+        self.progress = progress;
+        self.send_update();
+    }
+
+    fn set_badge(&mut self, badge: Option<&str>) {
+        self.count = badge.and_then(|badge| badge.trim().parse().ok());
+        self.send_update();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_launcher_entry_properties() {
+        let mut entry = LauncherEntry::default();
+        assert_eq!(
+            entry.properties(),
+            "{'progress': <0.000>, 'progress-visible': <false>, 'count': <int64 0>, 'count-visible': <false>}"
+        );
+
+        entry.progress = Some(0.5);
+        entry.count = Some(3);
+        assert_eq!(
+            entry.properties(),
+            "{'progress': <0.500>, 'progress-visible': <true>, 'count': <int64 3>, 'count-visible': <true>}"
+        );
+    }
+
+    #[test]
+    fn test_updates_are_coalesced_to_the_newest() {
+        let (sender, receiver) = channel();
+        for update in &["first", "second", "cleared"] {
+            sender.send(update.to_string()).unwrap();
+        }
+        drop(sender);
+
+        let mut emitted = Vec::new();
+        run_updates(receiver, Duration::ZERO, |properties| {
+            emitted.push(properties.to_owned())
+        });
+        assert_eq!(emitted, vec!["cleared"]);
+    }
+}
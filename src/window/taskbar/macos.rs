@@ -0,0 +1,85 @@
+use std::os::raw::c_void;
+
+use glutin::{platform::macos::WindowExtMacOS, window::Window};
+use objc::{class, msg_send, runtime::Object, sel, sel_impl};
+
+use crate::window::taskbar::TaskbarIndicator;
+
+type Id = *mut Object;
+
+const NS_UTF8_STRING_ENCODING: usize = 4;
+
+unsafe fn ns_string(text: &str) -> Id {
+    let string: Id = msg_send![class!(NSString), alloc];
+    let string: Id = msg_send![string,
+        initWithBytes: text.as_ptr() as *const c_void
+        length: text.len()
+        encoding: NS_UTF8_STRING_ENCODING];
+    msg_send![string, autorelease]
+}
+
+// The label under the window's thumbnail in the dock while it is minimized
+pub fn set_miniwindow_title(window: &Window, text: &str) {
+    unsafe {
+        let ns_window = window.ns_window() as Id;
+        let _: () = msg_send![ns_window, setMiniwindowTitle: ns_string(text)];
+    }
+}
+
+// The dock only has a badge, so the progress is shown as a percentage in it while no badge is set
+#[derive(Default)]
+pub struct DockTile {
+    progress: Option<f64>,
+    badge: Option<String>,
+}
+
+impl DockTile {
+    fn label(&self) -> Option<String> {
+        self.badge.clone().or_else(|| {
+            self.progress
+                .map(|progress| format!("{}%", (progress * 100.0).round()))
+        })
+    }
+
+    fn update(&self) {
+        unsafe {
+            let label: Id = match self.label() {
+                Some(label) => ns_string(&label),
+                None => std::ptr::null_mut(),
+            };
+            let app: Id = msg_send![class!(NSApplication), sharedApplication];
+            let dock_tile: Id = msg_send![app, dockTile];
+            let _: () = msg_send![dock_tile, setBadgeLabel: label];
+            let _: () = msg_send![dock_tile, display];
+        }
+    }
+}
+
+impl TaskbarIndicator for DockTile {
+    fn set_progress(&mut self, progress: Option<f64>) {
+        self.progress = progress;
+        self.update();
+    }
+
+    fn set_badge(&mut self, badge: Option<&str>) {
+        self.badge = badge.map(str::to_owned);
+        self.update();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_badge_takes_the_place_of_progress() {
+        let mut dock_tile = DockTile {
+            progress: Some(0.42),
+            badge: None,
+        };
+        assert_eq!(dock_tile.label(), Some("42%".to_owned()));
+
+        dock_tile.badge = Some("!".to_owned());
+        assert_eq!(dock_tile.label(), Some("!".to_owned()));
+    }
+}
@@ -0,0 +1,95 @@
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "windows")]
+mod windows;
+
+use glutin::window::Window;
+#[cfg(not(target_os = "macos"))]
+use log::debug;
+
+// Progress and a badge shown on the window's taskbar or dock entry, set by neovim through
+// neovide.set_progress and neovide.set_icon_badge. Each platform shows what its shell supports and
+// ignores the rest.
+pub trait TaskbarIndicator {
+    // A fraction between 0 and 1, None hides the progress
+    fn set_progress(&mut self, progress: Option<f64>);
+    fn set_badge(&mut self, badge: Option<&str>);
+
+    fn clear(&mut self) {
+        self.set_progress(None);
+        self.set_badge(None);
+    }
+}
+
+// Platforms without a taskbar api, and taskbars that couldn't be reached
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+struct NoTaskbar;
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+impl TaskbarIndicator for NoTaskbar {
+    fn set_progress(&mut self, _progress: Option<f64>) {}
+    fn set_badge(&mut self, _badge: Option<&str>) {}
+}
+
+#[cfg(target_os = "windows")]
+pub fn create_taskbar_indicator(window: &Window) -> Box<dyn TaskbarIndicator> {
+    match windows::WindowsTaskbar::new(window) {
+        Some(taskbar) => Box::new(taskbar),
+        None => Box::new(NoTaskbar),
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn create_taskbar_indicator(_window: &Window) -> Box<dyn TaskbarIndicator> {
+    Box::new(macos::DockTile::default())
+}
+
+#[cfg(target_os = "linux")]
+pub fn create_taskbar_indicator(_window: &Window) -> Box<dyn TaskbarIndicator> {
+    Box::new(linux::LauncherEntry::default())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+pub fn create_taskbar_indicator(_window: &Window) -> Box<dyn TaskbarIndicator> {
+    Box::new(NoTaskbar)
+}
+
+// The text neovim sets with set_icon, which labels the window where it is shown apart from the
+// title. Only the minimized window in the dock has such a label, the taskbars of the other
+// platforms show the title.
+#[cfg(target_os = "macos")]
+pub fn set_icon_text(window: &Window, text: &str) {
+    macos::set_miniwindow_title(window, text);
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn set_icon_text(_window: &Window, text: &str) {
+    debug!(
+        "Ignoring the icon text {:?}, there is nowhere to show it",
+        text
+    );
+}
+
+// The argument of neovide.set_progress is a percentage, anything negative clears it
+pub fn parse_progress(percent: f64) -> Option<f64> {
+    if percent < 0.0 {
+        None
+    } else {
+        Some((percent / 100.0).min(1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_progress() {
+        assert_eq!(parse_progress(-1.0), None);
+        assert_eq!(parse_progress(0.0), Some(0.0));
+        assert_eq!(parse_progress(50.0), Some(0.5));
+        assert_eq!(parse_progress(250.0), Some(1.0));
+    }
+}
@@ -0,0 +1,82 @@
+use std::ptr::null_mut;
+
+use glutin::{platform::windows::WindowExtWindows, window::Window};
+use log::warn;
+use winapi::{
+    shared::{windef::HWND, winerror::SUCCEEDED},
+    um::{
+        combaseapi::{CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER},
+        objbase::COINIT_APARTMENTTHREADED,
+        shobjidl_core::{CLSID_TaskbarList, ITaskbarList3, TBPF_NOPROGRESS, TBPF_NORMAL},
+    },
+    Interface,
+};
+
+use crate::window::taskbar::TaskbarIndicator;
+
+// The progress is reported in steps of this size
+const PROGRESS_TOTAL: u64 = 1000;
+
+// The taskbar button's progress bar. Windows only overlays icons on the button, not text, so
+// badges aren't shown.
+pub struct WindowsTaskbar {
+    taskbar: *mut ITaskbarList3,
+    hwnd: HWND,
+}
+
+impl WindowsTaskbar {
+    pub fn new(window: &Window) -> Option<WindowsTaskbar> {
+        let mut taskbar: *mut ITaskbarList3 = null_mut();
+        unsafe {
+            // Winit already initialized com on this thread for drag and drop, in which case this
+            // just adds a reference
+            CoInitializeEx(null_mut(), COINIT_APARTMENTTHREADED);
+            let result = CoCreateInstance(
+                &CLSID_TaskbarList,
+                null_mut(),
+                CLSCTX_INPROC_SERVER,
+                &ITaskbarList3::uuidof(),
+                &mut taskbar as *mut *mut ITaskbarList3 as *mut _,
+            );
+            if !SUCCEEDED(result) || taskbar.is_null() || !SUCCEEDED((*taskbar).HrInit()) {
+                warn!("Could not reach the taskbar, progress won't be shown");
+                return None;
+            }
+        }
+
+        Some(WindowsTaskbar {
+            taskbar,
+            hwnd: window.hwnd() as HWND,
+        })
+    }
+}
+
+impl TaskbarIndicator for WindowsTaskbar {
+    fn set_progress(&mut self, progress: Option<f64>) {
+        unsafe {
+            match progress {
+                Some(progress) => {
+                    (*self.taskbar).SetProgressState(self.hwnd, TBPF_NORMAL);
+                    (*self.taskbar).SetProgressValue(
+                        self.hwnd,
+                        (progress * PROGRESS_TOTAL as f64) as u64,
+                        PROGRESS_TOTAL,
+                    );
+                }
+                None => {
+                    (*self.taskbar).SetProgressState(self.hwnd, TBPF_NOPROGRESS);
+                }
+            }
+        }
+    }
+
+    fn set_badge(&mut self, _badge: Option<&str>) {}
+}
+
+impl Drop for WindowsTaskbar {
+    fn drop(&mut self) {
+        unsafe {
+            (*self.taskbar).Release();
+        }
+    }
+}
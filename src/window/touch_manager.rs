@@ -0,0 +1,335 @@
+use std::{collections::HashMap, time::Instant};
+
+use glutin::{
+    dpi::PhysicalPosition,
+    event::{Event, MouseButton, Touch, TouchPhase, WindowEvent},
+};
+
+use crate::{
+    renderer::Renderer,
+    settings::*,
+    window::{
+        keyboard_manager::KeyboardManager, mouse_manager::MouseManager,
+        windowed_context::WindowedContext, Frame, MouseSettings,
+    },
+};
+
+#[derive(Clone, SettingGroup)]
+#[setting_prefix = "touch"]
+pub struct TouchSettings {
+    pub enabled: bool,
+    // How far in pixels a finger may move and still count as a tap
+    pub tap_slop: f32,
+    // Seconds a finger has to be held still for a tap to become a right click
+    pub long_press_duration: f32,
+}
+
+impl Default for TouchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            tap_slop: 10.0,
+            long_press_duration: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TwoFingerMode {
+    Undecided,
+    Scroll,
+    Pinch,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Gesture {
+    Idle,
+    // A single finger which hasn't moved far enough to start a drag
+    Pending {
+        id: u64,
+        start: PhysicalPosition<f64>,
+        started: Instant,
+    },
+    Dragging {
+        id: u64,
+    },
+    TwoFinger {
+        mode: TwoFingerMode,
+        start_center: PhysicalPosition<f64>,
+        start_distance: f64,
+    },
+    // A gesture ended early, for example by lifting one of two fingers. Nothing else happens until
+    // every finger is lifted.
+    Finished,
+}
+
+fn distance(a: PhysicalPosition<f64>, b: PhysicalPosition<f64>) -> f64 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+fn center(a: PhysicalPosition<f64>, b: PhysicalPosition<f64>) -> PhysicalPosition<f64> {
+    PhysicalPosition::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0)
+}
+
+// Decides what a gesture with two fingers is once either the distance between the fingers or
+// their center has moved far enough from where they started
+fn decide_two_finger_mode(
+    start_center: PhysicalPosition<f64>,
+    start_distance: f64,
+    current_center: PhysicalPosition<f64>,
+    current_distance: f64,
+    tap_slop: f64,
+) -> TwoFingerMode {
+    if (current_distance - start_distance).abs() > tap_slop {
+        TwoFingerMode::Pinch
+    } else if distance(start_center, current_center) > tap_slop {
+        TwoFingerMode::Scroll
+    } else {
+        TwoFingerMode::Undecided
+    }
+}
+
+// Translates touch events into the mouse input neovim understands. One finger taps and drags like
+// the left mouse button, two fingers scroll or pinch to zoom the font.
+pub struct TouchManager {
+    fingers: HashMap<u64, PhysicalPosition<f64>>,
+    gesture: Gesture,
+    pending_zoom: f64,
+}
+
+impl TouchManager {
+    pub fn new() -> TouchManager {
+        TouchManager {
+            fingers: HashMap::new(),
+            gesture: Gesture::Idle,
+            pending_zoom: 1.0,
+        }
+    }
+
+    // The zoom pinched since the last call, as a factor of the current font size
+    pub fn take_zoom(&mut self) -> Option<f64> {
+        let zoom = std::mem::replace(&mut self.pending_zoom, 1.0);
+        if (zoom - 1.0).abs() > f64::EPSILON {
+            Some(zoom)
+        } else {
+            None
+        }
+    }
+
+    fn two_fingers(&self) -> Option<(PhysicalPosition<f64>, PhysicalPosition<f64>)> {
+        let mut positions = self.fingers.values().copied();
+        Some((positions.next()?, positions.next()?))
+    }
+
+    pub fn handle_event(
+        &mut self,
+        event: &Event<()>,
+        mouse_manager: &mut MouseManager,
+        keyboard_manager: &KeyboardManager,
+        renderer: &Renderer,
+        windowed_context: &WindowedContext,
+        frame: Frame,
+    ) {
+        let Touch {
+            phase,
+            location,
+            id,
+            ..
+        } = match event {
+            Event::WindowEvent {
+                event: WindowEvent::Touch(touch),
+                ..
+            } => *touch,
+            _ => return,
+        };
+
+        let settings = SETTINGS.get::<TouchSettings>();
+        // Touches are sent to neovim as mouse input, so they are off along with the mouse
+        if !settings.enabled || !SETTINGS.get::<MouseSettings>().enabled {
+            return;
+        }
+
+        let move_pointer = |mouse_manager: &mut MouseManager, position: PhysicalPosition<f64>| {
+            mouse_manager.handle_pointer_motion(
+                position.x as i32,
+                position.y as i32,
+                keyboard_manager,
+                renderer,
+                windowed_context,
+            );
+        };
+        let press = |mouse_manager: &mut MouseManager, button: MouseButton, down: bool| {
+            mouse_manager.handle_pointer_transition(
+                &button,
+                down,
+                keyboard_manager,
+                windowed_context,
+                frame,
+            );
+        };
+
+        match phase {
+            TouchPhase::Started => {
+                self.fingers.insert(id, location);
+                self.gesture = match (self.gesture, self.fingers.len()) {
+                    (Gesture::Idle, 1) => Gesture::Pending {
+                        id,
+                        start: location,
+                        started: Instant::now(),
+                    },
+                    (Gesture::Pending { .. }, 2) | (Gesture::Dragging { .. }, 2) => {
+                        // The drag ends where it was so that neovim doesn't see the button held
+                        if let Gesture::Dragging { .. } = self.gesture {
+                            press(mouse_manager, MouseButton::Left, false);
+                        }
+                        let (first, second) = self.two_fingers().unwrap();
+                        Gesture::TwoFinger {
+                            mode: TwoFingerMode::Undecided,
+                            start_center: center(first, second),
+                            start_distance: distance(first, second),
+                        }
+                    }
+                    _ => Gesture::Finished,
+                };
+            }
+            TouchPhase::Moved => {
+                let previous = match self.fingers.insert(id, location) {
+                    Some(previous) => previous,
+                    None => return,
+                };
+
+                match self.gesture {
+                    Gesture::Pending {
+                        id: pending_id,
+                        start,
+                        ..
+                    } if pending_id == id => {
+                        if distance(start, location) > settings.tap_slop as f64 {
+                            move_pointer(mouse_manager, start);
+                            press(mouse_manager, MouseButton::Left, true);
+                            move_pointer(mouse_manager, location);
+                            self.gesture = Gesture::Dragging { id };
+                        }
+                    }
+                    Gesture::Dragging { id: dragging_id } if dragging_id == id => {
+                        move_pointer(mouse_manager, location);
+                    }
+                    Gesture::TwoFinger {
+                        mode,
+                        start_center,
+                        start_distance,
+                    } => {
+                        let (first, second) = match self.two_fingers() {
+                            Some(fingers) => fingers,
+                            None => return,
+                        };
+                        let current_center = center(first, second);
+                        let current_distance = distance(first, second);
+
+                        let mode = match mode {
+                            TwoFingerMode::Undecided => decide_two_finger_mode(
+                                start_center,
+                                start_distance,
+                                current_center,
+                                current_distance,
+                                settings.tap_slop as f64,
+                            ),
+                            mode => mode,
+                        };
+                        self.gesture = Gesture::TwoFinger {
+                            mode,
+                            start_center,
+                            start_distance,
+                        };
+
+                        match mode {
+                            TwoFingerMode::Scroll => {
+                                // Each finger moving contributes half of the movement of the
+                                // center. Content follows the fingers, so moving them down
+                                // scrolls up.
+                                move_pointer(mouse_manager, current_center);
+                                mouse_manager.handle_pixel_scroll(
+                                    renderer.grid_renderer.font_dimensions.into(),
+                                    (
+                                        -(location.x - previous.x) as f32 / 2.0,
+                                        (location.y - previous.y) as f32 / 2.0,
+                                    ),
+                                    keyboard_manager,
+                                );
+                            }
+                            TwoFingerMode::Pinch => {
+                                let other_finger = self
+                                    .fingers
+                                    .iter()
+                                    .find(|(finger_id, _)| **finger_id != id)
+                                    .map(|(_, position)| *position);
+                                if let Some(other_finger) = other_finger {
+                                    let previous_distance = distance(previous, other_finger);
+                                    if previous_distance > 0.0 {
+                                        self.pending_zoom *= current_distance / previous_distance;
+                                    }
+                                }
+                            }
+                            TwoFingerMode::Undecided => {}
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.fingers.remove(&id);
+
+                match self.gesture {
+                    Gesture::Pending {
+                        id: pending_id,
+                        start,
+                        started,
+                    } if pending_id == id && phase == TouchPhase::Ended => {
+                        let button =
+                            if started.elapsed().as_secs_f32() >= settings.long_press_duration {
+                                MouseButton::Right
+                            } else {
+                                MouseButton::Left
+                            };
+                        move_pointer(mouse_manager, start);
+                        press(mouse_manager, button, true);
+                        press(mouse_manager, button, false);
+                    }
+                    Gesture::Dragging { id: dragging_id } if dragging_id == id => {
+                        press(mouse_manager, MouseButton::Left, false);
+                    }
+                    _ => {}
+                }
+
+                self.gesture = if self.fingers.is_empty() {
+                    Gesture::Idle
+                } else {
+                    Gesture::Finished
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decide_two_finger_mode() {
+        let start = PhysicalPosition::new(100.0, 100.0);
+
+        assert_eq!(
+            decide_two_finger_mode(start, 50.0, PhysicalPosition::new(103.0, 104.0), 52.0, 10.0),
+            TwoFingerMode::Undecided
+        );
+        assert_eq!(
+            decide_two_finger_mode(start, 50.0, PhysicalPosition::new(100.0, 130.0), 51.0, 10.0),
+            TwoFingerMode::Scroll
+        );
+        assert_eq!(
+            decide_two_finger_mode(start, 50.0, PhysicalPosition::new(100.0, 130.0), 80.0, 10.0),
+            TwoFingerMode::Pinch
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use std::ops::Deref;
+
+use glutin::{
+    event_loop::EventLoop, window::Window, window::WindowBuilder, ContextBuilder, CreationError,
+    GlProfile, NotCurrent, PossiblyCurrent, RawContext,
+};
+use log::warn;
+
+// What the opengl context of the window is made with, kept so that the same context can be made
+// again
+#[derive(Clone, Copy, Debug)]
+pub struct ContextConfig {
+    pub vsync: bool,
+    pub srgb: bool,
+    pub hardware_acceleration: bool,
+}
+
+impl ContextConfig {
+    fn builder(&self) -> ContextBuilder<'static, NotCurrent> {
+        ContextBuilder::new()
+            .with_pixel_format(24, 8)
+            .with_stencil_buffer(8)
+            .with_gl_profile(GlProfile::Core)
+            .with_vsync(self.vsync)
+            .with_srgb(self.srgb)
+            .with_hardware_acceleration(Some(self.hardware_acceleration))
+    }
+}
+
+// The window together with its opengl context. glutin's own WindowedContext can't give the window
+// it owns a new context, which a gpu reset needs since the old context is dead after one, so the
+// two are kept side by side here instead.
+pub struct WindowedContext {
+    // Declared before the window, the context has to be destroyed first
+    context: RawContext<PossiblyCurrent>,
+    window: Window,
+    config: ContextConfig,
+}
+
+impl WindowedContext {
+    pub fn new(
+        config: ContextConfig,
+        window_builder: WindowBuilder,
+        event_loop: &EventLoop<()>,
+    ) -> Result<WindowedContext, CreationError> {
+        let windowed_context = config
+            .builder()
+            .build_windowed(window_builder, event_loop)?;
+        let windowed_context = unsafe { windowed_context.make_current().unwrap() };
+        let (context, window) = unsafe { windowed_context.split() };
+
+        Ok(WindowedContext {
+            context,
+            window,
+            config,
+        })
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    // Replaces the context with a new one for the same window. Everything made through the old
+    // context has to be abandoned first, none of it is valid for the new one. Returns false and
+    // keeps the old context when a new one can't be made, which glutin never can for an existing
+    // window on macos.
+    pub fn recreate_context(&mut self) -> bool {
+        let context = match unsafe { build_raw_context(self.config.builder(), &self.window) } {
+            Ok(context) => context,
+            Err(error) => {
+                warn!("Could not recreate the opengl context: {}", error);
+                return false;
+            }
+        };
+        match unsafe { context.make_current() } {
+            Ok(context) => {
+                self.context = context;
+                true
+            }
+            Err((_, error)) => {
+                warn!(
+                    "Could not make the recreated opengl context current: {}",
+                    error
+                );
+                false
+            }
+        }
+    }
+}
+
+impl Deref for WindowedContext {
+    type Target = RawContext<PossiblyCurrent>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.context
+    }
+}
+
+#[cfg(target_os = "windows")]
+unsafe fn build_raw_context(
+    builder: ContextBuilder<NotCurrent>,
+    window: &Window,
+) -> Result<RawContext<NotCurrent>, CreationError> {
+    use glutin::platform::windows::{RawContextExt, WindowExtWindows};
+
+    builder.build_raw_context(window.hwnd() as *mut _)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+unsafe fn build_raw_context(
+    builder: ContextBuilder<NotCurrent>,
+    window: &Window,
+) -> Result<RawContext<NotCurrent>, CreationError> {
+    use glutin::platform::unix::{RawContextExt, WindowExtUnix};
+
+    if let (Some(display), Some(surface)) = (window.wayland_display(), window.wayland_surface()) {
+        let size = window.inner_size();
+        return builder.build_raw_wayland_context(
+            display as _,
+            surface as _,
+            size.width,
+            size.height,
+        );
+    }
+    match (window.xlib_xconnection(), window.xlib_window()) {
+        (Some(xconn), Some(xwin)) => builder.build_raw_x11_context(xconn, xwin),
+        _ => Err(CreationError::NotSupported(
+            "The window is neither a wayland nor an x11 one".to_owned(),
+        )),
+    }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn build_raw_context(
+    _builder: ContextBuilder<NotCurrent>,
+    _window: &Window,
+) -> Result<RawContext<NotCurrent>, CreationError> {
+    Err(CreationError::NotSupported(
+        "glutin can't make a context for an existing window on this platform".to_owned(),
+    ))
+}
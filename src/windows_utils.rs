@@ -12,6 +12,7 @@ use winapi::{
         wincon::{AttachConsole, ATTACH_PARENT_PROCESS},
         winnt::{KEY_WRITE, REG_OPTION_NON_VOLATILE, REG_SZ},
         winreg::{RegCloseKey, RegCreateKeyExA, RegDeleteTreeA, RegSetValueExA, HKEY_CURRENT_USER},
+        winuser::{MessageBoxA, MB_ICONERROR, MB_OK},
     },
 };
 
@@ -32,6 +33,20 @@ fn get_binary_path() -> String {
     }
 }
 
+#[cfg(target_os = "windows")]
+pub fn show_error_dialog(title: &str, message: &str) {
+    let title = CString::new(title).unwrap_or_default();
+    let message = CString::new(message).unwrap_or_default();
+    unsafe {
+        MessageBoxA(
+            null_mut(),
+            message.as_ptr(),
+            title.as_ptr(),
+            MB_OK | MB_ICONERROR,
+        );
+    }
+}
+
 #[cfg(target_os = "windows")]
 pub fn unregister_rightclick() -> bool {
     let str_registry_path_1 =
@@ -53,7 +68,7 @@ pub fn register_rightclick_directory() -> bool {
     let str_registry_command_path =
         CString::new("Software\\Classes\\Directory\\Background\\shell\\Neovide\\command").unwrap();
     let str_icon = CString::new("Icon").unwrap();
-    let str_command = CString::new(format!("{} \"%V\"", neovide_path).as_bytes()).unwrap();
+    let str_command = CString::new(format!("\"{}\" \"%V\"", neovide_path).as_bytes()).unwrap();
     let str_description = CString::new("Open with Neovide").unwrap();
     let str_neovide_path = CString::new(neovide_path.as_bytes()).unwrap();
     unsafe {
@@ -141,7 +156,7 @@ pub fn register_rightclick_file() -> bool {
     let str_registry_command_path =
         CString::new("Software\\Classes\\*\\shell\\Neovide\\command").unwrap();
     let str_icon = CString::new("Icon").unwrap();
-    let str_command = CString::new(format!("{} \"%1\"", neovide_path).as_bytes()).unwrap();
+    let str_command = CString::new(format!("\"{}\" \"%1\"", neovide_path).as_bytes()).unwrap();
     let str_description = CString::new("Open with Neovide").unwrap();
     let str_neovide_path = CString::new(neovide_path.as_bytes()).unwrap();
     unsafe {